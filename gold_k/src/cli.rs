@@ -0,0 +1,646 @@
+use anyhow::{Result, anyhow};
+use clap::Args;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs;
+use tracing::info;
+
+use crate::config::get_global_config;
+use crate::models::{KlineData, MonitorConfig};
+use crate::repository::{
+    self, ApiKeyRepository, MonitorConfigRepository, RiskRepository, StrategyScriptRepository,
+};
+use crate::services::backtest::{BacktestOptions, BacktestReport, run_backtest};
+use crate::services::emergency::FlattenReport;
+use crate::services::optimizer::{Objective, OptimizeResult, ParamSet, ParamSpace, optimize};
+use crate::services::scripting::ScriptStrategy;
+use crate::services::walk_forward::{WalkForwardOptions, WalkForwardReport, walk_forward};
+use crate::services::{
+    DingTalkService, Exchange, GateService, KlineStore, PaperExchange, emergency_flatten,
+};
+use tokio::sync::RwLock;
+
+#[derive(Args, Debug, Clone)]
+pub struct StrategyArgs {
+    /// 监控配置ID，不指定时按交易对和K线维度查找
+    #[arg(long)]
+    pub config_id: Option<i64>,
+
+    /// 交易对，如 BTC_USDT
+    #[arg(long)]
+    pub symbol: Option<String>,
+
+    /// K线维度，如 5m
+    #[arg(long)]
+    pub interval: Option<String>,
+
+    /// K线数据JSON文件（KlineData数组），不指定时从本地K线存储读取
+    #[arg(long)]
+    pub file: Option<String>,
+
+    /// 使用最近多少天的K线，本地缺失的部分会先从交易所回补
+    #[arg(long, default_value_t = 30.0)]
+    pub days: f64,
+
+    /// 初始资金(USDT)
+    #[arg(long, default_value_t = 10000.0)]
+    pub initial_balance: f64,
+
+    /// 单边手续费率
+    #[arg(long, default_value_t = 0.0005)]
+    pub fee_rate: f64,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct BacktestArgs {
+    #[command(flatten)]
+    pub strategy: StrategyArgs,
+
+    /// 回测报告输出的JSON文件
+    #[arg(long)]
+    pub output: Option<String>,
+}
+
+/// 参数搜索空间，每个参数用逗号分隔多个候选值，不指定时使用配置中的当前值
+#[derive(Args, Debug, Clone)]
+pub struct ParamSpaceArgs {
+    #[arg(long, value_delimiter = ',')]
+    pub shadow_ratio: Vec<f64>,
+
+    #[arg(long, value_delimiter = ',')]
+    pub main_shadow_body_ratio: Vec<f64>,
+
+    #[arg(long, value_delimiter = ',')]
+    pub volume_multiplier: Vec<f64>,
+
+    #[arg(long, value_delimiter = ',')]
+    pub risk_reward_ratio: Vec<f64>,
+
+    #[arg(long, value_delimiter = ',')]
+    pub expected_profit_rate: Vec<f64>,
+
+    #[arg(long, value_delimiter = ',')]
+    pub history_hours: Vec<f64>,
+
+    /// 随机搜索的次数，在每个参数候选值的最小值和最大值之间采样；不指定时使用网格搜索
+    #[arg(long)]
+    pub random: Option<usize>,
+
+    /// 随机搜索的种子
+    #[arg(long, default_value_t = 42)]
+    pub seed: u64,
+
+    /// 排序使用的目标函数
+    #[arg(long, value_enum, default_value_t = Objective::NetPnl)]
+    pub objective: Objective,
+
+    /// 已平仓交易少于该数量的参数组合不参与排序
+    #[arg(long, default_value_t = 5)]
+    pub min_trades: usize,
+
+    /// 并行回测的线程数，默认为CPU核数
+    #[arg(long)]
+    pub workers: Option<usize>,
+}
+
+impl ParamSpaceArgs {
+    /// 生成候选参数组合
+    pub fn candidates(&self, config: &MonitorConfig) -> Vec<ParamSet> {
+        let space = ParamSpace {
+            shadow_ratio: self.shadow_ratio.clone(),
+            main_shadow_body_ratio: self.main_shadow_body_ratio.clone(),
+            volume_multiplier: self.volume_multiplier.clone(),
+            risk_reward_ratio: self.risk_reward_ratio.clone(),
+            expected_profit_rate: self.expected_profit_rate.clone(),
+            history_hours: self.history_hours.clone(),
+        };
+        let base = ParamSet::from_config(config);
+        match self.random {
+            Some(count) => space.sample(&base, count, self.seed),
+            None => space.grid(&base),
+        }
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers.unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1)
+        })
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct OptimizeArgs {
+    #[command(flatten)]
+    pub strategy: StrategyArgs,
+
+    #[command(flatten)]
+    pub space: ParamSpaceArgs,
+
+    /// 输出排名前几的参数组合
+    #[arg(long, default_value_t = 10)]
+    pub top: usize,
+
+    /// 把最优参数写回监控配置
+    #[arg(long)]
+    pub save: bool,
+
+    /// 全部结果输出的JSON文件
+    #[arg(long)]
+    pub output: Option<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct WalkForwardArgs {
+    #[command(flatten)]
+    pub strategy: StrategyArgs,
+
+    #[command(flatten)]
+    pub space: ParamSpaceArgs,
+
+    /// 样本内（寻优）窗口天数
+    #[arg(long, default_value_t = 7.0)]
+    pub in_sample_days: f64,
+
+    /// 样本外（验证）窗口天数
+    #[arg(long, default_value_t = 2.0)]
+    pub out_of_sample_days: f64,
+
+    /// 窗口滚动天数，默认等于样本外窗口天数
+    #[arg(long)]
+    pub step_days: Option<f64>,
+
+    /// 滚动验证报告输出的JSON文件
+    #[arg(long)]
+    pub output: Option<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct BackfillArgs {
+    /// 交易对，如 BTC_USDT
+    #[arg(long)]
+    pub symbol: String,
+
+    /// K线维度，如 5m
+    #[arg(long)]
+    pub interval: String,
+
+    /// 回补最近多少天的K线
+    #[arg(long, default_value_t = 30.0)]
+    pub days: f64,
+
+    /// 结算币种
+    #[arg(long, default_value = "usdt")]
+    pub settle: String,
+
+    /// 只回补缺失的K线，不重新获取已有的K线
+    #[arg(long)]
+    pub repair: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct FlattenArgs {
+    /// 确认执行；不加时只列出将要平掉的持仓
+    #[arg(long)]
+    pub yes: bool,
+
+    /// 恢复自动下单，不做平仓
+    #[arg(long, conflicts_with = "yes")]
+    pub resume: bool,
+}
+
+/// 紧急平仓命令：暂停自动下单，撤销所有挂单和止盈止损委托，市价平掉实盘和模拟持仓
+///
+/// 运行中的web服务里的监控会因为暂停状态不再下单，但仍需在页面上停止监控
+pub async fn flatten(args: FlattenArgs) -> Result<()> {
+    let db = connect_db().await?;
+    if args.resume {
+        RiskRepository::set_halted(&db, false, None).await?;
+        println!("已恢复自动下单");
+        return Ok(());
+    }
+
+    let key = ApiKeyRepository::get_active(&db).await?;
+    let mut gate = GateService::new();
+    let mut dingtalk = DingTalkService::new();
+    if let Some(key) = &key {
+        gate.apply_api_key(key);
+        if let Some(url) = key.webhook_url.as_deref().filter(|u| !u.is_empty()) {
+            dingtalk.set_webhook_url(url);
+        }
+    }
+    let gate: Arc<RwLock<dyn Exchange>> = Arc::new(RwLock::new(gate));
+    let paper: Arc<RwLock<dyn Exchange>> =
+        Arc::new(RwLock::new(PaperExchange::new(db.clone(), gate.clone())));
+    let exchanges = [gate, paper];
+
+    if !args.yes {
+        for exchange in &exchanges {
+            let exchange = exchange.read().await;
+            match exchange.get_positions("usdt").await {
+                Ok(positions) => {
+                    println!("[{}] 持仓 {} 个", exchange.name(), positions.len());
+                    for p in positions {
+                        println!(
+                            "  {:<14} {:<10} {:>8}张  标记价 {:<12} 浮动盈亏 {:.4}",
+                            p.symbol, p.mode, p.size, p.mark_price, p.unrealized_pnl
+                        );
+                    }
+                }
+                Err(e) => println!("[{}] 查询持仓失败: {}", exchange.name(), e),
+            }
+        }
+        println!("加 --yes 执行紧急平仓");
+        return Ok(());
+    }
+
+    let reports = emergency_flatten(&db, &exchanges, Some(&dingtalk)).await?;
+    print_flatten(&reports);
+    Ok(())
+}
+
+/// 回补命令：把历史K线保存到本地K线存储
+pub async fn backfill(args: BackfillArgs) -> Result<()> {
+    let db = connect_db().await?;
+    let gate = market_exchange(&db).await?;
+    let store = KlineStore::new(db);
+
+    let to = now()?;
+    let from = to - (args.days * 86400.0) as i64;
+    let fetched = if args.repair {
+        store
+            .repair_gaps(&gate, &args.settle, &args.symbol, &args.interval, from, to)
+            .await?
+    } else {
+        store
+            .backfill(&gate, &args.settle, &args.symbol, &args.interval, from, to)
+            .await?
+    };
+    info!(
+        "Fetched {} klines for {} {}",
+        fetched, args.symbol, args.interval
+    );
+
+    let gaps = store
+        .find_gaps(&args.settle, &args.symbol, &args.interval, from, to)
+        .await?;
+    println!("获取K线: {}", fetched);
+    println!("剩余缺口: {}", gaps.len());
+    for (gap_from, gap_to) in gaps {
+        println!(
+            "  {} ~ {}",
+            utils::format_timestamp(gap_from, 8),
+            utils::format_timestamp(gap_to, 8)
+        );
+    }
+
+    Ok(())
+}
+
+/// 回测命令：用历史K线回放影线策略并输出统计结果
+pub async fn backtest(args: BacktestArgs) -> Result<()> {
+    let db = connect_db().await?;
+    let config = load_config(&db, &args.strategy).await?;
+    let options = load_options(&db, &config, &args.strategy).await?;
+    let klines = load_klines(&db, &config, &args.strategy).await?;
+
+    info!(
+        "Running backtest for {} {} on {} klines",
+        config.symbol,
+        config.interval_type,
+        klines.len()
+    );
+    let report = run_backtest(&config, &klines, &options);
+    print_report(&report);
+
+    if let Some(output) = &args.output {
+        fs::write(output, serde_json::to_string_pretty(&report)?).await?;
+        info!("Backtest report written to {}", output);
+    }
+
+    Ok(())
+}
+
+/// 参数寻优命令：在一段历史K线上搜索影线策略的最优阈值
+pub async fn optimize_params(args: OptimizeArgs) -> Result<()> {
+    let db = connect_db().await?;
+    let config = load_config(&db, &args.strategy).await?;
+    let options = load_options(&db, &config, &args.strategy).await?;
+    let klines = Arc::new(load_klines(&db, &config, &args.strategy).await?);
+
+    let candidates = args.space.candidates(&config);
+    info!(
+        "Optimizing {} {} over {} parameter sets on {} klines",
+        config.symbol,
+        config.interval_type,
+        candidates.len(),
+        klines.len()
+    );
+    let results = optimize(
+        &config,
+        klines,
+        &options,
+        candidates,
+        args.space.objective,
+        args.space.min_trades,
+        args.space.workers(),
+    )
+    .await?;
+
+    print_results(&results, args.top);
+
+    if let Some(output) = &args.output {
+        fs::write(output, serde_json::to_string_pretty(&results)?).await?;
+        info!("Optimize results written to {}", output);
+    }
+
+    if args.save {
+        let best = results
+            .first()
+            .ok_or_else(|| anyhow!("No parameter set has enough trades"))?;
+        MonitorConfigRepository::update_params(&db, &best.params.apply(&config)).await?;
+        println!("最优参数已写回监控配置 {}", config.id.unwrap_or_default());
+    }
+
+    Ok(())
+}
+
+/// 滚动验证命令：逐窗口寻优并在之后的样本外数据上验证，检查参数是否过拟合
+pub async fn walk_forward_validate(args: WalkForwardArgs) -> Result<()> {
+    let db = connect_db().await?;
+    let config = load_config(&db, &args.strategy).await?;
+    let options = load_options(&db, &config, &args.strategy).await?;
+    let klines = Arc::new(load_klines(&db, &config, &args.strategy).await?);
+
+    let klines_per_day = 86400.0 / config.interval_type_to_seconds().max(1) as f64;
+    let to_klines = |days: f64| (days * klines_per_day).round() as usize;
+    let wf = WalkForwardOptions {
+        in_sample: to_klines(args.in_sample_days),
+        out_of_sample: to_klines(args.out_of_sample_days),
+        step: to_klines(args.step_days.unwrap_or(args.out_of_sample_days)),
+        objective: args.space.objective,
+        min_trades: args.space.min_trades,
+        workers: args.space.workers(),
+    };
+
+    let candidates = args.space.candidates(&config);
+    info!(
+        "Walk-forward for {} {}: {} parameter sets, windows {}/{} klines",
+        config.symbol,
+        config.interval_type,
+        candidates.len(),
+        wf.in_sample,
+        wf.out_of_sample
+    );
+    let report = walk_forward(&config, klines, &options, candidates, &wf).await?;
+    print_walk_forward(&report);
+
+    if let Some(output) = &args.output {
+        fs::write(output, serde_json::to_string_pretty(&report)?).await?;
+        info!("Walk-forward report written to {}", output);
+    }
+
+    Ok(())
+}
+
+pub async fn connect_db() -> Result<SqlitePool> {
+    let c = get_global_config().await;
+    repository::connect(&c.database_url).await
+}
+
+pub async fn load_config(db: &SqlitePool, args: &StrategyArgs) -> Result<MonitorConfig> {
+    if let Some(id) = args.config_id {
+        return MonitorConfigRepository::get_by_id(db, id)
+            .await?
+            .ok_or_else(|| anyhow!("Monitor config {} not found", id));
+    }
+
+    let (Some(symbol), Some(interval)) = (&args.symbol, &args.interval) else {
+        return Err(anyhow!(
+            "Either --config-id or --symbol and --interval is required"
+        ));
+    };
+    MonitorConfigRepository::find_by_symbol(db, symbol, interval)
+        .await?
+        .ok_or_else(|| anyhow!("No monitor config found for {} {}", symbol, interval))
+}
+
+pub async fn load_options(
+    db: &SqlitePool,
+    config: &MonitorConfig,
+    args: &StrategyArgs,
+) -> Result<BacktestOptions> {
+    let mut options = BacktestOptions {
+        initial_balance: args.initial_balance,
+        fee_rate: args.fee_rate,
+        ..Default::default()
+    };
+
+    // 使用合约的价格精度和数量乘数，保证与实盘一致
+    if let Some(contract) = ApiKeyRepository::get_contract_by_symbol(db, &config.symbol).await? {
+        options.order_price_round = contract.order_price_round;
+        options.quanto_multiplier = contract.quanto_multiplier.parse().unwrap_or(1.0);
+    }
+
+    // 脚本策略使用配置的脚本版本，和监控时一致
+    if config.strategy == "script" {
+        let script = StrategyScriptRepository::get(db, &config.script_name, config.script_version)
+            .await?
+            .ok_or_else(|| {
+                anyhow!(
+                    "Script {} v{} not found",
+                    config.script_name,
+                    config.script_version
+                )
+            })?;
+        options.script = Some(Arc::new(ScriptStrategy::compile(&script)?));
+    }
+
+    Ok(options)
+}
+
+pub async fn load_klines(
+    db: &SqlitePool,
+    config: &MonitorConfig,
+    args: &StrategyArgs,
+) -> Result<Vec<KlineData>> {
+    let mut klines: Vec<KlineData> = if let Some(file) = &args.file {
+        serde_json::from_str(&fs::read_to_string(file).await?)?
+    } else {
+        let gate = market_exchange(db).await?;
+        let from = now()? - (args.days * 86400.0) as i64;
+        KlineStore::new(db.clone())
+            .load_closed(&gate, "usdt", &config.symbol, &config.interval_type, from)
+            .await?
+    };
+
+    klines.sort_by_key(|k| k.timestamp);
+    klines.dedup_by_key(|k| k.timestamp);
+    Ok(klines)
+}
+
+/// 用当前激活的API配置创建行情交易所
+async fn market_exchange(db: &SqlitePool) -> Result<GateService> {
+    let key = ApiKeyRepository::get_active(db)
+        .await?
+        .ok_or_else(|| anyhow!("No active API key found"))?;
+    let mut gate = GateService::new();
+    gate.apply_api_key(&key);
+    Ok(gate)
+}
+
+fn now() -> Result<i64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64)
+}
+
+fn print_flatten(reports: &[FlattenReport]) {
+    println!("自动下单已暂停，使用 flatten --resume 恢复");
+    for report in reports {
+        println!(
+            "[{}] 撤销挂单 {}，撤销止盈止损 {}，平仓 {}",
+            report.exchange,
+            report.cancelled_orders,
+            report.cancelled_price_orders,
+            report.closed.len()
+        );
+        for c in &report.closed {
+            println!(
+                "  {} {:<14} {:>8}张 @ {:<12} {}",
+                if c.success { "OK  " } else { "FAIL" },
+                c.symbol,
+                c.size,
+                c.mark_price,
+                c.message
+            );
+        }
+        for e in &report.errors {
+            println!("  错误: {}", e);
+        }
+    }
+}
+
+fn print_results(results: &[OptimizeResult], top: usize) {
+    println!("有效参数组合: {}", results.len());
+    println!(
+        "{:>4} {:>12} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>6} {:>8} {:>10} {:>8}",
+        "排名",
+        "得分",
+        "影线比",
+        "影线实体",
+        "量倍数",
+        "盈亏比",
+        "收益率",
+        "历史h",
+        "交易",
+        "胜率",
+        "净盈亏",
+        "回撤%"
+    );
+    for (i, r) in results.iter().take(top).enumerate() {
+        println!(
+            "{:>4} {:>12.4} {:>8.2} {:>8.2} {:>8.2} {:>8.2} {:>8.2} {:>8.2} {:>6} {:>7.2}% {:>10.4} {:>8.2}",
+            i + 1,
+            r.score,
+            r.params.shadow_ratio,
+            r.params.main_shadow_body_ratio,
+            r.params.volume_multiplier,
+            r.params.risk_reward_ratio,
+            r.params.expected_profit_rate,
+            r.params.history_hours,
+            r.report.closed_trades,
+            r.report.win_rate * 100.0,
+            r.report.net_pnl,
+            r.report.max_drawdown_pct
+        );
+    }
+}
+
+fn print_walk_forward(report: &WalkForwardReport) {
+    let format_time = |t: i64| utils::format_timestamp(t, 8);
+    let net_pnl = |r: &Option<BacktestReport>| r.as_ref().map(|r| r.net_pnl).unwrap_or(0.0);
+
+    println!("交易对: {} {}", report.symbol, report.interval_type);
+    for w in &report.windows {
+        let Some(params) = &w.best_params else {
+            println!(
+                "窗口{}: {} ~ {} 没有满足最少交易数的参数",
+                w.index + 1,
+                format_time(w.in_sample_start),
+                format_time(w.out_of_sample_end)
+            );
+            continue;
+        };
+        println!(
+            "窗口{}: 样本外 {} ~ {}, 样本内得分 {:.4}, 样本外得分 {}, 样本内盈亏 {:.4}, 样本外盈亏 {:.4}",
+            w.index + 1,
+            format_time(w.out_of_sample_start),
+            format_time(w.out_of_sample_end),
+            w.in_sample_score,
+            w.out_of_sample_score
+                .map(|score| format!("{:.4}", score))
+                .unwrap_or_else(|| "-".to_string()),
+            net_pnl(&w.in_sample),
+            net_pnl(&w.out_of_sample)
+        );
+        println!(
+            "    参数: 影线比 {:.2}, 影线实体 {:.2}, 量倍数 {:.2}, 盈亏比 {:.2}, 收益率 {:.2}, 历史h {:.2}",
+            params.shadow_ratio,
+            params.main_shadow_body_ratio,
+            params.volume_multiplier,
+            params.risk_reward_ratio,
+            params.expected_profit_rate,
+            params.history_hours
+        );
+    }
+
+    println!(
+        "有效窗口: {}/{}, 样本外盈利窗口: {}",
+        report.valid_windows,
+        report.windows.len(),
+        report.profitable_out_of_sample_windows
+    );
+    println!(
+        "平均得分: 样本内 {:.4}, 样本外 {:.4}, 效率 {:.2}",
+        report.avg_in_sample_score, report.avg_out_of_sample_score, report.efficiency
+    );
+    println!(
+        "累计盈亏: 样本内 {:.4} USDT, 样本外 {:.4} USDT",
+        report.in_sample_net_pnl, report.out_of_sample_net_pnl
+    );
+    println!("参数稳定性:");
+    for p in &report.param_stability {
+        println!(
+            "    {}: 均值 {:.4}, 标准差 {:.4}, 范围 {:.4} ~ {:.4}, 变异系数 {:.2}",
+            p.name, p.mean, p.std_dev, p.min, p.max, p.coefficient_of_variation
+        );
+    }
+}
+
+fn print_report(report: &BacktestReport) {
+    let format_time = |t: Option<i64>| t.map(|t| utils::format_timestamp(t, 8)).unwrap_or_default();
+
+    println!("交易对: {} {}", report.symbol, report.interval_type);
+    println!(
+        "时间范围: {} ~ {} ({}根K线)",
+        format_time(report.start_time),
+        format_time(report.end_time),
+        report.total_klines
+    );
+    println!(
+        "信号数: {}, 已平仓: {}, 盈利: {}, 亏损: {}",
+        report.total_signals, report.closed_trades, report.wins, report.losses
+    );
+    println!("胜率: {:.2}%", report.win_rate * 100.0);
+    println!("净盈亏: {:.4} USDT", report.net_pnl);
+    match report.profit_factor {
+        Some(profit_factor) => println!("盈亏因子: {:.2}", profit_factor),
+        None => println!("盈亏因子: -（没有亏损交易）"),
+    }
+    println!("夏普比率: {:.4}", report.sharpe_ratio);
+    println!(
+        "最大回撤: {:.4} USDT ({:.2}%)",
+        report.max_drawdown, report.max_drawdown_pct
+    );
+}
@@ -2,7 +2,7 @@ use std::env;
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
-use gold_k::{config, web};
+use gold_k::{cli, config, web};
 use tokio::fs;
 use validator::Validate;
 
@@ -23,6 +23,16 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     Web,
+    /// 用历史K线回测影线策略
+    Backtest(cli::BacktestArgs),
+    /// 搜索影线策略的最优参数
+    Optimize(Box<cli::OptimizeArgs>),
+    /// 滚动窗口寻优并做样本外验证
+    WalkForward(Box<cli::WalkForwardArgs>),
+    /// 回补历史K线到本地K线存储
+    Backfill(cli::BackfillArgs),
+    /// 紧急平仓：暂停自动下单，撤销挂单和止盈止损，市价平掉所有持仓
+    Flatten(cli::FlattenArgs),
 }
 
 #[tokio::main]
@@ -48,6 +58,21 @@ async fn main() -> Result<()> {
             // Start the web server
             web::start().await?;
         }
+        Commands::Backtest(args) => {
+            cli::backtest(args).await?;
+        }
+        Commands::Optimize(args) => {
+            cli::optimize_params(*args).await?;
+        }
+        Commands::WalkForward(args) => {
+            cli::walk_forward_validate(*args).await?;
+        }
+        Commands::Backfill(args) => {
+            cli::backfill(args).await?;
+        }
+        Commands::Flatten(args) => {
+            cli::flatten(args).await?;
+        }
     }
 
     Ok(())
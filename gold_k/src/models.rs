@@ -15,11 +15,17 @@ pub struct ApiKey {
     pub updated_at: i64,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Default)]
 pub struct Contract {
     pub order_price_round: String, // 合约价格精度
     pub quanto_multiplier: String, // 合约数量乘数
     pub name: String,              // 合约名称, BTC_USDT
+    #[serde(default)]
+    pub order_size_min: i64, // 最小下单张数
+    #[serde(default)]
+    pub order_size_max: i64, // 最大下单张数，0表示不限制
+    #[serde(default)]
+    pub taker_fee_rate: String, // 吃单手续费率
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow, Default)]
@@ -42,6 +48,16 @@ pub struct Signal {
     pub shadow_ratio: f64,
     pub volume_multiplier: f64,
     pub avg_volume: Option<f64>,
+    pub blocked_reason: Option<String>, // 被风控、指标过滤或多周期确认拦截时的原因
+    pub strategy: String,               // 产生信号的策略，见 services::patterns::STRATEGIES
+    pub pattern: Option<String>,        // 识别到的K线形态，长影线策略为空
+    pub direction: String,              // 'long' or 'short'
+    pub stop_price: Option<f64>,        // 形态的止损价，为空时使用K线的最高/最低价
+    pub indicator_filters: Option<String>, // 指标过滤结果，JSON数组，见 services::indicators::FilterResult
+    pub confirmation: Option<String>, // 多周期确认的依据，JSON对象，见 services::confirmation::Confirmation
+    pub script_version: Option<i64>,  // 脚本策略的脚本版本，脚本名称见 pattern
+    pub entry_price: Option<f64>,     // 脚本给出的入场价，为空时使用收盘价
+    pub target_price: Option<f64>,    // 脚本给出的止盈价，为空时按盈亏比计算
     pub created_at: i64,
 }
 
@@ -58,6 +74,160 @@ pub struct Order {
     pub signal_id: Option<i64>,
     pub timestamp: i64,
     pub created_at: i64,
+    pub exchange: String, // 'gate' or 'paper'
+    pub exchange_order_id: Option<String>,
+    pub client_order_id: Option<String>,
+    pub status: String, // 'pending', 'open', 'filled', 'cancelled', 'rejected', 'unknown'
+    pub filled_size: i64,
+    pub avg_fill_price: Option<f64>,
+    pub fee: f64,
+    pub take_profit_order_id: Option<String>,
+    pub take_profit_status: Option<String>, // 'open', 'triggered', 'cancelled', 'failed'
+    pub stop_loss_order_id: Option<String>,
+    pub stop_loss_status: Option<String>,
+    pub error_message: Option<String>,
+    pub updated_at: Option<i64>,
+    pub execution_mode: String, // 'web' 或 'api'，api下单的止盈止损由对账任务在成交后补充
+    pub exit_price: Option<f64>,
+    pub exit_reason: Option<String>, // 'tp', 'sl', 'manual'
+    pub closed_at: Option<i64>,
+    pub interval_type: Option<String>,
+    pub break_even_r: f64,
+    pub trailing_mode: String, // 'none', 'fixed', 'percent', 'atr'
+    pub trailing_value: f64,
+    pub initial_stop_price: Option<f64>, // 下单时的止损价，stop_loss_price 为移动后的止损价
+    pub take_profit_ladder: String,      // 分批止盈，例如 '50:1,30:2,20'，空表示只有一个止盈
+    pub ladder_break_even: bool,         // 第一批止盈后止损移到开仓价
+    pub closed_size: i64,                // 分批止盈已经平掉的张数
+}
+
+/// 下单结果，和交易信号一起保存为订单记录
+#[derive(Debug, Clone, Default)]
+pub struct OrderPlacement {
+    pub exchange: String,
+    pub client_order_id: Option<String>,
+    pub exchange_order_id: Option<String>,
+    pub status: String,
+    pub take_profit_order_id: Option<String>,
+    pub stop_loss_order_id: Option<String>,
+    pub error_message: Option<String>,
+    pub execution_mode: String,
+    // 移动止损规则，从监控配置复制
+    pub interval_type: String,
+    pub break_even_r: f64,
+    pub trailing_mode: String,
+    pub trailing_value: f64,
+    // 分批止盈规则，只用于签名API下单
+    pub take_profit_ladder: String,
+    pub ladder_break_even: bool,
+}
+
+/// 对账得到的订单最新状态
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OrderUpdate {
+    pub exchange_order_id: Option<String>,
+    pub status: String,
+    pub filled_size: i64,
+    pub avg_fill_price: Option<f64>,
+    pub fee: f64,
+    pub take_profit_order_id: Option<String>,
+    pub take_profit_status: Option<String>,
+    pub stop_loss_order_id: Option<String>,
+    pub stop_loss_status: Option<String>,
+    pub error_message: Option<String>,
+    pub exit_price: Option<f64>,
+    pub exit_reason: Option<String>,
+    pub closed_at: Option<i64>,
+}
+
+/// 移动止损的记录
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, Default)]
+pub struct StopAdjustment {
+    pub id: i64,
+    pub order_id: i64,
+    pub reason: String, // 'break_even', 'trailing'
+    pub old_stop: f64,
+    pub new_stop: f64,
+    pub price: f64, // 触发调整的K线收盘价
+    pub stop_loss_order_id: Option<String>,
+    pub created_at: i64,
+}
+
+/// 策略脚本的一个版本
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, Default, PartialEq)]
+pub struct StrategyScript {
+    pub id: i64,
+    pub name: String,
+    pub version: i64,
+    pub source: String, // Rhai脚本，见 services::scripting
+    pub created_at: i64,
+}
+
+/// 分批止盈中的一批
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, Default, PartialEq)]
+pub struct TakeProfitTranche {
+    pub id: i64,
+    pub order_id: i64,
+    pub seq: i64,
+    pub size: i64,
+    pub r_multiple: f64,
+    pub trigger_price: f64,
+    pub trigger_order_id: Option<String>,
+    pub status: String, // 'pending' 未创建委托, 'open', 'triggered', 'cancelled'
+    pub fill_price: Option<f64>,
+    pub fee: f64,
+    pub created_at: i64,
+    pub updated_at: Option<i64>,
+}
+
+/// 持仓快照
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, Default)]
+pub struct PositionSnapshot {
+    pub id: i64,
+    pub exchange: String,
+    pub symbol: String,
+    pub mode: String, // 'single', 'dual_long', 'dual_short'
+    pub size: i64,    // 张，正数多头，负数空头
+    pub entry_price: f64,
+    pub mark_price: f64,
+    pub unrealized_pnl: f64,
+    pub realized_pnl: f64,
+    pub leverage: f64,
+    pub liq_price: f64,
+    pub created_at: i64,
+}
+
+/// 一笔已平仓的完整交易
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, Default)]
+pub struct RoundTrip {
+    pub id: i64,
+    pub exchange: String,
+    pub symbol: String,
+    pub side: String, // 'buy' or 'sell'，开仓方向
+    pub size: i64,
+    pub entry_price: f64,
+    pub exit_price: f64,
+    pub exit_reason: String, // 'tp', 'sl', 'manual'
+    pub gross_pnl: f64,
+    pub fee: f64,
+    pub realized_pnl: f64, // 扣除手续费后的盈亏
+    pub order_id: i64,
+    pub signal_id: Option<i64>,
+    pub opened_at: i64,
+    pub closed_at: i64,
+    pub created_at: i64,
+}
+
+/// 已实现盈亏汇总，`key` 为日期或交易对
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, Default)]
+pub struct PnlSummary {
+    pub key: String,
+    pub trades: i64,
+    pub wins: i64,
+    pub losses: i64,
+    pub gross_pnl: f64,
+    pub fee: f64,
+    pub realized_pnl: f64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow, Default)]
@@ -79,12 +249,115 @@ pub struct MonitorConfig {
     pub short_k_short: bool,     // 阴K才做空
     pub trade_direction: String, // 'both', 'long', 'short'
     pub order_type: String,      // 'market' 或 'limit'
+    #[serde(default)]
+    pub paper_trading: bool, // 模拟交易，不真实下单
+    #[serde(default)]
+    pub sizing_mode: String, // 'fixed' 固定张数, 'risk_amount' 固定风险金额, 'risk_percent' 权益百分比
+    #[serde(default)]
+    pub risk_per_trade: f64, // 每笔风险，risk_amount为USDT，risk_percent为%
+    #[serde(default)]
+    pub close_grace_secs: i64, // K线收盘后等待多少秒再检查，给交易所生成K线的时间
+    #[serde(default)]
+    pub close_retry_secs: i64, // 宽限时间过后还没有获取到这根K线时的重试间隔(秒)
+    #[serde(default)]
+    pub execution_mode: String, // 'web' 网页端接口（需要cookie），'api' 签名API
+    #[serde(default)]
+    pub break_even_r: f64, // 浮盈达到初始风险的多少倍后止损移到开仓价，0表示不启用
+    #[serde(default)]
+    pub trailing_mode: String, // 移动止损：'none', 'fixed' 固定距离, 'percent' 百分比, 'atr' ATR倍数
+    #[serde(default)]
+    pub trailing_value: f64,
+    #[serde(default)]
+    pub take_profit_ladder: String, // 分批止盈：'百分比:R倍数'，逗号分隔，没有R倍数的一批由止损平仓
+    #[serde(default)]
+    pub ladder_break_even: bool, // 第一批止盈后止损移到开仓价
+    #[serde(default)]
+    pub strategy: String, // 信号策略：'long_shadow' 长影线，K线形态，或 'script' 脚本，见 services::patterns::STRATEGIES
+    // 指标过滤，0或false表示不启用，见 services::indicators
+    #[serde(default)]
+    pub trend_ema_period: i64, // 收盘价在EMA之上才做多，之下才做空
+    #[serde(default)]
+    pub rsi_period: i64,
+    #[serde(default)]
+    pub rsi_overbought: f64, // RSI高于此值不做多
+    #[serde(default)]
+    pub rsi_oversold: f64, // RSI低于此值不做空
+    #[serde(default)]
+    pub min_atr: f64, // ATR(14)低于此值不下单
+    #[serde(default)]
+    pub bb_period: i64, // 收盘价在布林带上轨之外不做多，下轨之外不做空
+    #[serde(default)]
+    pub macd_confirm: bool, // MACD柱为正才做多，为负才做空
+    #[serde(default)]
+    pub vwap_confirm: bool, // 收盘价在历史时间内的VWAP之上才做多，之下才做空
+    #[serde(default)]
+    pub confirm_interval: String, // 多周期确认的大周期，空表示不启用，见 services::confirmation
+    #[serde(default)]
+    pub confirm_mode: String, // 'candle' 大周期K线阴阳, 'ema' 大周期EMA, 'shadow' 大周期长影线
+    #[serde(default)]
+    pub confirm_ema_period: i64,
+    #[serde(default)]
+    pub entry_rule: String, // 自定义入场规则，空表示不启用，见 services::rules
+    #[serde(default)]
+    pub rule_error: Option<String>, // 最近一次规则求值的错误，保存配置时清空
+    #[serde(default)]
+    pub script_name: String, // 脚本策略运行的脚本，见 services::scripting
+    #[serde(default)]
+    pub script_version: i64, // 脚本版本，0表示最新版本
     pub is_active: bool,
     pub created_at: Option<i64>,
     pub updated_at: Option<i64>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// 自动下单的风控设置，数量和金额为0表示不限制
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, Default, PartialEq)]
+pub struct RiskSettings {
+    pub enabled: bool,
+    pub max_positions: i64,            // 同时持仓（含未成交订单）的最大数量
+    pub max_positions_per_symbol: i64, // 单个交易对同时持仓的最大数量
+    pub max_daily_loss: f64,           // 当日（北京时间）已实现亏损上限(USDT)
+    pub max_orders_per_hour: i64,
+    pub max_consecutive_losses: i64, // 连续亏损次数达到后进入冷却
+    pub cooldown_minutes: i64,
+    #[serde(default)]
+    pub halted: bool, // 紧急平仓或余额不足后暂停自动下单，只能通过恢复接口修改
+    #[serde(default)]
+    pub halted_reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct PaperAccount {
+    pub id: i64,
+    pub initial_balance: f64,
+    pub balance: f64,
+    pub realized_pnl: f64,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct PaperOrder {
+    pub id: i64,
+    pub symbol: String,
+    pub side: String,       // 'buy' or 'sell'
+    pub order_type: String, // 'market' 或 'limit'
+    pub order_size: i64,    // 张
+    pub quanto_multiplier: f64,
+    pub order_price: f64,
+    pub take_profit_price: Option<f64>,
+    pub stop_loss_price: Option<f64>,
+    pub status: String, // 'pending', 'open', 'closed', 'cancelled'
+    pub fill_price: Option<f64>,
+    pub filled_at: Option<i64>,
+    pub exit_price: Option<f64>,
+    pub exit_reason: Option<String>, // 'tp', 'sl' or 'manual'
+    pub closed_at: Option<i64>,
+    pub realized_pnl: Option<f64>,
+    pub fee_rate: f64, // 吃单手续费率
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct KlineData {
     pub timestamp: i64,
     pub open: f64,
@@ -135,22 +408,50 @@ pub struct DingTalkMarkdown {
     pub text: String,
 }
 
+/// k线类型转换为分钟数，未知类型返回0
+pub fn interval_to_minutes(interval_type: &str) -> f64 {
+    match interval_type {
+        "1m" => 1.0,
+        "3m" => 3.0,
+        "5m" => 5.0,
+        "15m" => 15.0,
+        "30m" => 30.0,
+        "1h" => 60.0,
+        "4h" => 240.0,
+        "1d" => 1440.0,
+        _ => 0.0,
+    }
+}
+
+/// k线类型转换为秒数，未知类型返回0
+pub fn interval_to_seconds(interval_type: &str) -> i64 {
+    (interval_to_minutes(interval_type) * 60.0) as i64
+}
+
 impl MonitorConfig {
     pub fn interval_type_to_minutes(&self) -> f64 {
-        match self.interval_type.as_str() {
-            "1m" => 1.0,
-            "3m" => 3.0,
-            "5m" => 5.0,
-            "15m" => 15.0,
-            "30m" => 30.0,
-            "1h" => 60.0,
-            "4h" => 240.0,
-            "1d" => 1440.0,
-            _ => 0.0,
-        }
+        interval_to_minutes(&self.interval_type)
     }
 
     pub fn interval_type_to_seconds(&self) -> i64 {
-        (self.interval_type_to_minutes() * 60.0) as i64
+        interval_to_seconds(&self.interval_type)
+    }
+
+    /// 计算平均成交量所需的历史K线数量
+    pub fn required_history(&self) -> usize {
+        (self.history_hours * 60.0 / self.interval_type_to_minutes()) as usize
+    }
+
+    /// 分析信号所需的K线数量（含最新一根），取成交量历史、指标过滤、入场规则和脚本中最多的
+    pub fn required_klines(&self) -> usize {
+        let script = if self.strategy == "script" {
+            crate::services::scripting::SCRIPT_KLINES
+        } else {
+            0
+        };
+        (self.required_history() + 1)
+            .max(crate::services::indicators::required_klines(self))
+            .max(crate::services::rules::required_klines(&self.entry_rule))
+            .max(script)
     }
 }
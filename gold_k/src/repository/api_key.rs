@@ -51,6 +51,20 @@ impl ApiKeyRepository {
         Ok(contracts.into_iter().find(|c| c.name == symbol))
     }
 
+    /// 获取合约的数量乘数，找不到合约时按1计算
+    pub async fn get_quanto_multiplier(pool: &SqlitePool, symbol: &str) -> Result<f64> {
+        let contract = Self::get_contract_by_symbol(pool, symbol).await?;
+        Ok(contract
+            .and_then(|c| c.quanto_multiplier.parse::<f64>().ok())
+            .unwrap_or(1.0))
+    }
+
+    /// 获取合约的吃单手续费率，合约数据里没有时返回None
+    pub async fn get_taker_fee_rate(pool: &SqlitePool, symbol: &str) -> Result<Option<f64>> {
+        let contract = Self::get_contract_by_symbol(pool, symbol).await?;
+        Ok(contract.and_then(|c| c.taker_fee_rate.parse::<f64>().ok()))
+    }
+
     /// 获取contracts
     pub async fn get_contracts(pool: &SqlitePool) -> Result<Option<String>> {
         let key = ApiKeyRepository::get_active(pool).await?;
@@ -0,0 +1,214 @@
+use crate::models::KlineData;
+use anyhow::Result;
+use sqlx::SqlitePool;
+
+pub struct KlineRepository;
+
+impl KlineRepository {
+    /// 批量保存K线，已存在的K线会被覆盖（未收盘的K线会在之后被更新）
+    pub async fn upsert_batch(
+        pool: &SqlitePool,
+        settle: &str,
+        contract: &str,
+        interval_type: &str,
+        klines: &[KlineData],
+    ) -> Result<()> {
+        let mut tx = pool.begin().await?;
+
+        for kline in klines {
+            sqlx::query(
+                r#"
+                INSERT INTO klines (
+                    settle, contract, interval_type, timestamp, open, high, low, close, volume
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT (settle, contract, interval_type, timestamp) DO UPDATE SET
+                    open = excluded.open,
+                    high = excluded.high,
+                    low = excluded.low,
+                    close = excluded.close,
+                    volume = excluded.volume,
+                    updated_at = strftime('%s', 'now')
+                "#,
+            )
+            .bind(settle)
+            .bind(contract)
+            .bind(interval_type)
+            .bind(kline.timestamp)
+            .bind(kline.open)
+            .bind(kline.high)
+            .bind(kline.low)
+            .bind(kline.close)
+            .bind(kline.volume)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// 获取时间范围内的K线，按时间升序排列，`from`和`to`都包含在内
+    pub async fn get_range(
+        pool: &SqlitePool,
+        settle: &str,
+        contract: &str,
+        interval_type: &str,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<KlineData>> {
+        let klines = sqlx::query_as::<_, KlineData>(
+            r#"
+            SELECT timestamp, open, high, low, close, volume FROM klines
+            WHERE settle = ? AND contract = ? AND interval_type = ? AND timestamp >= ? AND timestamp <= ?
+            ORDER BY timestamp
+            "#,
+        )
+        .bind(settle)
+        .bind(contract)
+        .bind(interval_type)
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await?;
+        Ok(klines)
+    }
+
+    /// 获取最新的若干根K线，按时间升序排列
+    pub async fn get_latest(
+        pool: &SqlitePool,
+        settle: &str,
+        contract: &str,
+        interval_type: &str,
+        limit: i64,
+    ) -> Result<Vec<KlineData>> {
+        let mut klines = sqlx::query_as::<_, KlineData>(
+            r#"
+            SELECT timestamp, open, high, low, close, volume FROM klines
+            WHERE settle = ? AND contract = ? AND interval_type = ?
+            ORDER BY timestamp DESC LIMIT ?
+            "#,
+        )
+        .bind(settle)
+        .bind(contract)
+        .bind(interval_type)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+        klines.reverse();
+        Ok(klines)
+    }
+
+    /// 获取合约最近一次更新的收盘价，不区分K线维度
+    pub async fn get_last_close(
+        pool: &SqlitePool,
+        settle: &str,
+        contract: &str,
+    ) -> Result<Option<f64>> {
+        let close = sqlx::query_scalar::<_, f64>(
+            r#"
+            SELECT close FROM klines
+            WHERE settle = ? AND contract = ?
+            ORDER BY updated_at DESC, timestamp DESC LIMIT 1
+            "#,
+        )
+        .bind(settle)
+        .bind(contract)
+        .fetch_optional(pool)
+        .await?;
+        Ok(close)
+    }
+
+    /// 获取时间范围内所有K线的时间戳，用于检测缺口
+    pub async fn get_timestamps(
+        pool: &SqlitePool,
+        settle: &str,
+        contract: &str,
+        interval_type: &str,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<i64>> {
+        let timestamps = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT timestamp FROM klines
+            WHERE settle = ? AND contract = ? AND interval_type = ? AND timestamp >= ? AND timestamp <= ?
+            ORDER BY timestamp
+            "#,
+        )
+        .bind(settle)
+        .bind(contract)
+        .bind(interval_type)
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await?;
+        Ok(timestamps)
+    }
+
+    /// 获取最新一根K线的时间戳
+    pub async fn latest_timestamp(
+        pool: &SqlitePool,
+        settle: &str,
+        contract: &str,
+        interval_type: &str,
+    ) -> Result<Option<i64>> {
+        let timestamp = sqlx::query_scalar::<_, Option<i64>>(
+            "SELECT MAX(timestamp) FROM klines WHERE settle = ? AND contract = ? AND interval_type = ?",
+        )
+        .bind(settle)
+        .bind(contract)
+        .bind(interval_type)
+        .fetch_one(pool)
+        .await?;
+        Ok(timestamp)
+    }
+
+    /// 记录交易所确认没有K线的时间段
+    pub async fn save_empty_range(
+        pool: &SqlitePool,
+        settle: &str,
+        contract: &str,
+        interval_type: &str,
+        from: i64,
+        to: i64,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO kline_empty_ranges (settle, contract, interval_type, range_from, range_to)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(settle)
+        .bind(contract)
+        .bind(interval_type)
+        .bind(from)
+        .bind(to)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// 获取与时间范围重叠的无K线时间段
+    pub async fn get_empty_ranges(
+        pool: &SqlitePool,
+        settle: &str,
+        contract: &str,
+        interval_type: &str,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<(i64, i64)>> {
+        let ranges = sqlx::query_as::<_, (i64, i64)>(
+            r#"
+            SELECT range_from, range_to FROM kline_empty_ranges
+            WHERE settle = ? AND contract = ? AND interval_type = ? AND range_to >= ? AND range_from <= ?
+            "#,
+        )
+        .bind(settle)
+        .bind(contract)
+        .bind(interval_type)
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await?;
+        Ok(ranges)
+    }
+}
@@ -1,9 +1,35 @@
 pub mod api_key;
+pub mod kline;
 pub mod monitor_config;
 pub mod order;
+pub mod paper;
+pub mod position;
+pub mod risk;
+pub mod round_trip;
 pub mod signal;
+pub mod stop_adjustment;
+pub mod strategy_script;
+pub mod take_profit_tranche;
 
 pub use api_key::ApiKeyRepository;
+pub use kline::KlineRepository;
 pub use monitor_config::MonitorConfigRepository;
 pub use order::OrderRepository;
+pub use paper::PaperRepository;
+pub use position::PositionRepository;
+pub use risk::RiskRepository;
+pub use round_trip::RoundTripRepository;
 pub use signal::SignalRepository;
+pub use stop_adjustment::StopAdjustmentRepository;
+pub use strategy_script::StrategyScriptRepository;
+pub use take_profit_tranche::TakeProfitTrancheRepository;
+
+use anyhow::Result;
+use sqlx::SqlitePool;
+
+/// 连接数据库并运行迁移
+pub async fn connect(database_url: &str) -> Result<SqlitePool> {
+    let db = SqlitePool::connect(database_url).await?;
+    sqlx::migrate!("../migrations").run(&db).await?;
+    Ok(db)
+}
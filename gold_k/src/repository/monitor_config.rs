@@ -24,6 +24,68 @@ impl MonitorConfigRepository {
         Ok(configs)
     }
 
+    /// 根据ID获取监控配置
+    pub async fn get_by_id(pool: &SqlitePool, id: i64) -> Result<Option<MonitorConfig>> {
+        let config =
+            sqlx::query_as::<_, MonitorConfig>("SELECT * FROM monitor_configs WHERE id = ?")
+                .bind(id)
+                .fetch_optional(pool)
+                .await?;
+        Ok(config)
+    }
+
+    /// 根据交易对和K线类型获取监控配置，优先返回激活的配置
+    pub async fn find_by_symbol(
+        pool: &SqlitePool,
+        symbol: &str,
+        interval_type: &str,
+    ) -> Result<Option<MonitorConfig>> {
+        let config = sqlx::query_as::<_, MonitorConfig>(
+            "SELECT * FROM monitor_configs WHERE symbol = ? AND interval_type = ? ORDER BY is_active DESC, id LIMIT 1",
+        )
+        .bind(symbol)
+        .bind(interval_type)
+        .fetch_optional(pool)
+        .await?;
+        Ok(config)
+    }
+
+    /// 更新监控配置的策略阈值，用于写回参数寻优的结果
+    pub async fn update_params(pool: &SqlitePool, config: &MonitorConfig) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE monitor_configs
+            SET shadow_ratio = ?, main_shadow_body_ratio = ?, volume_multiplier = ?,
+                risk_reward_ratio = ?, expected_profit_rate = ?, history_hours = ?,
+                updated_at = strftime('%s', 'now')
+            WHERE id = ?
+            "#,
+        )
+        .bind(config.shadow_ratio)
+        .bind(config.main_shadow_body_ratio)
+        .bind(config.volume_multiplier)
+        .bind(config.risk_reward_ratio)
+        .bind(config.expected_profit_rate)
+        .bind(config.history_hours)
+        .bind(config.id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// 记录入场规则最近一次求值的错误，求值成功时清空
+    pub async fn set_rule_error(pool: &SqlitePool, id: i64, error: Option<&str>) -> Result<()> {
+        sqlx::query(
+            "UPDATE monitor_configs SET rule_error = ? WHERE id = ? AND rule_error IS NOT ?",
+        )
+        .bind(error)
+        .bind(id)
+        .bind(error)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
     /// 删除所有监控配置
     pub async fn delete_all(pool: &SqlitePool) -> Result<()> {
         sqlx::query("DELETE FROM monitor_configs")
@@ -50,8 +112,14 @@ impl MonitorConfigRepository {
                     main_shadow_body_ratio, volume_multiplier, order_size,
                     risk_reward_ratio, enable_auto_trading, enable_dingtalk,
                     long_k_long, short_k_short, trade_direction, is_active,
-                    order_type, expected_profit_rate
-                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                    order_type, expected_profit_rate, paper_trading,
+                    sizing_mode, risk_per_trade, close_grace_secs, close_retry_secs, execution_mode,
+                    break_even_r, trailing_mode, trailing_value, take_profit_ladder, ladder_break_even,
+                    strategy, trend_ema_period, rsi_period, rsi_overbought, rsi_oversold,
+                    min_atr, bb_period, macd_confirm, vwap_confirm,
+                    confirm_interval, confirm_mode, confirm_ema_period, entry_rule,
+                    script_name, script_version
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
                 "#,
             )
             .bind(&config.symbol)
@@ -71,6 +139,52 @@ impl MonitorConfigRepository {
             .bind(config.is_active)
             .bind(&config.order_type)
             .bind(config.expected_profit_rate)
+            .bind(config.paper_trading)
+            .bind(if config.sizing_mode.is_empty() {
+                "fixed"
+            } else {
+                &config.sizing_mode
+            })
+            .bind(config.risk_per_trade)
+            .bind(config.close_grace_secs)
+            .bind(config.close_retry_secs.max(1))
+            .bind(if config.execution_mode.is_empty() {
+                "web"
+            } else {
+                &config.execution_mode
+            })
+            .bind(config.break_even_r)
+            .bind(if config.trailing_mode.is_empty() {
+                "none"
+            } else {
+                &config.trailing_mode
+            })
+            .bind(config.trailing_value)
+            .bind(config.take_profit_ladder.trim())
+            .bind(config.ladder_break_even)
+            .bind(if config.strategy.is_empty() {
+                "long_shadow"
+            } else {
+                &config.strategy
+            })
+            .bind(config.trend_ema_period)
+            .bind(config.rsi_period)
+            .bind(config.rsi_overbought)
+            .bind(config.rsi_oversold)
+            .bind(config.min_atr)
+            .bind(config.bb_period)
+            .bind(config.macd_confirm)
+            .bind(config.vwap_confirm)
+            .bind(&config.confirm_interval)
+            .bind(if config.confirm_mode.is_empty() {
+                "candle"
+            } else {
+                &config.confirm_mode
+            })
+            .bind(config.confirm_ema_period)
+            .bind(config.entry_rule.trim())
+            .bind(config.script_name.trim())
+            .bind(config.script_version)
             .execute(&mut *tx)
             .await?;
         }
@@ -1,18 +1,17 @@
+use crate::models::{Order, OrderPlacement, OrderUpdate, TradingSignal};
 use anyhow::Result;
 use sqlx::SqlitePool;
-use crate::models::{Order, TradingSignal};
 
 pub struct OrderRepository;
 
 impl OrderRepository {
     /// 获取最近的订单，限制数量
     pub async fn get_recent(pool: &SqlitePool, limit: i64) -> Result<Vec<Order>> {
-        let orders = sqlx::query_as::<_, Order>(
-            "SELECT * FROM orders ORDER BY timestamp DESC LIMIT ?"
-        )
-        .bind(limit)
-        .fetch_all(pool)
-        .await?;
+        let orders =
+            sqlx::query_as::<_, Order>("SELECT * FROM orders ORDER BY timestamp DESC LIMIT ?")
+                .bind(limit)
+                .fetch_all(pool)
+                .await?;
         Ok(orders)
     }
 
@@ -24,11 +23,198 @@ impl OrderRepository {
         Ok(count)
     }
 
-    /// 保存交易信号生成的订单
+    /// 获取需要对账的订单：未完成的订单，以及已成交但止盈止损还未结束的订单
+    pub async fn get_unsettled(pool: &SqlitePool, since: i64) -> Result<Vec<Order>> {
+        let orders = sqlx::query_as::<_, Order>(
+            r#"
+            SELECT * FROM orders
+            WHERE created_at >= ? AND (
+                status IN ('pending', 'open')
+                OR (status = 'filled' AND (
+                    take_profit_status IS NULL OR take_profit_status = 'open'
+                    OR stop_loss_status IS NULL OR stop_loss_status = 'open'
+                ))
+                -- 平仓后还没有撤销的分批止盈委托
+                OR EXISTS (
+                    SELECT 1 FROM take_profit_tranches t
+                    WHERE t.order_id = orders.id AND t.status = 'open'
+                )
+            )
+            ORDER BY id
+            "#,
+        )
+        .bind(since)
+        .fetch_all(pool)
+        .await?;
+        Ok(orders)
+    }
+
+    /// 统计持仓中的订单数量：未成交的订单，以及已成交且止盈止损都未触发的订单
+    pub async fn count_active(
+        pool: &SqlitePool,
+        exchange: &str,
+        symbol: Option<&str>,
+        since: i64,
+    ) -> Result<i64> {
+        let count = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COUNT(*) FROM orders
+            WHERE exchange = ? AND (? IS NULL OR symbol = ?) AND created_at >= ? AND (
+                status IN ('pending', 'open')
+                OR (status = 'filled'
+                    AND COALESCE(take_profit_status, '') != 'triggered'
+                    AND COALESCE(stop_loss_status, '') != 'triggered'
+                    AND (
+                        take_profit_status IS NULL OR take_profit_status = 'open'
+                        OR stop_loss_status IS NULL OR stop_loss_status = 'open'
+                    ))
+            )
+            "#,
+        )
+        .bind(exchange)
+        .bind(symbol)
+        .bind(symbol)
+        .bind(since)
+        .fetch_one(pool)
+        .await?;
+        Ok(count)
+    }
+
+    /// 统计一段时间内提交到交易所的订单数量（不含被拒绝的订单）
+    pub async fn count_placed_since(pool: &SqlitePool, exchange: &str, since: i64) -> Result<i64> {
+        let count = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM orders WHERE exchange = ? AND created_at >= ? AND status != 'rejected'"
+        )
+        .bind(exchange)
+        .bind(since)
+        .fetch_one(pool)
+        .await?;
+        Ok(count)
+    }
+
+    pub async fn get_by_id(pool: &SqlitePool, id: i64) -> Result<Option<Order>> {
+        let order = sqlx::query_as::<_, Order>("SELECT * FROM orders WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?;
+        Ok(order)
+    }
+
+    /// 分批止盈成交后累加已平仓张数
+    pub async fn add_closed_size(pool: &SqlitePool, id: i64, size: i64) -> Result<()> {
+        sqlx::query(
+            "UPDATE orders SET closed_size = closed_size + ?, updated_at = strftime('%s', 'now') WHERE id = ?",
+        )
+        .bind(size)
+        .bind(id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// 获取需要移动止损的订单：已成交未平仓、止损委托进行中，并且设置了移动止损规则
+    pub async fn get_trailing(
+        pool: &SqlitePool,
+        exchange: &str,
+        symbol: &str,
+        interval_type: &str,
+    ) -> Result<Vec<Order>> {
+        let orders = sqlx::query_as::<_, Order>(
+            r#"
+            SELECT * FROM orders
+            WHERE exchange = ? AND symbol = ? AND interval_type = ?
+                AND status = 'filled' AND exit_reason IS NULL
+                AND stop_loss_status = 'open' AND stop_loss_order_id IS NOT NULL
+                AND (break_even_r > 0 OR trailing_mode != 'none')
+            ORDER BY id
+            "#,
+        )
+        .bind(exchange)
+        .bind(symbol)
+        .bind(interval_type)
+        .fetch_all(pool)
+        .await?;
+        Ok(orders)
+    }
+
+    /// 移动止损后更新止损价和止损委托ID
+    pub async fn update_stop_loss(
+        pool: &SqlitePool,
+        id: i64,
+        stop_loss_price: f64,
+        stop_loss_order_id: &str,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE orders
+            SET stop_loss_price = ?, stop_loss_order_id = ?, stop_loss_status = 'open',
+                updated_at = strftime('%s', 'now')
+            WHERE id = ?
+            "#,
+        )
+        .bind(stop_loss_price)
+        .bind(stop_loss_order_id)
+        .bind(id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// 更新对账得到的订单状态
+    ///
+    /// 对账期间止损委托被移动止损替换时不更新（返回false），下一轮对账按新的委托重新计算
+    pub async fn update_status(
+        pool: &SqlitePool,
+        id: i64,
+        stop_loss_order_id: Option<&str>,
+        update: &OrderUpdate,
+    ) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE orders SET
+                exchange_order_id = COALESCE(?, exchange_order_id),
+                status = ?,
+                filled_size = ?,
+                avg_fill_price = ?,
+                fee = ?,
+                take_profit_order_id = COALESCE(?, take_profit_order_id),
+                take_profit_status = ?,
+                stop_loss_order_id = COALESCE(?, stop_loss_order_id),
+                stop_loss_status = ?,
+                error_message = COALESCE(?, error_message),
+                exit_price = COALESCE(?, exit_price),
+                exit_reason = COALESCE(?, exit_reason),
+                closed_at = COALESCE(?, closed_at),
+                updated_at = strftime('%s', 'now')
+            WHERE id = ? AND stop_loss_order_id IS ?
+            "#,
+        )
+        .bind(&update.exchange_order_id)
+        .bind(&update.status)
+        .bind(update.filled_size)
+        .bind(update.avg_fill_price)
+        .bind(update.fee)
+        .bind(&update.take_profit_order_id)
+        .bind(&update.take_profit_status)
+        .bind(&update.stop_loss_order_id)
+        .bind(&update.stop_loss_status)
+        .bind(&update.error_message)
+        .bind(update.exit_price)
+        .bind(&update.exit_reason)
+        .bind(update.closed_at)
+        .bind(id)
+        .bind(stop_loss_order_id)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// 保存交易信号生成的订单以及下单结果
     pub async fn save_from_trading_signal(
         pool: &SqlitePool,
         trading_signal: &TradingSignal,
         signal_id: i64,
+        placement: &OrderPlacement,
     ) -> Result<i64> {
         let side = if trading_signal.signal_type == "long" {
             "buy"
@@ -40,8 +226,12 @@ impl OrderRepository {
             r#"
             INSERT INTO orders (
                 symbol, side, order_size, entry_price, take_profit_price, 
-                stop_loss_price, risk_reward_ratio, signal_id, timestamp
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+                stop_loss_price, risk_reward_ratio, signal_id, timestamp,
+                exchange, client_order_id, exchange_order_id, status,
+                take_profit_order_id, stop_loss_order_id, error_message, execution_mode,
+                interval_type, break_even_r, trailing_mode, trailing_value, initial_stop_price,
+                take_profit_ladder, ladder_break_even, updated_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, strftime('%s', 'now'))
             "#,
         )
         .bind(&trading_signal.symbol)
@@ -56,6 +246,29 @@ impl OrderRepository {
         )
         .bind(signal_id)
         .bind(trading_signal.timestamp)
+        .bind(&placement.exchange)
+        .bind(&placement.client_order_id)
+        .bind(&placement.exchange_order_id)
+        .bind(&placement.status)
+        .bind(&placement.take_profit_order_id)
+        .bind(&placement.stop_loss_order_id)
+        .bind(&placement.error_message)
+        .bind(if placement.execution_mode.is_empty() {
+            "web"
+        } else {
+            &placement.execution_mode
+        })
+        .bind(&placement.interval_type)
+        .bind(placement.break_even_r)
+        .bind(if placement.trailing_mode.is_empty() {
+            "none"
+        } else {
+            &placement.trailing_mode
+        })
+        .bind(placement.trailing_value)
+        .bind(trading_signal.stop_loss)
+        .bind(&placement.take_profit_ladder)
+        .bind(placement.ladder_break_even)
         .execute(pool)
         .await?;
 
@@ -63,6 +276,7 @@ impl OrderRepository {
     }
 
     /// 保存订单
+    #[allow(clippy::too_many_arguments)]
     pub async fn save(
         pool: &SqlitePool,
         symbol: &str,
@@ -0,0 +1,234 @@
+use crate::models::{PaperAccount, PaperOrder};
+use anyhow::{Result, anyhow};
+use sqlx::SqlitePool;
+
+pub struct PaperRepository;
+
+impl PaperRepository {
+    /// 获取模拟账户
+    pub async fn get_account(pool: &SqlitePool) -> Result<PaperAccount> {
+        let account =
+            sqlx::query_as::<_, PaperAccount>("SELECT * FROM paper_accounts WHERE id = 1")
+                .fetch_optional(pool)
+                .await?;
+        account.ok_or_else(|| anyhow!("Paper account not initialized"))
+    }
+
+    /// 重置模拟账户，清空所有模拟订单
+    pub async fn reset(pool: &SqlitePool, initial_balance: f64) -> Result<()> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query("DELETE FROM paper_orders")
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(
+            r#"
+            UPDATE paper_accounts
+            SET initial_balance = ?, balance = ?, realized_pnl = 0, updated_at = strftime('%s', 'now')
+            WHERE id = 1
+            "#,
+        )
+        .bind(initial_balance)
+        .bind(initial_balance)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// 获取最近的模拟订单，限制数量
+    pub async fn get_recent(pool: &SqlitePool, limit: i64) -> Result<Vec<PaperOrder>> {
+        let orders = sqlx::query_as::<_, PaperOrder>(
+            "SELECT * FROM paper_orders ORDER BY created_at DESC, id DESC LIMIT ?",
+        )
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+        Ok(orders)
+    }
+
+    /// 根据ID获取模拟订单
+    pub async fn get_by_id(pool: &SqlitePool, id: i64) -> Result<Option<PaperOrder>> {
+        let order = sqlx::query_as::<_, PaperOrder>("SELECT * FROM paper_orders WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?;
+        Ok(order)
+    }
+
+    /// 获取指定交易对最近的模拟订单
+    pub async fn get_recent_by_symbol(
+        pool: &SqlitePool,
+        symbol: &str,
+        limit: i64,
+    ) -> Result<Vec<PaperOrder>> {
+        let orders = sqlx::query_as::<_, PaperOrder>(
+            "SELECT * FROM paper_orders WHERE symbol = ? ORDER BY id DESC LIMIT ?",
+        )
+        .bind(symbol)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+        Ok(orders)
+    }
+
+    /// 获取指定交易对未结束的模拟订单（挂单中或持仓中）
+    pub async fn get_active_by_symbol(pool: &SqlitePool, symbol: &str) -> Result<Vec<PaperOrder>> {
+        let orders = sqlx::query_as::<_, PaperOrder>(
+            "SELECT * FROM paper_orders WHERE symbol = ? AND status IN ('pending', 'open') ORDER BY id",
+        )
+        .bind(symbol)
+        .fetch_all(pool)
+        .await?;
+        Ok(orders)
+    }
+
+    /// 获取所有持仓中的模拟订单
+    pub async fn get_open(pool: &SqlitePool) -> Result<Vec<PaperOrder>> {
+        let orders = sqlx::query_as::<_, PaperOrder>(
+            "SELECT * FROM paper_orders WHERE status = 'open' ORDER BY symbol, id",
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(orders)
+    }
+
+    /// 撤销所有未成交的模拟订单，返回撤销的数量
+    pub async fn cancel_pending(pool: &SqlitePool) -> Result<u64> {
+        let result =
+            sqlx::query("UPDATE paper_orders SET status = 'cancelled' WHERE status = 'pending'")
+                .execute(pool)
+                .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// 移动持仓中模拟订单的止损价格
+    pub async fn update_stop_loss(pool: &SqlitePool, id: i64, stop_loss_price: f64) -> Result<()> {
+        let result = sqlx::query(
+            "UPDATE paper_orders SET stop_loss_price = ? WHERE id = ? AND status = 'open'",
+        )
+        .bind(stop_loss_price)
+        .bind(id)
+        .execute(pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(anyhow!("Paper order {} is not open", id));
+        }
+        Ok(())
+    }
+
+    /// 清除持仓中模拟订单的止盈止损价格，返回清除的触发条件数量
+    pub async fn clear_triggers(pool: &SqlitePool) -> Result<u64> {
+        let count = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COALESCE(SUM((take_profit_price IS NOT NULL) + (stop_loss_price IS NOT NULL)), 0)
+            FROM paper_orders WHERE status = 'open'
+            "#,
+        )
+        .fetch_one(pool)
+        .await?;
+        sqlx::query(
+            "UPDATE paper_orders SET take_profit_price = NULL, stop_loss_price = NULL WHERE status = 'open'",
+        )
+        .execute(pool)
+        .await?;
+        Ok(count as u64)
+    }
+
+    /// 保存新的模拟订单
+    pub async fn save(pool: &SqlitePool, order: &PaperOrder) -> Result<i64> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO paper_orders (
+                symbol, side, order_type, order_size, quanto_multiplier, order_price,
+                take_profit_price, stop_loss_price, status, fill_price, filled_at, fee_rate,
+                created_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&order.symbol)
+        .bind(&order.side)
+        .bind(&order.order_type)
+        .bind(order.order_size)
+        .bind(order.quanto_multiplier)
+        .bind(order.order_price)
+        .bind(order.take_profit_price)
+        .bind(order.stop_loss_price)
+        .bind(&order.status)
+        .bind(order.fill_price)
+        .bind(order.filled_at)
+        .bind(order.fee_rate)
+        .bind(order.created_at)
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    /// 标记模拟订单已成交
+    pub async fn mark_filled(
+        pool: &SqlitePool,
+        id: i64,
+        fill_price: f64,
+        filled_at: i64,
+    ) -> Result<()> {
+        sqlx::query(
+            "UPDATE paper_orders SET status = 'open', fill_price = ?, filled_at = ? WHERE id = ? AND status = 'pending'",
+        )
+        .bind(fill_price)
+        .bind(filled_at)
+        .bind(id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// 平仓并把已实现盈亏计入账户余额（在事务中执行）
+    pub async fn close_position(
+        pool: &SqlitePool,
+        id: i64,
+        exit_price: f64,
+        exit_reason: &str,
+        closed_at: i64,
+        realized_pnl: f64,
+    ) -> Result<()> {
+        let mut tx = pool.begin().await?;
+
+        let result = sqlx::query(
+            r#"
+            UPDATE paper_orders
+            SET status = 'closed', exit_price = ?, exit_reason = ?, closed_at = ?, realized_pnl = ?
+            WHERE id = ? AND status = 'open'
+            "#,
+        )
+        .bind(exit_price)
+        .bind(exit_reason)
+        .bind(closed_at)
+        .bind(realized_pnl)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
+        // 已经被其他任务平仓，不重复计入
+        if result.rows_affected() == 0 {
+            return Ok(());
+        }
+
+        sqlx::query(
+            r#"
+            UPDATE paper_accounts
+            SET balance = balance + ?, realized_pnl = realized_pnl + ?, updated_at = strftime('%s', 'now')
+            WHERE id = 1
+            "#,
+        )
+        .bind(realized_pnl)
+        .bind(realized_pnl)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+}
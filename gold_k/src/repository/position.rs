@@ -0,0 +1,50 @@
+use crate::models::PositionSnapshot;
+use anyhow::Result;
+use sqlx::SqlitePool;
+
+pub struct PositionRepository;
+
+impl PositionRepository {
+    /// 保存持仓快照
+    pub async fn save_snapshot(pool: &SqlitePool, snapshot: &PositionSnapshot) -> Result<i64> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO position_snapshots (
+                exchange, symbol, mode, size, entry_price, mark_price,
+                unrealized_pnl, realized_pnl, leverage, liq_price
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&snapshot.exchange)
+        .bind(&snapshot.symbol)
+        .bind(&snapshot.mode)
+        .bind(snapshot.size)
+        .bind(snapshot.entry_price)
+        .bind(snapshot.mark_price)
+        .bind(snapshot.unrealized_pnl)
+        .bind(snapshot.realized_pnl)
+        .bind(snapshot.leverage)
+        .bind(snapshot.liq_price)
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    /// 获取每个交易对（和持仓模式）最新的快照
+    pub async fn get_latest(pool: &SqlitePool, exchange: &str) -> Result<Vec<PositionSnapshot>> {
+        let snapshots = sqlx::query_as::<_, PositionSnapshot>(
+            r#"
+            SELECT * FROM position_snapshots
+            WHERE id IN (
+                SELECT MAX(id) FROM position_snapshots WHERE exchange = ? GROUP BY symbol, mode
+            )
+            ORDER BY symbol, mode
+            "#,
+        )
+        .bind(exchange)
+        .fetch_all(pool)
+        .await?;
+        Ok(snapshots)
+    }
+}
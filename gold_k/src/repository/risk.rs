@@ -0,0 +1,51 @@
+use crate::models::RiskSettings;
+use anyhow::{Result, anyhow};
+use sqlx::SqlitePool;
+
+pub struct RiskRepository;
+
+impl RiskRepository {
+    /// 获取风控设置
+    pub async fn get_settings(pool: &SqlitePool) -> Result<RiskSettings> {
+        let settings =
+            sqlx::query_as::<_, RiskSettings>("SELECT * FROM risk_settings WHERE id = 1")
+                .fetch_optional(pool)
+                .await?;
+        settings.ok_or_else(|| anyhow!("Risk settings not initialized"))
+    }
+
+    /// 暂停或恢复所有自动下单，恢复时清空暂停原因
+    pub async fn set_halted(pool: &SqlitePool, halted: bool, reason: Option<&str>) -> Result<()> {
+        sqlx::query(
+            "UPDATE risk_settings SET halted = ?, halted_reason = ?, updated_at = strftime('%s', 'now') WHERE id = 1",
+        )
+        .bind(halted)
+        .bind(reason.filter(|_| halted))
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// 保存风控设置，不修改暂停状态
+    pub async fn save_settings(pool: &SqlitePool, settings: &RiskSettings) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE risk_settings
+            SET enabled = ?, max_positions = ?, max_positions_per_symbol = ?, max_daily_loss = ?,
+                max_orders_per_hour = ?, max_consecutive_losses = ?, cooldown_minutes = ?,
+                updated_at = strftime('%s', 'now')
+            WHERE id = 1
+            "#,
+        )
+        .bind(settings.enabled)
+        .bind(settings.max_positions)
+        .bind(settings.max_positions_per_symbol)
+        .bind(settings.max_daily_loss)
+        .bind(settings.max_orders_per_hour)
+        .bind(settings.max_consecutive_losses)
+        .bind(settings.cooldown_minutes)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+use crate::models::{PnlSummary, RoundTrip};
+use anyhow::Result;
+use sqlx::SqlitePool;
+
+pub struct RoundTripRepository;
+
+impl RoundTripRepository {
+    /// 保存已平仓的交易，同一个订单只记录一次
+    pub async fn save(pool: &SqlitePool, trip: &RoundTrip) -> Result<i64> {
+        let result = sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO round_trips (
+                exchange, symbol, side, size, entry_price, exit_price, exit_reason,
+                gross_pnl, fee, realized_pnl, order_id, signal_id, opened_at, closed_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&trip.exchange)
+        .bind(&trip.symbol)
+        .bind(&trip.side)
+        .bind(trip.size)
+        .bind(trip.entry_price)
+        .bind(trip.exit_price)
+        .bind(&trip.exit_reason)
+        .bind(trip.gross_pnl)
+        .bind(trip.fee)
+        .bind(trip.realized_pnl)
+        .bind(trip.order_id)
+        .bind(trip.signal_id)
+        .bind(trip.opened_at)
+        .bind(trip.closed_at)
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    /// 获取最近平仓的交易
+    pub async fn get_recent(pool: &SqlitePool, since: i64, limit: i64) -> Result<Vec<RoundTrip>> {
+        let trips = sqlx::query_as::<_, RoundTrip>(
+            "SELECT * FROM round_trips WHERE closed_at >= ? ORDER BY closed_at DESC LIMIT ?",
+        )
+        .bind(since)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+        Ok(trips)
+    }
+
+    /// 获取某个交易所最近平仓的交易，按平仓时间倒序
+    pub async fn get_latest_by_exchange(
+        pool: &SqlitePool,
+        exchange: &str,
+        limit: i64,
+    ) -> Result<Vec<RoundTrip>> {
+        let trips = sqlx::query_as::<_, RoundTrip>(
+            "SELECT * FROM round_trips WHERE exchange = ? ORDER BY closed_at DESC, id DESC LIMIT ?",
+        )
+        .bind(exchange)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+        Ok(trips)
+    }
+
+    /// 某个交易所一段时间内的已实现盈亏合计
+    pub async fn realized_pnl_since(pool: &SqlitePool, exchange: &str, since: i64) -> Result<f64> {
+        let pnl = sqlx::query_scalar::<_, f64>(
+            "SELECT COALESCE(SUM(realized_pnl), 0.0) FROM round_trips WHERE exchange = ? AND closed_at >= ?",
+        )
+        .bind(exchange)
+        .bind(since)
+        .fetch_one(pool)
+        .await?;
+        Ok(pnl)
+    }
+
+    /// 按日期（UTC+8）汇总已实现盈亏
+    pub async fn summary_by_day(pool: &SqlitePool, since: i64) -> Result<Vec<PnlSummary>> {
+        Self::summary(pool, "date(closed_at, 'unixepoch', '+8 hours')", since).await
+    }
+
+    /// 按交易对汇总已实现盈亏
+    pub async fn summary_by_symbol(pool: &SqlitePool, since: i64) -> Result<Vec<PnlSummary>> {
+        Self::summary(pool, "symbol", since).await
+    }
+
+    /// 汇总全部已实现盈亏
+    pub async fn summary_total(pool: &SqlitePool, since: i64) -> Result<PnlSummary> {
+        let mut summaries = Self::summary(pool, "'total'", since).await?;
+        Ok(summaries.pop().unwrap_or_else(|| PnlSummary {
+            key: "total".to_string(),
+            ..Default::default()
+        }))
+    }
+
+    async fn summary(pool: &SqlitePool, key: &str, since: i64) -> Result<Vec<PnlSummary>> {
+        let sql = format!(
+            r#"
+            SELECT {key} AS key,
+                COUNT(*) AS trades,
+                COALESCE(SUM(CASE WHEN realized_pnl > 0 THEN 1 ELSE 0 END), 0) AS wins,
+                COALESCE(SUM(CASE WHEN realized_pnl <= 0 THEN 1 ELSE 0 END), 0) AS losses,
+                COALESCE(SUM(gross_pnl), 0.0) AS gross_pnl,
+                COALESCE(SUM(fee), 0.0) AS fee,
+                COALESCE(SUM(realized_pnl), 0.0) AS realized_pnl
+            FROM round_trips
+            WHERE closed_at >= ?
+            GROUP BY 1
+            ORDER BY 1
+            "#
+        );
+        let summaries = sqlx::query_as::<_, PnlSummary>(&sql)
+            .bind(since)
+            .fetch_all(pool)
+            .await?;
+        Ok(summaries)
+    }
+}
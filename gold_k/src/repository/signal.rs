@@ -23,24 +23,36 @@ impl SignalRepository {
         Ok(count)
     }
 
-    /// 检查指定条件的信号是否已存在
+    /// 检查指定条件的信号是否已存在，不同策略在同一根K线上的信号分别记录
     pub async fn exists(
         pool: &SqlitePool,
         symbol: &str,
         timestamp: i64,
         interval_type: &str,
+        strategy: &str,
     ) -> Result<bool> {
         let count = sqlx::query_scalar::<_, i64>(
-            "SELECT COUNT(*) FROM signals WHERE symbol = ? AND timestamp = ? AND interval_type = ?",
+            "SELECT COUNT(*) FROM signals WHERE symbol = ? AND timestamp = ? AND interval_type = ? AND strategy = ?",
         )
         .bind(symbol)
         .bind(timestamp)
         .bind(interval_type)
+        .bind(strategy)
         .fetch_one(pool)
         .await?;
         Ok(count > 0)
     }
 
+    /// 记录信号被风控拦截的原因
+    pub async fn set_blocked_reason(pool: &SqlitePool, id: i64, reason: &str) -> Result<()> {
+        sqlx::query("UPDATE signals SET blocked_reason = ? WHERE id = ?")
+            .bind(reason)
+            .bind(id)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
     /// 保存信号到数据库
     pub async fn save(pool: &SqlitePool, signal: &Signal) -> Result<i64> {
         let result = sqlx::query(
@@ -48,8 +60,10 @@ impl SignalRepository {
             INSERT INTO signals (
                 symbol, timestamp, open_price, high_price, low_price, close_price, 
                 volume, interval_type, candle_type, shadow_type, body_length, 
-                main_shadow_length, shadow_ratio, volume_multiplier, avg_volume
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                main_shadow_length, shadow_ratio, volume_multiplier, avg_volume,
+                strategy, pattern, direction, stop_price, indicator_filters, confirmation,
+                blocked_reason, script_version, entry_price, target_price
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#,
         )
         .bind(&signal.symbol)
@@ -67,6 +81,16 @@ impl SignalRepository {
         .bind(signal.shadow_ratio)
         .bind(signal.volume_multiplier)
         .bind(signal.avg_volume)
+        .bind(&signal.strategy)
+        .bind(&signal.pattern)
+        .bind(&signal.direction)
+        .bind(signal.stop_price)
+        .bind(&signal.indicator_filters)
+        .bind(&signal.confirmation)
+        .bind(&signal.blocked_reason)
+        .bind(signal.script_version)
+        .bind(signal.entry_price)
+        .bind(signal.target_price)
         .execute(pool)
         .await?;
 
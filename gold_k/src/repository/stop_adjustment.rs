@@ -0,0 +1,39 @@
+use crate::models::StopAdjustment;
+use anyhow::Result;
+use sqlx::SqlitePool;
+
+pub struct StopAdjustmentRepository;
+
+impl StopAdjustmentRepository {
+    /// 保存一次移动止损的记录
+    pub async fn save(pool: &SqlitePool, adjustment: &StopAdjustment) -> Result<i64> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO stop_adjustments (
+                order_id, reason, old_stop, new_stop, price, stop_loss_order_id
+            ) VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(adjustment.order_id)
+        .bind(&adjustment.reason)
+        .bind(adjustment.old_stop)
+        .bind(adjustment.new_stop)
+        .bind(adjustment.price)
+        .bind(&adjustment.stop_loss_order_id)
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    /// 获取订单的移动止损记录，按时间升序
+    pub async fn get_by_order(pool: &SqlitePool, order_id: i64) -> Result<Vec<StopAdjustment>> {
+        let adjustments = sqlx::query_as::<_, StopAdjustment>(
+            "SELECT * FROM stop_adjustments WHERE order_id = ? ORDER BY id",
+        )
+        .bind(order_id)
+        .fetch_all(pool)
+        .await?;
+        Ok(adjustments)
+    }
+}
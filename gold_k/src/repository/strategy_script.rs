@@ -0,0 +1,68 @@
+use crate::models::StrategyScript;
+use anyhow::Result;
+use sqlx::SqlitePool;
+
+pub struct StrategyScriptRepository;
+
+impl StrategyScriptRepository {
+    /// 保存脚本的新版本，返回分配的版本号
+    pub async fn save(pool: &SqlitePool, name: &str, source: &str) -> Result<i64> {
+        let mut tx = pool.begin().await?;
+        let (version,): (i64,) = sqlx::query_as(
+            "SELECT COALESCE(MAX(version), 0) + 1 FROM strategy_scripts WHERE name = ?",
+        )
+        .bind(name)
+        .fetch_one(&mut *tx)
+        .await?;
+        sqlx::query("INSERT INTO strategy_scripts (name, version, source) VALUES (?, ?, ?)")
+            .bind(name)
+            .bind(version)
+            .bind(source)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(version)
+    }
+
+    /// 获取脚本的指定版本，版本为0时返回最新版本
+    pub async fn get(
+        pool: &SqlitePool,
+        name: &str,
+        version: i64,
+    ) -> Result<Option<StrategyScript>> {
+        let script = sqlx::query_as::<_, StrategyScript>(
+            "SELECT * FROM strategy_scripts WHERE name = ? AND (? = 0 OR version = ?) ORDER BY version DESC LIMIT 1",
+        )
+        .bind(name)
+        .bind(version)
+        .bind(version)
+        .fetch_optional(pool)
+        .await?;
+        Ok(script)
+    }
+
+    /// 获取脚本的所有版本，按版本降序排列
+    pub async fn get_versions(pool: &SqlitePool, name: &str) -> Result<Vec<StrategyScript>> {
+        let scripts = sqlx::query_as::<_, StrategyScript>(
+            "SELECT * FROM strategy_scripts WHERE name = ? ORDER BY version DESC",
+        )
+        .bind(name)
+        .fetch_all(pool)
+        .await?;
+        Ok(scripts)
+    }
+
+    /// 获取每个脚本的最新版本，按名称排列
+    pub async fn get_latest_all(pool: &SqlitePool) -> Result<Vec<StrategyScript>> {
+        let scripts = sqlx::query_as::<_, StrategyScript>(
+            r#"
+            SELECT * FROM strategy_scripts s
+            WHERE version = (SELECT MAX(version) FROM strategy_scripts WHERE name = s.name)
+            ORDER BY name
+            "#,
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(scripts)
+    }
+}
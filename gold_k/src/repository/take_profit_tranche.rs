@@ -0,0 +1,92 @@
+use crate::models::TakeProfitTranche;
+use anyhow::Result;
+use sqlx::SqlitePool;
+
+pub struct TakeProfitTrancheRepository;
+
+impl TakeProfitTrancheRepository {
+    /// 保存订单的分批止盈，此时还没有创建触发委托
+    pub async fn save_batch(pool: &SqlitePool, tranches: &[TakeProfitTranche]) -> Result<()> {
+        let mut tx = pool.begin().await?;
+        for tranche in tranches {
+            sqlx::query(
+                r#"
+                INSERT INTO take_profit_tranches (
+                    order_id, seq, size, r_multiple, trigger_price, status
+                ) VALUES (?, ?, ?, ?, ?, 'pending')
+                "#,
+            )
+            .bind(tranche.order_id)
+            .bind(tranche.seq)
+            .bind(tranche.size)
+            .bind(tranche.r_multiple)
+            .bind(tranche.trigger_price)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn get_by_order(pool: &SqlitePool, order_id: i64) -> Result<Vec<TakeProfitTranche>> {
+        let tranches = sqlx::query_as::<_, TakeProfitTranche>(
+            "SELECT * FROM take_profit_tranches WHERE order_id = ? ORDER BY seq",
+        )
+        .bind(order_id)
+        .fetch_all(pool)
+        .await?;
+        Ok(tranches)
+    }
+
+    /// 触发委托创建成功
+    pub async fn mark_open(pool: &SqlitePool, id: i64, trigger_order_id: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE take_profit_tranches
+            SET trigger_order_id = ?, status = 'open', updated_at = strftime('%s', 'now')
+            WHERE id = ?
+            "#,
+        )
+        .bind(trigger_order_id)
+        .bind(id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// 触发成交，返回是否是这一次更新的，避免重复累加已平仓张数
+    pub async fn mark_triggered(
+        pool: &SqlitePool,
+        id: i64,
+        fill_price: f64,
+        fee: f64,
+    ) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE take_profit_tranches
+            SET status = 'triggered', fill_price = ?, fee = ?, updated_at = strftime('%s', 'now')
+            WHERE id = ? AND status = 'open'
+            "#,
+        )
+        .bind(fill_price)
+        .bind(fee)
+        .bind(id)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn mark_cancelled(pool: &SqlitePool, id: i64) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE take_profit_tranches
+            SET status = 'cancelled', updated_at = strftime('%s', 'now')
+            WHERE id = ?
+            "#,
+        )
+        .bind(id)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,437 @@
+use crate::models::{Contract, KlineData, MonitorConfig, PaperOrder, TradingSignal};
+use crate::services::monitor::{
+    MonitorService, expected_profit_rate, place_order_by_long_short_config,
+};
+use crate::services::paper::{PaperEvent, paper_pnl, simulate_order};
+use crate::services::scripting::ScriptStrategy;
+use crate::services::{confirmation, rules};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// 回测参数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BacktestOptions {
+    pub initial_balance: f64,      // 初始资金(USDT)
+    pub fee_rate: f64,             // 单边手续费率，例如 0.0005
+    pub quanto_multiplier: f64,    // 合约数量乘数
+    pub order_price_round: String, // 合约价格精度
+    #[serde(skip)]
+    pub script: Option<Arc<ScriptStrategy>>, // 脚本策略编译好的脚本
+}
+
+impl Default for BacktestOptions {
+    fn default() -> Self {
+        Self {
+            initial_balance: 10000.0,
+            fee_rate: 0.0005,
+            quanto_multiplier: 1.0,
+            order_price_round: "0.00000001".to_string(),
+            script: None,
+        }
+    }
+}
+
+impl BacktestOptions {
+    /// 传给脚本的合约信息
+    fn contract(&self, symbol: &str) -> Contract {
+        Contract {
+            name: symbol.to_string(),
+            order_price_round: self.order_price_round.clone(),
+            quanto_multiplier: self.quanto_multiplier.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+/// 一笔回测交易
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BacktestTrade {
+    pub signal_timestamp: i64,
+    pub signal_type: String, // 'long' or 'short'
+    pub order_size: i64,
+    pub entry_time: Option<i64>,
+    pub entry_price: f64,
+    pub stop_loss: f64,
+    pub take_profit: f64,
+    pub exit_time: Option<i64>,
+    pub exit_price: Option<f64>,
+    pub exit_reason: Option<String>, // 'tp', 'sl'；为空表示回测结束时仍未平仓
+    pub fee: f64,
+    pub pnl: f64, // 扣除手续费后的已实现盈亏
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EquityPoint {
+    pub timestamp: i64,
+    pub equity: f64,
+}
+
+/// 回测报告
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct BacktestReport {
+    pub symbol: String,
+    pub interval_type: String,
+    pub start_time: Option<i64>,
+    pub end_time: Option<i64>,
+    pub total_klines: usize,
+    pub total_signals: usize, // 通过全部过滤条件的信号数
+    pub trades: Vec<BacktestTrade>,
+    pub closed_trades: usize,
+    pub wins: usize,
+    pub losses: usize,
+    pub win_rate: f64, // 0~1
+    pub gross_profit: f64,
+    pub gross_loss: f64,
+    pub net_pnl: f64,
+    pub profit_factor: Option<f64>, // 总盈利 / 总亏损，没有亏损时没有定义，为空
+    pub sharpe_ratio: f64,          // 按每笔交易收益率计算，未年化
+    pub max_drawdown: f64,          // 最大回撤(USDT)
+    pub max_drawdown_pct: f64,      // 最大回撤(%)
+    pub equity_curve: Vec<EquityPoint>,
+}
+
+/// 用与监控相同的信号分析、指标过滤、入场规则、多周期确认、方向过滤和预计收益率过滤逻辑生成交易信号
+///
+/// `klines` 必须按时间升序且全部已收盘，`index` 为当作最新收盘K线的位置
+pub fn signal_at(
+    config: &MonitorConfig,
+    klines: &[KlineData],
+    index: usize,
+    options: &BacktestOptions,
+) -> Option<TradingSignal> {
+    let latest = &klines[index];
+    // 脚本出错或超时视为没有信号
+    let contract = options.contract(&config.symbol);
+    let signal = MonitorService::detect_signal(
+        latest,
+        &klines[..index],
+        config,
+        options.script.as_deref(),
+        Some(&contract),
+    )
+    .ok()
+    .flatten()?;
+
+    // 没有通过指标过滤
+    if signal.blocked_reason.is_some() {
+        return None;
+    }
+
+    // 自定义入场规则，求值出错时视为不满足
+    if !config.entry_rule.trim().is_empty()
+        && !matches!(
+            rules::evaluate(&config.entry_rule, &signal, &klines[..index], latest),
+            Ok(true)
+        )
+    {
+        return None;
+    }
+
+    // 多周期确认，大周期K线由回测的K线合成
+    if !config.confirm_interval.is_empty()
+        && !confirmation::confirm_with_base(config, &signal.direction, &klines[..=index]).passed
+    {
+        return None;
+    }
+
+    if !place_order_by_long_short_config(config, &signal) {
+        return None;
+    }
+
+    if expected_profit_rate(&signal, latest.close) <= config.expected_profit_rate {
+        return None;
+    }
+
+    MonitorService::generate_trading_signal(&signal, config, options.order_price_round.clone())
+}
+
+/// 在一段K线上回放影线策略，模拟每个交易信号的入场、止盈和止损
+pub fn run_backtest(
+    config: &MonitorConfig,
+    klines: &[KlineData],
+    options: &BacktestOptions,
+) -> BacktestReport {
+    run_backtest_from(config, klines, 0, options)
+}
+
+/// 与 `run_backtest` 相同，但只统计 `start` 及之后的K线产生的信号，之前的K线只作为历史数据
+pub fn run_backtest_from(
+    config: &MonitorConfig,
+    klines: &[KlineData],
+    start: usize,
+    options: &BacktestOptions,
+) -> BacktestReport {
+    let interval_secs = config.interval_type_to_seconds();
+    let mut report = BacktestReport {
+        symbol: config.symbol.clone(),
+        interval_type: config.interval_type.clone(),
+        start_time: klines.get(start).map(|k| k.timestamp),
+        end_time: klines.last().map(|k| k.timestamp),
+        total_klines: klines.len().saturating_sub(start),
+        ..Default::default()
+    };
+
+    // 第一根K线没有历史数据，不会产生信号
+    for index in start.max(1)..klines.len() {
+        let Some(trading_signal) = signal_at(config, klines, index, options) else {
+            continue;
+        };
+        report.total_signals += 1;
+        report.trades.push(simulate_trade(
+            config,
+            &trading_signal,
+            klines,
+            index,
+            interval_secs,
+            options,
+        ));
+    }
+
+    summarize(&mut report, options.initial_balance);
+    report
+}
+
+/// 信号K线收盘后下单，用之后的K线撮合入场和止盈止损
+fn simulate_trade(
+    config: &MonitorConfig,
+    trading_signal: &TradingSignal,
+    klines: &[KlineData],
+    index: usize,
+    interval_secs: i64,
+    options: &BacktestOptions,
+) -> BacktestTrade {
+    let placed_at = klines[index].timestamp + interval_secs;
+    let is_market = config.order_type != "limit";
+
+    let order = PaperOrder {
+        id: 0,
+        symbol: trading_signal.symbol.clone(),
+        side: if trading_signal.signal_type == "long" {
+            "buy"
+        } else {
+            "sell"
+        }
+        .to_string(),
+        order_type: config.order_type.clone(),
+        order_size: trading_signal.order_size,
+        quanto_multiplier: options.quanto_multiplier,
+        order_price: trading_signal.entry_price,
+        take_profit_price: Some(trading_signal.take_profit),
+        stop_loss_price: Some(trading_signal.stop_loss),
+        status: if is_market { "open" } else { "pending" }.to_string(),
+        fill_price: is_market.then_some(trading_signal.entry_price),
+        filled_at: is_market.then_some(placed_at),
+        exit_price: None,
+        exit_reason: None,
+        closed_at: None,
+        realized_pnl: None,
+        fee_rate: options.fee_rate,
+        created_at: placed_at,
+    };
+
+    let mut trade = BacktestTrade {
+        signal_timestamp: trading_signal.timestamp,
+        signal_type: trading_signal.signal_type.clone(),
+        order_size: trading_signal.order_size,
+        entry_time: order.filled_at,
+        entry_price: trading_signal.entry_price,
+        stop_loss: trading_signal.stop_loss,
+        take_profit: trading_signal.take_profit,
+        exit_time: None,
+        exit_price: None,
+        exit_reason: None,
+        fee: 0.0,
+        pnl: 0.0,
+    };
+
+    for event in simulate_order(&order, &klines[index + 1..], interval_secs) {
+        match event {
+            PaperEvent::Filled { price, at } => {
+                trade.entry_time = Some(at);
+                trade.entry_price = price;
+            }
+            PaperEvent::Closed { price, reason, at } => {
+                let notional =
+                    (trade.entry_price + price) * order.order_size as f64 * order.quanto_multiplier;
+                trade.fee = notional * options.fee_rate;
+                trade.pnl = paper_pnl(&order, trade.entry_price, price) - trade.fee;
+                trade.exit_time = Some(at);
+                trade.exit_price = Some(price);
+                trade.exit_reason = Some(reason);
+            }
+        }
+    }
+
+    trade
+}
+
+/// 统计胜率、盈亏因子、最大回撤和资金曲线，按平仓时间排序计入
+fn summarize(report: &mut BacktestReport, initial_balance: f64) {
+    let mut closed: Vec<&BacktestTrade> = report
+        .trades
+        .iter()
+        .filter(|t| t.exit_time.is_some())
+        .collect();
+    closed.sort_by_key(|t| t.exit_time);
+
+    let mut equity = initial_balance;
+    let mut peak = initial_balance;
+    let mut returns = Vec::with_capacity(closed.len());
+    report.equity_curve.push(EquityPoint {
+        timestamp: report.start_time.unwrap_or_default(),
+        equity,
+    });
+
+    for trade in &closed {
+        if trade.pnl > 0.0 {
+            report.wins += 1;
+            report.gross_profit += trade.pnl;
+        } else {
+            report.losses += 1;
+            report.gross_loss += -trade.pnl;
+        }
+
+        if equity > 0.0 {
+            returns.push(trade.pnl / equity);
+        }
+        equity += trade.pnl;
+        peak = peak.max(equity);
+        let drawdown = peak - equity;
+        if drawdown > report.max_drawdown {
+            report.max_drawdown = drawdown;
+            report.max_drawdown_pct = if peak > 0.0 {
+                drawdown / peak * 100.0
+            } else {
+                0.0
+            };
+        }
+
+        report.equity_curve.push(EquityPoint {
+            timestamp: trade.exit_time.unwrap_or_default(),
+            equity,
+        });
+    }
+
+    report.closed_trades = closed.len();
+    report.net_pnl = report.gross_profit - report.gross_loss;
+    report.win_rate = if closed.is_empty() {
+        0.0
+    } else {
+        report.wins as f64 / closed.len() as f64
+    };
+    report.sharpe_ratio = sharpe_ratio(&returns);
+    report.profit_factor =
+        (report.gross_loss > 0.0).then(|| report.gross_profit / report.gross_loss);
+}
+
+/// 收益率序列的均值除以样本标准差，样本不足或没有波动时为0
+fn sharpe_ratio(returns: &[f64]) -> f64 {
+    if returns.len() < 2 {
+        return 0.0;
+    }
+    let n = returns.len() as f64;
+    let mean = returns.iter().sum::<f64>() / n;
+    let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    let std_dev = variance.sqrt();
+    if std_dev > 0.0 { mean / std_dev } else { 0.0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kline(timestamp: i64, open: f64, high: f64, low: f64, close: f64, volume: f64) -> KlineData {
+        KlineData {
+            timestamp,
+            open,
+            high,
+            low,
+            close,
+            volume,
+        }
+    }
+
+    fn config() -> MonitorConfig {
+        MonitorConfig {
+            symbol: "BTC_USDT".into(),
+            interval_type: "1m".into(),
+            history_hours: 0.05, // 3根K线
+            shadow_ratio: 2.0,
+            main_shadow_body_ratio: 1.0,
+            volume_multiplier: 1.5,
+            order_size: 1,
+            risk_reward_ratio: 1.0,
+            expected_profit_rate: 0.1,
+            trade_direction: "both".into(),
+            order_type: "market".into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_run_backtest_long_shadow_take_profit() {
+        let klines = vec![
+            kline(0, 100.0, 101.0, 99.0, 100.0, 10.0),
+            kline(60, 100.0, 101.0, 99.0, 100.0, 10.0),
+            kline(120, 100.0, 101.0, 99.0, 100.0, 10.0),
+            // 长下影线放量阳线：入场100.5，止损95，止盈106
+            kline(180, 100.0, 100.6, 95.0, 100.5, 30.0),
+            kline(240, 100.5, 103.0, 99.0, 102.0, 10.0),
+            kline(300, 102.0, 107.0, 101.0, 106.5, 10.0),
+        ];
+        let options = BacktestOptions {
+            fee_rate: 0.0,
+            ..Default::default()
+        };
+        let report = run_backtest(&config(), &klines, &options);
+
+        assert_eq!(report.total_signals, 1);
+        assert_eq!(report.closed_trades, 1);
+        let trade = &report.trades[0];
+        assert_eq!(trade.signal_type, "long");
+        assert_eq!(trade.exit_reason.as_deref(), Some("tp"));
+        assert_eq!(trade.exit_price, Some(106.0));
+        assert!((report.net_pnl - 5.5).abs() < 1e-9);
+        assert_eq!(report.win_rate, 1.0);
+        assert_eq!(report.equity_curve.len(), 2);
+        assert_eq!(report.max_drawdown, 0.0);
+    }
+
+    #[test]
+    fn test_summarize_drawdown_and_profit_factor() {
+        let trade = |exit_time: i64, pnl: f64| BacktestTrade {
+            signal_timestamp: 0,
+            signal_type: "long".into(),
+            order_size: 1,
+            entry_time: Some(0),
+            entry_price: 100.0,
+            stop_loss: 90.0,
+            take_profit: 110.0,
+            exit_time: Some(exit_time),
+            exit_price: Some(100.0),
+            exit_reason: Some("tp".into()),
+            fee: 0.0,
+            pnl,
+        };
+        let mut report = BacktestReport {
+            trades: vec![trade(3, -30.0), trade(1, 100.0), trade(2, -20.0)],
+            ..Default::default()
+        };
+        summarize(&mut report, 1000.0);
+
+        assert_eq!(report.wins, 1);
+        assert_eq!(report.losses, 2);
+        assert_eq!(report.profit_factor, Some(2.0));
+        assert_eq!(report.net_pnl, 50.0);
+        assert_eq!(report.max_drawdown, 50.0);
+        assert_eq!(report.equity_curve.last().unwrap().equity, 1050.0);
+
+        // 没有亏损时盈亏因子没有定义
+        let mut report = BacktestReport {
+            trades: vec![trade(1, 100.0), trade(2, 20.0)],
+            ..Default::default()
+        };
+        summarize(&mut report, 1000.0);
+        assert_eq!(report.profit_factor, None);
+    }
+}
@@ -0,0 +1,307 @@
+use crate::models::{KlineData, MonitorConfig, interval_to_seconds};
+use crate::services::indicators::ema;
+use crate::services::monitor::MonitorService;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+/// 多周期确认方式：'candle' 大周期K线阴阳，'ema' 大周期收盘价和EMA的位置，'shadow' 大周期K线满足长影线规则
+pub const CONFIRM_MODES: &[&str] = &["candle", "ema", "shadow"];
+
+/// 多周期确认的依据，和信号一起保存
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct Confirmation {
+    pub interval: String,
+    pub mode: String,
+    pub timestamp: Option<i64>, // 用于确认的大周期K线开始时间，没有已收盘的K线时为空
+    pub close: Option<f64>,
+    pub ema: Option<f64>,
+    pub direction: Option<String>, // 大周期给出的方向 'long' 或 'short'，没有方向时为空
+    pub passed: bool,
+}
+
+impl Confirmation {
+    /// 没有通过时的拦截原因
+    pub fn blocked_reason(&self) -> Option<String> {
+        (!self.passed).then(|| format!("多周期确认未通过: {} {}", self.interval, self.mode))
+    }
+}
+
+fn mode(config: &MonitorConfig) -> &str {
+    if config.confirm_mode.is_empty() {
+        "candle"
+    } else {
+        &config.confirm_mode
+    }
+}
+
+/// 大周期长影线规则使用的配置：沿用影线和成交量阈值，不再套用指标过滤和多周期确认
+fn shadow_config(config: &MonitorConfig) -> MonitorConfig {
+    MonitorConfig {
+        symbol: config.symbol.clone(),
+        interval_type: config.confirm_interval.clone(),
+        history_hours: config.history_hours,
+        shadow_ratio: config.shadow_ratio,
+        main_shadow_body_ratio: config.main_shadow_body_ratio,
+        volume_multiplier: config.volume_multiplier,
+        strategy: "long_shadow".to_string(),
+        ..Default::default()
+    }
+}
+
+/// 确认需要的大周期已收盘K线数量；EMA多取一倍的K线预热
+pub fn required_klines(config: &MonitorConfig) -> usize {
+    match mode(config) {
+        "ema" => (config.confirm_ema_period.max(1) as usize) * 2,
+        "shadow" => shadow_config(config).required_klines(),
+        _ => 1,
+    }
+}
+
+/// 回测时合成大周期K线需要的信号周期K线数量，没有启用确认时为0。
+/// 多取一根大周期K线，最前面的可能不完整
+pub fn base_klines(config: &MonitorConfig) -> usize {
+    let base_secs = config.interval_type_to_seconds();
+    let interval_secs = interval_to_seconds(&config.confirm_interval);
+    if config.confirm_interval.is_empty() || base_secs <= 0 || interval_secs <= 0 {
+        return 0;
+    }
+    (required_klines(config) + 1) * (interval_secs / base_secs) as usize
+}
+
+/// 开始时间加周期不晚于 `close_time` 的K线，即 `close_time` 时已经收盘的K线
+pub fn closed_before(klines: &[KlineData], interval_secs: i64, close_time: i64) -> &[KlineData] {
+    let end = klines.partition_point(|k| k.timestamp + interval_secs <= close_time);
+    &klines[..end]
+}
+
+/// 用大周期已收盘的K线（按时间升序）确认信号方向
+pub fn confirm(config: &MonitorConfig, direction: &str, klines: &[KlineData]) -> Confirmation {
+    let mut confirmation = Confirmation {
+        interval: config.confirm_interval.clone(),
+        mode: mode(config).to_string(),
+        ..Default::default()
+    };
+    let Some(latest) = klines.last() else {
+        return confirmation;
+    };
+    confirmation.timestamp = Some(latest.timestamp);
+    confirmation.close = Some(latest.close);
+
+    let htf_direction = match mode(config) {
+        "ema" => {
+            let closes: Vec<f64> = klines.iter().map(|k| k.close).collect();
+            confirmation.ema = ema(&closes, config.confirm_ema_period.max(1) as usize);
+            confirmation.ema.and_then(|ema| {
+                if latest.close > ema {
+                    Some("long")
+                } else if latest.close < ema {
+                    Some("short")
+                } else {
+                    None
+                }
+            })
+        }
+        "shadow" => MonitorService::analyze_kline_signal(
+            latest,
+            &klines[..klines.len() - 1],
+            &shadow_config(config),
+        )
+        .map(|signal| {
+            if signal.direction == "long" {
+                "long"
+            } else {
+                "short"
+            }
+        }),
+        _ => {
+            if latest.close > latest.open {
+                Some("long")
+            } else if latest.close < latest.open {
+                Some("short")
+            } else {
+                None
+            }
+        }
+    };
+
+    confirmation.passed = htf_direction == Some(direction);
+    confirmation.direction = htf_direction.map(str::to_string);
+    confirmation
+}
+
+/// 把小周期K线合成大周期K线，只保留每根都齐全的大周期K线
+pub fn aggregate(klines: &[KlineData], base_secs: i64, interval_secs: i64) -> Vec<KlineData> {
+    let per_candle = (interval_secs / base_secs.max(1)) as usize;
+    let mut result: Vec<(KlineData, usize)> = Vec::new();
+    for kline in klines {
+        let start = kline.timestamp - kline.timestamp.rem_euclid(interval_secs);
+        match result.last_mut() {
+            Some((candle, count)) if candle.timestamp == start => {
+                candle.high = candle.high.max(kline.high);
+                candle.low = candle.low.min(kline.low);
+                candle.close = kline.close;
+                candle.volume += kline.volume;
+                *count += 1;
+            }
+            _ => result.push((
+                KlineData {
+                    timestamp: start,
+                    ..kline.clone()
+                },
+                1,
+            )),
+        }
+    }
+    result
+        .into_iter()
+        .filter(|(_, count)| *count == per_candle)
+        .map(|(candle, _)| candle)
+        .collect()
+}
+
+/// 回测时用信号周期的K线合成大周期K线来确认，`klines` 的最后一根为信号K线
+pub fn confirm_with_base(
+    config: &MonitorConfig,
+    direction: &str,
+    klines: &[KlineData],
+) -> Confirmation {
+    let base_secs = config.interval_type_to_seconds();
+    let interval_secs = interval_to_seconds(&config.confirm_interval);
+    let Some(latest) = klines.last() else {
+        return confirm(config, direction, &[]);
+    };
+    if base_secs <= 0 || interval_secs <= 0 {
+        return confirm(config, direction, &[]);
+    }
+    let window = base_klines(config);
+    let candles = aggregate(
+        &klines[klines.len().saturating_sub(window)..],
+        base_secs,
+        interval_secs,
+    );
+    let closed = closed_before(&candles, interval_secs, latest.timestamp + base_secs);
+    confirm(config, direction, closed)
+}
+
+/// 保存配置时检查多周期确认参数
+pub fn validate(config: &MonitorConfig) -> Result<()> {
+    if config.confirm_interval.is_empty() {
+        return Ok(());
+    }
+    let base_secs = config.interval_type_to_seconds();
+    let interval_secs = interval_to_seconds(&config.confirm_interval);
+    if interval_secs <= 0 {
+        return Err(anyhow!("未知的确认周期 {}", config.confirm_interval));
+    }
+    if base_secs <= 0 || interval_secs <= base_secs || interval_secs % base_secs != 0 {
+        return Err(anyhow!(
+            "确认周期 {} 必须大于K线维度 {} 并且是它的整数倍",
+            config.confirm_interval,
+            config.interval_type
+        ));
+    }
+    if !CONFIRM_MODES.contains(&mode(config)) {
+        return Err(anyhow!("未知的确认方式 {}", config.confirm_mode));
+    }
+    if mode(config) == "ema" && config.confirm_ema_period <= 0 {
+        return Err(anyhow!("确认EMA周期必须大于0"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kline(timestamp: i64, open: f64, close: f64) -> KlineData {
+        KlineData {
+            timestamp,
+            open,
+            high: open.max(close) + 1.0,
+            low: open.min(close) - 1.0,
+            close,
+            volume: 1.0,
+        }
+    }
+
+    fn config(mode: &str) -> MonitorConfig {
+        MonitorConfig {
+            symbol: "BTC_USDT".into(),
+            interval_type: "15m".into(),
+            confirm_interval: "1h".into(),
+            confirm_mode: mode.into(),
+            confirm_ema_period: 3,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_confirm() {
+        let rising: Vec<KlineData> = (0..6)
+            .map(|i| kline(i * 3600, 100.0 + i as f64, 101.0 + i as f64))
+            .collect();
+        let candle = confirm(&config("candle"), "long", &rising);
+        assert!(candle.passed);
+        assert_eq!(candle.timestamp, Some(5 * 3600));
+        assert_eq!(candle.direction.as_deref(), Some("long"));
+        assert!(!confirm(&config("candle"), "short", &rising).passed);
+
+        let ema = confirm(&config("ema"), "long", &rising);
+        assert!(ema.passed);
+        assert!(ema.ema.is_some_and(|ema| ema < 106.0));
+        assert_eq!(
+            confirm(&config("ema"), "short", &rising).blocked_reason(),
+            Some("多周期确认未通过: 1h ema".to_string())
+        );
+
+        // 没有大周期K线时不通过
+        let empty = confirm(&config("candle"), "long", &[]);
+        assert!(!empty.passed);
+        assert_eq!(empty.timestamp, None);
+    }
+
+    #[test]
+    fn test_aggregate_and_closed_before() {
+        // 15分钟K线，第一根小时K线缺少开头，不完整
+        let base: Vec<KlineData> = (1..9)
+            .map(|i| kline(i * 900, 100.0 + i as f64, 100.5 + i as f64))
+            .collect();
+        let hourly = aggregate(&base, 900, 3600);
+        assert_eq!(hourly.len(), 1);
+        assert_eq!(hourly[0].timestamp, 3600);
+        assert_eq!(hourly[0].open, 104.0);
+        assert_eq!(hourly[0].close, 107.5);
+        assert_eq!(hourly[0].volume, 4.0);
+
+        // 信号K线收盘前还没收盘的大周期K线不能使用
+        assert_eq!(closed_before(&hourly, 3600, 7200).len(), 1);
+        assert_eq!(closed_before(&hourly, 3600, 7199).len(), 0);
+
+        let confirmation = confirm_with_base(&config("candle"), "long", &base);
+        assert!(confirmation.passed);
+        assert_eq!(confirmation.timestamp, Some(3600));
+        // 小时K线还没收盘
+        assert!(!confirm_with_base(&config("candle"), "long", &base[..6]).passed);
+
+        // 两根小时K线，每根4根15分钟K线
+        assert_eq!(base_klines(&config("candle")), 8);
+        assert_eq!(base_klines(&MonitorConfig::default()), 0);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&MonitorConfig::default()).is_ok());
+        assert!(validate(&config("candle")).is_ok());
+        let smaller = MonitorConfig {
+            confirm_interval: "5m".into(),
+            ..config("candle")
+        };
+        assert!(validate(&smaller).is_err());
+        assert!(validate(&config("volume")).is_err());
+        let ema = MonitorConfig {
+            confirm_ema_period: 0,
+            ..config("ema")
+        };
+        assert!(validate(&ema).is_err());
+    }
+}
@@ -1,4 +1,6 @@
 use crate::models::{DingTalkMarkdown, DingTalkMessage, DingTalkText, Signal, TradingSignal};
+use crate::services::emergency::FlattenReport;
+use crate::services::patterns;
 use anyhow::Result;
 use reqwest::Client;
 use serde_json::Value;
@@ -11,6 +13,7 @@ pub struct DingTalkService {
 }
 
 impl DingTalkService {
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
         Self {
             client: Client::new(),
@@ -57,10 +60,20 @@ impl DingTalkService {
         } else {
             "阴线"
         };
-        let shadow_type_text = if signal.shadow_type == "upper" {
-            "上影线"
-        } else {
-            "下影线"
+        let shadow_type_text = match &signal.pattern {
+            Some(script) if signal.strategy == "script" => format!(
+                " 脚本{} v{} {}",
+                script,
+                signal.script_version.unwrap_or(0),
+                if signal.direction == "long" {
+                    "做多"
+                } else {
+                    "做空"
+                }
+            ),
+            Some(pattern) => patterns::label(pattern).to_string(),
+            None if signal.shadow_type == "upper" => "上影线".to_string(),
+            None => "下影线".to_string(),
         };
 
         let shadow_multiple =
@@ -173,6 +186,74 @@ impl DingTalkService {
         self.send_markdown_message(&title, &markdown_text).await
     }
 
+    pub async fn send_risk_alert(
+        &self,
+        trading_signal: &TradingSignal,
+        reason: &str,
+    ) -> Result<()> {
+        let title = format!("🛑 风控拦截 - {}", trading_signal.symbol);
+        let markdown_text = format!(
+            r#"
+# {}
+---
+- **交易对**: {}
+- **时间**: {}
+- **方向**: {}
+- **入场价**: {:.4}
+- **拦截原因**: {}
+---
+> 信号已记录，本次未自动下单
+            "#,
+            title,
+            trading_signal.symbol,
+            utils::format_timestamp(trading_signal.timestamp, 8),
+            if trading_signal.signal_type == "long" {
+                "做多"
+            } else {
+                "做空"
+            },
+            trading_signal.entry_price,
+            reason
+        );
+
+        self.send_markdown_message(&title, &markdown_text).await
+    }
+
+    pub async fn send_flatten_report(&self, reports: &[FlattenReport]) -> Result<()> {
+        let title = "🚨 紧急平仓完成".to_string();
+        let mut markdown_text = format!(
+            "# {}\n---\n- **时间**: {}\n- **自动下单**: 已暂停，需要手动恢复\n",
+            title,
+            utils::format_timestamp(chrono::Utc::now().timestamp(), 8)
+        );
+
+        for report in reports {
+            markdown_text.push_str(&format!(
+                "---\n## {}\n- **撤销挂单**: {}\n- **撤销止盈止损**: {}\n",
+                report.exchange, report.cancelled_orders, report.cancelled_price_orders
+            ));
+            if report.closed.is_empty() {
+                markdown_text.push_str("- **平仓**: 无持仓\n");
+            }
+            for position in &report.closed {
+                markdown_text.push_str(&format!(
+                    "- {} {} {}张 @ {:.4}，浮动盈亏 {:.4}：{}\n",
+                    if position.success { "✅" } else { "❌" },
+                    position.symbol,
+                    position.size,
+                    position.mark_price,
+                    position.unrealized_pnl,
+                    position.message
+                ));
+            }
+            for error in &report.errors {
+                markdown_text.push_str(&format!("- ⚠️ {}\n", error));
+            }
+        }
+
+        self.send_markdown_message(&title, &markdown_text).await
+    }
+
     pub async fn test_connection(&self) -> Result<()> {
         self.send_text_message(
             "🔔 Gate.io K线监控工具测试消息\n\n如果您收到此消息，说明钉钉机器人配置成功！",
@@ -180,6 +261,7 @@ impl DingTalkService {
         .await
     }
 
+    #[allow(clippy::collapsible_if)]
     async fn send_message(&self, message: DingTalkMessage) -> Result<()> {
         let webhook_url = self
             .webhook_url
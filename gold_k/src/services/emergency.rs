@@ -0,0 +1,266 @@
+use crate::repository::RiskRepository;
+use crate::services::DingTalkService;
+use crate::services::exchange::Exchange;
+use crate::services::gate_error::GateError;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{error, info, warn};
+
+/// 单个持仓的平仓结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClosedPosition {
+    pub symbol: String,
+    pub mode: String,
+    pub size: i64,
+    pub mark_price: f64,
+    pub unrealized_pnl: f64,
+    pub success: bool,
+    pub message: String,
+    pub order_id: Option<String>,
+}
+
+/// 一个交易所的紧急平仓结果，出错的步骤记录在 `errors` 中，不影响后续步骤
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlattenReport {
+    pub exchange: String,
+    pub cancelled_orders: usize,
+    pub cancelled_price_orders: usize,
+    pub closed: Vec<ClosedPosition>,
+    pub errors: Vec<String>,
+}
+
+/// 紧急平仓：暂停自动下单，逐个交易所撤销挂单和止盈止损委托并市价平仓，最后发送钉钉通知
+///
+/// 调用方需要先停止监控服务，避免平仓过程中又产生新订单
+pub async fn emergency_flatten(
+    db: &SqlitePool,
+    exchanges: &[Arc<RwLock<dyn Exchange>>],
+    dingtalk: Option<&DingTalkService>,
+) -> Result<Vec<FlattenReport>> {
+    RiskRepository::set_halted(db, true, Some("紧急平仓")).await?;
+    warn!("Emergency flatten started, automatic trading halted");
+
+    let mut reports = Vec::new();
+    for exchange in exchanges {
+        let exchange = exchange.read().await;
+        let report = flatten(&*exchange, "usdt").await;
+        info!("Flatten report for {}: {:?}", report.exchange, report);
+        reports.push(report);
+    }
+
+    if let Some(dingtalk) = dingtalk.filter(|d| d.has_webhook())
+        && let Err(e) = dingtalk.send_flatten_report(&reports).await
+    {
+        error!("Failed to send DingTalk flatten report: {}", e);
+    }
+
+    Ok(reports)
+}
+
+/// 撤销所有挂单和价格触发委托，然后用只减仓市价单平掉所有持仓
+pub async fn flatten(exchange: &dyn Exchange, settle: &str) -> FlattenReport {
+    let mut report = FlattenReport {
+        exchange: exchange.name().to_string(),
+        cancelled_orders: 0,
+        cancelled_price_orders: 0,
+        closed: Vec::new(),
+        errors: Vec::new(),
+    };
+
+    match exchange.cancel_all_orders(settle).await {
+        Ok(count) => report.cancelled_orders = count,
+        Err(e) => {
+            report.cancelled_orders = partially_cancelled(&e);
+            report.errors.push(format!("撤销挂单失败: {}", e));
+        }
+    }
+    match exchange.cancel_all_price_orders(settle).await {
+        Ok(count) => report.cancelled_price_orders = count,
+        Err(e) => {
+            report.cancelled_price_orders = partially_cancelled(&e);
+            report.errors.push(format!("撤销止盈止损失败: {}", e));
+        }
+    }
+
+    let positions = match exchange.get_positions(settle).await {
+        Ok(positions) => positions,
+        Err(e) => {
+            report.errors.push(format!("查询持仓失败: {}", e));
+            return report;
+        }
+    };
+    for position in positions {
+        let result = exchange.close_position(&position, settle).await;
+        let (success, message, order_id) = match result {
+            Ok(response) => (response.success, response.message, response.order_id),
+            Err(e) => (false, e.to_string(), None),
+        };
+        if !success {
+            error!(
+                "Failed to close {} position {}: {}",
+                report.exchange, position.symbol, message
+            );
+        }
+        report.closed.push(ClosedPosition {
+            symbol: position.symbol,
+            mode: position.mode,
+            size: position.size,
+            mark_price: position.mark_price,
+            unrealized_pnl: position.unrealized_pnl,
+            success,
+            message,
+            order_id,
+        });
+    }
+
+    report
+}
+
+/// 批量撤单部分失败时已经撤销的数量
+fn partially_cancelled(error: &anyhow::Error) -> usize {
+    match error.downcast_ref::<GateError>() {
+        Some(GateError::PartialCancel { cancelled, .. }) => *cancelled,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ApiKey, KlineData};
+    use crate::services::exchange::{
+        ExchangeOrder, OrderRequest, OrderResponse, Position, PriceOrder,
+    };
+    use anyhow::anyhow;
+    use async_trait::async_trait;
+    use serde_json::Value;
+    use std::sync::Mutex;
+
+    /// 撤销挂单时部分合约失败、其中一个持仓平仓失败的交易所
+    #[derive(Debug, Default)]
+    struct FailingExchange {
+        closed: Mutex<Vec<String>>,
+    }
+
+    fn position(symbol: &str, size: i64) -> Position {
+        Position {
+            symbol: symbol.to_string(),
+            mode: "single".to_string(),
+            size,
+            entry_price: 100.0,
+            mark_price: 101.0,
+            unrealized_pnl: 1.0,
+            realized_pnl: 0.0,
+            leverage: 10.0,
+            liq_price: None,
+        }
+    }
+
+    #[async_trait]
+    impl Exchange for FailingExchange {
+        fn name(&self) -> &str {
+            "mock"
+        }
+
+        fn apply_api_key(&mut self, _key: &ApiKey) {}
+
+        fn has_credentials(&self) -> bool {
+            true
+        }
+
+        async fn get_kline_data(
+            &self,
+            _symbol: &str,
+            _interval: &str,
+            _limit: usize,
+            _settle: &str,
+        ) -> Result<Vec<KlineData>> {
+            unimplemented!()
+        }
+
+        async fn get_kline_data_range(
+            &self,
+            _symbol: &str,
+            _interval: &str,
+            _from: i64,
+            _to: i64,
+            _settle: &str,
+        ) -> Result<Vec<KlineData>> {
+            unimplemented!()
+        }
+
+        async fn get_contracts(&self, _settle: &str) -> Result<Vec<Value>> {
+            unimplemented!()
+        }
+
+        async fn place_order(&self, _order: &OrderRequest, _settle: &str) -> Result<OrderResponse> {
+            unimplemented!()
+        }
+
+        async fn get_order(&self, _order_id: &str, _settle: &str) -> Result<ExchangeOrder> {
+            unimplemented!()
+        }
+
+        async fn get_price_orders(&self, _symbol: &str, _settle: &str) -> Result<Vec<PriceOrder>> {
+            unimplemented!()
+        }
+
+        async fn get_positions(&self, _settle: &str) -> Result<Vec<Position>> {
+            Ok(vec![position("BTC_USDT", 2), position("ETH_USDT", -3)])
+        }
+
+        async fn cancel_all_orders(&self, _settle: &str) -> Result<usize> {
+            Err(GateError::PartialCancel {
+                cancelled: 4,
+                failures: vec!["ETH_USDT: timeout".to_string()],
+            }
+            .into())
+        }
+
+        async fn cancel_all_price_orders(&self, _settle: &str) -> Result<usize> {
+            Ok(2)
+        }
+
+        async fn close_position(
+            &self,
+            position: &Position,
+            _settle: &str,
+        ) -> Result<OrderResponse> {
+            if position.symbol == "BTC_USDT" {
+                return Err(anyhow!("connection reset"));
+            }
+            self.closed.lock().unwrap().push(position.symbol.clone());
+            Ok(OrderResponse {
+                success: true,
+                message: "ok".to_string(),
+                order_id: Some("1".to_string()),
+                take_profit_order_id: None,
+                stop_loss_order_id: None,
+                raw: Value::Null,
+            })
+        }
+
+        async fn get_account_info(&self) -> Result<crate::services::exchange::AccountInfo> {
+            unimplemented!()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flatten_continues_after_errors() {
+        let exchange = FailingExchange::default();
+        let report = flatten(&exchange, "usdt").await;
+
+        assert_eq!(report.cancelled_orders, 4);
+        assert_eq!(report.cancelled_price_orders, 2);
+        assert_eq!(report.errors.len(), 1);
+        assert!(report.errors[0].contains("ETH_USDT"));
+
+        assert_eq!(report.closed.len(), 2);
+        assert!(!report.closed[0].success);
+        assert!(report.closed[1].success);
+        assert_eq!(*exchange.closed.lock().unwrap(), vec!["ETH_USDT"]);
+    }
+}
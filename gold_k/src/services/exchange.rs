@@ -0,0 +1,212 @@
+use crate::models::{ApiKey, KlineData};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// 与交易所无关的下单请求，由具体交易所实现转换成各自的请求格式
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderRequest {
+    pub symbol: String,
+    pub order_type: String, // 'market' 或 'limit'
+    pub side: String,       // 'buy' or 'sell'
+    pub price: f64,
+    pub size: i64, // 张
+    pub take_profit: Option<f64>,
+    pub stop_loss: Option<f64>,
+    #[serde(default)]
+    pub client_order_id: Option<String>, // 自定义订单ID，Gate.io要求以t-开头
+    #[serde(default)]
+    pub execution_mode: String, // 'web' 网页端接口一起设置止盈止损（需要cookie），'api' 签名API成交后单独设置
+}
+
+/// 只减仓的价格触发委托，签名API下单成交后单独设置止盈止损
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TriggerOrderRequest {
+    pub symbol: String,
+    pub side: String, // 平仓方向，'sell' 平多，'buy' 平空
+    pub size: i64,    // 张
+    pub trigger_price: f64,
+    pub kind: String, // 'tp' 止盈 或 'sl' 止损
+}
+
+impl TriggerOrderRequest {
+    /// 平掉 `entry_side` 方向开仓的持仓
+    pub fn exit(symbol: &str, entry_side: &str, size: i64, trigger_price: f64, kind: &str) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            side: if entry_side == "buy" { "sell" } else { "buy" }.to_string(),
+            size: size.abs(),
+            trigger_price,
+            kind: kind.to_string(),
+        }
+    }
+
+    /// 价格涨到触发价时触发（平多止盈、平空止损），否则跌到触发价时触发
+    pub fn triggers_above(&self) -> bool {
+        (self.side == "sell") == (self.kind == "tp")
+    }
+}
+
+/// 下单结果，`raw` 保留交易所的原始响应
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderResponse {
+    pub success: bool,
+    pub message: String,
+    pub order_id: Option<String>,
+    pub take_profit_order_id: Option<String>,
+    pub stop_loss_order_id: Option<String>,
+    pub raw: Value,
+}
+
+/// 交易所中的订单状态
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ExchangeOrder {
+    pub id: String,
+    pub text: Option<String>,
+    pub status: String, // 'open', 'filled', 'cancelled'
+    pub size: i64,
+    pub filled_size: i64,
+    pub fill_price: Option<f64>,
+    pub fee_rate: f64, // 成交使用的手续费率，用来估算手续费
+}
+
+/// 止盈止损等价格触发委托
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PriceOrder {
+    pub id: String,
+    pub status: String, // 'open', 'triggered', 'cancelled', 'failed'
+    pub trigger_price: f64,
+    pub order_type: String,       // 例如 close-long-position
+    pub trade_id: Option<String>, // 触发后生成的订单ID
+    pub create_time: i64,
+}
+
+/// 当前持仓，`size` 为正表示多仓，为负表示空仓
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Position {
+    pub symbol: String,
+    pub mode: String, // 'single', 'dual_long', 'dual_short'
+    pub size: i64,    // 张
+    pub entry_price: f64,
+    pub mark_price: f64,
+    pub unrealized_pnl: f64,
+    pub realized_pnl: f64,
+    pub leverage: f64, // 0表示全仓
+    pub liq_price: Option<f64>,
+}
+
+/// 账户信息，`valid` 表示当前凭据（cookie）是否仍然有效
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountInfo {
+    pub valid: bool,
+    pub equity: Option<f64>, // 账户权益(USDT)，包含未实现盈亏
+    pub raw: Value,
+}
+
+/// 交易所抽象，监控服务和web接口只依赖这个trait，方便接入其他交易所或测试替身
+#[async_trait]
+pub trait Exchange: Send + Sync + std::fmt::Debug {
+    /// 交易所名称，用于日志
+    fn name(&self) -> &str;
+
+    /// 根据数据库中的API配置更新凭据
+    fn apply_api_key(&mut self, key: &ApiKey);
+
+    fn has_credentials(&self) -> bool;
+
+    /// 获取K线数据，按时间升序返回
+    async fn get_kline_data(
+        &self,
+        symbol: &str,
+        interval: &str,
+        limit: usize,
+        settle: &str,
+    ) -> Result<Vec<KlineData>>;
+
+    /// 获取时间范围内的K线数据（秒级时间戳，包含两端），按时间升序返回
+    async fn get_kline_data_range(
+        &self,
+        symbol: &str,
+        interval: &str,
+        from: i64,
+        to: i64,
+        settle: &str,
+    ) -> Result<Vec<KlineData>>;
+
+    /// 获取合约元数据，原样返回方便持久化
+    async fn get_contracts(&self, settle: &str) -> Result<Vec<Value>>;
+
+    /// 下单，同时设置止盈止损
+    async fn place_order(&self, order: &OrderRequest, settle: &str) -> Result<OrderResponse>;
+
+    /// 创建只减仓的价格触发委托，返回委托ID
+    async fn place_trigger_order(
+        &self,
+        _order: &TriggerOrderRequest,
+        _settle: &str,
+    ) -> Result<String> {
+        Err(anyhow::anyhow!("{} 不支持单独设置止盈止损", self.name()))
+    }
+
+    /// 把止损移动到新的触发价，返回新的止损委托ID
+    ///
+    /// 默认先创建新的止损委托再撤销旧的，保证任何时候都有止损；撤销失败时撤回新委托，保持原来的止损
+    async fn replace_stop_loss(
+        &self,
+        old_order_id: &str,
+        order: &TriggerOrderRequest,
+        settle: &str,
+    ) -> Result<String> {
+        let new_order_id = self.place_trigger_order(order, settle).await?;
+        if let Err(e) = self.cancel_price_order(old_order_id, settle).await {
+            self.cancel_price_order(&new_order_id, settle).await?;
+            return Err(e);
+        }
+        Ok(new_order_id)
+    }
+
+    /// 撤销一个进行中的价格触发委托
+    async fn cancel_price_order(&self, _order_id: &str, _settle: &str) -> Result<()> {
+        Err(anyhow::anyhow!("{} 不支持撤销单个触发委托", self.name()))
+    }
+
+    /// 查询订单，`order_id` 可以是交易所订单ID或下单时的自定义ID
+    async fn get_order(&self, order_id: &str, settle: &str) -> Result<ExchangeOrder>;
+
+    /// 查询合约的价格触发委托（包括已结束的）
+    async fn get_price_orders(&self, symbol: &str, settle: &str) -> Result<Vec<PriceOrder>>;
+
+    /// 查询所有非空持仓
+    async fn get_positions(&self, settle: &str) -> Result<Vec<Position>>;
+
+    /// 撤销所有未成交的订单，返回撤销的数量
+    async fn cancel_all_orders(&self, settle: &str) -> Result<usize>;
+
+    /// 撤销所有进行中的价格触发委托（止盈止损），返回撤销的数量
+    async fn cancel_all_price_orders(&self, settle: &str) -> Result<usize>;
+
+    /// 用只减仓的市价单平掉持仓
+    async fn close_position(&self, position: &Position, settle: &str) -> Result<OrderResponse>;
+
+    /// 查询账户信息，同时用来判断凭据是否有效
+    async fn get_account_info(&self) -> Result<AccountInfo>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trigger_order_exit() {
+        let tp = TriggerOrderRequest::exit("BTC_USDT", "buy", 3, 110.0, "tp");
+        assert_eq!(tp.side, "sell");
+        assert!(tp.triggers_above());
+        assert!(!TriggerOrderRequest::exit("BTC_USDT", "buy", 3, 95.0, "sl").triggers_above());
+
+        let sl = TriggerOrderRequest::exit("BTC_USDT", "sell", -3, 105.0, "sl");
+        assert_eq!((sl.side.as_str(), sl.size), ("buy", 3));
+        assert!(sl.triggers_above());
+        assert!(!TriggerOrderRequest::exit("BTC_USDT", "sell", 3, 90.0, "tp").triggers_above());
+    }
+}
@@ -1,16 +1,29 @@
-use crate::models::KlineData;
-use anyhow::{Result, anyhow};
+use crate::models::{ApiKey, KlineData};
+use crate::services::build_order_data;
+use crate::services::exchange::{
+    AccountInfo, Exchange, ExchangeOrder, OrderRequest, OrderResponse, Position, PriceOrder,
+    TriggerOrderRequest,
+};
+use crate::services::gate_error::{GateError, GateResult};
+use crate::services::rate_limit::{EndpointClass, RateLimiter, RetryPolicy};
+use anyhow::Result;
+use async_trait::async_trait;
 use hmac::{Hmac, Mac};
-use reqwest::Client;
+use reqwest::header::HeaderMap;
+use reqwest::{Client, Method, RequestBuilder};
 use serde_json::Value;
 use serde_urlencoded;
 use sha2::{Digest, Sha512};
 use std::collections::HashMap;
-use std::time::{SystemTime, UNIX_EPOCH};
-use tracing::{debug, warn};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tokio::time::{Duration, sleep};
+use tracing::{debug, error, warn};
 
 type HmacSha512 = Hmac<Sha512>;
 
+/// 签名API下单后等待成交的时间，超时未成交的订单由对账任务在成交后设置止盈止损
+const FILL_WAIT_SECS: u64 = 10;
+
 #[derive(Debug, Clone)]
 pub struct GateService {
     client: Client,
@@ -19,9 +32,12 @@ pub struct GateService {
     base_url: String,
     cookie: Option<String>,
     contracts: Option<String>,
+    limiter: RateLimiter,
+    retry: RetryPolicy,
 }
 
 impl GateService {
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
         Self {
             client: Client::new(),
@@ -30,6 +46,8 @@ impl GateService {
             base_url: "https://api.gateio.ws/api/v4".to_string(),
             cookie: None,
             contracts: None,
+            limiter: RateLimiter::default(),
+            retry: RetryPolicy::default(),
         }
     }
 
@@ -57,19 +75,19 @@ impl GateService {
         query_string: &str,
         body: &str,
         timestamp: u64,
-    ) -> Result<String> {
+    ) -> GateResult<String> {
         let secret_key = self
             .secret_key
             .as_ref()
-            .ok_or_else(|| anyhow!("Secret key not set"))?;
+            .ok_or_else(|| GateError::NotConfigured("Secret key not set".to_string()))?;
 
         // 计算 body 的 SHA512 哈希
         let body_hash = sha2::Sha512::digest(body.as_bytes());
         let body_hash_hex = hex::encode(body_hash);
 
-        // 构建签名字符串
+        // 构建签名字符串，请求路径需要包含 /api/v4 前缀
         let string_to_sign = format!(
-            "{}\n{}\n{}\n{}\n{}",
+            "{}\n/api/v4{}\n{}\n{}\n{}",
             method.to_uppercase(),
             url_path,
             query_string,
@@ -81,7 +99,7 @@ impl GateService {
 
         // 生成 HMAC-SHA512 签名
         let mut mac = HmacSha512::new_from_slice(secret_key.as_bytes())
-            .map_err(|e| anyhow!("Invalid secret key: {}", e))?;
+            .map_err(|e| GateError::NotConfigured(format!("Invalid secret key: {}", e)))?;
         mac.update(string_to_sign.as_bytes());
         let signature = hex::encode(mac.finalize().into_bytes());
 
@@ -94,23 +112,55 @@ impl GateService {
         interval: &str,
         limit: usize,
         settle: &str,
-    ) -> Result<Vec<KlineData>> {
-        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
-
+    ) -> GateResult<Vec<KlineData>> {
         let limit_str = limit.to_string();
         let mut query_params = HashMap::new();
         query_params.insert("contract", symbol);
         query_params.insert("interval", interval);
         query_params.insert("limit", &limit_str);
 
-        let query_string = serde_urlencoded::to_string(&query_params)?;
+        self.fetch_candlesticks(&query_params, settle).await
+    }
+
+    /// 获取时间范围内的K线数据，`from`和`to`为秒级时间戳
+    ///
+    /// Gate.io 的 `limit` 不能和 `from`/`to` 同时使用，单次最多返回2000根，范围更大时需要调用方分页
+    pub async fn get_kline_data_range(
+        &self,
+        symbol: &str,
+        interval: &str,
+        from: i64,
+        to: i64,
+        settle: &str,
+    ) -> GateResult<Vec<KlineData>> {
+        let from_str = from.to_string();
+        let to_str = to.to_string();
+        let mut query_params = HashMap::new();
+        query_params.insert("contract", symbol);
+        query_params.insert("interval", interval);
+        query_params.insert("from", &from_str);
+        query_params.insert("to", &to_str);
+
+        self.fetch_candlesticks(&query_params, settle).await
+    }
+
+    async fn fetch_candlesticks(
+        &self,
+        query_params: &HashMap<&str, &str>,
+        settle: &str,
+    ) -> GateResult<Vec<KlineData>> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+        let query_string = serde_urlencoded::to_string(query_params)?;
         let url_path = format!("/futures/{}/candlesticks", settle);
         let url = format!("{}{}?{}", self.base_url, url_path, query_string);
 
         debug!("Request URL: {}", url);
 
         if !self.has_credentials() {
-            return Err(anyhow!("API credentials not configured"));
+            return Err(GateError::NotConfigured(
+                "API credentials not configured".to_string(),
+            ));
         }
 
         let signature = self
@@ -120,75 +170,62 @@ impl GateService {
         let api_key = self
             .api_key
             .as_ref()
-            .ok_or_else(|| anyhow!("API key not set"))?;
+            .ok_or_else(|| GateError::NotConfigured("API key not set".to_string()))?;
 
-        let response = self
+        let request = self
             .client
             .get(&url)
             .header("KEY", api_key)
             .header("Timestamp", timestamp.to_string())
             .header("SIGN", signature)
             .header("Content-Type", "application/json")
-            .header("Accept", "application/json")
-            .send()
-            .await?;
-
-        let status = response.status();
-        let response_text = response.text().await?;
+            .header("Accept", "application/json");
+        let response_text = self.execute(EndpointClass::Public, request).await?;
 
-        debug!("Response status: {}", status);
         debug!("Response body: {}", response_text);
 
-        if !status.is_success() {
-            return Err(anyhow!(
-                "API request failed: {} - {}",
-                status,
-                response_text
-            ));
-        }
-
         let data: Value = serde_json::from_str(&response_text)?;
 
         // Gate.io K线数据格式: [{"o":"","t":1234, "c":"", "l": "", "h": "", "v": 1 }]
         let klines = data
             .as_array()
-            .ok_or_else(|| anyhow!("Invalid response format"))?;
+            .ok_or_else(|| GateError::InvalidResponse("Invalid response format".to_string()))?;
 
         let mut result = Vec::new();
         for kline in klines {
             let kline_obj = kline
                 .as_object()
-                .ok_or_else(|| anyhow!("Invalid kline format"))?;
+                .ok_or_else(|| GateError::InvalidResponse("Invalid kline format".to_string()))?;
 
             let timestamp = kline_obj
                 .get("t")
                 .and_then(|t| t.as_i64())
-                .ok_or_else(|| anyhow!("Invalid timestamp"))?;
+                .ok_or_else(|| GateError::InvalidResponse("Invalid timestamp".to_string()))?;
 
             let volume = kline_obj
                 .get("v")
                 .and_then(|v| v.as_f64())
-                .ok_or_else(|| anyhow!("Invalid volume"))?;
+                .ok_or_else(|| GateError::InvalidResponse("Invalid volume".to_string()))?;
 
             let close = kline_obj
                 .get("c")
                 .and_then(|c| c.as_str().and_then(|s| s.parse::<f64>().ok()))
-                .ok_or_else(|| anyhow!("Invalid close price"))?;
+                .ok_or_else(|| GateError::InvalidResponse("Invalid close price".to_string()))?;
 
             let high = kline_obj
                 .get("h")
                 .and_then(|h| h.as_str().and_then(|s| s.parse::<f64>().ok()))
-                .ok_or_else(|| anyhow!("Invalid high price"))?;
+                .ok_or_else(|| GateError::InvalidResponse("Invalid high price".to_string()))?;
 
             let low = kline_obj
                 .get("l")
                 .and_then(|l| l.as_str().and_then(|s| s.parse::<f64>().ok()))
-                .ok_or_else(|| anyhow!("Invalid low price"))?;
+                .ok_or_else(|| GateError::InvalidResponse("Invalid low price".to_string()))?;
 
             let open = kline_obj
                 .get("o")
                 .and_then(|o| o.as_str().and_then(|s| s.parse::<f64>().ok()))
-                .ok_or_else(|| anyhow!("Invalid open price"))?;
+                .ok_or_else(|| GateError::InvalidResponse("Invalid open price".to_string()))?;
 
             result.push(KlineData {
                 timestamp,
@@ -203,14 +240,16 @@ impl GateService {
         Ok(result)
     }
 
-    pub async fn get_contracts(&self, settle: &str) -> Result<Vec<Value>> {
+    pub async fn get_contracts(&self, settle: &str) -> GateResult<Vec<Value>> {
         let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
 
         let url_path = format!("/futures/{}/contracts", settle);
         let url = format!("{}{}", self.base_url, url_path);
 
         if !self.has_credentials() {
-            return Err(anyhow!("API credentials not configured"));
+            return Err(GateError::NotConfigured(
+                "API credentials not configured".to_string(),
+            ));
         }
 
         let signature = self
@@ -220,29 +259,17 @@ impl GateService {
         let api_key = self
             .api_key
             .as_ref()
-            .ok_or_else(|| anyhow!("API key not set"))?;
+            .ok_or_else(|| GateError::NotConfigured("API key not set".to_string()))?;
 
-        let response = self
+        let request = self
             .client
             .get(&url)
             .header("KEY", api_key)
             .header("Timestamp", timestamp.to_string())
             .header("SIGN", signature)
             .header("Content-Type", "application/json")
-            .header("Accept", "application/json")
-            .send()
-            .await?;
-
-        let status = response.status();
-        let response_text = response.text().await?;
-
-        if !status.is_success() {
-            return Err(anyhow!(
-                "API request failed: {} - {}",
-                status,
-                response_text
-            ));
-        }
+            .header("Accept", "application/json");
+        let response_text = self.execute(EndpointClass::Public, request).await?;
 
         let contracts: Vec<Value> = serde_json::from_str(&response_text)?;
         Ok(contracts)
@@ -255,7 +282,7 @@ impl GateService {
         size: f64,
         price: Option<f64>,
         settle: &str,
-    ) -> Result<Value> {
+    ) -> GateResult<Value> {
         let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
 
         let mut order_data = serde_json::json!({
@@ -277,7 +304,9 @@ impl GateService {
         let url = format!("{}{}", self.base_url, url_path);
 
         if !self.has_credentials() {
-            return Err(anyhow!("API credentials not configured"));
+            return Err(GateError::NotConfigured(
+                "API credentials not configured".to_string(),
+            ));
         }
 
         let signature = self
@@ -287,9 +316,9 @@ impl GateService {
         let api_key = self
             .api_key
             .as_ref()
-            .ok_or_else(|| anyhow!("API key not set"))?;
+            .ok_or_else(|| GateError::NotConfigured("API key not set".to_string()))?;
 
-        let response = self
+        let request = self
             .client
             .post(&url)
             .header("KEY", api_key)
@@ -297,23 +326,359 @@ impl GateService {
             .header("SIGN", signature)
             .header("Content-Type", "application/json")
             .header("Accept", "application/json")
-            .body(body)
-            .send()
-            .await?;
+            .body(body);
+        let response_text = self.execute(EndpointClass::PlaceOrder, request).await?;
 
-        let status = response.status();
-        let response_text = response.text().await?;
+        let result: Value = serde_json::from_str(&response_text)?;
+        Ok(result)
+    }
+
+    /// 使用签名API发送GET请求，返回解析后的JSON
+    async fn signed_get(&self, url_path: &str, query_string: &str) -> GateResult<Value> {
+        self.signed_request("GET", url_path, query_string, None)
+            .await
+    }
 
-        if !status.is_success() {
-            return Err(anyhow!(
-                "API request failed: {} - {}",
-                status,
-                response_text
+    /// 使用签名API发送请求，`body` 为JSON请求体
+    async fn signed_request(
+        &self,
+        method: &str,
+        url_path: &str,
+        query_string: &str,
+        body: Option<&Value>,
+    ) -> GateResult<Value> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+        let url = if query_string.is_empty() {
+            format!("{}{}", self.base_url, url_path)
+        } else {
+            format!("{}{}?{}", self.base_url, url_path, query_string)
+        };
+
+        if !self.has_credentials() {
+            return Err(GateError::NotConfigured(
+                "API credentials not configured".to_string(),
             ));
         }
 
-        let result: Value = serde_json::from_str(&response_text)?;
-        Ok(result)
+        let body = match body {
+            Some(body) => serde_json::to_string(body)?,
+            None => String::new(),
+        };
+        let signature = self
+            .generate_signature(method, url_path, query_string, &body, timestamp)
+            .await?;
+
+        let api_key = self
+            .api_key
+            .as_ref()
+            .ok_or_else(|| GateError::NotConfigured("API key not set".to_string()))?;
+
+        // 通过签名API发送的POST都是下单，DELETE都是撤单
+        let class = match method {
+            "POST" => EndpointClass::PlaceOrder,
+            "DELETE" => EndpointClass::CancelOrder,
+            _ => EndpointClass::Private,
+        };
+        let request = self
+            .client
+            .request(
+                Method::from_bytes(method.as_bytes())
+                    .map_err(|e| GateError::Client(e.to_string()))?,
+                &url,
+            )
+            .header("KEY", api_key)
+            .header("Timestamp", timestamp.to_string())
+            .header("SIGN", signature)
+            .header("Content-Type", "application/json")
+            .header("Accept", "application/json")
+            .body(body);
+        let response_text = self.execute(class, request).await?;
+
+        if response_text.is_empty() {
+            return Ok(Value::Null);
+        }
+        Ok(serde_json::from_str(&response_text)?)
+    }
+
+    /// 查询订单详情，`order_id` 可以是订单ID或下单时的text
+    pub async fn get_order(&self, order_id: &str, settle: &str) -> GateResult<ExchangeOrder> {
+        let url_path = format!("/futures/{}/orders/{}", settle, order_id);
+        let data = self.signed_get(&url_path, "").await?;
+        parse_order(&data)
+    }
+
+    /// 查询合约的止盈止损等价格触发委托，包括进行中和已结束的
+    pub async fn get_price_orders(
+        &self,
+        symbol: &str,
+        settle: &str,
+    ) -> GateResult<Vec<PriceOrder>> {
+        let url_path = format!("/futures/{}/price_orders", settle);
+        let mut orders = Vec::new();
+
+        for status in ["open", "finished"] {
+            let query_string = serde_urlencoded::to_string([
+                ("status", status),
+                ("contract", symbol),
+                ("limit", "100"),
+            ])?;
+            let data = self.signed_get(&url_path, &query_string).await?;
+            for item in data
+                .as_array()
+                .ok_or_else(|| GateError::InvalidResponse("Invalid response format".to_string()))?
+            {
+                orders.push(parse_price_order(item)?);
+            }
+        }
+
+        Ok(orders)
+    }
+
+    /// 查询所有非空持仓
+    pub async fn get_positions(&self, settle: &str) -> GateResult<Vec<Position>> {
+        let url_path = format!("/futures/{}/positions", settle);
+        let data = self.signed_get(&url_path, "holding=true").await?;
+        data.as_array()
+            .ok_or_else(|| GateError::InvalidResponse("Invalid response format".to_string()))?
+            .iter()
+            .map(parse_position)
+            .filter(|p| !matches!(p, Ok(p) if p.size == 0))
+            .collect()
+    }
+
+    /// 撤销所有合约的未成交订单，返回撤销的数量
+    pub async fn cancel_all_orders(&self, settle: &str) -> GateResult<usize> {
+        let url_path = format!("/futures/{}/orders", settle);
+        self.cancel_open_by_contract(&url_path).await
+    }
+
+    /// 撤销所有合约进行中的价格触发委托，返回撤销的数量
+    pub async fn cancel_all_price_orders(&self, settle: &str) -> GateResult<usize> {
+        let url_path = format!("/futures/{}/price_orders", settle);
+        self.cancel_open_by_contract(&url_path).await
+    }
+
+    /// 分页查询 `url_path` 下所有 `status=open` 的委托，按合约逐个批量撤销
+    ///
+    /// 某个合约撤销失败时继续撤销其它合约，最后返回 `GateError::PartialCancel`
+    async fn cancel_open_by_contract(&self, url_path: &str) -> GateResult<usize> {
+        const PAGE_SIZE: usize = 100;
+
+        let mut contracts = Vec::new();
+        let mut offset = 0;
+        loop {
+            let query_string = serde_urlencoded::to_string([
+                ("status", "open".to_string()),
+                ("limit", PAGE_SIZE.to_string()),
+                ("offset", offset.to_string()),
+            ])?;
+            let page = self.signed_get(url_path, &query_string).await?;
+            let count = page.as_array().map(|a| a.len()).unwrap_or(0);
+            contracts.extend(unique_contracts(&page));
+            if count < PAGE_SIZE {
+                break;
+            }
+            offset += count;
+        }
+        contracts.sort();
+        contracts.dedup();
+
+        let mut cancelled = 0;
+        let mut failures = Vec::new();
+        for contract in contracts {
+            let query_string = serde_urlencoded::to_string([("contract", contract.as_str())])?;
+            match self
+                .signed_request("DELETE", url_path, &query_string, None)
+                .await
+            {
+                Ok(result) => cancelled += result.as_array().map(|a| a.len()).unwrap_or(0),
+                Err(e) => {
+                    error!("Failed to cancel open orders for {}: {}", contract, e);
+                    failures.push(format!("{}: {}", contract, e));
+                }
+            }
+        }
+
+        if failures.is_empty() {
+            Ok(cancelled)
+        } else {
+            Err(GateError::PartialCancel {
+                cancelled,
+                failures,
+            })
+        }
+    }
+
+    /// 撤销一个价格触发委托
+    pub async fn cancel_price_order(&self, order_id: &str, settle: &str) -> GateResult<()> {
+        let url_path = format!("/futures/{}/price_orders/{}", settle, order_id);
+        self.signed_request("DELETE", &url_path, "", None).await?;
+        Ok(())
+    }
+
+    /// 用只减仓的市价单平掉持仓
+    pub async fn close_position(&self, position: &Position, settle: &str) -> GateResult<Value> {
+        let mut order = serde_json::json!({
+            "contract": position.symbol,
+            "size": -position.size,
+            "price": "0",
+            "tif": "ioc",
+            "reduce_only": true,
+            "text": "t-gk-flatten",
+        });
+        // 双向持仓模式下用auto_size指定平哪一边，size必须为0
+        match position.mode.as_str() {
+            "dual_long" => {
+                order["size"] = 0.into();
+                order["auto_size"] = "close_long".into();
+            }
+            "dual_short" => {
+                order["size"] = 0.into();
+                order["auto_size"] = "close_short".into();
+            }
+            _ => {}
+        }
+
+        let url_path = format!("/futures/{}/orders", settle);
+        self.signed_request("POST", &url_path, "", Some(&order))
+            .await
+    }
+
+    /// 使用签名API下单：先下开仓单，成交后再创建只减仓的止盈止损触发委托
+    ///
+    /// 等待时间内没有成交的限价单直接返回，由对账任务在成交后设置止盈止损；
+    /// 止盈止损创建失败时开仓单仍然算成功，同样交给对账任务重试
+    pub async fn place_order_with_trigger_orders(
+        &self,
+        order: &OrderRequest,
+        settle: &str,
+    ) -> GateResult<OrderResponse> {
+        let mut body = serde_json::json!({
+            "contract": order.symbol,
+            "size": if order.side == "buy" { order.size.abs() } else { -order.size.abs() },
+        });
+        if order.order_type == "limit" {
+            body["price"] = Value::String(order.price.to_string());
+            body["tif"] = Value::String("gtc".to_string());
+        } else {
+            body["price"] = Value::String("0".to_string()); // 市价单
+            body["tif"] = Value::String("ioc".to_string());
+        }
+        if let Some(text) = &order.client_order_id {
+            body["text"] = Value::String(text.clone());
+        }
+
+        let url_path = format!("/futures/{}/orders", settle);
+        let raw = self
+            .signed_request("POST", &url_path, "", Some(&body))
+            .await?;
+        let order_id = find_id(&raw, &["/id"]).ok_or_else(|| {
+            GateError::InvalidResponse(format!("Invalid order response: {}", raw))
+        })?;
+        let mut response = OrderResponse {
+            success: true,
+            message: "success".to_string(),
+            order_id: Some(order_id.clone()),
+            take_profit_order_id: None,
+            stop_loss_order_id: None,
+            raw,
+        };
+
+        let filled = match self.wait_for_fill(&order_id, settle).await {
+            Ok(filled) => filled,
+            Err(e) => {
+                warn!("Failed to query order {} after placing: {}", order_id, e);
+                response.message = format!("查询成交失败，成交后由对账任务设置止盈止损: {}", e);
+                return Ok(response);
+            }
+        };
+        match filled.status.as_str() {
+            "filled" => {}
+            "cancelled" => {
+                response.success = false;
+                response.message = "订单未成交已撤销".to_string();
+                return Ok(response);
+            }
+            _ => {
+                response.message = "订单未成交，成交后由对账任务设置止盈止损".to_string();
+                return Ok(response);
+            }
+        }
+
+        let mut errors = Vec::new();
+        let legs = [("tp", order.take_profit), ("sl", order.stop_loss)];
+        for (kind, price) in legs {
+            let Some(price) = price.filter(|p| *p > 0.0) else {
+                continue;
+            };
+            let trigger = TriggerOrderRequest::exit(
+                &order.symbol,
+                &order.side,
+                filled.filled_size,
+                price,
+                kind,
+            );
+            match self.create_price_order(&trigger, settle).await {
+                Ok(id) if kind == "tp" => response.take_profit_order_id = Some(id),
+                Ok(id) => response.stop_loss_order_id = Some(id),
+                Err(e) => {
+                    error!(
+                        "Failed to create {} trigger for {}: {}",
+                        kind, order.symbol, e
+                    );
+                    errors.push(format!("{}: {}", kind, e));
+                }
+            }
+        }
+        if !errors.is_empty() {
+            response.message = format!("止盈止损设置失败，对账任务会重试: {}", errors.join("; "));
+        }
+        Ok(response)
+    }
+
+    /// 轮询订单直到不再是未成交状态，或者超过等待时间
+    async fn wait_for_fill(&self, order_id: &str, settle: &str) -> GateResult<ExchangeOrder> {
+        let deadline = Instant::now() + Duration::from_secs(FILL_WAIT_SECS);
+        loop {
+            let order = self.get_order(order_id, settle).await?;
+            if order.status != "open" || Instant::now() >= deadline {
+                return Ok(order);
+            }
+            sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    /// 创建只减仓的价格触发委托，标记价格触发后市价平仓，返回委托ID
+    pub async fn create_price_order(
+        &self,
+        order: &TriggerOrderRequest,
+        settle: &str,
+    ) -> GateResult<String> {
+        let body = serde_json::json!({
+            "initial": {
+                "contract": order.symbol,
+                "size": if order.side == "buy" { order.size } else { -order.size },
+                "price": "0",
+                "tif": "ioc",
+                "reduce_only": true,
+            },
+            "trigger": {
+                "strategy_type": 0, // 按价格触发
+                "price_type": 1,    // 标记价格
+                "price": order.trigger_price.to_string(),
+                "rule": if order.triggers_above() { 1 } else { 2 }, // 1: >= 触发价，2: <= 触发价
+            },
+            "order_type": if order.side == "sell" { "close-long-order" } else { "close-short-order" },
+        });
+
+        let url_path = format!("/futures/{}/price_orders", settle);
+        let result = self
+            .signed_request("POST", &url_path, "", Some(&body))
+            .await?;
+        find_id(&result, &["/id"]).ok_or_else(|| {
+            GateError::InvalidResponse(format!("Invalid price order response: {}", result))
+        })
     }
 
     /// 使用Web API进行止盈止损下单
@@ -321,17 +686,16 @@ impl GateService {
         &self,
         order_data: Value,
         settle: &str,
-    ) -> Result<Value> {
+    ) -> GateResult<Value> {
         let web_api_url = format!(
             "https://www.gate.com/apiw/v2/futures/{}/price_orders/order_stop_order",
             settle
         );
 
         // 检查是否有cookie和contracts（作为CSRF token）
-        let cookie_string = self
-            .cookie
-            .as_ref()
-            .ok_or_else(|| anyhow!("Cookie未设置，请确保已在gate.com上登录"))?;
+        let cookie_string = self.cookie.as_ref().ok_or_else(|| {
+            GateError::NotConfigured("Cookie未设置，请确保已在gate.com上登录".to_string())
+        })?;
 
         let csrf_token = self.set_web_credentials()?;
 
@@ -342,7 +706,7 @@ impl GateService {
         debug!("cookie: {}", cookie_string);
         debug!("Request URL: {}", web_api_url);
 
-        let response = self
+        let request = self
             .client
             .post(&web_api_url)
             .header("Content-Type", "application/json")
@@ -357,47 +721,27 @@ impl GateService {
                 "User-Agent",
                 "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/137.0.0.0 Safari/537.36"
             )
-            .body(body)
-            .send()
-            .await?;
+            .body(body);
+        let response_text = self.execute(EndpointClass::Web, request).await?;
 
-        let status = response.status();
-        let response_text = response.text().await?;
-
-        debug!(
-            "Web API止盈止损响应状态: {}, 响应内容: {}",
-            status, response_text
-        );
+        debug!("Web API止盈止损响应内容: {}", response_text);
 
-        if !status.is_success() {
-            let error_data: Result<Value, _> = serde_json::from_str(&response_text);
-            let error_message = match error_data {
-                Ok(data) => data
-                    .get("message")
-                    .or_else(|| data.get("error"))
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("未知错误")
-                    .to_string(),
-                Err(_) => "解析错误响应失败".to_string(),
-            };
-
-            return Err(anyhow!("Web API错误 ({}): {}", status, error_message));
+        let result: Value = serde_json::from_str(&response_text)
+            .map_err(|e| GateError::InvalidResponse(format!("解析响应失败: {}", e)))?;
+        if let Some(error) = GateError::from_web_body(&result) {
+            return Err(error);
         }
 
-        let result: Value =
-            serde_json::from_str(&response_text).map_err(|e| anyhow!("解析响应失败: {}", e))?;
-
         Ok(result)
     }
 
     /// 查询账户信息，同时也用来判断cookie是否过期
-    pub async fn get_account_info(&self) -> Result<(Value, bool)> {
+    pub async fn get_account_info(&self) -> GateResult<(Value, bool)> {
         let url = "https://www.gate.com/apiw/v2/futures/usdt/accounts".to_string();
         // 检查是否有cookie和contracts（作为CSRF token）
-        let cookie_string = self
-            .cookie
-            .as_ref()
-            .ok_or_else(|| anyhow!("Cookie未设置，请确保已在gate.com上登录"))?;
+        let cookie_string = self.cookie.as_ref().ok_or_else(|| {
+            GateError::NotConfigured("Cookie未设置，请确保已在gate.com上登录".to_string())
+        })?;
 
         let csrf_token = self.set_web_credentials()?;
 
@@ -405,7 +749,7 @@ impl GateService {
         debug!("cookie: {}", cookie_string);
         debug!("Request URL: {}", url);
 
-        let response = self
+        let request = self
             .client
             .get(&url)
             .header("Cache-control", "no-cache")
@@ -424,47 +768,402 @@ impl GateService {
             .header(
                 "User-Agent",
                 "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/138.0.0.0 Safari/537.36"
-            )
-            .send()
-            .await?;
+            );
+        let response_text = self.execute(EndpointClass::Web, request).await?;
 
-        let status = response.status();
-        let response_text = response.text().await?;
-
-        if !status.is_success() {
-            return Err(anyhow!("API请求失败: {} - {}", status, response_text));
+        let result: Value = serde_json::from_str(&response_text)?;
+        match GateError::from_web_body(&result) {
+            Some(error) if error.is_auth() => {
+                warn!("账户cookie信息无效或会话已过期: {}", error);
+                Ok((result, false))
+            }
+            Some(error) => Err(error),
+            None => Ok((result, true)),
         }
+    }
 
-        let result: Value = serde_json::from_str(&response_text)?;
-        // result.message 包含"not authenticated"
-        if let Some(label) = result.get("label").and_then(|m| m.as_str()) {
-            if label.contains("INVALID_CREDENTIALS") || label.contains("Login_Session_Expired") {
-                warn!("账户cookie信息无效或会话已过期");
-                return Ok((result, false));
+    /// 按接口分类限流后发送请求，返回响应内容
+    ///
+    /// 429、5xx和网络错误按指数退避重试；下单等POST请求只在确定没有被处理时（429、连接失败）重试，避免重复下单
+    async fn execute(&self, class: EndpointClass, request: RequestBuilder) -> GateResult<String> {
+        let request = request.build()?;
+        let idempotent = request.method() != Method::POST;
+        let mut attempt = 0;
+
+        loop {
+            self.limiter.acquire(class).await;
+            let attempt_request = request
+                .try_clone()
+                .ok_or_else(|| GateError::Client("Request body cannot be retried".to_string()))?;
+
+            let result = async {
+                let response = self
+                    .client
+                    .execute(attempt_request)
+                    .await
+                    .map_err(|e| GateError::from_reqwest(&e))?;
+                let status = response.status();
+                let retry_after = rate_limit_reset(response.headers());
+                let text = response
+                    .text()
+                    .await
+                    .map_err(|e| GateError::from_reqwest(&e))?;
+                if status.is_success() {
+                    Ok(text)
+                } else {
+                    Err(GateError::from_response(status, &text, retry_after))
+                }
+            }
+            .await;
+
+            let error = match result {
+                Ok(text) => return Ok(text),
+                Err(error) => error,
+            };
+            attempt += 1;
+            if attempt >= self.retry.max_attempts || !error.is_retryable(idempotent) {
+                return Err(error);
             }
+
+            let mut delay = self.retry.backoff(attempt - 1);
+            if let GateError::RateLimited {
+                retry_after: Some(retry_after),
+                ..
+            } = &error
+            {
+                delay = delay.max((*retry_after).min(self.retry.max_delay));
+            }
+            warn!(
+                "Gate request {} {} failed ({}), retrying in {:?} ({}/{})",
+                request.method(),
+                request.url().path(),
+                error,
+                delay,
+                attempt,
+                self.retry.max_attempts - 1
+            );
+            sleep(delay).await;
         }
-        Ok((result, true))
     }
 
     /// 设置从浏览器获取的完整cookie字符串和CSRF token
-    pub fn set_web_credentials(&self) -> Result<String> {
+    pub fn set_web_credentials(&self) -> GateResult<String> {
         // 从cookie中提取CSRF token
         let cookie = self
             .cookie
             .as_ref()
-            .ok_or_else(|| anyhow!("Cookie未设置"))?;
+            .ok_or_else(|| GateError::NotConfigured("Cookie未设置".to_string()))?;
 
         for cookie_pair in cookie.split(';') {
             let trimmed = cookie_pair.trim();
-            if trimmed.starts_with("csrftoken=") {
-                let csrftoken = trimmed[10..].to_string();
-                return Ok(csrftoken);
+            if let Some(csrftoken) = trimmed.strip_prefix("csrftoken=") {
+                return Ok(csrftoken.to_string());
             }
         }
-        return Err(anyhow!("无法从cookie中提取CSRF Token"));
+        Err(GateError::NotConfigured(
+            "无法从cookie中提取CSRF Token".to_string(),
+        ))
     }
 }
 
+#[async_trait]
+impl Exchange for GateService {
+    fn name(&self) -> &str {
+        "gate"
+    }
+
+    fn apply_api_key(&mut self, key: &ApiKey) {
+        self.update_credentials(&key.api_key, &key.secret_key);
+        if let Some(cookie) = &key.cookie {
+            self.set_cookie(cookie);
+        }
+        if let Some(contracts) = &key.contracts {
+            self.set_contracts(contracts);
+        }
+    }
+
+    fn has_credentials(&self) -> bool {
+        GateService::has_credentials(self)
+    }
+
+    async fn get_kline_data(
+        &self,
+        symbol: &str,
+        interval: &str,
+        limit: usize,
+        settle: &str,
+    ) -> Result<Vec<KlineData>> {
+        Ok(GateService::get_kline_data(self, symbol, interval, limit, settle).await?)
+    }
+
+    async fn get_kline_data_range(
+        &self,
+        symbol: &str,
+        interval: &str,
+        from: i64,
+        to: i64,
+        settle: &str,
+    ) -> Result<Vec<KlineData>> {
+        Ok(GateService::get_kline_data_range(self, symbol, interval, from, to, settle).await?)
+    }
+
+    async fn get_contracts(&self, settle: &str) -> Result<Vec<Value>> {
+        Ok(GateService::get_contracts(self, settle).await?)
+    }
+
+    async fn place_order(&self, order: &OrderRequest, settle: &str) -> Result<OrderResponse> {
+        if order.execution_mode == "api" {
+            return Ok(self.place_order_with_trigger_orders(order, settle).await?);
+        }
+
+        let mut order_data = build_order_data(
+            &order.symbol,
+            &order.order_type,
+            &order.side,
+            order.price,
+            order.size,
+            order.take_profit,
+            order.stop_loss,
+        );
+        if let Some(text) = &order.client_order_id {
+            order_data["order"]["text"] = Value::String(text.clone());
+        }
+
+        let raw = self
+            .place_order_with_stop_profit_loss(order_data, settle)
+            .await?;
+
+        // Web API 返回 {"code": 200, "message": "..."}，code不为200表示下单失败
+        let success = raw.get("code").cloned() == Some(200.into());
+        let message = raw
+            .get("message")
+            .and_then(|m| m.as_str())
+            .unwrap_or(if success { "success" } else { "未知错误" })
+            .to_string();
+
+        // 响应中的订单ID格式不固定，取不到时由对账任务通过text查询
+        let data = raw.get("data").unwrap_or(&Value::Null);
+        Ok(OrderResponse {
+            success,
+            message,
+            order_id: find_id(data, &["/id", "/order_id", "/order/id"]),
+            take_profit_order_id: find_id(data, &["/stop_profit_id", "/stop_profit/id"]),
+            stop_loss_order_id: find_id(data, &["/stop_loss_id", "/stop_loss/id"]),
+            raw,
+        })
+    }
+
+    async fn place_trigger_order(
+        &self,
+        order: &TriggerOrderRequest,
+        settle: &str,
+    ) -> Result<String> {
+        Ok(GateService::create_price_order(self, order, settle).await?)
+    }
+
+    async fn cancel_price_order(&self, order_id: &str, settle: &str) -> Result<()> {
+        Ok(GateService::cancel_price_order(self, order_id, settle).await?)
+    }
+
+    async fn get_order(&self, order_id: &str, settle: &str) -> Result<ExchangeOrder> {
+        Ok(GateService::get_order(self, order_id, settle).await?)
+    }
+
+    async fn get_price_orders(&self, symbol: &str, settle: &str) -> Result<Vec<PriceOrder>> {
+        Ok(GateService::get_price_orders(self, symbol, settle).await?)
+    }
+
+    async fn get_positions(&self, settle: &str) -> Result<Vec<Position>> {
+        Ok(GateService::get_positions(self, settle).await?)
+    }
+
+    async fn cancel_all_orders(&self, settle: &str) -> Result<usize> {
+        Ok(GateService::cancel_all_orders(self, settle).await?)
+    }
+
+    async fn cancel_all_price_orders(&self, settle: &str) -> Result<usize> {
+        Ok(GateService::cancel_all_price_orders(self, settle).await?)
+    }
+
+    async fn close_position(&self, position: &Position, settle: &str) -> Result<OrderResponse> {
+        let raw = GateService::close_position(self, position, settle).await?;
+        Ok(OrderResponse {
+            success: true,
+            message: "success".to_string(),
+            order_id: find_id(&raw, &["/id"]),
+            take_profit_order_id: None,
+            stop_loss_order_id: None,
+            raw,
+        })
+    }
+
+    async fn get_account_info(&self) -> Result<AccountInfo> {
+        let (raw, valid) = GateService::get_account_info(self).await?;
+        Ok(AccountInfo {
+            valid,
+            equity: valid.then(|| parse_account_equity(&raw)).flatten(),
+            raw,
+        })
+    }
+}
+
+/// 按JSON Pointer依次查找ID，数字和字符串都转成字符串
+fn find_id(data: &Value, pointers: &[&str]) -> Option<String> {
+    pointers
+        .iter()
+        .filter_map(|p| data.pointer(p))
+        .find_map(|v| match v {
+            Value::Number(n) => Some(n.to_string()),
+            Value::String(s) if !s.is_empty() => Some(s.clone()),
+            _ => None,
+        })
+}
+
+/// 从订单列表中取出不重复的合约名称
+fn unique_contracts(orders: &Value) -> Vec<String> {
+    let mut contracts: Vec<String> = orders
+        .as_array()
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|o| o.get("contract").and_then(|c| c.as_str()))
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default();
+    contracts.sort();
+    contracts.dedup();
+    contracts
+}
+
+/// 429响应的 `X-Gate-RateLimit-Reset-Timestamp` 头是限制重置的毫秒时间戳
+fn rate_limit_reset(headers: &HeaderMap) -> Option<Duration> {
+    let reset_ms = headers
+        .get("X-Gate-RateLimit-Reset-Timestamp")?
+        .to_str()
+        .ok()?
+        .parse::<u64>()
+        .ok()?;
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_millis() as u64;
+    Some(Duration::from_millis(reset_ms.saturating_sub(now_ms)))
+}
+
+fn parse_f64(value: Option<&Value>) -> Option<f64> {
+    match value? {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+/// 解析 `/futures/{settle}/orders/{order_id}` 的响应
+fn parse_order(data: &Value) -> GateResult<ExchangeOrder> {
+    let id = find_id(data, &["/id"])
+        .ok_or_else(|| GateError::InvalidResponse("Invalid order id".to_string()))?;
+    let size = data
+        .get("size")
+        .and_then(|v| v.as_i64())
+        .ok_or_else(|| GateError::InvalidResponse("Invalid order size".to_string()))?;
+    let left = data.get("left").and_then(|v| v.as_i64()).unwrap_or(0);
+    let filled_size = (size - left).abs();
+
+    let status = match data.get("status").and_then(|v| v.as_str()) {
+        Some("finished") => {
+            // 部分成交后撤单也算成交，撤单前的成交已经形成持仓
+            if filled_size > 0 {
+                "filled"
+            } else {
+                "cancelled"
+            }
+        }
+        _ => "open",
+    };
+
+    // 市价单（ioc）是吃单，其余按挂单费率估算
+    let is_taker = data.get("tif").and_then(|v| v.as_str()) == Some("ioc");
+    let fee_rate = parse_f64(data.get(if is_taker { "tkfr" } else { "mkfr" })).unwrap_or(0.0);
+
+    Ok(ExchangeOrder {
+        id,
+        text: data.get("text").and_then(|v| v.as_str()).map(String::from),
+        status: status.to_string(),
+        size: size.abs(),
+        filled_size,
+        fill_price: parse_f64(data.get("fill_price")).filter(|p| *p > 0.0),
+        fee_rate,
+    })
+}
+
+/// 解析 `/futures/{settle}/price_orders` 中的一个触发委托
+fn parse_price_order(data: &Value) -> GateResult<PriceOrder> {
+    let id = find_id(data, &["/id"])
+        .ok_or_else(|| GateError::InvalidResponse("Invalid price order id".to_string()))?;
+    let status = match (
+        data.get("status").and_then(|v| v.as_str()),
+        data.get("finish_as").and_then(|v| v.as_str()),
+    ) {
+        (Some("open") | Some("inactive"), _) => "open",
+        (_, Some("succeeded")) => "triggered",
+        (_, Some("cancelled") | Some("expired")) => "cancelled",
+        _ => "failed",
+    };
+
+    Ok(PriceOrder {
+        id,
+        status: status.to_string(),
+        trigger_price: parse_f64(data.pointer("/trigger/price"))
+            .ok_or_else(|| GateError::InvalidResponse("Invalid trigger price".to_string()))?,
+        order_type: data
+            .get("order_type")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string(),
+        trade_id: find_id(data, &["/trade_id"]).filter(|id| id != "0"),
+        create_time: parse_f64(data.get("create_time")).unwrap_or(0.0) as i64,
+    })
+}
+
+/// 从合约账户信息中计算权益：账户余额加未实现盈亏
+fn parse_account_equity(raw: &Value) -> Option<f64> {
+    let account = match raw.get("data") {
+        Some(Value::Array(items)) => items.first()?,
+        Some(data) => data,
+        None => raw,
+    };
+    let total = parse_f64(account.get("total"))?;
+    Some(total + parse_f64(account.get("unrealised_pnl")).unwrap_or(0.0))
+}
+
+/// 解析 `/futures/{settle}/positions` 中的一个持仓
+fn parse_position(data: &Value) -> GateResult<Position> {
+    let symbol = data
+        .get("contract")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| GateError::InvalidResponse("Invalid position contract".to_string()))?;
+
+    Ok(Position {
+        symbol: symbol.to_string(),
+        mode: data
+            .get("mode")
+            .and_then(|v| v.as_str())
+            .unwrap_or("single")
+            .to_string(),
+        size: data
+            .get("size")
+            .and_then(|v| v.as_i64())
+            .ok_or_else(|| GateError::InvalidResponse("Invalid position size".to_string()))?,
+        entry_price: parse_f64(data.get("entry_price")).unwrap_or(0.0),
+        mark_price: parse_f64(data.get("mark_price")).unwrap_or(0.0),
+        unrealized_pnl: parse_f64(data.get("unrealised_pnl")).unwrap_or(0.0),
+        realized_pnl: parse_f64(data.get("realised_pnl")).unwrap_or(0.0),
+        leverage: parse_f64(data.get("leverage")).unwrap_or(0.0),
+        liq_price: parse_f64(data.get("liq_price")).filter(|p| *p > 0.0),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use tracing::info;
@@ -481,4 +1180,46 @@ mod tests {
         assert!(result.is_ok());
         assert!(!result.unwrap().1);
     }
+
+    #[test]
+    fn test_parse_responses() {
+        let order = parse_order(&serde_json::json!({
+            "id": 15675394, "text": "t-gk-1", "status": "finished", "finish_as": "filled",
+            "size": -10, "left": 0, "fill_price": "0.1234", "tif": "ioc",
+            "tkfr": "0.0005", "mkfr": "0.0002"
+        }))
+        .unwrap();
+        assert_eq!(order.id, "15675394");
+        assert_eq!(order.status, "filled");
+        assert_eq!(order.size, 10);
+        assert_eq!(order.filled_size, 10);
+        assert_eq!(order.fill_price, Some(0.1234));
+        assert_eq!(order.fee_rate, 0.0005);
+
+        let price_order = parse_price_order(&serde_json::json!({
+            "id": 1283293, "status": "finished", "finish_as": "succeeded",
+            "trigger": {"price": "0.15"}, "order_type": "close-short-position",
+            "trade_id": 2983274, "create_time": 1700000000.5
+        }))
+        .unwrap();
+        assert_eq!(price_order.status, "triggered");
+        assert_eq!(price_order.trigger_price, 0.15);
+        assert_eq!(price_order.trade_id.as_deref(), Some("2983274"));
+
+        let position = parse_position(&serde_json::json!({
+            "contract": "BTC_USDT", "mode": "single", "size": -3, "leverage": "10",
+            "entry_price": "65000.1", "mark_price": "64900", "unrealised_pnl": "0.3",
+            "realised_pnl": "-0.02", "liq_price": "0"
+        }))
+        .unwrap();
+        assert_eq!(position.size, -3);
+        assert_eq!(position.entry_price, 65000.1);
+        assert_eq!(position.leverage, 10.0);
+        assert_eq!(position.liq_price, None);
+
+        let equity = parse_account_equity(&serde_json::json!({
+            "code": 200, "data": {"total": "1000.5", "unrealised_pnl": "-0.5", "currency": "USDT"}
+        }));
+        assert_eq!(equity, Some(1000.0));
+    }
 }
@@ -0,0 +1,345 @@
+use reqwest::StatusCode;
+use serde_json::Value;
+use std::fmt;
+use tokio::time::Duration;
+
+pub type GateResult<T> = std::result::Result<T, GateError>;
+
+/// Gate.io 接口错误，按响应中的 `label`/`message` 和HTTP状态码分类
+///
+/// v4 API 和网页端 apiw/v2 API 的错误都解析成这个类型；`Exchange` 的方法返回 `anyhow::Error`，
+/// 调用方用 `downcast_ref::<GateError>()` 取出
+#[derive(Debug, Clone, PartialEq)]
+pub enum GateError {
+    /// 没有配置API Key、Cookie等
+    NotConfigured(String),
+    /// API Key无效、签名错误、IP不在白名单、没有权限等
+    Auth {
+        label: Option<String>,
+        message: String,
+    },
+    /// 网页端登录会话过期，需要重新获取cookie
+    SessionExpired(String),
+    /// 余额或保证金不足
+    InsufficientBalance(String),
+    /// 价格或数量不符合合约的精度要求
+    InvalidPrecision(String),
+    /// 429 请求过于频繁，`retry_after` 为交易所返回的限制重置时间
+    RateLimited {
+        retry_after: Option<Duration>,
+        message: String,
+    },
+    /// 5xx 交易所服务错误
+    Server { status: StatusCode, message: String },
+    /// 连接失败，请求没有发出
+    Connect(String),
+    /// 超时或连接中断，请求可能已经被交易所处理
+    Network(String),
+    /// 其它请求错误，包括没有识别的 `label`，重试也不会成功
+    Request {
+        status: StatusCode,
+        label: Option<String>,
+        message: String,
+    },
+    /// 响应内容无法解析
+    InvalidResponse(String),
+    /// 本地构造请求失败
+    Client(String),
+    /// 批量撤单时部分合约撤销失败，`cancelled` 为成功撤销的数量
+    PartialCancel {
+        cancelled: usize,
+        failures: Vec<String>,
+    },
+}
+
+impl GateError {
+    /// 根据HTTP状态码和响应内容分类
+    pub fn from_response(status: StatusCode, body: &str, retry_after: Option<Duration>) -> Self {
+        let (label, message) = match serde_json::from_str::<Value>(body) {
+            Ok(data) => label_and_message(&data),
+            Err(_) => (None, body.to_string()),
+        };
+
+        if status == StatusCode::TOO_MANY_REQUESTS || label.as_deref() == Some("TOO_MANY_REQUESTS")
+        {
+            return Self::RateLimited {
+                retry_after,
+                message,
+            };
+        }
+        let known = match label.as_deref() {
+            Some(label) => Self::from_label(label, &message),
+            None => Self::from_message(&message),
+        };
+        if let Some(error) = known {
+            return error;
+        }
+        if status.is_server_error() {
+            Self::Server { status, message }
+        } else if matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) {
+            Self::Auth { label, message }
+        } else {
+            Self::Request {
+                status,
+                label,
+                message,
+            }
+        }
+    }
+
+    /// 网页端API出错时HTTP状态码也可能是200，错误放在响应的 `label` 或非200的 `code` 中
+    pub fn from_web_body(data: &Value) -> Option<Self> {
+        let has_label = data
+            .get("label")
+            .and_then(|v| v.as_str())
+            .is_some_and(|l| !l.is_empty());
+        let failed_code = data
+            .get("code")
+            .and_then(|v| v.as_i64())
+            .is_some_and(|code| code != 0 && code != 200);
+        if !has_label && !failed_code {
+            return None;
+        }
+        Some(Self::from_response(StatusCode::OK, &data.to_string(), None))
+    }
+
+    /// 已知的 `label`，没有识别的返回None
+    fn from_label(label: &str, message: &str) -> Option<Self> {
+        let message = message.to_string();
+        let error = match label {
+            "INVALID_KEY"
+            | "INVALID_SIGNATURE"
+            | "INVALID_CREDENTIALS"
+            | "IP_FORBIDDEN"
+            | "READ_ONLY"
+            | "FORBIDDEN"
+            | "ACCOUNT_LOCKED"
+            | "REQUEST_EXPIRED"
+            | "MISSING_REQUIRED_HEADER" => Self::Auth {
+                label: Some(label.to_string()),
+                message,
+            },
+            "Login_Session_Expired" | "NOT_LOGIN" => Self::SessionExpired(message),
+            "BALANCE_NOT_ENOUGH" | "INSUFFICIENT_AVAILABLE" | "MARGIN_BALANCE_NOT_ENOUGH" => {
+                Self::InsufficientBalance(message)
+            }
+            "INVALID_PRECISION" | "PRICE_TOO_DEVIATED" | "INVALID_PRICE" | "SIZE_TOO_SMALL" => {
+                Self::InvalidPrecision(message)
+            }
+            _ => return None,
+        };
+        Some(error)
+    }
+
+    /// 网页端API的错误经常没有 `label`，按错误信息识别
+    fn from_message(message: &str) -> Option<Self> {
+        let lower = message.to_lowercase();
+        let contains = |keywords: &[&str]| keywords.iter().any(|k| lower.contains(k));
+        if contains(&[
+            "not authenticated",
+            "session expired",
+            "请登录",
+            "登录已过期",
+        ]) {
+            Some(Self::SessionExpired(message.to_string()))
+        } else if contains(&[
+            "balance not enough",
+            "insufficient",
+            "余额不足",
+            "保证金不足",
+        ]) {
+            Some(Self::InsufficientBalance(message.to_string()))
+        } else if contains(&["precision", "精度"]) {
+            Some(Self::InvalidPrecision(message.to_string()))
+        } else {
+            None
+        }
+    }
+
+    pub fn from_reqwest(error: &reqwest::Error) -> Self {
+        if error.is_builder() {
+            Self::Client(error.to_string())
+        } else if error.is_connect() {
+            Self::Connect(error.to_string())
+        } else {
+            Self::Network(error.to_string())
+        }
+    }
+
+    /// 稍后重试可能成功的错误
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Self::RateLimited { .. } | Self::Server { .. } | Self::Connect(_) | Self::Network(_)
+        )
+    }
+
+    /// 是否可以自动重试；非幂等请求（下单）只在确定没有被处理时重试
+    pub fn is_retryable(&self, idempotent: bool) -> bool {
+        match self {
+            Self::RateLimited { .. } | Self::Connect(_) => true,
+            Self::Server { .. } | Self::Network(_) => idempotent,
+            _ => false,
+        }
+    }
+
+    /// API Key或cookie失效，需要人工更新
+    pub fn is_auth(&self) -> bool {
+        matches!(self, Self::Auth { .. } | Self::SessionExpired(_))
+    }
+}
+
+impl fmt::Display for GateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let with_label = |label: &Option<String>, message: &str| match label {
+            Some(label) => format!("{}: {}", label, message),
+            None => message.to_string(),
+        };
+        match self {
+            Self::NotConfigured(message) => write!(f, "{}", message),
+            Self::Auth { label, message } => {
+                write!(
+                    f,
+                    "API authentication failed: {}",
+                    with_label(label, message)
+                )
+            }
+            Self::SessionExpired(message) => write!(f, "Web session expired: {}", message),
+            Self::InsufficientBalance(message) => write!(f, "Insufficient balance: {}", message),
+            Self::InvalidPrecision(message) => write!(f, "Invalid precision: {}", message),
+            Self::RateLimited { message, .. } => write!(f, "API rate limited: {}", message),
+            Self::Server { status, message } => {
+                write!(f, "API request failed: {} - {}", status, message)
+            }
+            Self::Connect(message) => write!(f, "API connection failed: {}", message),
+            Self::Network(message) => write!(f, "API network error: {}", message),
+            Self::Request {
+                status,
+                label,
+                message,
+            } => write!(
+                f,
+                "API request failed: {} - {}",
+                status,
+                with_label(label, message)
+            ),
+            Self::InvalidResponse(message) => write!(f, "Invalid response: {}", message),
+            Self::Client(message) => write!(f, "Failed to build request: {}", message),
+            Self::PartialCancel {
+                cancelled,
+                failures,
+            } => write!(
+                f,
+                "Cancelled {} orders, failed for {}",
+                cancelled,
+                failures.join("; ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GateError {}
+
+impl From<reqwest::Error> for GateError {
+    fn from(error: reqwest::Error) -> Self {
+        Self::from_reqwest(&error)
+    }
+}
+
+impl From<serde_json::Error> for GateError {
+    fn from(error: serde_json::Error) -> Self {
+        Self::InvalidResponse(error.to_string())
+    }
+}
+
+impl From<serde_urlencoded::ser::Error> for GateError {
+    fn from(error: serde_urlencoded::ser::Error) -> Self {
+        Self::Client(error.to_string())
+    }
+}
+
+impl From<std::time::SystemTimeError> for GateError {
+    fn from(error: std::time::SystemTimeError) -> Self {
+        Self::Client(error.to_string())
+    }
+}
+
+/// v4 API 的错误格式为 {"label": "...", "message": "..."}，网页端还可能只有 `message` 或 `msg`
+fn label_and_message(data: &Value) -> (Option<String>, String) {
+    let label = data
+        .get("label")
+        .and_then(|v| v.as_str())
+        .filter(|l| !l.is_empty())
+        .map(String::from);
+    let message = ["message", "msg", "detail"]
+        .iter()
+        .find_map(|key| data.get(*key).and_then(|v| v.as_str()))
+        .map(String::from)
+        .unwrap_or_else(|| data.to_string());
+    (label, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_from_response() {
+        let error = GateError::from_response(
+            StatusCode::BAD_REQUEST,
+            r#"{"label":"INVALID_PARAM_VALUE","message":"invalid argument"}"#,
+            None,
+        );
+        assert_eq!(
+            error.to_string(),
+            "API request failed: 400 Bad Request - INVALID_PARAM_VALUE: invalid argument"
+        );
+        assert!(!error.is_transient());
+
+        let error = GateError::from_response(
+            StatusCode::BAD_REQUEST,
+            r#"{"label":"BALANCE_NOT_ENOUGH","message":"balance not enough"}"#,
+            None,
+        );
+        assert_eq!(
+            error,
+            GateError::InsufficientBalance("balance not enough".into())
+        );
+        let error = GateError::from_response(
+            StatusCode::UNAUTHORIZED,
+            r#"{"label":"INVALID_SIGNATURE","message":"Signature mismatch"}"#,
+            None,
+        );
+        assert!(error.is_auth());
+        assert!(GateError::from_response(StatusCode::FORBIDDEN, "<html>", None).is_auth());
+
+        let error = GateError::from_response(StatusCode::TOO_MANY_REQUESTS, "", None);
+        assert!(error.is_retryable(false));
+        let error = GateError::from_response(StatusCode::BAD_GATEWAY, "<html>", None);
+        assert!(error.is_retryable(true));
+        assert!(!error.is_retryable(false));
+    }
+
+    #[test]
+    fn test_from_web_body() {
+        assert_eq!(
+            GateError::from_web_body(&json!({"code": 200, "message": "success", "data": {}})),
+            None
+        );
+        let error = GateError::from_web_body(
+            &json!({"label": "Login_Session_Expired", "message": "not authenticated"}),
+        )
+        .unwrap();
+        assert_eq!(error, GateError::SessionExpired("not authenticated".into()));
+        let error = GateError::from_web_body(&json!({"code": 1001, "message": "订单价格精度错误"}))
+            .unwrap();
+        assert_eq!(
+            error,
+            GateError::InvalidPrecision("订单价格精度错误".into())
+        );
+        let error =
+            GateError::from_web_body(&json!({"code": 1002, "message": "系统繁忙"})).unwrap();
+        assert!(matches!(error, GateError::Request { .. }));
+    }
+}
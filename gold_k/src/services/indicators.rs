@@ -0,0 +1,448 @@
+use crate::models::{KlineData, MonitorConfig};
+use crate::services::stop_manager::ATR_PERIOD;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+/// 布林带的标准差倍数
+pub const BB_STD_DEV: f64 = 2.0;
+/// MACD的快线、慢线和信号线周期
+pub const MACD_FAST: usize = 12;
+pub const MACD_SLOW: usize = 26;
+pub const MACD_SIGNAL: usize = 9;
+
+/// 简单移动平均：最后 `period` 个值的平均值，数量不足时返回None
+pub fn sma(values: &[f64], period: usize) -> Option<f64> {
+    if period == 0 || values.len() < period {
+        return None;
+    }
+    Some(values[values.len() - period..].iter().sum::<f64>() / period as f64)
+}
+
+/// 指数移动平均序列，用前 `period` 个值的SMA作为初始值，结果从第 `period` 个值开始
+fn ema_series(values: &[f64], period: usize) -> Vec<f64> {
+    let Some(seed) = sma(&values[..period.min(values.len())], period) else {
+        return Vec::new();
+    };
+    let alpha = 2.0 / (period as f64 + 1.0);
+    let mut series = Vec::with_capacity(values.len() - period + 1);
+    series.push(seed);
+    for value in &values[period..] {
+        let previous = series[series.len() - 1];
+        series.push(previous + alpha * (value - previous));
+    }
+    series
+}
+
+/// 指数移动平均的最新值，数量不足时返回None
+pub fn ema(values: &[f64], period: usize) -> Option<f64> {
+    ema_series(values, period).last().copied()
+}
+
+/// 相对强弱指数（Wilder平滑），需要 `period + 1` 个收盘价
+pub fn rsi(closes: &[f64], period: usize) -> Option<f64> {
+    if period == 0 || closes.len() < period + 1 {
+        return None;
+    }
+    let changes: Vec<f64> = closes.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    let (mut gain, mut loss) = changes[..period]
+        .iter()
+        .fold((0.0, 0.0), |(gain, loss), change| {
+            (gain + change.max(0.0), loss + (-change).max(0.0))
+        });
+    gain /= period as f64;
+    loss /= period as f64;
+    for change in &changes[period..] {
+        gain = (gain * (period - 1) as f64 + change.max(0.0)) / period as f64;
+        loss = (loss * (period - 1) as f64 + (-change).max(0.0)) / period as f64;
+    }
+    if loss == 0.0 {
+        return Some(if gain == 0.0 { 50.0 } else { 100.0 });
+    }
+    Some(100.0 - 100.0 / (1.0 + gain / loss))
+}
+
+/// 平均真实波幅：最近 `period` 根K线真实波幅的平均值，K线按时间升序，数量不足时返回None
+pub fn atr(klines: &[KlineData], period: usize) -> Option<f64> {
+    if period == 0 || klines.len() < period + 1 {
+        return None;
+    }
+    let recent = &klines[klines.len() - period - 1..];
+    let total: f64 = recent
+        .windows(2)
+        .map(|pair| {
+            let previous_close = pair[0].close;
+            let kline = &pair[1];
+            (kline.high - kline.low)
+                .max((kline.high - previous_close).abs())
+                .max((kline.low - previous_close).abs())
+        })
+        .sum();
+    Some(total / period as f64)
+}
+
+/// 布林带
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bands {
+    pub middle: f64,
+    pub upper: f64,
+    pub lower: f64,
+}
+
+/// 布林带：中轨为SMA，上下轨为中轨加减 `std_dev` 倍的总体标准差
+pub fn bollinger(closes: &[f64], period: usize, std_dev: f64) -> Option<Bands> {
+    let middle = sma(closes, period)?;
+    let variance = closes[closes.len() - period..]
+        .iter()
+        .map(|close| (close - middle).powi(2))
+        .sum::<f64>()
+        / period as f64;
+    let width = variance.sqrt() * std_dev;
+    Some(Bands {
+        middle,
+        upper: middle + width,
+        lower: middle - width,
+    })
+}
+
+/// MACD线、信号线和柱
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Macd {
+    pub macd: f64,
+    pub signal: f64,
+    pub histogram: f64,
+}
+
+/// MACD：快慢EMA之差，信号线为差值的EMA，需要 `slow + signal - 1` 个收盘价
+pub fn macd(closes: &[f64], fast: usize, slow: usize, signal: usize) -> Option<Macd> {
+    if fast == 0 || fast >= slow {
+        return None;
+    }
+    let fast_series = ema_series(closes, fast);
+    let slow_series = ema_series(closes, slow);
+    // 两个序列都对齐到最后一个收盘价
+    let offset = fast_series.len().checked_sub(slow_series.len())?;
+    let diff: Vec<f64> = slow_series
+        .iter()
+        .zip(&fast_series[offset..])
+        .map(|(slow, fast)| fast - slow)
+        .collect();
+    let macd = *diff.last()?;
+    let signal = ema(&diff, signal)?;
+    Some(Macd {
+        macd,
+        signal,
+        histogram: macd - signal,
+    })
+}
+
+/// 成交量加权平均价，价格取 (最高 + 最低 + 收盘) / 3，没有成交量时返回None
+pub fn vwap(klines: &[KlineData]) -> Option<f64> {
+    let (value, volume) = klines.iter().fold((0.0, 0.0), |(value, volume), k| {
+        (
+            value + (k.high + k.low + k.close) / 3.0 * k.volume,
+            volume + k.volume,
+        )
+    });
+    (volume > 0.0).then(|| value / volume)
+}
+
+/// 一个指标过滤条件的结果，和信号一起保存
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FilterResult {
+    pub name: String,
+    pub value: Option<f64>, // 指标值，K线不足时为空
+    pub threshold: Option<f64>,
+    pub passed: bool,
+}
+
+impl FilterResult {
+    fn new(name: String, value: Option<f64>, threshold: Option<f64>, passed: bool) -> Self {
+        Self {
+            name,
+            value,
+            threshold,
+            passed,
+        }
+    }
+}
+
+/// 指标过滤需要的K线数量（含最新一根），没有启用时为0；EMA类指标多取一倍的K线预热
+pub fn required_klines(config: &MonitorConfig) -> usize {
+    let mut required = 0;
+    if config.trend_ema_period > 0 {
+        required = required.max(config.trend_ema_period as usize * 2);
+    }
+    if config.rsi_period > 0 {
+        required = required.max(config.rsi_period as usize * 2 + 1);
+    }
+    if config.min_atr > 0.0 {
+        required = required.max(ATR_PERIOD + 1);
+    }
+    if config.bb_period > 0 {
+        required = required.max(config.bb_period as usize);
+    }
+    if config.macd_confirm {
+        required = required.max((MACD_SLOW + MACD_SIGNAL) * 2);
+    }
+    required
+}
+
+/// 检查配置的指标过滤条件，`klines` 按时间升序，最后一根为信号K线，`vwap_klines` 为计算VWAP的根数
+///
+/// 指标数据不足时视为不通过，没有启用任何条件时返回空数组
+pub fn apply_filters(
+    config: &MonitorConfig,
+    direction: &str,
+    klines: &[KlineData],
+    vwap_klines: usize,
+) -> Vec<FilterResult> {
+    let Some(latest) = klines.last() else {
+        return Vec::new();
+    };
+    let close = latest.close;
+    let long = direction == "long";
+    let closes: Vec<f64> = klines.iter().map(|k| k.close).collect();
+    let mut results = Vec::new();
+
+    if config.trend_ema_period > 0 {
+        let value = ema(&closes, config.trend_ema_period as usize);
+        let passed = value.is_some_and(|ema| if long { close > ema } else { close < ema });
+        results.push(FilterResult::new(
+            format!("EMA({})", config.trend_ema_period),
+            value,
+            value,
+            passed,
+        ));
+    }
+
+    if config.rsi_period > 0 && (config.rsi_overbought > 0.0 || config.rsi_oversold > 0.0) {
+        let value = rsi(&closes, config.rsi_period as usize);
+        // 做多时检查超买，做空时检查超卖，对应的阈值为0时不限制
+        let (threshold, passed) = if long {
+            let bound = config.rsi_overbought;
+            (bound, bound <= 0.0 || value.is_some_and(|rsi| rsi <= bound))
+        } else {
+            let bound = config.rsi_oversold;
+            (bound, bound <= 0.0 || value.is_some_and(|rsi| rsi >= bound))
+        };
+        results.push(FilterResult::new(
+            format!("RSI({})", config.rsi_period),
+            value,
+            (threshold > 0.0).then_some(threshold),
+            passed,
+        ));
+    }
+
+    if config.min_atr > 0.0 {
+        let value = atr(klines, ATR_PERIOD);
+        results.push(FilterResult::new(
+            format!("ATR({})", ATR_PERIOD),
+            value,
+            Some(config.min_atr),
+            value.is_some_and(|atr| atr >= config.min_atr),
+        ));
+    }
+
+    if config.bb_period > 0 {
+        let bands = bollinger(&closes, config.bb_period as usize, BB_STD_DEV);
+        let threshold = bands.map(|b| if long { b.upper } else { b.lower });
+        let passed = threshold.is_some_and(|band| if long { close <= band } else { close >= band });
+        results.push(FilterResult::new(
+            format!("BB({})", config.bb_period),
+            Some(close),
+            threshold,
+            passed,
+        ));
+    }
+
+    if config.macd_confirm {
+        let value = macd(&closes, MACD_FAST, MACD_SLOW, MACD_SIGNAL).map(|m| m.histogram);
+        let passed = value.is_some_and(|h| if long { h > 0.0 } else { h < 0.0 });
+        results.push(FilterResult::new(
+            format!("MACD({},{},{})", MACD_FAST, MACD_SLOW, MACD_SIGNAL),
+            value,
+            Some(0.0),
+            passed,
+        ));
+    }
+
+    if config.vwap_confirm {
+        let value = vwap(&klines[klines.len().saturating_sub(vwap_klines.max(1))..]);
+        let passed = value.is_some_and(|vwap| if long { close > vwap } else { close < vwap });
+        results.push(FilterResult::new("VWAP".to_string(), value, value, passed));
+    }
+
+    results
+}
+
+/// 没有通过的过滤条件，用于记录拦截原因
+pub fn failed_filters(results: &[FilterResult]) -> Option<String> {
+    let failed: Vec<&str> = results
+        .iter()
+        .filter(|r| !r.passed)
+        .map(|r| r.name.as_str())
+        .collect();
+    (!failed.is_empty()).then(|| format!("指标过滤未通过: {}", failed.join(", ")))
+}
+
+/// 保存配置时检查指标过滤参数
+pub fn validate_filters(config: &MonitorConfig) -> Result<()> {
+    if config.trend_ema_period < 0 || config.rsi_period < 0 || config.bb_period < 0 {
+        return Err(anyhow!("指标周期不能为负数"));
+    }
+    if config.bb_period == 1 {
+        return Err(anyhow!("布林带周期至少为2"));
+    }
+    for bound in [config.rsi_overbought, config.rsi_oversold] {
+        if !(0.0..=100.0).contains(&bound) {
+            return Err(anyhow!("RSI阈值必须在0到100之间"));
+        }
+    }
+    if config.rsi_overbought > 0.0
+        && config.rsi_oversold > 0.0
+        && config.rsi_oversold >= config.rsi_overbought
+    {
+        return Err(anyhow!("RSI超卖阈值必须小于超买阈值"));
+    }
+    if (config.rsi_overbought > 0.0 || config.rsi_oversold > 0.0) && config.rsi_period == 0 {
+        return Err(anyhow!("设置RSI阈值时需要设置RSI周期"));
+    }
+    if config.min_atr < 0.0 {
+        return Err(anyhow!("最小ATR不能为负数"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kline(high: f64, low: f64, close: f64, volume: f64) -> KlineData {
+        KlineData {
+            timestamp: 0,
+            open: close,
+            high,
+            low,
+            close,
+            volume,
+        }
+    }
+
+    #[test]
+    fn test_moving_averages() {
+        let values = [1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_eq!(sma(&values, 3), Some(4.0));
+        assert_eq!(sma(&values, 6), None);
+        // 初始值为前3个的SMA=2，alpha=0.5
+        assert_eq!(ema(&values, 3), Some(4.0));
+        assert_eq!(ema(&[2.0, 2.0, 2.0, 8.0], 3), Some(5.0));
+        assert_eq!(ema(&values, 0), None);
+    }
+
+    #[test]
+    fn test_rsi() {
+        let rising: Vec<f64> = (0..20).map(|i| i as f64).collect();
+        assert_eq!(rsi(&rising, 14), Some(100.0));
+        assert_eq!(rsi(&rising[..14], 14), None);
+        // 涨跌幅度相同时为50
+        let zigzag: Vec<f64> = (0..15).map(|i| (i % 2) as f64).collect();
+        let value = rsi(&zigzag, 14).unwrap();
+        assert!((value - 50.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_atr() {
+        let klines = vec![
+            kline(11.0, 9.0, 10.0, 0.0),
+            kline(12.0, 10.0, 11.0, 0.0),
+            // 跳空：真实波幅取 |最高价 - 前收盘价|
+            kline(15.0, 14.0, 14.5, 0.0),
+            kline(14.0, 13.0, 13.5, 0.0),
+        ];
+        assert_eq!(atr(&klines, 4), None);
+        // 真实波幅 2, 4, 1.5
+        assert_eq!(atr(&klines, 3), Some(7.5 / 3.0));
+        assert_eq!(atr(&klines, 1), Some(1.5));
+    }
+
+    #[test]
+    fn test_bollinger_macd_vwap() {
+        let bands = bollinger(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0], 8, 2.0).unwrap();
+        assert_eq!(
+            bands,
+            Bands {
+                middle: 5.0,
+                upper: 9.0,
+                lower: 1.0
+            }
+        );
+
+        let rising: Vec<f64> = (0..60).map(|i| 100.0 + i as f64).collect();
+        let m = macd(&rising, MACD_FAST, MACD_SLOW, MACD_SIGNAL).unwrap();
+        assert!(m.macd > 0.0);
+        assert!(m.histogram.abs() < 1e-9);
+        assert_eq!(macd(&rising[..33], MACD_FAST, MACD_SLOW, MACD_SIGNAL), None);
+        assert!(macd(&rising[..34], MACD_FAST, MACD_SLOW, MACD_SIGNAL).is_some());
+
+        let klines = [kline(12.0, 9.0, 9.0, 1.0), kline(22.0, 19.0, 19.0, 3.0)];
+        assert_eq!(vwap(&klines), Some(17.5));
+        assert_eq!(vwap(&[kline(1.0, 1.0, 1.0, 0.0)]), None);
+    }
+
+    #[test]
+    fn test_apply_filters() {
+        // 持续上涨，最后一根收盘在EMA之上
+        let klines: Vec<KlineData> = (0..40)
+            .map(|i| {
+                let close = 100.0 + i as f64;
+                kline(close + 1.0, close - 1.0, close, 1.0)
+            })
+            .collect();
+        let config = MonitorConfig {
+            trend_ema_period: 10,
+            rsi_period: 14,
+            rsi_overbought: 70.0,
+            min_atr: 1.0,
+            ..Default::default()
+        };
+        let long = apply_filters(&config, "long", &klines, 10);
+        assert_eq!(
+            long.iter()
+                .map(|r| (r.name.as_str(), r.passed))
+                .collect::<Vec<_>>(),
+            vec![("EMA(10)", true), ("RSI(14)", false), ("ATR(14)", true)]
+        );
+        assert_eq!(
+            failed_filters(&long).as_deref(),
+            Some("指标过滤未通过: RSI(14)")
+        );
+        // 没有设置超卖阈值，做空时不检查RSI
+        let short = apply_filters(&config, "short", &klines, 10);
+        assert_eq!(
+            short.iter().map(|r| r.passed).collect::<Vec<_>>(),
+            vec![false, true, true]
+        );
+        // K线不足时不通过
+        let few = apply_filters(&config, "long", &klines[..5], 10);
+        assert!(few.iter().all(|r| !r.passed && r.value.is_none()));
+        assert!(apply_filters(&MonitorConfig::default(), "long", &klines, 10).is_empty());
+        assert_eq!(required_klines(&config), 29);
+    }
+
+    #[test]
+    fn test_validate_filters() {
+        assert!(validate_filters(&MonitorConfig::default()).is_ok());
+        let config = MonitorConfig {
+            rsi_period: 14,
+            rsi_overbought: 30.0,
+            rsi_oversold: 70.0,
+            ..Default::default()
+        };
+        assert!(validate_filters(&config).is_err());
+        let config = MonitorConfig {
+            rsi_overbought: 70.0,
+            ..Default::default()
+        };
+        assert!(validate_filters(&config).is_err());
+    }
+}
@@ -0,0 +1,347 @@
+use crate::models::{KlineData, interval_to_seconds};
+use crate::repository::KlineRepository;
+use crate::services::exchange::Exchange;
+use anyhow::{Result, anyhow};
+use sqlx::SqlitePool;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, info};
+
+/// Gate.io 单次请求最多返回2000根K线
+const MAX_PAGE_SIZE: i64 = 2000;
+
+/// 本地K线存储：历史K线保存在SQLite中，只向交易所请求缺失和最新的K线
+#[derive(Debug, Clone)]
+pub struct KlineStore {
+    db: SqlitePool,
+}
+
+impl KlineStore {
+    pub fn new(db: SqlitePool) -> Self {
+        Self { db }
+    }
+
+    /// 按时间范围分页回补已收盘的K线，返回保存的K线数量
+    pub async fn backfill(
+        &self,
+        exchange: &dyn Exchange,
+        settle: &str,
+        symbol: &str,
+        interval: &str,
+        from: i64,
+        to: i64,
+    ) -> Result<usize> {
+        let interval_secs = checked_interval_secs(interval)?;
+        let mut page_from = align_down(from, interval_secs);
+        let to = align_down(to, interval_secs);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let mut total = 0;
+
+        while page_from <= to {
+            let page_to = (page_from + (MAX_PAGE_SIZE - 1) * interval_secs).min(to);
+            let mut klines = exchange
+                .get_kline_data_range(symbol, interval, page_from, page_to, settle)
+                .await?;
+            // 未收盘的K线不保存，避免留下不完整的数据
+            klines.retain(|k| k.timestamp + interval_secs <= now);
+            KlineRepository::upsert_batch(&self.db, settle, symbol, interval, &klines).await?;
+            debug!(
+                "Backfilled {} klines for {} {} from {} to {}",
+                klines.len(),
+                symbol,
+                interval,
+                page_from,
+                page_to
+            );
+            total += klines.len();
+            page_from = page_to + interval_secs;
+        }
+
+        Ok(total)
+    }
+
+    /// 检测时间范围内缺失的K线，返回缺口的起止时间（包含两端）
+    pub async fn find_gaps(
+        &self,
+        settle: &str,
+        symbol: &str,
+        interval: &str,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<(i64, i64)>> {
+        let interval_secs = checked_interval_secs(interval)?;
+        let from = align_down(from, interval_secs);
+        let to = align_down(to, interval_secs);
+        let timestamps =
+            KlineRepository::get_timestamps(&self.db, settle, symbol, interval, from, to).await?;
+        Ok(missing_ranges(&timestamps, from, to, interval_secs))
+    }
+
+    /// 回补时间范围内所有缺失的K线，空的存储相当于完整回补
+    ///
+    /// 交易所没有返回任何K线、且后面已有K线的缺口（上线前、停盘期间）会被记录下来，之后不再请求
+    pub async fn repair_gaps(
+        &self,
+        exchange: &dyn Exchange,
+        settle: &str,
+        symbol: &str,
+        interval: &str,
+        from: i64,
+        to: i64,
+    ) -> Result<usize> {
+        let interval_secs = checked_interval_secs(interval)?;
+        let gaps = self.find_gaps(settle, symbol, interval, from, to).await?;
+        if gaps.is_empty() {
+            return Ok(0);
+        }
+        let empty_ranges =
+            KlineRepository::get_empty_ranges(&self.db, settle, symbol, interval, from, to).await?;
+        let mut total = 0;
+        for (gap_from, gap_to) in gaps {
+            if is_known_empty(&empty_ranges, gap_from, gap_to) {
+                continue;
+            }
+            info!(
+                "Repairing kline gap for {} {}: {} ~ {}",
+                symbol, interval, gap_from, gap_to
+            );
+            let saved = self
+                .backfill(exchange, settle, symbol, interval, gap_from, gap_to)
+                .await?;
+            // 末尾的缺口可能只是交易所还没生成K线，下次继续请求
+            if saved == 0 && gap_to < align_down(to, interval_secs) {
+                KlineRepository::save_empty_range(
+                    &self.db, settle, symbol, interval, gap_from, gap_to,
+                )
+                .await?;
+            }
+            total += saved;
+        }
+        Ok(total)
+    }
+
+    /// 同步最新K线，返回最近 `count` 根已收盘K线，以及交易所返回的当前未收盘K线（如果有）
+    ///
+    /// 历史部分从本地读取，只向交易所请求上次同步之后的K线；本地没有足够历史时先回补
+    pub async fn sync_latest(
+        &self,
+        exchange: &dyn Exchange,
+        settle: &str,
+        symbol: &str,
+        interval: &str,
+        count: usize,
+    ) -> Result<Vec<KlineData>> {
+        let interval_secs = checked_interval_secs(interval)?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let current = align_down(now, interval_secs);
+        let from = current - count as i64 * interval_secs;
+
+        let last_closed = current - interval_secs;
+        let mut current_kline = None;
+
+        match KlineRepository::latest_timestamp(&self.db, settle, symbol, interval).await? {
+            Some(latest) if latest >= from => {
+                // 只获取最后一根本地K线之后的K线，包括当前未收盘的K线
+                let limit = ((current - latest) / interval_secs + 1).clamp(1, MAX_PAGE_SIZE);
+                let mut klines = exchange
+                    .get_kline_data(symbol, interval, limit as usize, settle)
+                    .await?;
+                if klines.last().is_some_and(|k| k.timestamp >= current) {
+                    current_kline = klines.pop();
+                }
+                KlineRepository::upsert_batch(&self.db, settle, symbol, interval, &klines).await?;
+            }
+            _ => {
+                self.backfill(exchange, settle, symbol, interval, from, last_closed)
+                    .await?;
+            }
+        }
+
+        // 服务停止期间可能留下缺口
+        self.repair_gaps(exchange, settle, symbol, interval, from, last_closed)
+            .await?;
+
+        let mut klines =
+            KlineRepository::get_range(&self.db, settle, symbol, interval, from, last_closed)
+                .await?;
+        klines.extend(current_kline);
+        Ok(klines)
+    }
+
+    /// 保存行情推送的已收盘K线，返回以它结尾的最近 `count` 根K线
+    ///
+    /// 本地窗口内有缺口（推送断开期间漏掉的K线）时返回None，由调用方通过 `sync_latest` 向交易所补齐
+    pub async fn append_closed(
+        &self,
+        settle: &str,
+        symbol: &str,
+        interval: &str,
+        kline: &KlineData,
+        count: usize,
+    ) -> Result<Option<Vec<KlineData>>> {
+        let interval_secs = checked_interval_secs(interval)?;
+        KlineRepository::upsert_batch(
+            &self.db,
+            settle,
+            symbol,
+            interval,
+            std::slice::from_ref(kline),
+        )
+        .await?;
+
+        let to = align_down(kline.timestamp, interval_secs);
+        let from = to - (count.max(1) as i64 - 1) * interval_secs;
+        let klines =
+            KlineRepository::get_range(&self.db, settle, symbol, interval, from, to).await?;
+        let timestamps: Vec<i64> = klines.iter().map(|k| k.timestamp).collect();
+        let gaps = missing_ranges(&timestamps, from, to, interval_secs);
+        if !gaps.is_empty() {
+            let empty_ranges =
+                KlineRepository::get_empty_ranges(&self.db, settle, symbol, interval, from, to)
+                    .await?;
+            if !gaps
+                .iter()
+                .all(|&(gap_from, gap_to)| is_known_empty(&empty_ranges, gap_from, gap_to))
+            {
+                debug!(
+                    "Local klines for {} {} have gaps before {}: {:?}",
+                    symbol, interval, to, gaps
+                );
+                return Ok(None);
+            }
+        }
+        Ok(Some(klines))
+    }
+
+    /// 读取时间范围内已收盘的K线，先回补缺失的部分
+    pub async fn load_closed(
+        &self,
+        exchange: &dyn Exchange,
+        settle: &str,
+        symbol: &str,
+        interval: &str,
+        from: i64,
+    ) -> Result<Vec<KlineData>> {
+        let interval_secs = checked_interval_secs(interval)?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let last_closed = align_down(now, interval_secs) - interval_secs;
+
+        self.repair_gaps(exchange, settle, symbol, interval, from, last_closed)
+            .await?;
+        KlineRepository::get_range(&self.db, settle, symbol, interval, from, last_closed).await
+    }
+}
+
+fn checked_interval_secs(interval: &str) -> Result<i64> {
+    match interval_to_seconds(interval) {
+        0 => Err(anyhow!("Unsupported interval: {}", interval)),
+        secs => Ok(secs),
+    }
+}
+
+fn align_down(timestamp: i64, interval_secs: i64) -> i64 {
+    timestamp - timestamp.rem_euclid(interval_secs)
+}
+
+/// 根据已有的时间戳（升序）计算 `from` 到 `to` 之间缺失的时间段
+pub fn missing_ranges(
+    timestamps: &[i64],
+    from: i64,
+    to: i64,
+    interval_secs: i64,
+) -> Vec<(i64, i64)> {
+    let mut gaps = Vec::new();
+    let mut expected = from;
+
+    for &timestamp in timestamps {
+        if timestamp > to {
+            break;
+        }
+        if timestamp < expected {
+            continue;
+        }
+        if timestamp > expected {
+            gaps.push((expected, timestamp - interval_secs));
+        }
+        expected = timestamp + interval_secs;
+    }
+
+    if expected <= to {
+        gaps.push((expected, to));
+    }
+
+    gaps
+}
+
+/// 缺口是否完全落在已确认没有K线的时间段内
+fn is_known_empty(empty_ranges: &[(i64, i64)], from: i64, to: i64) -> bool {
+    empty_ranges
+        .iter()
+        .any(|&(range_from, range_to)| range_from <= from && to <= range_to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_ranges() {
+        let timestamps = vec![120, 180, 360, 420];
+        assert_eq!(
+            missing_ranges(&timestamps, 0, 600, 60),
+            vec![(0, 60), (240, 300), (480, 600)]
+        );
+        assert!(missing_ranges(&timestamps, 120, 180, 60).is_empty());
+        assert_eq!(missing_ranges(&[], 0, 120, 60), vec![(0, 120)]);
+    }
+    #[test]
+    fn test_is_known_empty() {
+        let empty_ranges = vec![(0, 600), (1200, 1200)];
+        // 窗口前移后，开头的缺口变小，仍然在记录的时间段内
+        assert!(is_known_empty(&empty_ranges, 120, 600));
+        assert!(is_known_empty(&empty_ranges, 1200, 1200));
+        assert!(!is_known_empty(&empty_ranges, 540, 660));
+        assert!(!is_known_empty(&[], 0, 60));
+    }
+
+    #[tokio::test]
+    async fn test_append_closed_detects_gaps() {
+        let db = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("../migrations").run(&db).await.unwrap();
+        let store = KlineStore::new(db);
+        let kline = |timestamp| KlineData {
+            timestamp,
+            open: 1.0,
+            high: 1.0,
+            low: 1.0,
+            close: 1.0,
+            volume: 1.0,
+        };
+
+        for timestamp in [60, 120] {
+            store
+                .append_closed("usdt", "BTC_USDT", "1m", &kline(timestamp), 3)
+                .await
+                .unwrap();
+        }
+        let klines = store
+            .append_closed("usdt", "BTC_USDT", "1m", &kline(180), 3)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            klines.iter().map(|k| k.timestamp).collect::<Vec<_>>(),
+            vec![60, 120, 180]
+        );
+
+        // 漏掉了240这一根
+        let klines = store
+            .append_closed("usdt", "BTC_USDT", "1m", &kline(300), 3)
+            .await
+            .unwrap();
+        assert!(klines.is_none());
+    }
+}
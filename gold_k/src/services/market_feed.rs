@@ -0,0 +1,330 @@
+use crate::models::{KlineData, interval_to_seconds};
+use anyhow::{Result, anyhow};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, interval, sleep};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, error, info, warn};
+
+/// Gate.io USDT永续合约行情WebSocket地址
+pub const GATE_FUTURES_WS_URL: &str = "wss://fx-ws.gateio.ws/v4/ws/usdt";
+
+const CANDLESTICKS_CHANNEL: &str = "futures.candlesticks";
+/// 每隔这么久发送一次应用层心跳 futures.ping
+const HEARTBEAT_SECS: u64 = 10;
+/// 超过这么久没有收到任何消息，认为连接已经失效，主动重连
+const STALE_SECS: u64 = 30;
+/// 重连等待时间上限
+const MAX_BACKOFF_SECS: u64 = 60;
+/// 连接保持超过这么久后断开，重连等待时间从头开始计算
+const STABLE_SECS: u64 = 60;
+
+/// 一根K线收盘
+#[derive(Debug, Clone)]
+pub struct CandleClose {
+    pub symbol: String,
+    pub interval: String,
+    pub kline: KlineData,
+}
+
+/// K线行情推送：订阅 futures.candlesticks 频道，把K线收盘事件广播给监控任务
+///
+/// 断线后按指数退避自动重连并重新订阅；连接不可用期间监控任务仍按K线收盘时间定时获取，只是不会被提前唤醒
+#[derive(Debug, Clone)]
+pub struct CandleFeed {
+    url: String,
+    sender: broadcast::Sender<CandleClose>,
+}
+
+impl CandleFeed {
+    pub fn new(url: &str) -> Self {
+        let (sender, _) = broadcast::channel(256);
+        Self {
+            url: url.to_string(),
+            sender,
+        }
+    }
+
+    /// 接收K线收盘事件
+    pub fn subscribe_closes(&self) -> broadcast::Receiver<CandleClose> {
+        self.sender.subscribe()
+    }
+
+    /// 启动后台连接任务，`subscriptions` 为 (交易对, K线周期)
+    pub fn spawn(&self, subscriptions: Vec<(String, String)>) -> JoinHandle<()> {
+        let feed = self.clone();
+        tokio::spawn(async move {
+            let mut tracker = CandleTracker::default();
+            let mut backoff = 1;
+            loop {
+                let started = Instant::now();
+                match feed.run_connection(&subscriptions, &mut tracker).await {
+                    Ok(()) => warn!("Candle feed connection closed by server"),
+                    Err(e) => warn!("Candle feed connection error: {}", e),
+                }
+
+                if started.elapsed() >= Duration::from_secs(STABLE_SECS) {
+                    backoff = 1;
+                }
+                info!("Reconnecting candle feed in {}s", backoff);
+                sleep(Duration::from_secs(backoff)).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF_SECS);
+            }
+        })
+    }
+
+    /// 建立一次连接并订阅，直到连接断开或失效
+    async fn run_connection(
+        &self,
+        subscriptions: &[(String, String)],
+        tracker: &mut CandleTracker,
+    ) -> Result<()> {
+        let (stream, _) = connect_async(self.url.as_str()).await?;
+        let (mut write, mut read) = stream.split();
+        info!(
+            "Candle feed connected to {}, subscribing {} channels",
+            self.url,
+            subscriptions.len()
+        );
+
+        for (symbol, interval_type) in subscriptions {
+            let request = channel_request(CANDLESTICKS_CHANNEL, "subscribe", interval_type, symbol);
+            write
+                .send(Message::Text(request.to_string().into()))
+                .await?;
+        }
+
+        let mut heartbeat = interval(Duration::from_secs(HEARTBEAT_SECS));
+        let mut last_message = Instant::now();
+        loop {
+            tokio::select! {
+                _ = heartbeat.tick() => {
+                    if last_message.elapsed() > Duration::from_secs(STALE_SECS) {
+                        return Err(anyhow!("No message received for {}s", STALE_SECS));
+                    }
+                    let ping = json!({ "time": now_secs(), "channel": "futures.ping" });
+                    write.send(Message::Text(ping.to_string().into())).await?;
+                }
+                message = read.next() => {
+                    let Some(message) = message else {
+                        return Ok(());
+                    };
+                    last_message = Instant::now();
+                    match message? {
+                        Message::Text(text) => self.handle_text(&text, tracker),
+                        Message::Ping(data) => write.send(Message::Pong(data)).await?,
+                        Message::Close(frame) => {
+                            debug!("Candle feed close frame: {:?}", frame);
+                            return Ok(());
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    fn handle_text(&self, text: &str, tracker: &mut CandleTracker) {
+        let message: Value = match serde_json::from_str(text) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Invalid candle feed message: {} - {}", e, text);
+                return;
+            }
+        };
+        if message["channel"] != CANDLESTICKS_CHANNEL {
+            return;
+        }
+
+        match message["event"].as_str() {
+            Some("subscribe") => {
+                if message["error"].is_null() {
+                    debug!("Candle feed subscribed: {}", message["result"]);
+                } else {
+                    error!("Candle feed subscribe failed: {}", message["error"]);
+                }
+            }
+            Some("update") => {
+                for (symbol, interval_type, kline, window_closed) in
+                    parse_candle_updates(&message["result"])
+                {
+                    for close in tracker.update(&symbol, &interval_type, kline, window_closed) {
+                        debug!(
+                            "Candle closed for {} {} at {}",
+                            close.symbol, close.interval, close.kline.timestamp
+                        );
+                        // 没有监控任务在接收时忽略
+                        let _ = self.sender.send(close);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// 订阅/取消订阅请求，payload 为 [K线周期, 合约]
+fn channel_request(channel: &str, event: &str, interval_type: &str, symbol: &str) -> Value {
+    json!({
+        "time": now_secs(),
+        "channel": channel,
+        "event": event,
+        "payload": [interval_type, symbol],
+    })
+}
+
+fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// 解析K线推送，返回 (交易对, K线周期, K线, 是否已收盘)
+///
+/// Gate.io 推送格式: [{"t":1545129300,"v":27525555,"c":"95.4","h":"96.9","l":"89.5","o":"94.3","n":"1m_BTC_USDT","w":false}]，
+/// `w` 字段不一定存在
+pub fn parse_candle_updates(result: &Value) -> Vec<(String, String, KlineData, Option<bool>)> {
+    let Some(items) = result.as_array() else {
+        return Vec::new();
+    };
+    let price = |item: &Value, key: &str| item[key].as_str().and_then(|s| s.parse::<f64>().ok());
+
+    items
+        .iter()
+        .filter_map(|item| {
+            let (interval_type, symbol) = item["n"].as_str()?.split_once('_')?;
+            let kline = KlineData {
+                timestamp: item["t"].as_i64()?,
+                open: price(item, "o")?,
+                high: price(item, "h")?,
+                low: price(item, "l")?,
+                close: price(item, "c")?,
+                volume: item["v"].as_f64()?,
+            };
+            Some((
+                symbol.to_string(),
+                interval_type.to_string(),
+                kline,
+                item["w"].as_bool(),
+            ))
+        })
+        .collect()
+}
+
+/// 根据推送判断K线收盘：推送带有收盘标记，或者出现了下一根K线
+#[derive(Debug, Default)]
+pub struct CandleTracker {
+    latest: HashMap<(String, String), KlineData>,
+    last_closed: HashMap<(String, String), i64>,
+}
+
+impl CandleTracker {
+    pub fn update(
+        &mut self,
+        symbol: &str,
+        interval_type: &str,
+        kline: KlineData,
+        window_closed: Option<bool>,
+    ) -> Vec<CandleClose> {
+        let key = (symbol.to_string(), interval_type.to_string());
+        let interval_secs = interval_to_seconds(interval_type);
+        let last_closed = self.last_closed.get(&key).copied().unwrap_or(i64::MIN);
+        let mut closes = Vec::new();
+
+        // 只有紧挨着的上一根K线才算收盘，断线期间错过的K线由监控任务按收盘时间定时获取
+        if let Some(previous) = self.latest.get(&key)
+            && interval_secs > 0
+            && kline.timestamp == previous.timestamp + interval_secs
+            && previous.timestamp > last_closed
+        {
+            closes.push(CandleClose {
+                symbol: symbol.to_string(),
+                interval: interval_type.to_string(),
+                kline: previous.clone(),
+            });
+        }
+        if window_closed == Some(true) && kline.timestamp > last_closed {
+            closes.push(CandleClose {
+                symbol: symbol.to_string(),
+                interval: interval_type.to_string(),
+                kline: kline.clone(),
+            });
+        }
+        if let Some(close) = closes.last() {
+            self.last_closed.insert(key.clone(), close.kline.timestamp);
+        }
+
+        if self
+            .latest
+            .get(&key)
+            .is_none_or(|previous| kline.timestamp >= previous.timestamp)
+        {
+            self.latest.insert(key, kline);
+        }
+        closes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candle_close_detection() {
+        let result = json!([
+            {"t": 1_700_000_000, "v": 10, "c": "101", "h": "102", "l": "99", "o": "100", "n": "1m_BTC_USDT"},
+        ]);
+        let updates = parse_candle_updates(&result);
+        assert_eq!(updates.len(), 1);
+        let (symbol, interval_type, kline, window_closed) = updates[0].clone();
+        assert_eq!(symbol, "BTC_USDT");
+        assert_eq!(interval_type, "1m");
+        assert_eq!(kline.close, 101.0);
+        assert_eq!(window_closed, None);
+
+        let mut tracker = CandleTracker::default();
+        let at = |timestamp: i64, close: f64| KlineData {
+            timestamp,
+            close,
+            ..kline.clone()
+        };
+        assert!(
+            tracker
+                .update("BTC_USDT", "1m", at(1_700_000_000, 101.0), None)
+                .is_empty()
+        );
+        assert!(
+            tracker
+                .update("BTC_USDT", "1m", at(1_700_000_000, 103.0), None)
+                .is_empty()
+        );
+
+        // 下一根K线出现，上一根收盘，使用最后一次推送的数据
+        let closes = tracker.update("BTC_USDT", "1m", at(1_700_000_060, 104.0), None);
+        assert_eq!(closes.len(), 1);
+        assert_eq!(closes[0].kline.timestamp, 1_700_000_000);
+        assert_eq!(closes[0].kline.close, 103.0);
+
+        // 带收盘标记的推送只触发一次
+        let closes = tracker.update("BTC_USDT", "1m", at(1_700_000_060, 105.0), Some(true));
+        assert_eq!(closes.len(), 1);
+        assert!(
+            tracker
+                .update("BTC_USDT", "1m", at(1_700_000_120, 106.0), None)
+                .is_empty()
+        );
+
+        // 断线期间跳过的K线不算收盘
+        assert!(
+            tracker
+                .update("BTC_USDT", "1m", at(1_700_000_600, 107.0), None)
+                .is_empty()
+        );
+    }
+}
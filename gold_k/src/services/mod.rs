@@ -1,11 +1,41 @@
+pub mod backtest;
+pub mod confirmation;
 pub mod dingtalk;
+pub mod emergency;
+pub mod exchange;
 pub mod gate;
+pub mod gate_error;
+pub mod indicators;
+pub mod kline_store;
+pub mod market_feed;
 pub mod monitor;
+pub mod optimizer;
+pub mod paper;
+pub mod patterns;
+pub mod positions;
+pub mod rate_limit;
+pub mod reconciler;
+pub mod risk;
+pub mod rules;
+pub mod scripting;
+pub mod stop_manager;
+pub mod take_profit;
+pub mod walk_forward;
 
 pub use dingtalk::DingTalkService;
+pub use emergency::emergency_flatten;
+pub use exchange::{Exchange, OrderRequest};
 pub use gate::GateService;
+pub use gate_error::GateError;
+pub use kline_store::KlineStore;
+pub use market_feed::CandleFeed;
 pub use monitor::MonitorService;
+pub use paper::PaperExchange;
+pub use positions::PositionTracker;
+pub use reconciler::OrderReconciler;
+pub use risk::{RiskDecision, RiskManager};
 
+#[allow(clippy::collapsible_if)]
 pub fn build_order_data(
     symbol: &str,
     order_type: &str,
@@ -1,19 +1,29 @@
 use crate::models::*;
 use crate::repository::{
-    ApiKeyRepository, MonitorConfigRepository, OrderRepository, SignalRepository,
+    ApiKeyRepository, MonitorConfigRepository, OrderRepository, RiskRepository, SignalRepository,
+    StrategyScriptRepository,
+};
+use crate::services::confirmation::Confirmation;
+use crate::services::market_feed::GATE_FUTURES_WS_URL;
+use crate::services::scripting::ScriptStrategy;
+use crate::services::{
+    CandleFeed, DingTalkService, Exchange, GateError, GateService, KlineStore, OrderReconciler,
+    OrderRequest, PaperExchange, PositionTracker, RiskDecision, RiskManager, confirmation,
+    indicators, patterns, risk, rules, stop_manager, take_profit,
 };
-use crate::services::{DingTalkService, GateService, build_order_data};
 use anyhow::{Result, anyhow};
 use sqlx::SqlitePool;
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::RwLock;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::time::{Duration, interval};
 use tracing::{debug, error, info, warn};
 
 // precision: "0.01" -> 2
-fn round_price(price: f64, precision: &str) -> f64 {
+#[allow(clippy::implicit_saturating_sub)]
+pub fn round_price(price: f64, precision: &str) -> f64 {
     let mut decimal_places = precision.split('.').nth(1).map(|s| s.len()).unwrap_or(0);
     // 比官方少一位精度
     if decimal_places > 0 {
@@ -23,12 +33,85 @@ fn round_price(price: f64, precision: &str) -> f64 {
     (price * multiplier).round() / multiplier
 }
 
+fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+/// 计算下一根要分析的已收盘K线的开始时间，以及应该唤醒检查的时间（收盘时间加宽限秒数）
+///
+/// 上次分析的K线落后太多时直接跳到最近一根已收盘的K线
+fn next_candle_wake(
+    now: i64,
+    interval_secs: i64,
+    grace_secs: i64,
+    last_analyzed: Option<i64>,
+) -> (i64, i64) {
+    let latest_closed = now - now.rem_euclid(interval_secs) - interval_secs;
+    let closed_at = match last_analyzed {
+        Some(last) if last >= latest_closed => last + interval_secs,
+        _ => latest_closed,
+    };
+    (closed_at, closed_at + interval_secs + grace_secs.max(0))
+}
+
+/// 按 (交易对, K线周期) 分组，保持配置原来的顺序
+fn group_configs(configs: Vec<MonitorConfig>) -> Vec<((String, String), Vec<MonitorConfig>)> {
+    let mut groups: Vec<((String, String), Vec<MonitorConfig>)> = Vec::new();
+    for config in configs {
+        let key = (config.symbol.clone(), config.interval_type.clone());
+        match groups.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, group)) => group.push(config),
+            None => groups.push((key, vec![config])),
+        }
+    }
+    groups
+}
+
+/// 策略识别的结果，由 `MonitorService::build_signal` 做成交量和指标检查后生成信号
+#[derive(Debug, Default)]
+struct Detection {
+    shadow_type: &'static str,
+    main_shadow_length: f64,
+    main_profit: f64,
+    shadow_ratio: f64,
+    pattern: Option<String>,
+    direction: &'static str,
+    stop_price: Option<f64>,
+    entry_price: Option<f64>,
+    target_price: Option<f64>,
+    script_version: Option<i64>,
+}
+
+/// 一组K线相同的配置的监控任务共用的服务
+#[derive(Clone)]
+struct MonitorContext {
+    db: SqlitePool,
+    // 获取共用K线的行情交易所
+    market_exchange: Arc<RwLock<dyn Exchange>>,
+    // 有开启模拟交易的配置时，用同一组K线撮合模拟订单
+    paper: Option<PaperExchange>,
+    dingtalk_service: Arc<RwLock<DingTalkService>>,
+    is_running: Arc<RwLock<bool>>,
+    // 所有监控任务共用，保证同时下单时风控检查不会超出上限
+    risk: RiskManager,
+}
+
 #[derive(Debug, Clone)]
 pub struct MonitorService {
     db: SqlitePool,
     is_running: Arc<RwLock<bool>>,
     active_tasks: Arc<RwLock<HashMap<String, tokio::task::JoinHandle<()>>>>,
-    gate_service: Arc<RwLock<GateService>>,
+    // 订单对账等后台任务，停止监控时一起结束
+    background_tasks: Arc<RwLock<Vec<tokio::task::JoinHandle<()>>>>,
+    exchange: Arc<RwLock<dyn Exchange>>,
+    // 模拟交易所，开启模拟交易的配置使用它下单
+    paper_exchange: Arc<RwLock<dyn Exchange>>,
+    // 同一个模拟交易所，共用K线获取后用它撮合模拟订单
+    paper: PaperExchange,
+    risk: RiskManager,
     dingtalk_service: Arc<RwLock<DingTalkService>>,
     // 记录最后更新的API配置时间戳，用于检测配置变化
     last_config_update: Arc<RwLock<i64>>,
@@ -36,11 +119,22 @@ pub struct MonitorService {
 
 impl MonitorService {
     pub fn new(db: SqlitePool) -> Self {
+        Self::with_exchange(db, Arc::new(RwLock::new(GateService::new())))
+    }
+
+    /// 使用指定的交易所实现创建监控服务
+    pub fn with_exchange(db: SqlitePool, exchange: Arc<RwLock<dyn Exchange>>) -> Self {
+        let paper_exchange = PaperExchange::new(db.clone(), exchange.clone());
+        let risk = RiskManager::new(db.clone());
         Self {
             db,
             is_running: Arc::new(RwLock::new(false)),
             active_tasks: Arc::new(RwLock::new(HashMap::new())),
-            gate_service: Arc::new(RwLock::new(GateService::new())),
+            background_tasks: Arc::new(RwLock::new(Vec::new())),
+            exchange,
+            paper_exchange: Arc::new(RwLock::new(paper_exchange.clone())),
+            paper: paper_exchange,
+            risk,
             dingtalk_service: Arc::new(RwLock::new(DingTalkService::new())),
             last_config_update: Arc::new(RwLock::new(0)),
         }
@@ -64,23 +158,46 @@ impl MonitorService {
         *is_running = true;
         drop(is_running); // 释放所有权
 
-        // 为每个配置启动监控任务
+        // WebSocket推送K线收盘事件提前唤醒监控任务，连接不可用时各监控任务按K线收盘时间定时获取
+        let feed = CandleFeed::new(GATE_FUTURES_WS_URL);
+        let groups = group_configs(configs);
+        let subscriptions = groups.iter().map(|(key, _)| key.clone()).collect();
+        self.background_tasks
+            .write()
+            .await
+            .push(feed.spawn(subscriptions));
+
+        // 交易对和K线周期相同的配置共用一个监控任务，K线只获取一次
         let mut tasks = self.active_tasks.write().await;
-        for config in configs {
-            let task_handle = self.start_symbol_monitor(config.clone()).await;
-            tasks.insert(
-                format!("{}_{}", config.symbol, config.interval_type),
-                task_handle,
-            );
+        let mut total_configs = 0;
+        for ((symbol, interval_type), group) in groups {
+            total_configs += group.len();
+            let task_handle = self.start_symbol_monitor(group, &feed).await;
+            tasks.insert(format!("{}_{}", symbol, interval_type), task_handle);
         }
 
         info!(
-            "Monitor service started with {} active configurations",
+            "Monitor service started with {} active configurations in {} kline groups",
+            total_configs,
             tasks.len()
         );
 
+        // 每30秒对账一次订单状态
+        let reconciler = OrderReconciler::new(self.db.clone(), self.exchanges());
+        self.background_tasks
+            .write()
+            .await
+            .push(reconciler.spawn(Duration::from_secs(30)));
+
+        // 每分钟保存一次持仓快照
+        let tracker = PositionTracker::new(self.db.clone(), self.exchanges());
+        self.background_tasks
+            .write()
+            .await
+            .push(tracker.spawn(Duration::from_secs(60)));
+
         // 检查cookie是否有效
-        let gate_service = self.gate_service.clone();
+        let exchange = self.exchange.clone();
         let dingtalk_service = self.dingtalk_service.clone();
         let db_clone = self.db.clone();
         let last_config_update = self.last_config_update.clone();
@@ -100,40 +217,40 @@ impl MonitorService {
                     tokio::select! {
                         _ = health_check_interval.tick() => {
                             info!("🏥Checking task health status");
-                            
+
                         }
                         _ = cookie_check_interval.tick() => {
                             info!("🪛Checking cookie validity");
-                            
+
                             // 使用 tokio::time::timeout 包装整个cookie检查过程，防止卡住
                             let check_result = tokio::time::timeout(Duration::from_secs(60), async {
                                 // Cookie有效性检查 - 使用快速释放锁的模式
                                 let account_result = {
-                                    let gate_lock_result = tokio::time::timeout(
+                                    let exchange_lock_result = tokio::time::timeout(
                                         Duration::from_secs(10),
-                                        gate_service.read()
+                                        exchange.read()
                                     ).await;
-                                    
-                                    match gate_lock_result {
-                                        Ok(gate_service) => {
+
+                                    match exchange_lock_result {
+                                        Ok(exchange) => {
                                             tokio::time::timeout(
                                                 Duration::from_secs(30),
-                                                gate_service.get_account_info()
+                                                exchange.get_account_info()
                                             ).await
                                         }
                                         Err(_) => {
-                                            error!("Timeout waiting for gate service lock during cookie check");
-                                            return Err(anyhow!("Gate service lock timeout"));
+                                            error!("Timeout waiting for exchange lock during cookie check");
+                                            return Err(anyhow!("Exchange lock timeout"));
                                         }
                                     }
-                                }; // gate_service 锁在这里自动释放
-                                
+                                }; // exchange 锁在这里自动释放
+
                                 match account_result {
-                                    Ok(Ok(account_result)) => {
-                                        if !account_result.1 {
-                                            warn!("Cookie已失效，请重新登录, account: {:?}", account_result);
-                                            let msg = account_result.0.to_string();
-                                            
+                                    Ok(Ok(account_info)) => {
+                                        if !account_info.valid {
+                                            warn!("Cookie已失效，请重新登录, account: {:?}", account_info);
+                                            let msg = account_info.raw.to_string();
+
                                             // 分别获取钉钉服务锁 - 使用快速释放锁的模式
                                             let send_result = tokio::time::timeout(
                                                 Duration::from_secs(10),
@@ -144,7 +261,7 @@ impl MonitorService {
                                                     ).await
                                                 }
                                             ).await; // dingtalk_service 锁在这里自动释放
-                                            
+
                                             match send_result {
                                                 Ok(Ok(_)) => {
                                                     info!("Cookie invalidity notification sent successfully");
@@ -159,10 +276,10 @@ impl MonitorService {
                                         }
                                     }
                                     Ok(Err(e)) => {
-                                        // 如果e中包含403 Forbidden，则认为Cookie已失效
-                                        if e.to_string().contains("403 Forbidden") {
+                                        // 认证失败（403等）说明Cookie已失效或者ip不对
+                                        if e.downcast_ref::<GateError>().is_some_and(GateError::is_auth) {
                                             error!("Cookie已失效，或者ip不对，用国内ip, account: {:?}", e);
-                                            
+
                                             // 分别获取钉钉服务锁 - 使用快速释放锁的模式
                                             let send_result = tokio::time::timeout(
                                                 Duration::from_secs(10),
@@ -173,7 +290,7 @@ impl MonitorService {
                                                     ).await
                                                 }
                                             ).await; // dingtalk_service 锁在这里自动释放
-                                            
+
                                             match send_result {
                                                 Ok(Ok(_)) => {
                                                     info!("403 error notification sent successfully");
@@ -193,10 +310,10 @@ impl MonitorService {
                                         error!("Timeout getting account info during cookie check");
                                     }
                                 }
-                                
+
                                 Ok(())
                             }).await;
-                            
+
                             match check_result {
                                 Ok(_) => {
                                     info!("🪛Finished cookie validity check");
@@ -208,17 +325,17 @@ impl MonitorService {
                         }
                         _ = config_check_interval.tick() => {
                             info!("🔧Checking for config updates");
-                            
+
                             // 使用 tokio::time::timeout 包装配置检查过程，防止卡住
                             let config_result = tokio::time::timeout(Duration::from_secs(30), async {
                                 Self::check_and_update_config(
                                     &db_clone,
-                                    &gate_service,
+                                    &exchange,
                                     &dingtalk_service,
                                     &last_config_update
                                 ).await
                             }).await;
-                            
+
                             match config_result {
                                 Ok(Ok(_)) => {
                                     info!("🔧CFinished config update check");
@@ -266,10 +383,18 @@ impl MonitorService {
             task.abort();
             debug!("Stopped monitor task for {}", symbol);
         }
+        for task in self.background_tasks.write().await.drain(..) {
+            task.abort();
+        }
 
         info!("Monitor service stopped");
     }
 
+    /// 实盘和模拟交易所，对账、持仓跟踪和紧急平仓使用
+    pub fn exchanges(&self) -> Vec<Arc<RwLock<dyn Exchange>>> {
+        vec![self.exchange.clone(), self.paper_exchange.clone()]
+    }
+
     pub async fn get_status(&self) -> MonitorStatus {
         let is_running = *self.is_running.read().await;
         let tasks = self.active_tasks.read().await;
@@ -326,7 +451,7 @@ impl MonitorService {
     /// 这个方法是线程安全的，使用读写锁来保护共享资源
     async fn check_and_update_config(
         db: &SqlitePool,
-        gate_service: &Arc<RwLock<GateService>>,
+        exchange: &Arc<RwLock<dyn Exchange>>,
         dingtalk_service: &Arc<RwLock<DingTalkService>>,
         last_config_update: &Arc<RwLock<i64>>,
     ) -> Result<()> {
@@ -351,46 +476,29 @@ impl MonitorService {
             last_update, api_key.updated_at
         );
 
-        // 更新 GateService 配置 - 使用超时和快速释放锁
+        // 更新交易所配置 - 使用超时和快速释放锁
         {
-            let gate_lock_result = tokio::time::timeout(
-                Duration::from_secs(10),
-                gate_service.write()
-            ).await;
-            
-            match gate_lock_result {
-                Ok(mut gate) => {
-                    // 更新 API 凭据
-                    gate.update_credentials(&api_key.api_key, &api_key.secret_key);
-
-                    // 更新 cookie
-                    if let Some(cookie) = &api_key.cookie {
-                        gate.set_cookie(cookie);
-                        info!("Updated gate service cookie");
-                    }
-
-                    // 更新合约数据
-                    if let Some(contracts) = &api_key.contracts {
-                        gate.set_contracts(contracts);
-                        info!("Updated gate service contracts");
-                    }
-
-                    info!("Updated gate service API credentials");
+            let exchange_lock_result =
+                tokio::time::timeout(Duration::from_secs(10), exchange.write()).await;
+
+            match exchange_lock_result {
+                Ok(mut exchange) => {
+                    // 更新 API 凭据、cookie和合约数据
+                    exchange.apply_api_key(&api_key);
+                    info!("Updated {} exchange API credentials", exchange.name());
                 }
                 Err(_) => {
-                    error!("Timeout waiting for gate service write lock during config update");
-                    return Err(anyhow!("Gate service write lock timeout"));
+                    error!("Timeout waiting for exchange write lock during config update");
+                    return Err(anyhow!("Exchange write lock timeout"));
                 }
             }
-        } // gate 写锁在这里自动释放
+        } // exchange 写锁在这里自动释放
 
         // 更新 DingTalkService 配置 - 使用超时和快速释放锁
         if let Some(webhook_url) = &api_key.webhook_url {
-            let dingtalk_lock_result = tokio::time::timeout(
-                Duration::from_secs(5),
-                dingtalk_service.write()
-            ).await;
-            
+            let dingtalk_lock_result =
+                tokio::time::timeout(Duration::from_secs(5), dingtalk_service.write()).await;
+
             match dingtalk_lock_result {
                 Ok(mut dingtalk) => {
                     dingtalk.set_webhook_url(webhook_url);
@@ -405,11 +513,9 @@ impl MonitorService {
 
         // 更新最后配置更新时间戳 - 使用超时
         {
-            let last_update_lock_result = tokio::time::timeout(
-                Duration::from_secs(5),
-                last_config_update.write()
-            ).await;
-            
+            let last_update_lock_result =
+                tokio::time::timeout(Duration::from_secs(5), last_config_update.write()).await;
+
             match last_update_lock_result {
                 Ok(mut last_update) => {
                     *last_update = api_key.updated_at;
@@ -436,66 +542,60 @@ impl MonitorService {
         let api_key = ApiKeyRepository::get_active(&self.db).await?;
 
         if let Some(key) = api_key {
-            // 更新Gate服务配置 - 使用超时和快速释放锁
+            // 更新交易所配置 - 使用超时和快速释放锁
             {
-                let gate_lock_result = tokio::time::timeout(
-                    Duration::from_secs(10),
-                    self.gate_service.write()
-                ).await;
-                
-                match gate_lock_result {
-                    Ok(mut gate_service) => {
-                        gate_service.update_credentials(&key.api_key, &key.secret_key);
-
-                        // 更新cookie
-                        if let Some(cookie) = &key.cookie {
-                            gate_service.set_cookie(cookie);
-                        }
+                let exchange_lock_result =
+                    tokio::time::timeout(Duration::from_secs(10), self.exchange.write()).await;
 
-                        // 更新合约数据
-                        if let Some(contracts) = &key.contracts {
-                            gate_service.set_contracts(contracts);
-                        }
+                match exchange_lock_result {
+                    Ok(mut exchange) => {
+                        exchange.apply_api_key(&key);
                     }
                     Err(_) => {
-                        error!("Timeout waiting for gate service write lock during service update");
-                        return Err(anyhow!("Gate service write lock timeout during startup"));
+                        error!("Timeout waiting for exchange write lock during service update");
+                        return Err(anyhow!("Exchange write lock timeout during startup"));
                     }
                 }
-            } // gate_service 写锁在这里自动释放
+            } // exchange 写锁在这里自动释放
 
             // 更新钉钉服务配置 - 使用超时和快速释放锁
             if let Some(webhook_url) = &key.webhook_url {
-                let dingtalk_lock_result = tokio::time::timeout(
-                    Duration::from_secs(5),
-                    self.dingtalk_service.write()
-                ).await;
-                
+                let dingtalk_lock_result =
+                    tokio::time::timeout(Duration::from_secs(5), self.dingtalk_service.write())
+                        .await;
+
                 match dingtalk_lock_result {
                     Ok(mut dingtalk_service) => {
                         dingtalk_service.set_webhook_url(webhook_url);
                     }
                     Err(_) => {
-                        error!("Timeout waiting for dingtalk service write lock during service update");
-                        return Err(anyhow!("DingTalk service write lock timeout during startup"));
+                        error!(
+                            "Timeout waiting for dingtalk service write lock during service update"
+                        );
+                        return Err(anyhow!(
+                            "DingTalk service write lock timeout during startup"
+                        ));
                     }
                 }
             } // dingtalk_service 写锁在这里自动释放
 
             // 更新最后配置更新时间戳 - 使用超时
             {
-                let last_update_lock_result = tokio::time::timeout(
-                    Duration::from_secs(5),
-                    self.last_config_update.write()
-                ).await;
-                
+                let last_update_lock_result =
+                    tokio::time::timeout(Duration::from_secs(5), self.last_config_update.write())
+                        .await;
+
                 match last_update_lock_result {
                     Ok(mut last_update) => {
                         *last_update = key.updated_at;
                     }
                     Err(_) => {
-                        error!("Timeout waiting for config update timestamp write lock during service update");
-                        return Err(anyhow!("Config update timestamp write lock timeout during startup"));
+                        error!(
+                            "Timeout waiting for config update timestamp write lock during service update"
+                        );
+                        return Err(anyhow!(
+                            "Config update timestamp write lock timeout during startup"
+                        ));
                     }
                 }
             } // last_update 写锁在这里自动释放
@@ -506,176 +606,585 @@ impl MonitorService {
         }
     }
 
-    async fn start_symbol_monitor(&self, config: MonitorConfig) -> tokio::task::JoinHandle<()> {
-        Self::start_individual_symbol_monitor(
-            self.db.clone(),
-            self.gate_service.clone(),
-            self.dingtalk_service.clone(),
-            self.is_running.clone(),
-            config,
-        ).await
+    /// 启动一组K线相同的配置的监控任务，开启模拟交易的配置使用模拟交易所下单
+    async fn start_symbol_monitor(
+        &self,
+        configs: Vec<MonitorConfig>,
+        feed: &CandleFeed,
+    ) -> tokio::task::JoinHandle<()> {
+        let subscribers = configs
+            .into_iter()
+            .map(|config| {
+                let exchange = if config.paper_trading {
+                    self.paper_exchange.clone()
+                } else {
+                    self.exchange.clone()
+                };
+                (config, exchange)
+            })
+            .collect::<Vec<_>>();
+        let paper = subscribers
+            .iter()
+            .any(|(config, _)| config.paper_trading)
+            .then(|| self.paper.clone());
+        let ctx = MonitorContext {
+            db: self.db.clone(),
+            market_exchange: self.exchange.clone(),
+            paper,
+            dingtalk_service: self.dingtalk_service.clone(),
+            is_running: self.is_running.clone(),
+            risk: self.risk.clone(),
+        };
+        Self::start_individual_symbol_monitor(ctx, feed.clone(), subscribers).await
     }
 
     /// 启动单个符号监控任务的静态版本
+    ///
+    /// 每个 (交易对, K线周期) 只有一个任务获取K线，获取到的序列依次交给每个订阅的配置分析
     async fn start_individual_symbol_monitor(
-        db: SqlitePool,
-        gate_service: Arc<RwLock<GateService>>,
-        dingtalk_service: Arc<RwLock<DingTalkService>>,
-        is_running: Arc<RwLock<bool>>,
-        config: MonitorConfig,
+        ctx: MonitorContext,
+        feed: CandleFeed,
+        subscribers: Vec<(MonitorConfig, Arc<RwLock<dyn Exchange>>)>,
     ) -> tokio::task::JoinHandle<()> {
-        info!("Starting individual symbol monitor for {}", config.symbol);
+        let (symbol, interval_type) = subscribers
+            .first()
+            .map(|(c, _)| (c.symbol.clone(), c.interval_type.clone()))
+            .unwrap_or_default();
+        info!(
+            "Starting individual symbol monitor for {} {} with {} configs",
+            symbol,
+            interval_type,
+            subscribers.len()
+        );
         tokio::spawn(async move {
-            let mut interval_timer = interval(Duration::from_secs(config.frequency as u64));
+            let interval_seconds = interval_to_seconds(&interval_type);
+            if interval_seconds <= 0 {
+                error!(
+                    "Unsupported interval {} for {}, monitor not started",
+                    interval_type, symbol
+                );
+                return;
+            }
+            // 共用的K线要满足每个配置的历史长度，宽限时间和重试间隔取最短的
+            // 移动止损按ATR计算时需要足够的K线
+            let count = subscribers
+                .iter()
+                .map(|(c, _)| c.required_klines())
+                .max()
+                .unwrap_or(1)
+                .max(stop_manager::ATR_PERIOD + 1);
+            let grace_secs = subscribers
+                .iter()
+                .map(|(c, _)| c.close_grace_secs)
+                .min()
+                .unwrap_or(0);
+            // K线收盘后交易所还没有返回这根K线时的重试间隔
+            let retry_delay = Duration::from_secs(
+                subscribers
+                    .iter()
+                    .map(|(c, _)| c.close_retry_secs)
+                    .min()
+                    .unwrap_or(1)
+                    .max(1) as u64,
+            );
+            let mut candle_closes = feed.subscribe_closes();
+            let mut last_analyzed: Option<i64> = None;
             let mut consecutive_errors = 0u32;
             const MAX_CONSECUTIVE_ERRORS: u32 = 5;
 
-            loop {
-                interval_timer.tick().await;
+            'monitor: loop {
+                // 在下一根K线收盘加上宽限时间时唤醒，WebSocket推送收盘事件时提前唤醒并直接使用推送的K线
+                let (closed_at, wake_at) =
+                    next_candle_wake(now_secs(), interval_seconds, grace_secs, last_analyzed);
+                let wait =
+                    tokio::time::sleep(Duration::from_secs((wake_at - now_secs()).max(0) as u64));
+                tokio::pin!(wait);
+                let mut pushed = None;
+                loop {
+                    tokio::select! {
+                        _ = &mut wait => break,
+                        close = candle_closes.recv() => match close {
+                            Ok(close) if close.symbol == symbol
+                                && close.interval == interval_type
+                                && close.kline.timestamp >= closed_at =>
+                            {
+                                debug!("Candle closed for {} at {}", symbol, close.kline.timestamp);
+                                pushed = Some(close.kline);
+                                break;
+                            }
+                            Ok(_) => {}
+                            Err(RecvError::Lagged(skipped)) => {
+                                warn!("Monitor for {} lagged behind {} candle events", symbol, skipped);
+                            }
+                            // feed 持有发送端，正常情况下不会关闭
+                            Err(RecvError::Closed) => {
+                                error!("Candle feed closed unexpectedly, stopping monitor for {}", symbol);
+                                break 'monitor;
+                            }
+                        },
+                    }
+                }
 
                 // 检查是否应该继续运行
-                if !*is_running.read().await {
-                    warn!("Individual symbol monitor for {} is stopping", config.symbol);
+                if !*ctx.is_running.read().await {
+                    warn!("Individual symbol monitor for {} is stopping", symbol);
                     break;
                 }
 
-                // 添加全局超时保护，防止单次检查时间过长
-                let check_result = tokio::time::timeout(
-                    Duration::from_secs(30), // 30秒超时
-                    Self::check_symbol_signals(&db, &gate_service, &dingtalk_service, &config)
-                ).await;
-
-                match check_result {
-                    Ok(Ok(_)) => {
-                        // 成功处理，重置错误计数
+                // 推送的正好是要分析的K线且本地历史完整时不请求交易所；
+                // 推送断开或延迟（定时唤醒）、跳过了K线、本地有缺口时通过REST同步
+                let from_feed = match pushed.filter(|k: &KlineData| k.timestamp == closed_at) {
+                    Some(kline) => {
+                        Self::closed_klines_from_feed(&ctx, &symbol, &interval_type, &kline, count)
+                            .await
+                    }
+                    None => None,
+                };
+                let fetch_result = match from_feed {
+                    Some(klines) => Ok(Ok(Some(klines))),
+                    // 添加全局超时保护，防止单次获取时间过长
+                    None => {
+                        tokio::time::timeout(
+                            Duration::from_secs(30), // 30秒超时
+                            Self::fetch_closed_klines(
+                                &ctx,
+                                &symbol,
+                                &interval_type,
+                                count,
+                                closed_at,
+                            ),
+                        )
+                        .await
+                    }
+                };
+
+                match fetch_result {
+                    Ok(Ok(None)) => {
+                        // 下一根K线也已经收盘时放弃这一根，避免一直重试
+                        if now_secs() >= closed_at + 2 * interval_seconds {
+                            warn!(
+                                "Closed candle {} for {} not available, skipped",
+                                closed_at, symbol
+                            );
+                            last_analyzed = Some(closed_at);
+                        } else {
+                            debug!(
+                                "Closed candle {} for {} not available yet, retrying",
+                                closed_at, symbol
+                            );
+                            tokio::time::sleep(retry_delay).await;
+                        }
+                    }
+                    Ok(Ok(Some(klines))) => {
+                        last_analyzed = Some(closed_at);
+                        // 成功获取，重置错误计数
                         if consecutive_errors > 0 {
-                            info!("Individual symbol monitor for {} recovered after {} errors", config.symbol, consecutive_errors);
+                            info!(
+                                "Individual symbol monitor for {} recovered after {} errors",
+                                symbol, consecutive_errors
+                            );
                             consecutive_errors = 0;
                         }
+
+                        Self::dispatch_klines(&ctx, &subscribers, &klines, closed_at).await;
+                    }
+                    // 限频、交易所服务错误和网络错误在请求时已经重试过，稍后再试，不计入连续失败
+                    Ok(Err(e))
+                        if e.downcast_ref::<GateError>()
+                            .is_some_and(GateError::is_transient) =>
+                    {
+                        warn!(
+                            "Transient error fetching klines for {}, retrying later: {}",
+                            symbol, e
+                        );
+                        tokio::time::sleep(Duration::from_secs(10)).await;
                     }
                     Ok(Err(e)) => {
                         consecutive_errors += 1;
-                        error!("Error in individual monitor checking signals for {} (attempt {}/{}): {}", 
-                               config.symbol, consecutive_errors, MAX_CONSECUTIVE_ERRORS, e);
-                        
+                        error!(
+                            "Error in individual monitor fetching klines for {} (attempt {}/{}): {}",
+                            symbol, consecutive_errors, MAX_CONSECUTIVE_ERRORS, e
+                        );
+
                         if consecutive_errors >= MAX_CONSECUTIVE_ERRORS {
-                            error!("Individual symbol monitor for {} failed {} times consecutively, stopping task", 
-                                   config.symbol, MAX_CONSECUTIVE_ERRORS);
-                            
+                            error!(
+                                "Individual symbol monitor for {} failed {} times consecutively, stopping task",
+                                symbol, MAX_CONSECUTIVE_ERRORS
+                            );
+
                             // 发送警告通知
                             if let Ok(dingtalk) = tokio::time::timeout(
                                 Duration::from_secs(5),
-                                dingtalk_service.read()
-                            ).await {
+                                ctx.dingtalk_service.read(),
+                            )
+                            .await
+                            {
                                 let _ = dingtalk.send_text_message(&format!(
                                     "⚠️ K线监控警告：{}监控任务连续失败{}次，已停止。请检查网络连接和API状态。",
-                                    config.symbol, MAX_CONSECUTIVE_ERRORS
+                                    symbol, MAX_CONSECUTIVE_ERRORS
                                 )).await;
                             }
                             break;
                         }
-                        
+
                         // 错误后稍微延长等待时间，避免频繁重试
-                        tokio::time::sleep(Duration::from_secs(
-                            std::cmp::min(consecutive_errors as u64 * 5, 30)
-                        )).await;
+                        tokio::time::sleep(Duration::from_secs(std::cmp::min(
+                            consecutive_errors as u64 * 5,
+                            30,
+                        )))
+                        .await;
                     }
                     Err(_) => {
                         consecutive_errors += 1;
-                        error!("Timeout in individual monitor checking signals for {} (attempt {}/{})", 
-                               config.symbol, consecutive_errors, MAX_CONSECUTIVE_ERRORS);
-                        
+                        error!(
+                            "Timeout in individual monitor fetching klines for {} (attempt {}/{})",
+                            symbol, consecutive_errors, MAX_CONSECUTIVE_ERRORS
+                        );
+
                         if consecutive_errors >= MAX_CONSECUTIVE_ERRORS {
-                            error!("Individual symbol monitor for {} timed out {} times consecutively, stopping task", 
-                                   config.symbol, MAX_CONSECUTIVE_ERRORS);
+                            error!(
+                                "Individual symbol monitor for {} timed out {} times consecutively, stopping task",
+                                symbol, MAX_CONSECUTIVE_ERRORS
+                            );
                             break;
                         }
                     }
                 }
             }
-            
-            warn!("Individual symbol monitor task for {} has exited", config.symbol);
+
+            warn!("Individual symbol monitor task for {} has exited", symbol);
         })
     }
 
-    async fn check_symbol_signals(
-        db: &SqlitePool,
-        gate_service: &Arc<RwLock<GateService>>,
-        dingtalk_service: &Arc<RwLock<DingTalkService>>,
-        config: &MonitorConfig,
-    ) -> Result<()> {
-        info!(
-            "Checking signals for {} on {}",
-            config.symbol, config.interval_type
-        );
+    /// 把一组已收盘的K线交给模拟撮合、每个订阅的配置和移动止损
+    ///
+    /// 共用的K线从行情交易所获取，不经过模拟交易所，有模拟交易的配置时需要在这里撮合模拟订单。
+    /// `subscribers` 的交易对和K线周期都相同
+    async fn dispatch_klines(
+        ctx: &MonitorContext,
+        subscribers: &[(MonitorConfig, Arc<RwLock<dyn Exchange>>)],
+        klines: &[KlineData],
+        closed_at: i64,
+    ) {
+        let Some((first, _)) = subscribers.first() else {
+            return;
+        };
+        let (symbol, interval_type) = (first.symbol.as_str(), first.interval_type.as_str());
+
+        // 先撮合，信号分析和移动止损看到的是最新的模拟订单状态
+        if let Some(paper) = &ctx.paper
+            && let Err(e) = paper.process_klines(symbol, interval_type, klines).await
+        {
+            error!("Failed to process paper orders for {}: {}", symbol, e);
+        }
+
+        // 同一组K线分发给每个配置，单个配置出错不影响其他配置
+        for (config, exchange) in subscribers {
+            let check_result = tokio::time::timeout(
+                Duration::from_secs(30),
+                Self::check_symbol_signals(ctx, exchange, config, klines, closed_at),
+            )
+            .await;
+            match check_result {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    error!(
+                        "Error checking signals for {} (config {:?}): {}",
+                        symbol, config.id, e
+                    );
+                }
+                Err(_) => {
+                    error!(
+                        "Timeout checking signals for {} (config {:?})",
+                        symbol, config.id
+                    );
+                }
+            }
+        }
 
+        // 移动持仓订单的止损，同一个交易所只处理一次
+        let mut managed = Vec::new();
+        for (_, exchange) in subscribers {
+            let exchange = exchange.read().await;
+            if managed.contains(&exchange.name().to_string()) {
+                continue;
+            }
+            managed.push(exchange.name().to_string());
+            let stop_result = tokio::time::timeout(
+                Duration::from_secs(30),
+                stop_manager::manage_stops(&ctx.db, &*exchange, symbol, interval_type, klines),
+            )
+            .await;
+            match stop_result {
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => {
+                    error!(
+                        "Error managing stops for {} on {}: {}",
+                        symbol,
+                        exchange.name(),
+                        e
+                    );
+                }
+                Err(_) => {
+                    error!(
+                        "Timeout managing stops for {} on {}",
+                        symbol,
+                        exchange.name()
+                    );
+                }
+            }
+        }
+    }
+
+    /// 保存行情推送的已收盘K线，和本地历史一起组成最近 `count` 根K线
+    ///
+    /// 本地历史有缺口或保存失败时返回None，改为通过 `fetch_closed_klines` 同步。
+    /// 序列的最后一根就是推送的K线，它的收盘价作为最新价格
+    async fn closed_klines_from_feed(
+        ctx: &MonitorContext,
+        symbol: &str,
+        interval_type: &str,
+        kline: &KlineData,
+        count: usize,
+    ) -> Option<Vec<KlineData>> {
+        let store = KlineStore::new(ctx.db.clone());
+        match store
+            .append_closed("usdt", symbol, interval_type, kline, count)
+            .await
+        {
+            Ok(Some(klines)) => Some(klines),
+            Ok(None) => {
+                info!(
+                    "Local klines for {} {} have gaps, syncing from exchange",
+                    symbol, interval_type
+                );
+                None
+            }
+            Err(e) => {
+                warn!("Failed to store pushed kline for {}: {}", symbol, e);
+                None
+            }
+        }
+    }
+
+    /// 同步最近 `count` 根K线，交易所还没有返回开始时间为 `closed_at` 的已收盘K线时返回None
+    async fn fetch_closed_klines(
+        ctx: &MonitorContext,
+        symbol: &str,
+        interval_type: &str,
+        count: usize,
+        closed_at: i64,
+    ) -> Result<Option<Vec<KlineData>>> {
         // 获取K线数据 - 使用超时和快速释放锁的模式
         let klines = {
             // 添加锁获取超时
-            let gate_lock_result = tokio::time::timeout(
-                Duration::from_secs(10),
-                gate_service.read()
-            ).await;
-            
-            let gate = match gate_lock_result {
+            let exchange_lock_result =
+                tokio::time::timeout(Duration::from_secs(10), ctx.market_exchange.read()).await;
+
+            let exchange = match exchange_lock_result {
                 Ok(guard) => guard,
                 Err(_) => {
-                    error!("Timeout waiting for gate service lock for symbol: {}", config.symbol);
-                    return Err(anyhow!("Gate service lock timeout"));
+                    error!("Timeout waiting for exchange lock for symbol: {}", symbol);
+                    return Err(anyhow!("Exchange lock timeout"));
                 }
             };
-            
-            // 添加K线数据获取超时
+
+            // 历史K线从本地存储读取，只获取最新的K线；首次运行需要回补，超时时间放宽
+            let store = KlineStore::new(ctx.db.clone());
             let klines_result = tokio::time::timeout(
-                Duration::from_secs(10),
-                gate.get_kline_data(&config.symbol, &config.interval_type, 50, "usdt")
-            ).await;
-            
+                Duration::from_secs(30),
+                store.sync_latest(&*exchange, "usdt", symbol, interval_type, count),
+            )
+            .await;
+
             match klines_result {
                 Ok(Ok(klines)) => klines,
                 Ok(Err(e)) => {
-                    error!("Failed to get kline data for {}: {}", config.symbol, e);
+                    error!("Failed to get kline data for {}: {}", symbol, e);
                     return Err(e);
                 }
                 Err(_) => {
-                    error!("Timeout getting kline data for symbol: {}", config.symbol);
+                    error!("Timeout getting kline data for symbol: {}", symbol);
                     return Err(anyhow!("Kline data fetch timeout"));
                 }
             }
-        }; // gate锁在这里自动释放
+        }; // exchange锁在这里自动释放
+
+        // 本地存储只保存已收盘的K线，没有这一根说明交易所还没有生成
+        if klines.iter().any(|k| k.timestamp == closed_at) {
+            Ok(Some(klines))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// 用共用的K线序列分析开始时间为 `closed_at` 的已收盘K线
+    async fn check_symbol_signals(
+        ctx: &MonitorContext,
+        exchange: &Arc<RwLock<dyn Exchange>>,
+        config: &MonitorConfig,
+        klines: &[KlineData],
+        closed_at: i64,
+    ) -> Result<()> {
+        info!(
+            "Checking signals for {} on {}",
+            config.symbol, config.interval_type
+        );
 
-        if klines.len() < 5 {
+        let Some(index) = klines.iter().rposition(|k| k.timestamp == closed_at) else {
+            return Ok(());
+        };
+        if index < 4 {
             warn!("Insufficient kline data for {}", config.symbol);
             return Ok(());
         }
 
-        // 分析最新的K线
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as i64;
+        // 最后一根可能是当前未收盘的K线，它的收盘价就是最新价格
+        let last_close = klines[klines.len() - 1].close;
+        Self::handle_closed_kline(
+            ctx,
+            exchange,
+            config,
+            &klines[index],
+            &klines[..index],
+            last_close,
+        )
+        .await
+    }
 
-        let interval_seconds = config.interval_type_to_seconds();
-        let last_kline = &klines[klines.len() - 1];
+    /// 运行配置的策略。脚本策略从数据库加载脚本，在阻塞线程池里限时运行，出错或超时只记录日志
+    async fn run_strategy(
+        db: &SqlitePool,
+        config: &MonitorConfig,
+        latest_kline: &KlineData,
+        historical_klines: &[KlineData],
+    ) -> Option<Signal> {
+        if config.strategy != "script" {
+            return Self::analyze_kline_signal(latest_kline, historical_klines, config);
+        }
 
-        // 计算这个K线应该结束的时间
-        let kline_end_time = last_kline.timestamp + interval_seconds;
+        let script =
+            match StrategyScriptRepository::get(db, &config.script_name, config.script_version)
+                .await
+            {
+                Ok(Some(script)) => script,
+                Ok(None) => {
+                    warn!(
+                        "Script {} v{} not found for {}",
+                        config.script_name, config.script_version, config.symbol
+                    );
+                    return None;
+                }
+                Err(e) => {
+                    error!("Failed to load script {}: {}", config.script_name, e);
+                    return None;
+                }
+            };
+        let script = match ScriptStrategy::compile(&script) {
+            Ok(script) => script,
+            Err(e) => {
+                warn!(
+                    "Failed to compile script {} v{}: {}",
+                    script.name, script.version, e
+                );
+                return None;
+            }
+        };
+        let contract = ApiKeyRepository::get_contract_by_symbol(db, &config.symbol)
+            .await
+            .ok()
+            .flatten();
+
+        let symbol = config.symbol.clone();
+        let config = config.clone();
+        let latest = latest_kline.clone();
+        let historical = historical_klines.to_vec();
+        let result = tokio::task::spawn_blocking(move || {
+            Self::detect_signal(
+                &latest,
+                &historical,
+                &config,
+                Some(&script),
+                contract.as_ref(),
+            )
+        })
+        .await;
+        match result {
+            Ok(Ok(signal)) => signal,
+            Ok(Err(e)) => {
+                warn!("Script strategy failed for {}: {}", symbol, e);
+                None
+            }
+            Err(e) => {
+                error!("Script task failed for {}: {}", symbol, e);
+                None
+            }
+        }
+    }
 
-        // 使用已收盘的K线数据
-        let (latest_kline, historical_klines) = if now < kline_end_time {
-            (&klines[klines.len() - 2], &klines[..klines.len() - 2])
-        } else {
-            (&klines[klines.len() - 1], &klines[..klines.len() - 1])
+    /// 用信号K线收盘时已经收盘的大周期K线确认信号方向，获取K线失败时视为没有通过
+    async fn confirm_timeframe(
+        db: &SqlitePool,
+        exchange: &Arc<RwLock<dyn Exchange>>,
+        config: &MonitorConfig,
+        signal: &Signal,
+    ) -> Confirmation {
+        let count = confirmation::required_klines(config);
+        let store = KlineStore::new(db.clone());
+        let klines_result = {
+            let exchange = exchange.read().await;
+            store
+                .sync_latest(
+                    &*exchange,
+                    "usdt",
+                    &config.symbol,
+                    &config.confirm_interval,
+                    count,
+                )
+                .await
+        };
+        let klines = match klines_result {
+            Ok(klines) => klines,
+            Err(e) => {
+                warn!(
+                    "Failed to get {} klines to confirm signal for {}: {}",
+                    config.confirm_interval, config.symbol, e
+                );
+                Vec::new()
+            }
         };
 
+        let close_time = signal.timestamp + config.interval_type_to_seconds();
+        let closed = confirmation::closed_before(
+            &klines,
+            interval_to_seconds(&config.confirm_interval),
+            close_time,
+        );
+        confirmation::confirm(config, &signal.direction, closed)
+    }
+
+    /// 处理一根已收盘的K线：检测信号、发送通知，并按配置自动下单
+    async fn handle_closed_kline(
+        ctx: &MonitorContext,
+        exchange: &Arc<RwLock<dyn Exchange>>,
+        config: &MonitorConfig,
+        latest_kline: &KlineData,
+        historical_klines: &[KlineData],
+        last_close: f64,
+    ) -> Result<()> {
+        let (db, dingtalk_service) = (&ctx.db, &ctx.dingtalk_service);
+        let now = now_secs();
+
         // 检查是否满足信号条件
-        if let Some(signal) = Self::analyze_kline_signal(latest_kline, historical_klines, config) {
+        if let Some(signal) = Self::run_strategy(db, config, latest_kline, historical_klines).await
+        {
             // 检查是否已经记录过这个信号（防重复）
             if SignalRepository::exists(
-                &db,
+                db,
                 &config.symbol,
                 signal.timestamp,
                 &config.interval_type,
+                &signal.strategy,
             )
             .await?
             {
@@ -686,6 +1195,30 @@ impl MonitorService {
                 return Ok(());
             }
 
+            // 自定义入场规则，求值出错时记录到配置上，在监控页面显示
+            if !config.entry_rule.trim().is_empty() {
+                let result =
+                    rules::evaluate(&config.entry_rule, &signal, historical_klines, latest_kline);
+                if let Some(id) = config.id {
+                    let error = result.as_ref().err().map(|e| e.to_string());
+                    MonitorConfigRepository::set_rule_error(db, id, error.as_deref()).await?;
+                }
+                match result {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        debug!(
+                            "Signal filtered!! Entry rule not matched for {}",
+                            config.symbol
+                        );
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        warn!("Failed to evaluate entry rule for {}: {}", config.symbol, e);
+                        return Ok(());
+                    }
+                }
+            }
+
             let should_place_order = place_order_by_long_short_config(config, &signal);
 
             if !should_place_order {
@@ -701,7 +1234,7 @@ impl MonitorService {
             }
 
             // 利润释放够手续费
-            let expect_profit = signal.main_profit / last_kline.close * 100.0;
+            let expect_profit = expected_profit_rate(&signal, last_close);
             if expect_profit <= config.expected_profit_rate {
                 warn!(
                     "Signal filtered!! Expected profit ({:.2}%) is below the threshold ({:.2}%) for {}",
@@ -710,146 +1243,543 @@ impl MonitorService {
                 return Ok(());
             }
 
+            // 多周期确认：大周期方向一致才下单，确认依据和信号一起保存
+            let mut signal = signal;
+            if !config.confirm_interval.is_empty() {
+                let confirmation = Self::confirm_timeframe(db, exchange, config, &signal).await;
+                signal.confirmation = serde_json::to_string(&confirmation).ok();
+                if let Some(reason) = confirmation.blocked_reason() {
+                    signal.blocked_reason = Some(match signal.blocked_reason.take() {
+                        Some(filtered) => format!("{}; {}", filtered, reason),
+                        None => reason,
+                    });
+                }
+            }
+
             // 保存信号到数据库
             let signal_id = SignalRepository::save(db, &signal).await?;
 
+            // 没有通过指标过滤或多周期确认的信号只记录，不通知也不下单
+            if let Some(reason) = &signal.blocked_reason {
+                warn!("Signal filtered!! {} for {}", reason, config.symbol);
+                return Ok(());
+            }
+
             info!("New signal detected for {}: {:?}", config.symbol, signal);
 
             // 发送钉钉通知
             if config.enable_dingtalk {
                 // 使用超时和快速释放锁
-                let dingtalk_result = tokio::time::timeout(
-                    Duration::from_secs(10),
-                    async {
-                        let dingtalk = dingtalk_service.read().await;
-                        if dingtalk.has_webhook() {
-                            dingtalk.send_signal_alert(&signal).await
-                        } else {
-                            Ok(())
-                        }
+                let dingtalk_result = tokio::time::timeout(Duration::from_secs(10), async {
+                    let dingtalk = dingtalk_service.read().await;
+                    if dingtalk.has_webhook() {
+                        dingtalk.send_signal_alert(&signal).await
+                    } else {
+                        Ok(())
                     }
-                ).await;
-                
+                })
+                .await;
+
                 match dingtalk_result {
                     Ok(Ok(_)) => {
-                        info!("DingTalk signal alert sent successfully for {}", config.symbol);
+                        info!(
+                            "DingTalk signal alert sent successfully for {}",
+                            config.symbol
+                        );
                     }
                     Ok(Err(e)) => {
                         error!("Failed to send DingTalk alert for {}: {}", config.symbol, e);
                     }
                     Err(_) => {
-                        error!("Timeout sending DingTalk alert for symbol: {}", config.symbol);
+                        error!(
+                            "Timeout sending DingTalk alert for symbol: {}",
+                            config.symbol
+                        );
                     }
                 }
             }
 
-            let contract = ApiKeyRepository::get_contract_by_symbol(db, &signal.symbol).await?;
-            if contract.is_none() {
+            let Some(contract) =
+                ApiKeyRepository::get_contract_by_symbol(db, &signal.symbol).await?
+            else {
                 warn!("No contract found for symbol: {}", signal.symbol);
                 return Ok(());
-            }
+            };
 
             // 如果启用自动交易，生成交易信号
-            if config.enable_auto_trading {
-                if let Some(trading_signal) = Self::generate_trading_signal(
+            if config.enable_auto_trading
+                && let Some(mut trading_signal) = Self::generate_trading_signal(
                     &signal,
                     config,
-                    contract.unwrap().order_price_round,
-                ) {
-                    // 下单 - 使用超时和快速释放锁
+                    contract.order_price_round.clone(),
+                )
+            {
+                // 按仓位计算方式确定下单张数
+                let sizing_result = {
+                    let exchange = exchange.read().await;
+                    risk::order_size_for(&*exchange, config, &contract, &trading_signal).await
+                };
+
+                // 风控检查，拦截时记录原因并通知，信号保留
+                // 通过时占用的名额要保持到订单记录保存之后，同时下单的其他配置才能看到这一单
+                let exchange_name = exchange.read().await.name().to_string();
+                let decision = match sizing_result {
+                    Ok(size) => {
+                        trading_signal.order_size = size;
+                        match ctx.risk.check(&exchange_name, &config.symbol, now).await {
+                            Ok(decision) => decision,
+                            Err(e) => {
+                                // 风控状态查不到时不冒险下单
+                                error!("Failed to check risk for {}: {}", config.symbol, e);
+                                RiskDecision::Blocked(format!("风控检查失败: {}", e))
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        error!(
+                            "Failed to calculate order size for {}: {}",
+                            config.symbol, e
+                        );
+                        RiskDecision::Blocked(format!("仓位计算失败: {}", e))
+                    }
+                };
+                let _permit = match decision {
+                    RiskDecision::Allowed(permit) => permit,
+                    RiskDecision::Blocked(reason) => {
+                        warn!(
+                            "Order blocked by risk manager for {}: {}",
+                            config.symbol, reason
+                        );
+                        SignalRepository::set_blocked_reason(db, signal_id, &reason).await?;
+
+                        if config.enable_dingtalk {
+                            let dingtalk_result =
+                                tokio::time::timeout(Duration::from_secs(10), async {
+                                    let dingtalk = dingtalk_service.read().await;
+                                    if dingtalk.has_webhook() {
+                                        dingtalk.send_risk_alert(&trading_signal, &reason).await
+                                    } else {
+                                        Ok(())
+                                    }
+                                })
+                                .await;
+
+                            match dingtalk_result {
+                                Ok(Ok(_)) => {}
+                                Ok(Err(e)) => {
+                                    error!(
+                                        "Failed to send DingTalk risk alert for {}: {}",
+                                        config.symbol, e
+                                    );
+                                }
+                                Err(_) => {
+                                    error!(
+                                        "Timeout sending DingTalk risk alert for symbol: {}",
+                                        config.symbol
+                                    );
+                                }
+                            }
+                        }
+                        return Ok(());
+                    }
+                };
+
+                // 下单 - 使用超时和快速释放锁
+                // 分批止盈需要单独的只减仓触发委托，只有签名API下单支持，保存配置时已经检查
+                let ladder = if take_profit::supports_ladder(config) {
+                    config.take_profit_ladder.trim()
+                } else {
+                    ""
+                };
+                let order_request = OrderRequest {
+                    symbol: trading_signal.symbol.clone(),
+                    order_type: config.order_type.clone(),
+                    side: if trading_signal.signal_type == "long" {
+                        "buy".to_string()
+                    } else {
+                        "sell".to_string()
+                    },
+                    price: trading_signal.entry_price,
+                    size: trading_signal.order_size,
+                    // 分批止盈的每一批由对账任务在成交后创建
+                    take_profit: ladder.is_empty().then_some(trading_signal.take_profit),
+                    stop_loss: Some(trading_signal.stop_loss),
+                    // 自定义ID用于对账时查询订单
+                    client_order_id: Some(format!("t-gk-{}-{}", signal_id, now % 1_000_000)),
+                    // 模拟交易由模拟账户自己处理止盈止损
+                    execution_mode: if config.paper_trading || config.execution_mode.is_empty() {
+                        "web".to_string()
+                    } else {
+                        config.execution_mode.clone()
+                    },
+                };
+                let (exchange_name, order_result) = {
+                    let exchange = exchange.read().await;
                     let order_result = tokio::time::timeout(
                         Duration::from_secs(30),
-                        async {
-                            let order_data = build_order_data(
-                                &trading_signal.symbol,
-                                &config.order_type,
-                                if trading_signal.signal_type == "long" {
-                                    "buy"
-                                } else {
-                                    "sell"
-                                },
-                                trading_signal.entry_price,
-                                trading_signal.order_size,
-                                Some(trading_signal.take_profit),
-                                Some(trading_signal.stop_loss),
+                        exchange.place_order(&order_request, "usdt"),
+                    )
+                    .await;
+                    (exchange.name().to_string(), order_result)
+                };
+
+                let mut placement = OrderPlacement {
+                    exchange: exchange_name,
+                    client_order_id: order_request.client_order_id.clone(),
+                    execution_mode: order_request.execution_mode.clone(),
+                    status: "rejected".to_string(),
+                    interval_type: config.interval_type.clone(),
+                    break_even_r: config.break_even_r,
+                    trailing_mode: config.trailing_mode.clone(),
+                    trailing_value: config.trailing_value,
+                    take_profit_ladder: ladder.to_string(),
+                    ladder_break_even: config.ladder_break_even,
+                    ..Default::default()
+                };
+                match order_result {
+                    Ok(Ok(response)) => {
+                        if response.success {
+                            info!(
+                                "Order placed successfully for {}: {:?}",
+                                config.symbol, response.raw
+                            );
+                            placement.status = "pending".to_string();
+                            placement.exchange_order_id = response.order_id;
+                            placement.take_profit_order_id = response.take_profit_order_id;
+                            placement.stop_loss_order_id = response.stop_loss_order_id;
+                        } else {
+                            error!(
+                                "Failed to place order for {}: {:?}",
+                                config.symbol, response.raw
                             );
+                            placement.error_message = Some(response.message);
+                        }
+                    }
+                    Ok(Err(e)) => {
+                        error!("Failed to place order for {}: {}", config.symbol, e);
+                        // 下单失败不应该阻止后续处理，继续执行
+                        placement.error_message = Some(e.to_string());
+                        // 余额不足时继续下单也会失败，暂停自动下单等待人工处理
+                        if matches!(
+                            e.downcast_ref::<GateError>(),
+                            Some(GateError::InsufficientBalance(_))
+                        ) {
+                            Self::halt_on_insufficient_balance(
+                                db,
+                                dingtalk_service,
+                                &config.symbol,
+                                &e,
+                            )
+                            .await;
+                        }
+                    }
+                    Err(_) => {
+                        error!("Timeout placing order for symbol: {}", config.symbol);
+                        // 超时也不应该阻止后续处理，继续执行
+                        // 超时不代表下单失败，交给对账任务按自定义ID确认
+                        placement.status = "pending".to_string();
+                        placement.error_message = Some("下单超时".to_string());
+                    }
+                }
 
-                            let gate_service = gate_service.read().await;
-                            gate_service
-                                .place_order_with_stop_profit_loss(order_data, "usdt")
-                                .await
+                // 发送钉钉通知 - 使用超时和快速释放锁
+                if config.enable_dingtalk {
+                    let dingtalk_result = tokio::time::timeout(Duration::from_secs(10), async {
+                        let dingtalk = dingtalk_service.read().await;
+                        if dingtalk.has_webhook() {
+                            dingtalk.send_trading_signal(&trading_signal).await
+                        } else {
+                            Ok(())
                         }
-                    ).await;
-
-                    match order_result {
-                        Ok(Ok(response)) => {
-                            if response.get("code").cloned() == Some(200.into()) {
-                                info!("Order placed successfully for {}: {:?}", config.symbol, response);
-                            } else {
-                                error!("Failed to place order for {}: {:?}", config.symbol, response);
-                            }
+                    })
+                    .await;
+
+                    match dingtalk_result {
+                        Ok(Ok(_)) => {
+                            info!(
+                                "DingTalk trading signal sent successfully for {}",
+                                config.symbol
+                            );
                         }
                         Ok(Err(e)) => {
-                            error!("Failed to place order for {}: {}", config.symbol, e);
-                            // 下单失败不应该阻止后续处理，继续执行
+                            error!(
+                                "Failed to send DingTalk trading alert for {}: {}",
+                                config.symbol, e
+                            );
                         }
                         Err(_) => {
-                            error!("Timeout placing order for symbol: {}", config.symbol);
-                            // 超时也不应该阻止后续处理，继续执行
-                        }
-                    }
-
-                    // 发送钉钉通知 - 使用超时和快速释放锁
-                    if config.enable_dingtalk {
-                        let dingtalk_result = tokio::time::timeout(
-                            Duration::from_secs(10),
-                            async {
-                                let dingtalk = dingtalk_service.read().await;
-                                if dingtalk.has_webhook() {
-                                    dingtalk.send_trading_signal(&trading_signal).await
-                                } else {
-                                    Ok(())
-                                }
-                            }
-                        ).await;
-                        
-                        match dingtalk_result {
-                            Ok(Ok(_)) => {
-                                info!("DingTalk trading signal sent successfully for {}", config.symbol);
-                            }
-                            Ok(Err(e)) => {
-                                error!("Failed to send DingTalk trading alert for {}: {}", config.symbol, e);
-                            }
-                            Err(_) => {
-                                error!("Timeout sending DingTalk trading alert for symbol: {}", config.symbol);
-                            }
+                            error!(
+                                "Timeout sending DingTalk trading alert for symbol: {}",
+                                config.symbol
+                            );
                         }
                     }
+                }
 
-                    // 保存订单记录
-                    if let Err(e) = OrderRepository::save_from_trading_signal(db, &trading_signal, signal_id).await {
-                        error!("Failed to save trading signal for {}: {}", config.symbol, e);
-                    }
-
-                    info!("Trading signal generated for {}: {:?}", config.symbol, trading_signal);
+                // 保存订单记录，包括下单失败的订单
+                if let Err(e) = OrderRepository::save_from_trading_signal(
+                    db,
+                    &trading_signal,
+                    signal_id,
+                    &placement,
+                )
+                .await
+                {
+                    error!("Failed to save trading signal for {}: {}", config.symbol, e);
                 }
+
+                info!(
+                    "Trading signal generated for {}: {:?}",
+                    config.symbol, trading_signal
+                );
             }
         }
 
         Ok(())
     }
 
-    fn analyze_kline_signal(
+    /// 暂停所有自动下单并发送钉钉通知，需要在风控页面或 `flatten --resume` 恢复
+    async fn halt_on_insufficient_balance(
+        db: &SqlitePool,
+        dingtalk_service: &Arc<RwLock<DingTalkService>>,
+        symbol: &str,
+        error: &anyhow::Error,
+    ) {
+        if let Err(e) = RiskRepository::set_halted(db, true, Some("余额不足")).await {
+            error!("Failed to halt trading: {}", e);
+            return;
+        }
+        warn!(
+            "Insufficient balance placing order for {}, automatic trading halted",
+            symbol
+        );
+
+        let send_result = tokio::time::timeout(Duration::from_secs(10), async {
+            let dingtalk = dingtalk_service.read().await;
+            if dingtalk.has_webhook() {
+                dingtalk
+                    .send_text_message(&format!(
+                        "K线监控：{} 下单余额不足，已暂停自动下单，请充值后恢复: {}",
+                        symbol, error
+                    ))
+                    .await
+            } else {
+                Ok(())
+            }
+        })
+        .await;
+        match send_result {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => error!("Failed to send DingTalk message: {}", e),
+            Err(_) => error!("Timeout sending DingTalk insufficient balance notification"),
+        }
+    }
+
+    /// 按配置的策略分析一根已收盘的K线，监控和回测共用。
+    /// 内置策略直接识别；脚本策略运行 `script`，脚本缺失、出错或超时时返回Err
+    pub fn detect_signal(
+        latest: &KlineData,
+        historical: &[KlineData],
+        config: &MonitorConfig,
+        script: Option<&ScriptStrategy>,
+        contract: Option<&Contract>,
+    ) -> Result<Option<Signal>> {
+        if config.strategy != "script" {
+            return Ok(Self::analyze_kline_signal(latest, historical, config));
+        }
+        let script = script.ok_or_else(|| anyhow!("脚本 {} 不存在", config.script_name))?;
+        let Some(output) = script.run(config, historical, latest, contract)? else {
+            return Ok(None);
+        };
+        let (shadow_type, main_shadow_length, shadow_ratio) = Self::shadow_stats(latest);
+        let detection = Detection {
+            shadow_type,
+            main_shadow_length,
+            // 没有给出止盈价时按入场价到止损价的距离计算
+            main_profit: (output.entry.unwrap_or(latest.close) - output.stop).abs(),
+            shadow_ratio,
+            pattern: Some(script.name.clone()),
+            direction: output.direction,
+            stop_price: Some(output.stop),
+            entry_price: output.entry,
+            target_price: output.target,
+            script_version: Some(script.version),
+        };
+        Ok(Self::build_signal(
+            latest, historical, config, "script", detection,
+        ))
+    }
+
+    /// 分析一根已收盘的K线是否满足配置的内置策略：长影线，或者以这根K线结束的K线形态
+    pub fn analyze_kline_signal(
+        latest: &KlineData,
+        historical: &[KlineData],
+        config: &MonitorConfig,
+    ) -> Option<Signal> {
+        let strategy = if config.strategy.is_empty() {
+            "long_shadow"
+        } else {
+            config.strategy.as_str()
+        };
+
+        let detection = if strategy == "long_shadow" {
+            let (shadow_type, main_shadow_length, main_profit, shadow_ratio) =
+                Self::long_shadow(latest, config)?;
+            Detection {
+                shadow_type,
+                main_shadow_length,
+                main_profit,
+                shadow_ratio,
+                direction: if shadow_type == "lower" {
+                    "long"
+                } else {
+                    "short"
+                },
+                ..Default::default()
+            }
+        } else {
+            // 最多的形态需要三根K线
+            let mut window = historical[historical.len().saturating_sub(2)..].to_vec();
+            window.push(latest.clone());
+            let matched = patterns::detect(strategy, &window)?;
+            let (shadow_type, main_shadow_length, shadow_ratio) = Self::shadow_stats(latest);
+            Detection {
+                shadow_type,
+                main_shadow_length,
+                // 止盈按入场价到形态止损价的距离计算
+                main_profit: (latest.close - matched.stop_price).abs(),
+                shadow_ratio,
+                pattern: Some(matched.pattern.to_string()),
+                direction: matched.direction,
+                stop_price: Some(matched.stop_price),
+                ..Default::default()
+            }
+        };
+        Self::build_signal(latest, historical, config, strategy, detection)
+    }
+
+    /// 非长影线策略的影线统计，返回 (较长的影线类型, 影线长度, 和另一边影线的比例)
+    fn shadow_stats(latest: &KlineData) -> (&'static str, f64, f64) {
+        let upper_shadow_length = latest.high - latest.close.max(latest.open);
+        let lower_shadow_length = latest.open.min(latest.close) - latest.low;
+        let (shadow_type, main_shadow_length, other_shadow_length) =
+            if upper_shadow_length >= lower_shadow_length {
+                ("upper", upper_shadow_length, lower_shadow_length)
+            } else {
+                ("lower", lower_shadow_length, upper_shadow_length)
+            };
+        let shadow_ratio = if other_shadow_length > 0.0 {
+            main_shadow_length / other_shadow_length
+        } else {
+            main_shadow_length * 10000.0
+        };
+        (shadow_type, main_shadow_length, shadow_ratio)
+    }
+
+    /// 策略识别出信号后的共同检查：成交量和指标过滤，通过成交量检查后生成信号
+    fn build_signal(
         latest: &KlineData,
         historical: &[KlineData],
         config: &MonitorConfig,
+        strategy: &str,
+        detection: Detection,
     ) -> Option<Signal> {
-        // 计算影线和实体长度
         let body_length = (latest.close - latest.open).abs();
-        // 影线在上面
+
+        // 获取所需的阴线，通过config.history_hours和config.interval_type来确定需要多少历史数据
+        let required_history = config.required_history();
+
+        if required_history > historical.len() {
+            debug!(
+                "Not enough historical data, symbol: {}, required: {}, available: {}",
+                config.symbol,
+                required_history,
+                historical.len()
+            );
+            return None;
+        }
+
+        let historical_data = &historical[historical.len().saturating_sub(required_history)..];
+
+        // 计算平均成交量
+        let avg_volume =
+            historical_data.iter().map(|k| k.volume).sum::<f64>() / historical_data.len() as f64;
+
+        let volume_multiplier = latest.volume / avg_volume;
+
+        // 检查成交量是否满足条件
+        if volume_multiplier < config.volume_multiplier {
+            debug!(
+                "volume multiplier :{} < {} ",
+                volume_multiplier, config.volume_multiplier
+            );
+            return None;
+        }
+
+        // 确定K线类型
+        let candle_type = if latest.close > latest.open {
+            "bull"
+        } else {
+            "bear"
+        };
+
+        // 指标过滤：结果和信号一起保存，没有通过时记录拦截原因
+        let mut window = historical[historical
+            .len()
+            .saturating_sub(config.required_klines() - 1)..]
+            .to_vec();
+        window.push(latest.clone());
+        let filters =
+            indicators::apply_filters(config, detection.direction, &window, required_history + 1);
+        let indicator_filters = if filters.is_empty() {
+            None
+        } else {
+            serde_json::to_string(&filters).ok()
+        };
+
+        Some(Signal {
+            id: 0, // 将在数据库插入时设置
+            symbol: config.symbol.clone(),
+            timestamp: latest.timestamp,
+            open_price: latest.open,
+            high_price: latest.high,
+            low_price: latest.low,
+            close_price: latest.close,
+            volume: latest.volume,
+            interval_type: config.interval_type.clone(),
+            candle_type: candle_type.to_string(),
+            shadow_type: detection.shadow_type.to_string(),
+            body_length,
+            main_shadow_length: detection.main_shadow_length,
+            main_profit: detection.main_profit,
+            shadow_ratio: detection.shadow_ratio,
+            volume_multiplier,
+            avg_volume: Some(avg_volume),
+            blocked_reason: indicators::failed_filters(&filters),
+            strategy: strategy.to_string(),
+            pattern: detection.pattern,
+            direction: detection.direction.to_string(),
+            stop_price: detection.stop_price,
+            indicator_filters,
+            confirmation: None,
+            script_version: detection.script_version,
+            entry_price: detection.entry_price,
+            target_price: detection.target_price,
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs() as i64,
+        })
+    }
+
+    /// 长影线策略：主影线足够长，并且和另一边影线的比例满足配置，返回 (影线类型, 主影线长度, 潜在利润, 影线比例)
+    fn long_shadow(
+        latest: &KlineData,
+        config: &MonitorConfig,
+    ) -> Option<(&'static str, f64, f64, f64)> {
+        let body_length = (latest.close - latest.open).abs();
         let upper_shadow_length = latest.high - latest.close.max(latest.open);
         let upper_profit = latest.high - latest.close;
-        // 影线在下面
         let lower_shadow_length = latest.open.min(latest.close) - latest.low;
         let lower_profit = latest.close - latest.low;
 
@@ -859,7 +1789,7 @@ impl MonitorService {
 
         // 阴线/实体不符合
         if !has_long_upper && !has_long_lower {
-            warn!(
+            debug!(
                 "signal: {} shadow body ratio < {} ",
                 config.symbol, config.main_shadow_body_ratio
             );
@@ -918,85 +1848,25 @@ impl MonitorService {
 
         // 检查影线比例是否满足条件
         if shadow_ratio < config.shadow_ratio {
-            warn!("shadow ratio :{} < {} ", shadow_ratio, config.shadow_ratio);
-            return None;
-        }
-
-        // 获取所需的阴线，通过config.history_hours和config.interval_type来确定需要多少历史数据
-        let required_history =
-            (config.history_hours * 60.0 / config.interval_type_to_minutes()) as usize;
-
-        if required_history > historical.len() {
-            warn!(
-                "Not enough historical data, symbol: {}, required: {}, available: {}",
-                config.symbol,
-                required_history,
-                historical.len()
-            );
+            debug!("shadow ratio :{} < {} ", shadow_ratio, config.shadow_ratio);
             return None;
         }
 
-        let historical_data =
-            &historical[historical.len().saturating_sub(required_history as usize)..];
-
-        // 计算平均成交量
-        let avg_volume =
-            historical_data.iter().map(|k| k.volume).sum::<f64>() / historical_data.len() as f64;
-
-        let volume_multiplier = latest.volume / avg_volume;
-
-        // 检查成交量是否满足条件
-        if volume_multiplier < config.volume_multiplier {
-            warn!(
-                "volume multiplier :{} < {} ",
-                volume_multiplier, config.volume_multiplier
-            );
-            return None;
-        }
-
-        // 确定K线类型
-        let candle_type = if latest.close > latest.open {
-            "bull"
-        } else {
-            "bear"
-        };
-
-        Some(Signal {
-            id: 0, // 将在数据库插入时设置
-            symbol: config.symbol.clone(),
-            timestamp: latest.timestamp,
-            open_price: latest.open,
-            high_price: latest.high,
-            low_price: latest.low,
-            close_price: latest.close,
-            volume: latest.volume,
-            interval_type: config.interval_type.clone(),
-            candle_type: candle_type.to_string(),
-            shadow_type: shadow_type.to_string(),
-            body_length,
-            main_shadow_length,
-            main_profit,
-            shadow_ratio,
-            volume_multiplier,
-            avg_volume: Some(avg_volume),
-            created_at: SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs() as i64,
-        })
+        Some((shadow_type, main_shadow_length, main_profit, shadow_ratio))
     }
 
-    fn generate_trading_signal(
+    /// 根据信号生成交易信号（方向、入场价、止损价、止盈价）
+    pub fn generate_trading_signal(
         signal: &Signal,
         config: &MonitorConfig,
         order_price_round: String, // 订单价格精度
     ) -> Option<TradingSignal> {
-        // 根据影线类型确定交易方向
-        let signal_type = match signal.shadow_type.as_str() {
-            "upper" => "short",
-            "lower" => "long",
+        // 交易方向由策略给出：长影线策略下影线做多、上影线做空
+        let signal_type = match signal.direction.as_str() {
+            "long" => "long",
+            "short" => "short",
             _ => {
-                warn!("Unknown shadow type: {}", signal.shadow_type);
+                warn!("Unknown signal direction: {}", signal.direction);
                 return None;
             }
         };
@@ -1020,16 +1890,17 @@ impl MonitorService {
             _ => {}
         }
 
-        // 计算入场价、止损价和止盈价
-        let entry_price = signal.close_price;
+        // 计算入场价、止损价和止盈价，脚本给出的价格优先
+        let entry_price = signal.entry_price.unwrap_or(signal.close_price);
+        // 形态策略的止损在形态的另一端，长影线策略在这根K线的最低/最高价
         let (stop_loss, take_profit) = if signal_type == "long" {
-            let stop_loss = signal.low_price;
+            let stop_loss = signal.stop_price.unwrap_or(signal.low_price);
             let take_profit = entry_price + signal.main_profit * config.risk_reward_ratio;
-            (stop_loss, take_profit)
+            (stop_loss, signal.target_price.unwrap_or(take_profit))
         } else {
-            let stop_loss = signal.high_price;
+            let stop_loss = signal.stop_price.unwrap_or(signal.high_price);
             let take_profit = entry_price - signal.main_profit * config.risk_reward_ratio;
-            (stop_loss, take_profit)
+            (stop_loss, signal.target_price.unwrap_or(take_profit))
         };
 
         // 根据精度调整价格, 四舍五入
@@ -1045,12 +1916,29 @@ impl MonitorService {
             "low"
         };
 
-        let reason = format!(
-            "检测到{}影线信号，影线比例{:.1}:1，成交量倍数{:.1}x",
-            if signal_type == "long" { "下" } else { "上" },
-            signal.shadow_ratio,
-            signal.volume_multiplier
-        );
+        let reason = match &signal.pattern {
+            Some(script) if signal.strategy == "script" => format!(
+                "脚本{} v{}给出{}信号",
+                script,
+                signal.script_version.unwrap_or(0),
+                if signal_type == "long" {
+                    "做多"
+                } else {
+                    "做空"
+                }
+            ),
+            Some(pattern) => format!(
+                "检测到{}形态，成交量倍数{:.1}x",
+                patterns::label(pattern),
+                signal.volume_multiplier
+            ),
+            None => format!(
+                "检测到{}影线信号，影线比例{:.1}:1，成交量倍数{:.1}x",
+                if signal_type == "long" { "下" } else { "上" },
+                signal.shadow_ratio,
+                signal.volume_multiplier
+            ),
+        };
 
         Some(TradingSignal {
             symbol: signal.symbol.clone(),
@@ -1066,28 +1954,30 @@ impl MonitorService {
     }
 }
 
+/// 按当前价格计算信号的预计收益率(%)
+pub fn expected_profit_rate(signal: &Signal, price: f64) -> f64 {
+    signal.main_profit / price * 100.0
+}
+
 pub fn place_order_by_long_short_config(config: &MonitorConfig, signal: &Signal) -> bool {
-    let should_place_order = if !config.long_k_long && !config.short_k_short {
+    if !config.long_k_long && !config.short_k_short {
         // 两个都没配置，默认下订单
         true
     } else if config.long_k_long && config.short_k_short {
         // 两个都配置了，满足其中一个条件就下订单
-        (config.long_k_long && signal.candle_type == "bull" && signal.shadow_type == "lower")
-            || (config.short_k_short
-                && signal.candle_type == "bear"
-                && signal.shadow_type == "upper")
+        (config.long_k_long && signal.candle_type == "bull" && signal.direction == "long")
+            || (config.short_k_short && signal.candle_type == "bear" && signal.direction == "short")
     } else if config.long_k_long {
         // 只配置了long_k_long，只有阳线才下订单
-        (signal.shadow_type == "upper")
-            || (signal.candle_type == "bull" && signal.shadow_type == "lower")
+        (signal.direction == "short")
+            || (signal.candle_type == "bull" && signal.direction == "long")
     } else if config.short_k_short {
         // 只配置了short_k_short，只有阴线才下订单
-        (signal.shadow_type == "lower")
-            || (signal.candle_type == "bear" && signal.shadow_type == "upper")
+        (signal.direction == "long")
+            || (signal.candle_type == "bear" && signal.direction == "short")
     } else {
         false
-    };
-    return should_place_order;
+    }
 }
 
 #[cfg(test)]
@@ -1106,25 +1996,30 @@ mod tests {
     }
 
     #[tokio::test]
+    #[allow(clippy::bool_assert_comparison)]
     async fn test_place_order_by_long_short_config() {
         let signal1 = Signal {
             candle_type: "bull".into(),
             shadow_type: "lower".into(),
+            direction: "long".into(),
             ..Default::default()
         };
         let signal2 = Signal {
             candle_type: "bull".into(),
             shadow_type: "upper".into(),
+            direction: "short".into(),
             ..Default::default()
         };
         let signal3 = Signal {
             candle_type: "bear".into(),
             shadow_type: "lower".into(),
+            direction: "long".into(),
             ..Default::default()
         };
         let signal4 = Signal {
             candle_type: "bear".into(),
             shadow_type: "upper".into(),
+            direction: "short".into(),
             ..Default::default()
         };
         let config1 = MonitorConfig {
@@ -1169,6 +2064,38 @@ mod tests {
         assert_eq!(place_order_by_long_short_config(&config4, &signal4), true);
     }
 
+    #[test]
+    fn test_group_configs() {
+        let config = |id: i64, symbol: &str, interval_type: &str| MonitorConfig {
+            id: Some(id),
+            symbol: symbol.into(),
+            interval_type: interval_type.into(),
+            ..Default::default()
+        };
+        let groups = group_configs(vec![
+            config(1, "BTC_USDT", "5m"),
+            config(2, "ETH_USDT", "5m"),
+            config(3, "BTC_USDT", "5m"),
+            config(4, "BTC_USDT", "1h"),
+        ]);
+        let ids: Vec<Vec<i64>> = groups
+            .iter()
+            .map(|(_, group)| group.iter().filter_map(|c| c.id).collect())
+            .collect();
+        assert_eq!(ids, vec![vec![1, 3], vec![2], vec![4]]);
+        assert_eq!(groups[0].0, ("BTC_USDT".to_string(), "5m".to_string()));
+    }
+
+    #[test]
+    fn test_next_candle_wake() {
+        let now = 60_000 + 30;
+        // 刚启动时分析最近一根已收盘的K线，已经过了唤醒时间
+        assert_eq!(next_candle_wake(now, 60, 3, None), (59_940, 60_003));
+        assert_eq!(next_candle_wake(now, 60, 3, Some(59_940)), (60_000, 60_063));
+        // 落后太多时跳到最近一根已收盘的K线
+        assert_eq!(next_candle_wake(now, 60, 3, Some(59_000)), (59_940, 60_003));
+    }
+
     #[tokio::test]
     async fn test_config_update_detection() {
         // 模拟配置更新时间戳的变化
@@ -1195,4 +2122,94 @@ mod tests {
             "配置应该被检测为有更新"
         );
     }
+
+    #[tokio::test]
+    async fn test_dispatch_klines_fills_paper_orders() {
+        use crate::repository::PaperRepository;
+
+        let db = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("../migrations").run(&db).await.unwrap();
+
+        let market: Arc<RwLock<dyn Exchange>> = Arc::new(RwLock::new(GateService::new()));
+        let paper = PaperExchange::new(db.clone(), market.clone());
+        let order_id = PaperRepository::save(
+            &db,
+            &PaperOrder {
+                id: 0,
+                symbol: "BTC_USDT".to_string(),
+                side: "buy".to_string(),
+                order_type: "limit".to_string(),
+                order_size: 1,
+                quanto_multiplier: 1.0,
+                order_price: 100.0,
+                take_profit_price: Some(110.0),
+                stop_loss_price: Some(90.0),
+                status: "pending".to_string(),
+                fill_price: None,
+                filled_at: None,
+                exit_price: None,
+                exit_reason: None,
+                closed_at: None,
+                realized_pnl: None,
+                fee_rate: 0.0,
+                created_at: 0,
+            },
+        )
+        .await
+        .unwrap();
+
+        // 成交量倍数要求很高，不会产生信号，只验证撮合
+        let config = MonitorConfig {
+            symbol: "BTC_USDT".to_string(),
+            interval_type: "1m".to_string(),
+            history_hours: 0.1,
+            volume_multiplier: 1000.0,
+            paper_trading: true,
+            ..Default::default()
+        };
+        let klines: Vec<KlineData> = (0..10)
+            .map(|i| KlineData {
+                timestamp: 60 + i * 60,
+                open: 102.0,
+                high: 103.0,
+                // 第8根K线触及限价
+                low: if i == 7 { 99.5 } else { 101.0 },
+                close: 102.0,
+                volume: 1.0,
+            })
+            .collect();
+        let subscribers: Vec<(MonitorConfig, Arc<RwLock<dyn Exchange>>)> =
+            vec![(config, Arc::new(RwLock::new(paper.clone())))];
+        let mut ctx = MonitorContext {
+            db: db.clone(),
+            market_exchange: market,
+            paper: None,
+            dingtalk_service: Arc::new(RwLock::new(DingTalkService::new())),
+            is_running: Arc::new(RwLock::new(true)),
+            risk: RiskManager::new(db.clone()),
+        };
+        let closed_at = klines[9].timestamp;
+
+        // 不传模拟交易所时共用K线不会撮合模拟订单
+        MonitorService::dispatch_klines(&ctx, &subscribers, &klines, closed_at).await;
+        let order = PaperRepository::get_by_id(&db, order_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(order.status, "pending");
+
+        ctx.paper = Some(paper);
+        MonitorService::dispatch_klines(&ctx, &subscribers, &klines, closed_at).await;
+        let order = PaperRepository::get_by_id(&db, order_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(order.status, "open");
+        assert_eq!(order.fill_price, Some(100.0));
+        assert_eq!(order.filled_at, Some(klines[7].timestamp));
+    }
 }
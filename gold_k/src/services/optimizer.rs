@@ -0,0 +1,261 @@
+use crate::models::{KlineData, MonitorConfig};
+use crate::services::backtest::{BacktestOptions, BacktestReport, run_backtest};
+use anyhow::Result;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// 参数寻优的目标函数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Objective {
+    NetPnl,
+    Sharpe,
+    ProfitFactor,
+    WinRate,
+}
+
+impl Objective {
+    /// 回测结果的得分，没有亏损交易时盈亏因子没有定义，返回None
+    pub fn score(&self, report: &BacktestReport) -> Option<f64> {
+        match self {
+            Objective::NetPnl => Some(report.net_pnl),
+            Objective::Sharpe => Some(report.sharpe_ratio),
+            Objective::ProfitFactor => report.profit_factor,
+            Objective::WinRate => Some(report.win_rate),
+        }
+    }
+}
+
+/// 一组待优化的策略阈值
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ParamSet {
+    pub shadow_ratio: f64,
+    pub main_shadow_body_ratio: f64,
+    pub volume_multiplier: f64,
+    pub risk_reward_ratio: f64,
+    pub expected_profit_rate: f64,
+    pub history_hours: f64,
+}
+
+impl ParamSet {
+    pub fn from_config(config: &MonitorConfig) -> Self {
+        Self {
+            shadow_ratio: config.shadow_ratio,
+            main_shadow_body_ratio: config.main_shadow_body_ratio,
+            volume_multiplier: config.volume_multiplier,
+            risk_reward_ratio: config.risk_reward_ratio,
+            expected_profit_rate: config.expected_profit_rate,
+            history_hours: config.history_hours,
+        }
+    }
+
+    /// 返回替换了阈值的配置副本，其他字段保持不变
+    pub fn apply(&self, config: &MonitorConfig) -> MonitorConfig {
+        MonitorConfig {
+            shadow_ratio: self.shadow_ratio,
+            main_shadow_body_ratio: self.main_shadow_body_ratio,
+            volume_multiplier: self.volume_multiplier,
+            risk_reward_ratio: self.risk_reward_ratio,
+            expected_profit_rate: self.expected_profit_rate,
+            history_hours: self.history_hours,
+            ..config.clone()
+        }
+    }
+}
+
+/// 参数搜索空间，每个参数一组候选值，为空时使用配置中的当前值
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ParamSpace {
+    pub shadow_ratio: Vec<f64>,
+    pub main_shadow_body_ratio: Vec<f64>,
+    pub volume_multiplier: Vec<f64>,
+    pub risk_reward_ratio: Vec<f64>,
+    pub expected_profit_rate: Vec<f64>,
+    pub history_hours: Vec<f64>,
+}
+
+impl ParamSpace {
+    fn axes(&self, base: &ParamSet) -> [Vec<f64>; 6] {
+        let axis = |values: &Vec<f64>, current: f64| {
+            if values.is_empty() {
+                vec![current]
+            } else {
+                values.clone()
+            }
+        };
+        [
+            axis(&self.shadow_ratio, base.shadow_ratio),
+            axis(&self.main_shadow_body_ratio, base.main_shadow_body_ratio),
+            axis(&self.volume_multiplier, base.volume_multiplier),
+            axis(&self.risk_reward_ratio, base.risk_reward_ratio),
+            axis(&self.expected_profit_rate, base.expected_profit_rate),
+            axis(&self.history_hours, base.history_hours),
+        ]
+    }
+
+    /// 网格搜索：所有候选值的笛卡尔积
+    pub fn grid(&self, base: &ParamSet) -> Vec<ParamSet> {
+        let mut combos: Vec<Vec<f64>> = vec![Vec::new()];
+        for values in self.axes(base) {
+            combos = combos
+                .into_iter()
+                .flat_map(|combo| {
+                    values.iter().map(move |v| {
+                        let mut next = combo.clone();
+                        next.push(*v);
+                        next
+                    })
+                })
+                .collect();
+        }
+        combos.iter().map(|c| params_from_slice(c)).collect()
+    }
+
+    /// 随机搜索：每个参数在候选值的最小值和最大值之间均匀采样
+    pub fn sample(&self, base: &ParamSet, count: usize, seed: u64) -> Vec<ParamSet> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let ranges = self.axes(base).map(|values| {
+            let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            (min, max)
+        });
+
+        (0..count)
+            .map(|_| {
+                let values = ranges.map(|(min, max)| {
+                    if max > min {
+                        rng.gen_range(min..=max)
+                    } else {
+                        min
+                    }
+                });
+                params_from_slice(&values)
+            })
+            .collect()
+    }
+}
+
+fn params_from_slice(values: &[f64]) -> ParamSet {
+    ParamSet {
+        shadow_ratio: values[0],
+        main_shadow_body_ratio: values[1],
+        volume_multiplier: values[2],
+        risk_reward_ratio: values[3],
+        expected_profit_rate: values[4],
+        history_hours: values[5],
+    }
+}
+
+/// 一组参数的回测结果（不包含交易明细和资金曲线）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OptimizeResult {
+    pub params: ParamSet,
+    pub score: f64,
+    pub report: BacktestReport,
+}
+
+/// 并行回测所有候选参数，按目标函数降序返回
+///
+/// 已平仓交易少于 `min_trades` 的参数组合会被丢弃，避免少数几笔交易得到虚高的分数；
+/// 没有得分的参数组合（没有亏损交易时的盈亏因子）也不参与排序
+pub async fn optimize(
+    config: &MonitorConfig,
+    klines: Arc<Vec<KlineData>>,
+    options: &BacktestOptions,
+    candidates: Vec<ParamSet>,
+    objective: Objective,
+    min_trades: usize,
+    workers: usize,
+) -> Result<Vec<OptimizeResult>> {
+    let chunk_size = candidates.len().div_ceil(workers.max(1)).max(1);
+    let mut handles = Vec::new();
+
+    for chunk in candidates.chunks(chunk_size) {
+        let chunk = chunk.to_vec();
+        let config = config.clone();
+        let klines = klines.clone();
+        let options = options.clone();
+
+        // 回测是纯计算任务，放到阻塞线程池中执行
+        handles.push(tokio::task::spawn_blocking(move || {
+            chunk
+                .into_iter()
+                .filter_map(|params| {
+                    let mut report = run_backtest(&params.apply(&config), &klines, &options);
+                    report.trades.clear();
+                    report.equity_curve.clear();
+                    Some(OptimizeResult {
+                        params,
+                        score: objective.score(&report)?,
+                        report,
+                    })
+                })
+                .collect::<Vec<_>>()
+        }));
+    }
+
+    let mut results = Vec::new();
+    for handle in handles {
+        results.extend(
+            handle
+                .await?
+                .into_iter()
+                .filter(|r| r.report.closed_trades >= min_trades),
+        );
+    }
+    results.sort_by(|a, b| b.score.total_cmp(&a.score));
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grid_and_sample() {
+        let base = ParamSet::from_config(&MonitorConfig {
+            shadow_ratio: 2.0,
+            history_hours: 1.0,
+            ..Default::default()
+        });
+        let space = ParamSpace {
+            shadow_ratio: vec![1.5, 2.5],
+            volume_multiplier: vec![1.0, 1.5, 2.0],
+            ..Default::default()
+        };
+
+        let grid = space.grid(&base);
+        assert_eq!(grid.len(), 6);
+        assert!(grid.iter().all(|p| p.history_hours == 1.0));
+        assert!(grid.contains(&ParamSet {
+            shadow_ratio: 2.5,
+            volume_multiplier: 1.5,
+            ..base
+        }));
+
+        let samples = space.sample(&base, 20, 7);
+        assert_eq!(samples.len(), 20);
+        assert!(samples.iter().all(|p| (1.5..=2.5).contains(&p.shadow_ratio)
+            && (1.0..=2.0).contains(&p.volume_multiplier)
+            && p.history_hours == 1.0));
+        assert_eq!(samples, space.sample(&base, 20, 7));
+    }
+
+    #[test]
+    fn test_profit_factor_score_without_losses() {
+        let report = BacktestReport {
+            net_pnl: 120.0,
+            profit_factor: None,
+            ..Default::default()
+        };
+        assert_eq!(Objective::ProfitFactor.score(&report), None);
+        assert_eq!(Objective::NetPnl.score(&report), Some(120.0));
+        let report = BacktestReport {
+            profit_factor: Some(1.5),
+            ..Default::default()
+        };
+        assert_eq!(Objective::ProfitFactor.score(&report), Some(1.5));
+    }
+}
@@ -0,0 +1,643 @@
+use crate::models::{ApiKey, KlineData, PaperOrder, interval_to_seconds};
+use crate::repository::{ApiKeyRepository, KlineRepository, PaperRepository};
+use crate::services::exchange::{
+    AccountInfo, Exchange, ExchangeOrder, OrderRequest, OrderResponse, Position, PriceOrder,
+    TriggerOrderRequest,
+};
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use serde_json::{Value, json};
+use sqlx::SqlitePool;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+use tracing::{error, info};
+
+/// 模拟订单在一根K线上发生的事件
+#[derive(Debug, Clone, PartialEq)]
+pub enum PaperEvent {
+    Filled { price: f64, at: i64 },
+    Closed { price: f64, reason: String, at: i64 },
+}
+
+/// 合约数据里没有手续费率时使用的吃单手续费率
+pub const DEFAULT_TAKER_FEE_RATE: f64 = 0.0005;
+
+/// 用K线推进单个模拟订单的状态，返回依次发生的事件
+///
+/// 只使用下单后才开始的K线，市价单在第一根这样的K线开盘时成交。限价单成交的那根K线无法确定价格先后，
+/// 只检查止损；同一根K线同时触及止盈和止损时按止损处理
+pub fn simulate_order(
+    order: &PaperOrder,
+    klines: &[KlineData],
+    interval_secs: i64,
+) -> Vec<PaperEvent> {
+    let is_long = order.side == "buy";
+    let is_market = order.order_type != "limit";
+    let mut events = Vec::new();
+    let mut status = order.status.as_str();
+    let mut filled_at = order.filled_at;
+    let start = align_up(order.created_at, interval_secs);
+
+    for kline in klines {
+        if kline.timestamp < start || filled_at.is_some_and(|at| kline.timestamp < at) {
+            continue;
+        }
+
+        if status == "pending" {
+            let price = if is_market {
+                kline.open
+            } else {
+                // 限价单：价格触及下单价即成交
+                let touched = if is_long {
+                    kline.low <= order.order_price
+                } else {
+                    kline.high >= order.order_price
+                };
+                if !touched {
+                    continue;
+                }
+                order.order_price
+            };
+            events.push(PaperEvent::Filled {
+                price,
+                at: kline.timestamp,
+            });
+            status = "open";
+            filled_at = Some(kline.timestamp);
+        }
+
+        if status == "open" {
+            let fill_bar = !is_market && filled_at == Some(kline.timestamp);
+            let stop_hit = order.stop_loss_price.is_some_and(|sl| {
+                if is_long {
+                    kline.low <= sl
+                } else {
+                    kline.high >= sl
+                }
+            });
+            let profit_hit = !fill_bar
+                && order.take_profit_price.is_some_and(|tp| {
+                    if is_long {
+                        kline.high >= tp
+                    } else {
+                        kline.low <= tp
+                    }
+                });
+
+            let exit = if stop_hit {
+                order.stop_loss_price.map(|p| (p, "sl"))
+            } else if profit_hit {
+                order.take_profit_price.map(|p| (p, "tp"))
+            } else {
+                None
+            };
+
+            if let Some((price, reason)) = exit {
+                events.push(PaperEvent::Closed {
+                    price,
+                    reason: reason.to_string(),
+                    at: kline.timestamp,
+                });
+                break;
+            }
+        }
+    }
+
+    events
+}
+
+/// 向上取整到K线开始时间
+fn align_up(timestamp: i64, interval_secs: i64) -> i64 {
+    if interval_secs <= 0 {
+        return timestamp;
+    }
+    timestamp + (interval_secs - timestamp.rem_euclid(interval_secs)) % interval_secs
+}
+
+/// 计算模拟持仓的已实现盈亏(USDT)，不含手续费
+pub fn paper_pnl(order: &PaperOrder, entry_price: f64, exit_price: f64) -> f64 {
+    let direction = if order.side == "buy" { 1.0 } else { -1.0 };
+    (exit_price - entry_price) * order.order_size as f64 * order.quanto_multiplier * direction
+}
+
+/// 模拟订单的手续费(USDT)：市价入场和平仓（止盈止损触发后按市价成交）按吃单费率收取，限价入场按挂单不收取
+pub fn paper_fee(order: &PaperOrder, entry_price: f64, exit_price: f64) -> f64 {
+    let entry = if order.order_type == "limit" {
+        0.0
+    } else {
+        entry_price
+    };
+    (entry + exit_price) * order.order_size as f64 * order.quanto_multiplier * order.fee_rate
+}
+
+/// 模拟交易所：行情来自真实交易所，订单和持仓只记录在SQLite中
+#[derive(Debug, Clone)]
+pub struct PaperExchange {
+    db: SqlitePool,
+    market: Arc<RwLock<dyn Exchange>>,
+}
+
+impl PaperExchange {
+    pub fn new(db: SqlitePool, market: Arc<RwLock<dyn Exchange>>) -> Self {
+        Self { db, market }
+    }
+
+    /// 用最新的K线推进该交易对所有未结束的模拟订单
+    pub async fn process_klines(
+        &self,
+        symbol: &str,
+        interval: &str,
+        klines: &[KlineData],
+    ) -> Result<()> {
+        let interval_secs = interval_to_seconds(interval);
+        let orders = PaperRepository::get_active_by_symbol(&self.db, symbol).await?;
+
+        for order in orders {
+            let mut entry_price = order.fill_price.unwrap_or(order.order_price);
+            for event in simulate_order(&order, klines, interval_secs) {
+                match event {
+                    PaperEvent::Filled { price, at } => {
+                        PaperRepository::mark_filled(&self.db, order.id, price, at).await?;
+                        entry_price = price;
+                        info!(
+                            "Paper order {} filled for {} at {}",
+                            order.id, order.symbol, price
+                        );
+                    }
+                    PaperEvent::Closed { price, reason, at } => {
+                        let pnl = paper_pnl(&order, entry_price, price)
+                            - paper_fee(&order, entry_price, price);
+                        PaperRepository::close_position(
+                            &self.db, order.id, price, &reason, at, pnl,
+                        )
+                        .await?;
+                        info!(
+                            "Paper order {} closed for {} by {} at {}, pnl: {:.4}",
+                            order.id, order.symbol, reason, price, pnl
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn quanto_multiplier(&self, symbol: &str) -> f64 {
+        ApiKeyRepository::get_quanto_multiplier(&self.db, symbol)
+            .await
+            .unwrap_or(1.0)
+    }
+
+    async fn taker_fee_rate(&self, symbol: &str) -> f64 {
+        ApiKeyRepository::get_taker_fee_rate(&self.db, symbol)
+            .await
+            .ok()
+            .flatten()
+            .unwrap_or(DEFAULT_TAKER_FEE_RATE)
+    }
+}
+
+#[async_trait]
+impl Exchange for PaperExchange {
+    fn name(&self) -> &str {
+        "paper"
+    }
+
+    fn apply_api_key(&mut self, _key: &ApiKey) {
+        // 行情交易所的凭据由监控服务统一更新
+    }
+
+    fn has_credentials(&self) -> bool {
+        true
+    }
+
+    async fn get_kline_data(
+        &self,
+        symbol: &str,
+        interval: &str,
+        limit: usize,
+        settle: &str,
+    ) -> Result<Vec<KlineData>> {
+        let klines = {
+            let market = self.market.read().await;
+            market
+                .get_kline_data(symbol, interval, limit, settle)
+                .await?
+        };
+
+        // 撮合失败不影响信号分析
+        if let Err(e) = self.process_klines(symbol, interval, &klines).await {
+            error!("Failed to process paper orders for {}: {}", symbol, e);
+        }
+
+        Ok(klines)
+    }
+
+    async fn get_kline_data_range(
+        &self,
+        symbol: &str,
+        interval: &str,
+        from: i64,
+        to: i64,
+        settle: &str,
+    ) -> Result<Vec<KlineData>> {
+        let klines = {
+            let market = self.market.read().await;
+            market
+                .get_kline_data_range(symbol, interval, from, to, settle)
+                .await?
+        };
+
+        if let Err(e) = self.process_klines(symbol, interval, &klines).await {
+            error!("Failed to process paper orders for {}: {}", symbol, e);
+        }
+
+        Ok(klines)
+    }
+
+    async fn get_contracts(&self, settle: &str) -> Result<Vec<Value>> {
+        let market = self.market.read().await;
+        market.get_contracts(settle).await
+    }
+
+    async fn place_order(&self, order: &OrderRequest, _settle: &str) -> Result<OrderResponse> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+        let paper_order = PaperOrder {
+            id: 0,
+            symbol: order.symbol.clone(),
+            side: order.side.clone(),
+            order_type: order.order_type.clone(),
+            order_size: order.size.abs(),
+            quanto_multiplier: self.quanto_multiplier(&order.symbol).await,
+            order_price: order.price,
+            take_profit_price: order.take_profit.filter(|p| *p > 0.0),
+            stop_loss_price: order.stop_loss.filter(|p| *p > 0.0),
+            // 市价单在下一根K线开盘时成交，限价单在价格触及时成交
+            status: "pending".to_string(),
+            fill_price: None,
+            filled_at: None,
+            exit_price: None,
+            exit_reason: None,
+            closed_at: None,
+            realized_pnl: None,
+            fee_rate: self.taker_fee_rate(&order.symbol).await,
+            created_at: now,
+        };
+
+        let id = PaperRepository::save(&self.db, &paper_order).await?;
+        info!("Paper order {} placed: {:?}", id, order);
+
+        Ok(OrderResponse {
+            success: true,
+            message: "模拟下单成功".to_string(),
+            order_id: Some(id.to_string()),
+            take_profit_order_id: None,
+            stop_loss_order_id: None,
+            raw: json!({
+                "id": id,
+                "status": paper_order.status,
+                "paper": true,
+            }),
+        })
+    }
+
+    async fn get_order(&self, order_id: &str, _settle: &str) -> Result<ExchangeOrder> {
+        // 平仓成交的ID为 "{订单ID}-exit"，见 get_price_orders
+        let (id, is_exit) = match order_id.strip_suffix("-exit") {
+            Some(id) => (id, true),
+            None => (order_id, false),
+        };
+        let id: i64 = id
+            .parse()
+            .map_err(|_| anyhow!("Invalid paper order id: {}", order_id))?;
+        let order = PaperRepository::get_by_id(&self.db, id)
+            .await?
+            .ok_or_else(|| anyhow!("Paper order {} not found", id))?;
+
+        if is_exit {
+            return Ok(ExchangeOrder {
+                id: order_id.to_string(),
+                text: None,
+                status: "filled".to_string(),
+                size: order.order_size,
+                filled_size: order.order_size,
+                fill_price: order.exit_price,
+                fee_rate: order.fee_rate,
+            });
+        }
+
+        let (status, filled_size) = match order.status.as_str() {
+            "pending" => ("open", 0),
+            "cancelled" => ("cancelled", 0),
+            _ => ("filled", order.order_size),
+        };
+        Ok(ExchangeOrder {
+            id: order.id.to_string(),
+            text: None,
+            status: status.to_string(),
+            size: order.order_size,
+            filled_size,
+            fill_price: order.fill_price,
+            // 限价入场按挂单处理，不收取手续费
+            fee_rate: if order.order_type == "limit" {
+                0.0
+            } else {
+                order.fee_rate
+            },
+        })
+    }
+
+    async fn get_price_orders(&self, symbol: &str, _settle: &str) -> Result<Vec<PriceOrder>> {
+        // 模拟订单的止盈止损记录在订单上，这里转换成触发委托的形式
+        let orders = PaperRepository::get_recent_by_symbol(&self.db, symbol, 100).await?;
+        let mut price_orders = Vec::new();
+        for order in orders {
+            let close_type = if order.side == "buy" {
+                "close-long-position"
+            } else {
+                "close-short-position"
+            };
+            let legs = [
+                ("tp", order.take_profit_price),
+                ("sl", order.stop_loss_price),
+            ];
+            for (reason, trigger_price) in legs {
+                let Some(trigger_price) = trigger_price else {
+                    continue;
+                };
+                let status = match order.status.as_str() {
+                    "pending" | "open" => "open",
+                    "closed" if order.exit_reason.as_deref() == Some(reason) => "triggered",
+                    _ => "cancelled",
+                };
+                price_orders.push(PriceOrder {
+                    id: format!("{}-{}", order.id, reason),
+                    status: status.to_string(),
+                    trigger_price,
+                    order_type: close_type.to_string(),
+                    trade_id: (status == "triggered").then(|| format!("{}-exit", order.id)),
+                    create_time: order.created_at,
+                });
+            }
+        }
+        Ok(price_orders)
+    }
+
+    async fn get_positions(&self, settle: &str) -> Result<Vec<Position>> {
+        // 模拟订单之间互不抵消，按双向持仓模式分别汇总多仓和空仓
+        let mut groups: BTreeMap<(String, bool), Vec<PaperOrder>> = BTreeMap::new();
+        for order in PaperRepository::get_open(&self.db).await? {
+            groups
+                .entry((order.symbol.clone(), order.side == "buy"))
+                .or_default()
+                .push(order);
+        }
+
+        let mut positions = Vec::new();
+        for ((symbol, is_long), orders) in groups {
+            let size: i64 = orders.iter().map(|o| o.order_size).sum();
+            if size == 0 {
+                continue;
+            }
+            let entry_price = orders
+                .iter()
+                .map(|o| o.fill_price.unwrap_or(o.order_price) * o.order_size as f64)
+                .sum::<f64>()
+                / size as f64;
+            let mark_price = KlineRepository::get_last_close(&self.db, settle, &symbol)
+                .await?
+                .unwrap_or(entry_price);
+            let unrealized_pnl = orders
+                .iter()
+                .map(|o| paper_pnl(o, o.fill_price.unwrap_or(o.order_price), mark_price))
+                .sum();
+
+            positions.push(Position {
+                symbol,
+                mode: if is_long { "dual_long" } else { "dual_short" }.to_string(),
+                size: if is_long { size } else { -size },
+                entry_price,
+                mark_price,
+                unrealized_pnl,
+                realized_pnl: 0.0,
+                leverage: 0.0,
+                liq_price: None,
+            });
+        }
+        Ok(positions)
+    }
+
+    async fn cancel_all_orders(&self, _settle: &str) -> Result<usize> {
+        Ok(PaperRepository::cancel_pending(&self.db).await? as usize)
+    }
+
+    async fn cancel_all_price_orders(&self, _settle: &str) -> Result<usize> {
+        // 模拟订单的止盈止损记录在订单上，撤销即清除触发价格
+        Ok(PaperRepository::clear_triggers(&self.db).await? as usize)
+    }
+
+    async fn replace_stop_loss(
+        &self,
+        old_order_id: &str,
+        order: &TriggerOrderRequest,
+        _settle: &str,
+    ) -> Result<String> {
+        // 模拟订单的触发委托ID为 "{订单ID}-sl"，直接修改订单上的止损价
+        let id = old_order_id
+            .strip_suffix("-sl")
+            .and_then(|id| id.parse::<i64>().ok())
+            .ok_or_else(|| anyhow!("Invalid paper stop loss id: {}", old_order_id))?;
+        PaperRepository::update_stop_loss(&self.db, id, order.trigger_price).await?;
+        Ok(old_order_id.to_string())
+    }
+
+    async fn close_position(&self, position: &Position, _settle: &str) -> Result<OrderResponse> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let side = if position.size > 0 { "buy" } else { "sell" };
+        let mut closed = Vec::new();
+
+        for order in PaperRepository::get_active_by_symbol(&self.db, &position.symbol).await? {
+            if order.status != "open" || order.side != side {
+                continue;
+            }
+            let entry_price = order.fill_price.unwrap_or(order.order_price);
+            let pnl = paper_pnl(&order, entry_price, position.mark_price)
+                - paper_fee(&order, entry_price, position.mark_price);
+            PaperRepository::close_position(
+                &self.db,
+                order.id,
+                position.mark_price,
+                "manual",
+                now,
+                pnl,
+            )
+            .await?;
+            closed.push(order.id);
+        }
+
+        info!(
+            "Paper position {} {} closed at {}: {:?}",
+            position.symbol, position.mode, position.mark_price, closed
+        );
+        Ok(OrderResponse {
+            success: true,
+            message: format!("模拟平仓{}笔", closed.len()),
+            order_id: None,
+            take_profit_order_id: None,
+            stop_loss_order_id: None,
+            raw: json!({ "closed": closed, "paper": true }),
+        })
+    }
+
+    async fn get_account_info(&self) -> Result<AccountInfo> {
+        let account = PaperRepository::get_account(&self.db).await?;
+        let unrealized_pnl: f64 = self
+            .get_positions("usdt")
+            .await?
+            .iter()
+            .map(|p| p.unrealized_pnl)
+            .sum();
+        Ok(AccountInfo {
+            valid: true,
+            equity: Some(account.balance + unrealized_pnl),
+            raw: serde_json::to_value(account)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kline(timestamp: i64, open: f64, high: f64, low: f64, close: f64) -> KlineData {
+        KlineData {
+            timestamp,
+            open,
+            high,
+            low,
+            close,
+            volume: 1.0,
+        }
+    }
+
+    fn order(side: &str, status: &str) -> PaperOrder {
+        PaperOrder {
+            id: 1,
+            symbol: "BTC_USDT".into(),
+            side: side.into(),
+            order_type: if status == "pending" {
+                "limit"
+            } else {
+                "market"
+            }
+            .into(),
+            order_size: 2,
+            quanto_multiplier: 0.5,
+            order_price: 100.0,
+            take_profit_price: Some(110.0),
+            stop_loss_price: Some(95.0),
+            status: status.into(),
+            fill_price: (status == "open").then_some(100.0),
+            filled_at: (status == "open").then_some(1000),
+            exit_price: None,
+            exit_reason: None,
+            closed_at: None,
+            realized_pnl: None,
+            fee_rate: 0.0005,
+            created_at: 1000,
+        }
+    }
+
+    #[test]
+    fn test_simulate_market_long_take_profit() {
+        let mut o = order("buy", "pending");
+        o.order_type = "market".into();
+        // 信号收盘加宽限时间后下单，落在K线中间
+        o.created_at = 1010;
+        let klines = vec![
+            kline(900, 99.0, 101.0, 98.0, 100.0),
+            // 下单时已经开始的K线，包含下单前的价格，不参与撮合
+            kline(1000, 100.0, 111.0, 94.0, 104.0),
+            kline(1060, 104.0, 109.0, 103.0, 108.0),
+            kline(1120, 108.0, 111.0, 107.0, 110.0),
+        ];
+        let events = simulate_order(&o, &klines, 60);
+        assert_eq!(
+            events,
+            vec![
+                PaperEvent::Filled {
+                    price: 104.0,
+                    at: 1060
+                },
+                PaperEvent::Closed {
+                    price: 110.0,
+                    reason: "tp".into(),
+                    at: 1120
+                },
+            ]
+        );
+        assert_eq!(paper_pnl(&o, 104.0, 110.0), 6.0);
+        // 入场和平仓都按吃单费率收取手续费
+        assert!((paper_fee(&o, 104.0, 110.0) - 0.107).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_simulate_limit_fill_bar_ignores_take_profit() {
+        let o = order("buy", "pending");
+        // 成交的K线同时触及止盈，先后顺序未知，不算止盈
+        let klines = vec![
+            kline(1020, 101.0, 112.0, 99.0, 105.0),
+            kline(1080, 105.0, 106.0, 94.0, 96.0),
+        ];
+        let events = simulate_order(&o, &klines, 60);
+        assert_eq!(
+            events,
+            vec![
+                PaperEvent::Filled {
+                    price: 100.0,
+                    at: 1020
+                },
+                PaperEvent::Closed {
+                    price: 95.0,
+                    reason: "sl".into(),
+                    at: 1080
+                },
+            ]
+        );
+        // 限价入场不收手续费
+        assert!((paper_fee(&o, 100.0, 95.0) - 0.0475).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_simulate_limit_short_fill_then_stop() {
+        let mut o = order("sell", "pending");
+        o.take_profit_price = Some(90.0);
+        o.stop_loss_price = Some(105.0);
+        let klines = vec![
+            kline(1000, 98.0, 99.0, 97.0, 98.5),
+            kline(1060, 98.5, 101.0, 98.0, 100.5),
+            // 同时触及止盈和止损，按止损处理
+            kline(1120, 100.5, 106.0, 89.0, 95.0),
+        ];
+        let events = simulate_order(&o, &klines, 60);
+        assert_eq!(
+            events,
+            vec![
+                PaperEvent::Filled {
+                    price: 100.0,
+                    at: 1060
+                },
+                PaperEvent::Closed {
+                    price: 105.0,
+                    reason: "sl".into(),
+                    at: 1120
+                },
+            ]
+        );
+        assert_eq!(paper_pnl(&o, 100.0, 105.0), -5.0);
+    }
+}
@@ -0,0 +1,319 @@
+use crate::models::KlineData;
+
+/// 可选的策略，`long_shadow` 为原来的长影线策略，`script` 运行配置的脚本（见 services::scripting），其余为K线形态
+pub const STRATEGIES: &[&str] = &[
+    "long_shadow",
+    "engulfing",
+    "pin_bar",
+    "inside_bar",
+    "outside_bar",
+    "star",
+    "doji",
+    "script",
+];
+
+/// 锤子线/射击之星：主影线至少是实体的倍数
+const PIN_SHADOW_BODY_RATIO: f64 = 2.0;
+/// 锤子线/射击之星：另一边影线不超过K线振幅的比例
+const PIN_OPPOSITE_SHADOW_RATIO: f64 = 0.25;
+/// 十字星：实体不超过振幅的比例
+const DOJI_BODY_RATIO: f64 = 0.1;
+/// 蜻蜓/墓碑十字：长影线至少占振幅的比例
+const DOJI_SHADOW_RATIO: f64 = 0.6;
+/// 早晨之星/黄昏之星：第一根实体至少占振幅的比例，第二根实体不超过第一根实体的比例
+const STAR_FIRST_BODY_RATIO: f64 = 0.5;
+const STAR_MIDDLE_BODY_RATIO: f64 = 0.3;
+/// 外包线：收盘价位于振幅两端的比例以内
+const OUTSIDE_CLOSE_RATIO: f64 = 1.0 / 3.0;
+
+/// 识别到的形态，`stop_price` 为形态的另一端，作为止损价
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatternMatch {
+    pub pattern: &'static str,
+    pub direction: &'static str, // 'long' or 'short'
+    pub stop_price: f64,
+}
+
+impl PatternMatch {
+    fn long(pattern: &'static str, stop_price: f64) -> Self {
+        Self {
+            pattern,
+            direction: "long",
+            stop_price,
+        }
+    }
+
+    fn short(pattern: &'static str, stop_price: f64) -> Self {
+        Self {
+            pattern,
+            direction: "short",
+            stop_price,
+        }
+    }
+}
+
+/// 按策略识别形态，`klines` 按时间升序，最后一根为刚收盘的K线
+pub fn detect(strategy: &str, klines: &[KlineData]) -> Option<PatternMatch> {
+    match strategy {
+        "engulfing" => engulfing(klines),
+        "pin_bar" => pin_bar(klines),
+        "inside_bar" => inside_bar(klines),
+        "outside_bar" => outside_bar(klines),
+        "star" => star(klines),
+        "doji" => doji(klines),
+        _ => None,
+    }
+}
+
+/// 形态的中文名称，用于通知和页面展示
+pub fn label(pattern: &str) -> &'static str {
+    match pattern {
+        "bullish_engulfing" => "看涨吞没",
+        "bearish_engulfing" => "看跌吞没",
+        "hammer" => "锤子线",
+        "shooting_star" => "射击之星",
+        "bullish_inside_bar" => "看涨内包线",
+        "bearish_inside_bar" => "看跌内包线",
+        "bullish_outside_bar" => "看涨外包线",
+        "bearish_outside_bar" => "看跌外包线",
+        "morning_star" => "早晨之星",
+        "evening_star" => "黄昏之星",
+        "dragonfly_doji" => "蜻蜓十字",
+        "gravestone_doji" => "墓碑十字",
+        _ => "未知形态",
+    }
+}
+
+/// 一根K线的实体、上下影线和振幅
+#[derive(Debug, Clone, Copy)]
+struct Candle {
+    body: f64,
+    upper: f64,
+    lower: f64,
+    range: f64,
+    bull: bool,
+    bear: bool,
+}
+
+impl Candle {
+    fn new(kline: &KlineData) -> Self {
+        Self {
+            body: (kline.close - kline.open).abs(),
+            upper: kline.high - kline.close.max(kline.open),
+            lower: kline.open.min(kline.close) - kline.low,
+            range: kline.high - kline.low,
+            bull: kline.close > kline.open,
+            bear: kline.close < kline.open,
+        }
+    }
+}
+
+/// 最后 `N` 根K线
+fn last<const N: usize>(klines: &[KlineData]) -> Option<&[KlineData; N]> {
+    klines.get(klines.len().checked_sub(N)?..)?.try_into().ok()
+}
+
+/// 吞没：阳线实体完全覆盖前一根阴线实体（看涨），反之看跌
+pub fn engulfing(klines: &[KlineData]) -> Option<PatternMatch> {
+    let [previous, current] = last::<2>(klines)?;
+    let (p, c) = (Candle::new(previous), Candle::new(current));
+    if c.body <= p.body {
+        return None;
+    }
+    let stop_low = previous.low.min(current.low);
+    let stop_high = previous.high.max(current.high);
+    if p.bear && c.bull && current.open <= previous.close && current.close >= previous.open {
+        Some(PatternMatch::long("bullish_engulfing", stop_low))
+    } else if p.bull && c.bear && current.open >= previous.close && current.close <= previous.open {
+        Some(PatternMatch::short("bearish_engulfing", stop_high))
+    } else {
+        None
+    }
+}
+
+/// 锤子线（长下影线，看涨）和射击之星（长上影线，看跌）
+pub fn pin_bar(klines: &[KlineData]) -> Option<PatternMatch> {
+    let [current] = last::<1>(klines)?;
+    let c = Candle::new(current);
+    if c.range <= 0.0 {
+        return None;
+    }
+    if c.lower >= c.body * PIN_SHADOW_BODY_RATIO
+        && c.lower > 0.0
+        && c.upper <= c.range * PIN_OPPOSITE_SHADOW_RATIO
+    {
+        Some(PatternMatch::long("hammer", current.low))
+    } else if c.upper >= c.body * PIN_SHADOW_BODY_RATIO
+        && c.upper > 0.0
+        && c.lower <= c.range * PIN_OPPOSITE_SHADOW_RATIO
+    {
+        Some(PatternMatch::short("shooting_star", current.high))
+    } else {
+        None
+    }
+}
+
+/// 内包线：振幅在前一根K线（母线）之内，按母线方向顺势，止损在母线另一端
+pub fn inside_bar(klines: &[KlineData]) -> Option<PatternMatch> {
+    let [mother, current] = last::<2>(klines)?;
+    if current.high >= mother.high || current.low <= mother.low {
+        return None;
+    }
+    let m = Candle::new(mother);
+    if m.bull {
+        Some(PatternMatch::long("bullish_inside_bar", mother.low))
+    } else if m.bear {
+        Some(PatternMatch::short("bearish_inside_bar", mother.high))
+    } else {
+        None
+    }
+}
+
+/// 外包线：振幅覆盖前一根K线，并且收盘在振幅的一端
+pub fn outside_bar(klines: &[KlineData]) -> Option<PatternMatch> {
+    let [previous, current] = last::<2>(klines)?;
+    if current.high <= previous.high || current.low >= previous.low {
+        return None;
+    }
+    let c = Candle::new(current);
+    if c.bull && current.high - current.close <= c.range * OUTSIDE_CLOSE_RATIO {
+        Some(PatternMatch::long("bullish_outside_bar", current.low))
+    } else if c.bear && current.close - current.low <= c.range * OUTSIDE_CLOSE_RATIO {
+        Some(PatternMatch::short("bearish_outside_bar", current.high))
+    } else {
+        None
+    }
+}
+
+/// 早晨之星/黄昏之星：大实体、小实体，第三根反向收盘越过第一根实体的中点
+pub fn star(klines: &[KlineData]) -> Option<PatternMatch> {
+    let [first, middle, last] = last::<3>(klines)?;
+    let (f, m, l) = (Candle::new(first), Candle::new(middle), Candle::new(last));
+    if f.range <= 0.0
+        || f.body < f.range * STAR_FIRST_BODY_RATIO
+        || m.body > f.body * STAR_MIDDLE_BODY_RATIO
+    {
+        return None;
+    }
+    let midpoint = (first.open + first.close) / 2.0;
+    let stop_low = first.low.min(middle.low).min(last.low);
+    let stop_high = first.high.max(middle.high).max(last.high);
+    if f.bear && l.bull && last.close > midpoint {
+        Some(PatternMatch::long("morning_star", stop_low))
+    } else if f.bull && l.bear && last.close < midpoint {
+        Some(PatternMatch::short("evening_star", stop_high))
+    } else {
+        None
+    }
+}
+
+/// 十字星：蜻蜓十字看涨，墓碑十字看跌；普通十字和长腿十字没有方向，不产生信号
+pub fn doji(klines: &[KlineData]) -> Option<PatternMatch> {
+    let [current] = last::<1>(klines)?;
+    let c = Candle::new(current);
+    if c.range <= 0.0 || c.body > c.range * DOJI_BODY_RATIO {
+        return None;
+    }
+    if c.lower >= c.range * DOJI_SHADOW_RATIO && c.upper <= c.range * DOJI_BODY_RATIO {
+        Some(PatternMatch::long("dragonfly_doji", current.low))
+    } else if c.upper >= c.range * DOJI_SHADOW_RATIO && c.lower <= c.range * DOJI_BODY_RATIO {
+        Some(PatternMatch::short("gravestone_doji", current.high))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn k(open: f64, high: f64, low: f64, close: f64) -> KlineData {
+        KlineData {
+            timestamp: 0,
+            open,
+            high,
+            low,
+            close,
+            volume: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_two_candle_patterns() {
+        let bullish = [k(105.0, 106.0, 99.0, 100.0), k(99.5, 107.0, 98.0, 106.0)];
+        assert_eq!(
+            engulfing(&bullish),
+            Some(PatternMatch::long("bullish_engulfing", 98.0))
+        );
+        let bearish = [k(100.0, 106.0, 99.0, 105.0), k(105.5, 107.0, 98.0, 99.0)];
+        assert_eq!(
+            engulfing(&bearish).map(|m| m.pattern),
+            Some("bearish_engulfing")
+        );
+        assert_eq!(engulfing(&bullish[..1]), None);
+
+        let inside = [k(100.0, 110.0, 95.0, 108.0), k(104.0, 106.0, 100.0, 103.0)];
+        assert_eq!(
+            inside_bar(&inside),
+            Some(PatternMatch::long("bullish_inside_bar", 95.0))
+        );
+        assert_eq!(outside_bar(&inside), None);
+
+        let outside = [k(102.0, 104.0, 100.0, 101.0), k(101.0, 110.0, 99.0, 109.0)];
+        assert_eq!(
+            outside_bar(&outside),
+            Some(PatternMatch::long("bullish_outside_bar", 99.0))
+        );
+        // 收盘不在振幅一端
+        let weak = [k(102.0, 104.0, 100.0, 101.0), k(101.0, 110.0, 99.0, 104.0)];
+        assert_eq!(outside_bar(&weak), None);
+    }
+
+    #[test]
+    fn test_single_candle_patterns() {
+        assert_eq!(
+            pin_bar(&[k(100.0, 101.0, 94.0, 100.5)]),
+            Some(PatternMatch::long("hammer", 94.0))
+        );
+        assert_eq!(
+            pin_bar(&[k(100.5, 107.0, 100.0, 100.0)]),
+            Some(PatternMatch::short("shooting_star", 107.0))
+        );
+        assert_eq!(pin_bar(&[k(100.0, 105.0, 95.0, 101.0)]), None);
+
+        assert_eq!(
+            doji(&[k(100.0, 100.1, 95.0, 100.05)]).map(|m| m.pattern),
+            Some("dragonfly_doji")
+        );
+        assert_eq!(
+            doji(&[k(100.0, 105.0, 99.95, 100.0)]).map(|m| m.pattern),
+            Some("gravestone_doji")
+        );
+        // 长腿十字没有方向
+        assert_eq!(doji(&[k(100.0, 105.0, 95.0, 100.0)]), None);
+    }
+
+    #[test]
+    fn test_star() {
+        let morning = [
+            k(110.0, 111.0, 100.0, 101.0),
+            k(100.5, 101.5, 98.0, 100.0),
+            k(100.5, 108.0, 100.0, 107.0),
+        ];
+        assert_eq!(
+            star(&morning),
+            Some(PatternMatch::long("morning_star", 98.0))
+        );
+        let evening = [
+            k(100.0, 111.0, 99.0, 110.0),
+            k(110.5, 113.0, 110.0, 111.0),
+            k(110.5, 111.0, 102.0, 103.0),
+        ];
+        assert_eq!(
+            star(&evening),
+            Some(PatternMatch::short("evening_star", 113.0))
+        );
+        assert_eq!(detect("star", &morning[1..]), None);
+        assert_eq!(detect("long_shadow", &morning), None);
+    }
+}
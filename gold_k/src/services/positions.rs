@@ -0,0 +1,227 @@
+use crate::models::{Order, OrderUpdate, PositionSnapshot, RoundTrip, TakeProfitTranche};
+use crate::repository::{ApiKeyRepository, PositionRepository, RoundTripRepository};
+use crate::services::exchange::{Exchange, Position};
+use anyhow::{Result, anyhow};
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, interval};
+use tracing::{error, info, warn};
+
+/// 持仓没有变化时，至少每隔这么久保存一次快照，用来记录浮动盈亏
+const SNAPSHOT_REFRESH_SECS: i64 = 15 * 60;
+
+/// 持仓跟踪：定期查询交易所的持仓并保存快照
+#[derive(Debug, Clone)]
+pub struct PositionTracker {
+    db: SqlitePool,
+    exchanges: Vec<Arc<RwLock<dyn Exchange>>>,
+}
+
+impl PositionTracker {
+    pub fn new(db: SqlitePool, exchanges: Vec<Arc<RwLock<dyn Exchange>>>) -> Self {
+        Self { db, exchanges }
+    }
+
+    /// 启动后台快照任务
+    pub fn spawn(self, period: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = interval(period);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.snapshot_once().await {
+                    error!("Failed to snapshot positions: {}", e);
+                }
+            }
+        })
+    }
+
+    /// 查询所有交易所的持仓并保存有变化的快照，返回保存的数量
+    pub async fn snapshot_once(&self) -> Result<usize> {
+        let mut saved = 0;
+        for exchange in &self.exchanges {
+            let exchange = exchange.read().await;
+            if !exchange.has_credentials() {
+                continue;
+            }
+            match exchange.get_positions("usdt").await {
+                Ok(positions) => {
+                    saved += save_snapshots(&self.db, exchange.name(), &positions).await?;
+                }
+                Err(e) => warn!("Failed to get positions from {}: {}", exchange.name(), e),
+            }
+        }
+        Ok(saved)
+    }
+}
+
+/// 保存持仓快照；和上一次快照相比持仓没有变化且未超过刷新间隔时跳过
+///
+/// 上一次快照中有、本次已经没有的持仓会记录一条数量为0的快照，表示已平仓
+pub async fn save_snapshots(
+    db: &SqlitePool,
+    exchange: &str,
+    positions: &[Position],
+) -> Result<usize> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    let latest = PositionRepository::get_latest(db, exchange).await?;
+    let mut saved = 0;
+
+    let mut snapshots: Vec<PositionSnapshot> = positions
+        .iter()
+        .map(|p| PositionSnapshot {
+            exchange: exchange.to_string(),
+            symbol: p.symbol.clone(),
+            mode: p.mode.clone(),
+            size: p.size,
+            entry_price: p.entry_price,
+            mark_price: p.mark_price,
+            unrealized_pnl: p.unrealized_pnl,
+            realized_pnl: p.realized_pnl,
+            leverage: p.leverage,
+            liq_price: p.liq_price.unwrap_or(0.0),
+            ..Default::default()
+        })
+        .collect();
+    for last in &latest {
+        let still_open = snapshots
+            .iter()
+            .any(|s| s.symbol == last.symbol && s.mode == last.mode);
+        if last.size != 0 && !still_open {
+            snapshots.push(PositionSnapshot {
+                size: 0,
+                unrealized_pnl: 0.0,
+                ..last.clone()
+            });
+        }
+    }
+
+    for snapshot in &snapshots {
+        let previous = latest
+            .iter()
+            .find(|s| s.symbol == snapshot.symbol && s.mode == snapshot.mode);
+        let changed = previous.is_none_or(|p| {
+            p.size != snapshot.size
+                || p.entry_price != snapshot.entry_price
+                || p.realized_pnl != snapshot.realized_pnl
+                || (snapshot.size != 0 && now - p.created_at >= SNAPSHOT_REFRESH_SECS)
+        });
+        if changed {
+            PositionRepository::save_snapshot(db, snapshot).await?;
+            saved += 1;
+        }
+    }
+
+    Ok(saved)
+}
+
+/// 平仓后记录一笔完整交易，平仓原因和时间取自对账结果
+///
+/// 有平仓订单ID时平仓价使用它的成交价，否则使用对账时记录的平仓价；
+/// 有分批止盈时 `partial_exits` 为已经触发的批次，平仓价为各部分成交价按张数的加权平均
+pub async fn record_round_trip(
+    db: &SqlitePool,
+    exchange: &dyn Exchange,
+    order: &Order,
+    update: &OrderUpdate,
+    exit_trade_id: Option<&str>,
+    partial_exits: &[TakeProfitTranche],
+) -> Result<RoundTrip> {
+    let quanto_multiplier = ApiKeyRepository::get_quanto_multiplier(db, &order.symbol).await?;
+    let size = if update.filled_size > 0 {
+        update.filled_size
+    } else {
+        order.order_size
+    };
+    let entry_price = update.avg_fill_price.unwrap_or(order.entry_price);
+    let exit_reason = update.exit_reason.as_deref().unwrap_or("manual");
+    let closed_at = update.closed_at.unwrap_or(order.created_at);
+
+    let partial_size: i64 = partial_exits.iter().map(|t| t.size).sum();
+    let remaining = (size - partial_size).max(0);
+    let mut exit_price = 0.0;
+    let mut exit_fee_rate = 0.0;
+    if remaining > 0 {
+        exit_price = update
+            .exit_price
+            .ok_or_else(|| anyhow!("订单{}没有平仓价格", order.id))?;
+        if let Some(trade_id) = exit_trade_id {
+            match exchange.get_order(trade_id, "usdt").await {
+                Ok(exit_order) => {
+                    exit_price = exit_order.fill_price.unwrap_or(exit_price);
+                    exit_fee_rate = exit_order.fee_rate;
+                }
+                Err(e) => warn!("Failed to get exit order {}: {}", trade_id, e),
+            }
+        }
+    }
+    let exit_fee = remaining as f64 * exit_price * quanto_multiplier * exit_fee_rate
+        + partial_exits.iter().map(|t| t.fee).sum::<f64>();
+    let exit_price = if partial_exits.is_empty() {
+        exit_price
+    } else {
+        let partial_value: f64 = partial_exits
+            .iter()
+            .map(|t| t.size as f64 * t.fill_price.unwrap_or(t.trigger_price))
+            .sum();
+        (partial_value + remaining as f64 * exit_price) / (partial_size + remaining) as f64
+    };
+
+    let gross_pnl = gross_pnl(
+        &order.side,
+        size,
+        entry_price,
+        exit_price,
+        quanto_multiplier,
+    );
+    let fee = update.fee + exit_fee;
+    let trip = RoundTrip {
+        exchange: order.exchange.clone(),
+        symbol: order.symbol.clone(),
+        side: order.side.clone(),
+        size,
+        entry_price,
+        exit_price,
+        exit_reason: exit_reason.to_string(),
+        gross_pnl,
+        fee,
+        realized_pnl: gross_pnl - fee,
+        order_id: order.id,
+        signal_id: order.signal_id,
+        opened_at: order.created_at,
+        closed_at,
+        ..Default::default()
+    };
+
+    RoundTripRepository::save(db, &trip).await?;
+    info!(
+        "Round trip for order {} ({}) closed by {}: {:.4} USDT",
+        order.id, order.symbol, exit_reason, trip.realized_pnl
+    );
+    Ok(trip)
+}
+
+/// 计算未扣除手续费的盈亏(USDT)
+pub fn gross_pnl(
+    side: &str,
+    size: i64,
+    entry_price: f64,
+    exit_price: f64,
+    quanto_multiplier: f64,
+) -> f64 {
+    let direction = if side == "buy" { 1.0 } else { -1.0 };
+    (exit_price - entry_price) * size as f64 * quanto_multiplier * direction
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gross_pnl() {
+        assert!((gross_pnl("buy", 10, 100.0, 110.0, 0.01) - 1.0).abs() < 1e-12);
+        assert!((gross_pnl("sell", 10, 100.0, 110.0, 0.01) + 1.0).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,151 @@
+use rand::Rng;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Mutex;
+use tokio::time::{Duration, sleep};
+
+/// Gate.io 接口的频率限制分类
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EndpointClass {
+    /// 行情等公开接口：每个接口 200次/10秒
+    Public,
+    /// 查询订单、持仓等私有接口：每个接口 200次/10秒
+    Private,
+    /// 合约下单：100次/秒
+    PlaceOrder,
+    /// 合约撤单：100次/秒
+    CancelOrder,
+    /// 网页端 apiw/v2 接口，没有公开的限制，按较低的频率请求
+    Web,
+}
+
+impl EndpointClass {
+    /// 令牌桶容量和每秒补充的令牌数
+    fn limits(self) -> (f64, f64) {
+        match self {
+            Self::Public | Self::Private => (200.0, 20.0),
+            Self::PlaceOrder | Self::CancelOrder => (100.0, 100.0),
+            Self::Web => (5.0, 1.0),
+        }
+    }
+}
+
+/// 令牌桶，令牌不足时返回需要等待的时间
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    capacity: f64,
+    refill_per_sec: f64,
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: f64, refill_per_sec: f64, now: Instant) -> Self {
+        Self {
+            capacity,
+            refill_per_sec,
+            tokens: capacity,
+            updated_at: now,
+        }
+    }
+
+    pub fn try_acquire(&mut self, now: Instant) -> Result<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.updated_at = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - self.tokens) / self.refill_per_sec,
+            ))
+        }
+    }
+}
+
+/// 按接口分类限制请求频率，克隆后共用同一组令牌桶
+#[derive(Debug, Clone, Default)]
+pub struct RateLimiter {
+    buckets: Arc<Mutex<HashMap<EndpointClass, TokenBucket>>>,
+}
+
+impl RateLimiter {
+    /// 等待直到可以发送一个该分类的请求
+    pub async fn acquire(&self, class: EndpointClass) {
+        loop {
+            let wait = {
+                let mut buckets = self.buckets.lock().await;
+                let now = Instant::now();
+                let bucket = buckets.entry(class).or_insert_with(|| {
+                    let (capacity, refill_per_sec) = class.limits();
+                    TokenBucket::new(capacity, refill_per_sec, now)
+                });
+                match bucket.try_acquire(now) {
+                    Ok(()) => return,
+                    Err(wait) => wait,
+                }
+            };
+            sleep(wait).await;
+        }
+    }
+}
+
+/// 失败重试的次数和退避时间
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(8),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 第 `attempt` 次（从0开始）失败后的等待时间：指数退避，随机取上限的50%~100%，避免多个任务同时重试
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self.base_delay.saturating_mul(2u32.saturating_pow(attempt));
+        let capped = exponential.min(self.max_delay);
+        capped.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(2.0, 10.0, start);
+        assert!(bucket.try_acquire(start).is_ok());
+        assert!(bucket.try_acquire(start).is_ok());
+        let wait = bucket.try_acquire(start).unwrap_err();
+        assert!((wait.as_secs_f64() - 0.1).abs() < 1e-9);
+        // 补充的令牌不超过容量
+        let later = start + Duration::from_secs(10);
+        assert!(bucket.try_acquire(later).is_ok());
+        assert!(bucket.try_acquire(later).is_ok());
+        assert!(bucket.try_acquire(later).is_err());
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::default();
+        for attempt in 0..10 {
+            let delay = policy.backoff(attempt);
+            let cap = (policy.base_delay * 2u32.pow(attempt.min(8))).min(policy.max_delay);
+            assert!(delay >= cap / 2 && delay <= cap);
+        }
+    }
+}
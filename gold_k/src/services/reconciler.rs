@@ -0,0 +1,809 @@
+use crate::models::{Order, OrderUpdate};
+use crate::repository::{ApiKeyRepository, OrderRepository, TakeProfitTrancheRepository};
+use crate::services::exchange::{Exchange, Position, PriceOrder, TriggerOrderRequest};
+use crate::services::positions::record_round_trip;
+use crate::services::take_profit;
+use anyhow::{Result, anyhow};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, interval};
+use tracing::{error, info, warn};
+
+/// 只对账最近7天的订单
+const RECONCILE_WINDOW_SECS: i64 = 7 * 86400;
+/// 没有交易所订单ID的订单超过这个时间仍查不到，视为下单失败
+const PENDING_TIMEOUT_SECS: i64 = 600;
+
+/// 订单对账：定期查询交易所的订单和触发委托，更新本地订单的成交和止盈止损状态
+#[derive(Debug, Clone)]
+pub struct OrderReconciler {
+    db: SqlitePool,
+    exchanges: Vec<Arc<RwLock<dyn Exchange>>>,
+}
+
+impl OrderReconciler {
+    /// `exchanges` 按名称匹配订单记录中的 `exchange` 字段
+    pub fn new(db: SqlitePool, exchanges: Vec<Arc<RwLock<dyn Exchange>>>) -> Self {
+        Self { db, exchanges }
+    }
+
+    /// 启动后台对账任务
+    pub fn spawn(self, period: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = interval(period);
+            loop {
+                ticker.tick().await;
+                match self.reconcile_once().await {
+                    Ok(0) => {}
+                    Ok(updated) => info!("Reconciled {} orders", updated),
+                    Err(e) => error!("Failed to reconcile orders: {}", e),
+                }
+            }
+        })
+    }
+
+    /// 执行一轮对账，返回状态有变化的订单数量
+    pub async fn reconcile_once(&self) -> Result<usize> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let orders = OrderRepository::get_unsettled(&self.db, now - RECONCILE_WINDOW_SECS).await?;
+        let mut price_orders: HashMap<(String, String), Vec<PriceOrder>> = HashMap::new();
+        let mut positions: HashMap<String, Vec<Position>> = HashMap::new();
+        let mut updated = 0;
+
+        for order in orders {
+            let Some(exchange) = self.exchange_for(&order.exchange).await else {
+                continue;
+            };
+            let exchange = exchange.read().await;
+
+            // 分批止盈触发后已平仓张数和止损委托会变化，重新读取订单
+            let order =
+                match take_profit::sync_tranches(&self.db, &*exchange, &order, &mut price_orders)
+                    .await
+                {
+                    Ok(true) => OrderRepository::get_by_id(&self.db, order.id)
+                        .await?
+                        .unwrap_or(order),
+                    Ok(false) => order,
+                    Err(e) => {
+                        warn!(
+                            "Failed to sync take profit tranches of order {}: {}",
+                            order.id, e
+                        );
+                        order
+                    }
+                };
+
+            let result = reconcile_order(
+                &self.db,
+                &*exchange,
+                &order,
+                &mut price_orders,
+                &mut positions,
+                now,
+            )
+            .await;
+            match result {
+                Ok(Some(mut update)) => {
+                    // 刚平仓，记录这笔交易的已实现盈亏，平仓价改为实际成交价
+                    if order.exit_reason.is_none() && update.exit_reason.is_some() {
+                        let key = (order.exchange.clone(), order.symbol.clone());
+                        let exit_trade_id = exit_trade_id(&update, price_orders.get(&key));
+                        let partial_exits: Vec<_> =
+                            TakeProfitTrancheRepository::get_by_order(&self.db, order.id)
+                                .await?
+                                .into_iter()
+                                .filter(|t| t.status == "triggered")
+                                .collect();
+                        match record_round_trip(
+                            &self.db,
+                            &*exchange,
+                            &order,
+                            &update,
+                            exit_trade_id.as_deref(),
+                            &partial_exits,
+                        )
+                        .await
+                        {
+                            Ok(trip) => update.exit_price = Some(trip.exit_price),
+                            Err(e) => {
+                                warn!("Failed to record round trip for order {}: {}", order.id, e)
+                            }
+                        }
+                    }
+
+                    let saved = OrderRepository::update_status(
+                        &self.db,
+                        order.id,
+                        order.stop_loss_order_id.as_deref(),
+                        &update,
+                    )
+                    .await?;
+                    if !saved {
+                        info!(
+                            "Stop of order {} moved during reconcile, retry next round",
+                            order.id
+                        );
+                        continue;
+                    }
+                    info!(
+                        "Order {} for {} updated: {} -> {}",
+                        order.id, order.symbol, order.status, update.status
+                    );
+                    updated += 1;
+
+                    if order.exit_reason.is_none()
+                        && update.exit_reason.is_some()
+                        && let Err(e) =
+                            take_profit::cancel_tranches(&self.db, &*exchange, &order).await
+                    {
+                        warn!(
+                            "Failed to cancel take profit tranches of order {}: {}",
+                            order.id, e
+                        );
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Failed to reconcile order {}: {}", order.id, e),
+            }
+        }
+
+        Ok(updated)
+    }
+
+    async fn exchange_for(&self, name: &str) -> Option<Arc<RwLock<dyn Exchange>>> {
+        for exchange in &self.exchanges {
+            if exchange.read().await.name() == name {
+                return Some(exchange.clone());
+            }
+        }
+        None
+    }
+}
+
+/// 查询单个订单的最新状态，没有变化时返回None
+async fn reconcile_order(
+    db: &SqlitePool,
+    exchange: &dyn Exchange,
+    order: &Order,
+    price_orders: &mut HashMap<(String, String), Vec<PriceOrder>>,
+    positions: &mut HashMap<String, Vec<Position>>,
+    now: i64,
+) -> Result<Option<OrderUpdate>> {
+    let current = OrderUpdate {
+        exchange_order_id: order.exchange_order_id.clone(),
+        status: order.status.clone(),
+        filled_size: order.filled_size,
+        avg_fill_price: order.avg_fill_price,
+        fee: order.fee,
+        take_profit_order_id: order.take_profit_order_id.clone(),
+        take_profit_status: order.take_profit_status.clone(),
+        stop_loss_order_id: order.stop_loss_order_id.clone(),
+        stop_loss_status: order.stop_loss_status.clone(),
+        error_message: order.error_message.clone(),
+        exit_price: order.exit_price,
+        exit_reason: order.exit_reason.clone(),
+        closed_at: order.closed_at,
+    };
+    let mut update = current.clone();
+
+    if matches!(order.status.as_str(), "pending" | "open") {
+        let lookup_id = order
+            .exchange_order_id
+            .as_deref()
+            .or(order.client_order_id.as_deref())
+            .ok_or_else(|| anyhow!("Order has neither exchange id nor client id"))?;
+
+        match exchange.get_order(lookup_id, "usdt").await {
+            Ok(exchange_order) => {
+                let quanto_multiplier =
+                    ApiKeyRepository::get_quanto_multiplier(db, &order.symbol).await?;
+                let fill_price = exchange_order.fill_price.unwrap_or(0.0);
+
+                update.exchange_order_id = Some(exchange_order.id);
+                update.status = exchange_order.status;
+                update.filled_size = exchange_order.filled_size;
+                update.avg_fill_price = exchange_order.fill_price;
+                update.fee = exchange_order.filled_size as f64
+                    * fill_price
+                    * quanto_multiplier
+                    * exchange_order.fee_rate;
+            }
+            Err(e)
+                if order.exchange_order_id.is_none()
+                    && now - order.created_at > PENDING_TIMEOUT_SECS =>
+            {
+                update.status = "rejected".to_string();
+                update.error_message = Some(format!("交易所未找到订单: {}", e));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    // 上一轮对账前已经成交、还没有平仓记录，但交易所已经没有这个方向的持仓
+    let closed_manually = order.status == "filled"
+        && order.exit_reason.is_none()
+        && match has_position(exchange, order, positions).await {
+            Ok(open) => !open,
+            Err(e) => {
+                warn!("Failed to get positions from {}: {}", order.exchange, e);
+                false
+            }
+        };
+
+    // 分批止盈的订单没有单独的止盈委托，每一批由 take_profit::sync_tranches 处理
+    let ladder = !order.take_profit_ladder.is_empty();
+    let ladder_done = ladder && update.filled_size > 0 && order.closed_size >= update.filled_size;
+
+    // 部分成交时已经按当时的成交张数设置了止盈止损，成交张数增加后改为新的张数；
+    // 调整失败时保留上一轮的成交状态，下一轮对账重新查询并重试
+    if order.execution_mode == "api"
+        && update.filled_size > order.filled_size
+        && let Err(e) = resize_trigger_orders(exchange, order, &mut update).await
+    {
+        warn!(
+            "Failed to resize triggers of order {} to {}: {}",
+            order.id, update.filled_size, e
+        );
+        update.status = order.status.clone();
+        update.filled_size = order.filled_size;
+        update.avg_fill_price = order.avg_fill_price;
+        update.fee = order.fee;
+        update.error_message = Some(format!("调整止盈止损张数失败: {}", e));
+    }
+
+    // 签名API下单的订单有成交后才能设置止盈止损，下单时没有设置成功的在这里补上；
+    // 限价单部分成交时先保护已成交的部分
+    if order.execution_mode == "api" && update.filled_size > 0 && !closed_manually && !ladder_done {
+        attach_trigger_orders(exchange, order, &mut update).await;
+    }
+
+    let tracking = |status: &Option<String>| status.is_none() || status.as_deref() == Some("open");
+    if update.status == "filled"
+        && ((!ladder && tracking(&update.take_profit_status)) || tracking(&update.stop_loss_status))
+    {
+        let key = (order.exchange.clone(), order.symbol.clone());
+        if !price_orders.contains_key(&key) {
+            let orders = exchange.get_price_orders(&order.symbol, "usdt").await?;
+            price_orders.insert(key.clone(), orders);
+        }
+        let candidates = &price_orders[&key];
+
+        if !ladder
+            && let Some(tp) = match_price_order(
+                candidates,
+                update.take_profit_order_id.as_deref(),
+                order.take_profit_price,
+                order.created_at,
+            )
+        {
+            update.take_profit_order_id = Some(tp.id.clone());
+            update.take_profit_status = Some(tp.status.clone());
+        }
+        if let Some(sl) = match_price_order(
+            candidates,
+            update.stop_loss_order_id.as_deref(),
+            order.stop_loss_price,
+            order.created_at,
+        ) {
+            update.stop_loss_order_id = Some(sl.id.clone());
+            update.stop_loss_status = Some(sl.status.clone());
+        }
+    }
+
+    if update.status == "filled" {
+        settle_exit(
+            exchange,
+            order,
+            &mut update,
+            closed_manually,
+            ladder_done,
+            now,
+        )
+        .await;
+    }
+
+    Ok((update != current).then_some(update))
+}
+
+/// 交易所中是否还有订单方向的持仓，每轮对账每个交易所只查询一次
+async fn has_position(
+    exchange: &dyn Exchange,
+    order: &Order,
+    positions: &mut HashMap<String, Vec<Position>>,
+) -> Result<bool> {
+    if !positions.contains_key(&order.exchange) {
+        let current = exchange.get_positions("usdt").await?;
+        positions.insert(order.exchange.clone(), current);
+    }
+    Ok(positions[&order.exchange].iter().any(|p| {
+        p.symbol == order.symbol
+            && if order.side == "buy" {
+                p.size > 0
+            } else {
+                p.size < 0
+            }
+    }))
+}
+
+/// OCO：止盈或止损一边触发后撤销另一边，并记录平仓原因和价格；
+/// 分批止盈全部触发也算止盈平仓；两边都没有触发但持仓已经没有了，视为手动平仓，撤销两边
+///
+/// 撤销失败的委托保持open，下一轮对账重试
+async fn settle_exit(
+    exchange: &dyn Exchange,
+    order: &Order,
+    update: &mut OrderUpdate,
+    closed_manually: bool,
+    ladder_done: bool,
+    now: i64,
+) {
+    let triggered = |status: &Option<String>| status.as_deref() == Some("triggered");
+    let reason = if triggered(&update.take_profit_status) || ladder_done {
+        "tp"
+    } else if triggered(&update.stop_loss_status) {
+        "sl"
+    } else if closed_manually {
+        "manual"
+    } else {
+        return;
+    };
+
+    if update.exit_reason.is_none() {
+        update.exit_reason = Some(reason.to_string());
+        update.closed_at = Some(now);
+        // 先按触发价记录，保存交易记录时改为实际成交价；手动平仓没有成交记录，用最新价格近似
+        // 分批止盈全部触发时平仓价由每一批的成交价计算
+        update.exit_price = match reason {
+            "tp" if ladder_done => None,
+            "tp" => Some(order.take_profit_price),
+            "sl" => Some(order.stop_loss_price),
+            _ => match exchange
+                .get_kline_data(&order.symbol, "1m", 1, "usdt")
+                .await
+            {
+                Ok(klines) => klines.last().map(|k| k.close),
+                Err(e) => {
+                    warn!("Failed to get latest price for {}: {}", order.symbol, e);
+                    None
+                }
+            },
+        };
+        info!(
+            "Order {} for {} closed by {}",
+            order.id, order.symbol, reason
+        );
+    }
+
+    let legs = [
+        (&update.take_profit_order_id, &mut update.take_profit_status),
+        (&update.stop_loss_order_id, &mut update.stop_loss_status),
+    ];
+    for (order_id, status) in legs {
+        match (status.as_deref(), order_id) {
+            (Some("open"), Some(order_id)) => {
+                match exchange.cancel_price_order(order_id, "usdt").await {
+                    Ok(()) => {
+                        info!("Cancelled trigger {} of order {}", order_id, order.id);
+                        *status = Some("cancelled".to_string());
+                    }
+                    Err(e) => warn!("Failed to cancel trigger {}: {}", order_id, e),
+                }
+            }
+            // 没有设置成功的一边不需要撤销
+            (None, _) => *status = Some("cancelled".to_string()),
+            _ => {}
+        }
+    }
+}
+
+/// 触发的止盈或止损委托生成的平仓订单ID
+fn exit_trade_id(update: &OrderUpdate, price_orders: Option<&Vec<PriceOrder>>) -> Option<String> {
+    let leg_id = match update.exit_reason.as_deref() {
+        Some("tp") => update.take_profit_order_id.as_ref(),
+        Some("sl") => update.stop_loss_order_id.as_ref(),
+        _ => None,
+    }?;
+    price_orders?
+        .iter()
+        .find(|p| &p.id == leg_id)
+        .and_then(|p| p.trade_id.clone())
+}
+
+/// 为有成交的订单按已成交张数创建还没有的止盈止损触发委托，失败的下一轮对账重试
+async fn attach_trigger_orders(exchange: &dyn Exchange, order: &Order, update: &mut OrderUpdate) {
+    let legs = [
+        (
+            "tp",
+            order.take_profit_price,
+            &mut update.take_profit_order_id,
+            &mut update.take_profit_status,
+        ),
+        (
+            "sl",
+            order.stop_loss_price,
+            &mut update.stop_loss_order_id,
+            &mut update.stop_loss_status,
+        ),
+    ];
+    let ladder = !order.take_profit_ladder.is_empty();
+    for (kind, price, order_id, status) in legs {
+        if order_id.is_some() || status.is_some() || price <= 0.0 || (ladder && kind == "tp") {
+            continue;
+        }
+        // 分批止盈已经平掉的部分不需要止损
+        let size = update.filled_size - order.closed_size;
+        let trigger = TriggerOrderRequest::exit(&order.symbol, &order.side, size, price, kind);
+        match exchange.place_trigger_order(&trigger, "usdt").await {
+            Ok(id) => {
+                info!("Created {} trigger {} for order {}", kind, id, order.id);
+                *order_id = Some(id);
+                *status = Some("open".to_string());
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to create {} trigger for order {}: {}",
+                    kind, order.id, e
+                );
+                update.error_message = Some(format!("设置止盈止损失败: {}", e));
+            }
+        }
+    }
+}
+
+/// 把按上一轮成交张数设置的止盈止损改为当前成交张数
+///
+/// 止损用 `replace_stop_loss` 先创建新委托再撤销旧的；止盈同样先创建再撤销，撤销失败时撤回新委托。
+/// 先调整止损，止损失败时不动止盈；已经调整成功的一边记录新的委托ID
+async fn resize_trigger_orders(
+    exchange: &dyn Exchange,
+    order: &Order,
+    update: &mut OrderUpdate,
+) -> Result<()> {
+    let size = update.filled_size - order.closed_size;
+    let open = |order_id: &Option<String>, status: &Option<String>| match status.as_deref() {
+        Some("open") => order_id.clone(),
+        _ => None,
+    };
+
+    if let Some(old_order_id) = open(&order.stop_loss_order_id, &order.stop_loss_status) {
+        let trigger = TriggerOrderRequest::exit(
+            &order.symbol,
+            &order.side,
+            size,
+            order.stop_loss_price,
+            "sl",
+        );
+        let new_order_id = exchange
+            .replace_stop_loss(&old_order_id, &trigger, "usdt")
+            .await?;
+        info!(
+            "Stop loss of order {} resized to {}: {}",
+            order.id, size, new_order_id
+        );
+        update.stop_loss_order_id = Some(new_order_id);
+    }
+
+    if order.take_profit_ladder.is_empty()
+        && let Some(old_order_id) = open(&order.take_profit_order_id, &order.take_profit_status)
+    {
+        let trigger = TriggerOrderRequest::exit(
+            &order.symbol,
+            &order.side,
+            size,
+            order.take_profit_price,
+            "tp",
+        );
+        let new_order_id = exchange.place_trigger_order(&trigger, "usdt").await?;
+        if let Err(e) = exchange.cancel_price_order(&old_order_id, "usdt").await {
+            exchange.cancel_price_order(&new_order_id, "usdt").await?;
+            return Err(e);
+        }
+        info!(
+            "Take profit of order {} resized to {}: {}",
+            order.id, size, new_order_id
+        );
+        update.take_profit_order_id = Some(new_order_id);
+    }
+
+    Ok(())
+}
+
+/// 找到订单对应的触发委托：已知ID时按ID查找，否则按触发价格和创建时间匹配
+pub fn match_price_order<'a>(
+    price_orders: &'a [PriceOrder],
+    known_id: Option<&str>,
+    trigger_price: f64,
+    since: i64,
+) -> Option<&'a PriceOrder> {
+    if let Some(id) = known_id {
+        return price_orders.iter().find(|p| p.id == id);
+    }
+
+    price_orders
+        .iter()
+        .filter(|p| p.create_time >= since - 60)
+        .filter(|p| (p.trigger_price - trigger_price).abs() <= trigger_price.abs() * 1e-6)
+        .min_by_key(|p| p.create_time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ApiKey, KlineData};
+    use crate::services::exchange::{ExchangeOrder, OrderRequest, OrderResponse};
+    use async_trait::async_trait;
+    use serde_json::Value;
+    use std::sync::Mutex;
+
+    /// 返回固定成交张数的限价单，记录创建和撤销的触发委托
+    #[derive(Debug, Default)]
+    struct PartialFillExchange {
+        filled_size: i64,
+        placed: Mutex<Vec<(String, i64)>>,
+        cancelled: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl Exchange for PartialFillExchange {
+        fn name(&self) -> &str {
+            "gate"
+        }
+
+        fn apply_api_key(&mut self, _key: &ApiKey) {}
+
+        fn has_credentials(&self) -> bool {
+            true
+        }
+
+        async fn get_kline_data(
+            &self,
+            _symbol: &str,
+            _interval: &str,
+            _limit: usize,
+            _settle: &str,
+        ) -> Result<Vec<KlineData>> {
+            unimplemented!()
+        }
+
+        async fn get_kline_data_range(
+            &self,
+            _symbol: &str,
+            _interval: &str,
+            _from: i64,
+            _to: i64,
+            _settle: &str,
+        ) -> Result<Vec<KlineData>> {
+            unimplemented!()
+        }
+
+        async fn get_contracts(&self, _settle: &str) -> Result<Vec<Value>> {
+            unimplemented!()
+        }
+
+        async fn place_order(&self, _order: &OrderRequest, _settle: &str) -> Result<OrderResponse> {
+            unimplemented!()
+        }
+
+        async fn place_trigger_order(
+            &self,
+            order: &TriggerOrderRequest,
+            _settle: &str,
+        ) -> Result<String> {
+            let mut placed = self.placed.lock().unwrap();
+            placed.push((order.kind.clone(), order.size));
+            Ok(format!("{}-{}", order.kind, placed.len()))
+        }
+
+        async fn cancel_price_order(&self, order_id: &str, _settle: &str) -> Result<()> {
+            self.cancelled.lock().unwrap().push(order_id.to_string());
+            Ok(())
+        }
+
+        async fn get_order(&self, order_id: &str, _settle: &str) -> Result<ExchangeOrder> {
+            Ok(ExchangeOrder {
+                id: order_id.to_string(),
+                text: None,
+                status: "open".to_string(),
+                size: 10,
+                filled_size: self.filled_size,
+                fill_price: Some(100.0),
+                fee_rate: 0.0,
+            })
+        }
+
+        async fn get_price_orders(&self, _symbol: &str, _settle: &str) -> Result<Vec<PriceOrder>> {
+            Ok(Vec::new())
+        }
+
+        async fn get_positions(&self, _settle: &str) -> Result<Vec<Position>> {
+            Ok(Vec::new())
+        }
+
+        async fn cancel_all_orders(&self, _settle: &str) -> Result<usize> {
+            unimplemented!()
+        }
+
+        async fn cancel_all_price_orders(&self, _settle: &str) -> Result<usize> {
+            unimplemented!()
+        }
+
+        async fn close_position(
+            &self,
+            _position: &Position,
+            _settle: &str,
+        ) -> Result<OrderResponse> {
+            unimplemented!()
+        }
+
+        async fn get_account_info(&self) -> Result<crate::services::exchange::AccountInfo> {
+            unimplemented!()
+        }
+    }
+
+    fn open_limit_order() -> Order {
+        Order {
+            id: 1,
+            symbol: "BTC_USDT".into(),
+            side: "buy".into(),
+            order_size: 10,
+            entry_price: 100.0,
+            take_profit_price: 110.0,
+            stop_loss_price: 95.0,
+            risk_reward_ratio: 2.0,
+            signal_id: None,
+            timestamp: 1000,
+            created_at: 1000,
+            exchange: "gate".into(),
+            exchange_order_id: Some("42".into()),
+            client_order_id: None,
+            status: "open".into(),
+            filled_size: 0,
+            avg_fill_price: None,
+            fee: 0.0,
+            take_profit_order_id: None,
+            take_profit_status: None,
+            stop_loss_order_id: None,
+            stop_loss_status: None,
+            error_message: None,
+            updated_at: None,
+            execution_mode: "api".into(),
+            exit_price: None,
+            exit_reason: None,
+            closed_at: None,
+            interval_type: None,
+            break_even_r: 0.0,
+            trailing_mode: "none".into(),
+            trailing_value: 0.0,
+            initial_stop_price: None,
+            take_profit_ladder: String::new(),
+            ladder_break_even: false,
+            closed_size: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_partial_fill_attaches_and_resizes_triggers() {
+        let db = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("../migrations").run(&db).await.unwrap();
+
+        // 部分成交：按已成交的3张设置止盈止损
+        let exchange = PartialFillExchange {
+            filled_size: 3,
+            ..Default::default()
+        };
+        let mut order = open_limit_order();
+        let update = reconcile_order(
+            &db,
+            &exchange,
+            &order,
+            &mut HashMap::new(),
+            &mut HashMap::new(),
+            1100,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(update.status, "open");
+        assert_eq!(
+            *exchange.placed.lock().unwrap(),
+            vec![("tp".to_string(), 3), ("sl".to_string(), 3)]
+        );
+        assert_eq!(update.take_profit_order_id.as_deref(), Some("tp-1"));
+        assert_eq!(update.stop_loss_order_id.as_deref(), Some("sl-2"));
+
+        // 继续成交到7张：止盈止损改为7张，旧委托撤销
+        order.filled_size = update.filled_size;
+        order.take_profit_order_id = update.take_profit_order_id;
+        order.take_profit_status = update.take_profit_status;
+        order.stop_loss_order_id = update.stop_loss_order_id;
+        order.stop_loss_status = update.stop_loss_status;
+        let exchange = PartialFillExchange {
+            filled_size: 7,
+            ..Default::default()
+        };
+        let update = reconcile_order(
+            &db,
+            &exchange,
+            &order,
+            &mut HashMap::new(),
+            &mut HashMap::new(),
+            1200,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(update.filled_size, 7);
+        assert_eq!(
+            *exchange.placed.lock().unwrap(),
+            vec![("sl".to_string(), 7), ("tp".to_string(), 7)]
+        );
+        assert_eq!(
+            *exchange.cancelled.lock().unwrap(),
+            vec!["sl-2".to_string(), "tp-1".to_string()]
+        );
+        assert_eq!(update.stop_loss_order_id.as_deref(), Some("sl-1"));
+        assert_eq!(update.take_profit_order_id.as_deref(), Some("tp-2"));
+    }
+
+    fn price_order(id: &str, trigger_price: f64, create_time: i64) -> PriceOrder {
+        PriceOrder {
+            id: id.into(),
+            status: "open".into(),
+            trigger_price,
+            order_type: "close-long-position".into(),
+            trade_id: None,
+            create_time,
+        }
+    }
+
+    #[test]
+    fn test_match_price_order() {
+        let orders = vec![
+            price_order("1", 110.0, 900), // 更早的订单留下的委托
+            price_order("2", 110.0, 1010),
+            price_order("3", 95.0, 1010),
+            price_order("4", 110.0, 1200),
+        ];
+        assert_eq!(
+            match_price_order(&orders, None, 110.0, 1000).unwrap().id,
+            "2"
+        );
+        assert_eq!(
+            match_price_order(&orders, None, 95.0, 1000).unwrap().id,
+            "3"
+        );
+        assert_eq!(
+            match_price_order(&orders, Some("4"), 110.0, 1000)
+                .unwrap()
+                .id,
+            "4"
+        );
+        assert!(match_price_order(&orders, None, 100.0, 1000).is_none());
+    }
+
+    #[test]
+    fn test_exit_trade_id() {
+        let mut triggered = price_order("2", 110.0, 1010);
+        triggered.status = "triggered".into();
+        triggered.trade_id = Some("99".into());
+        let orders = vec![triggered, price_order("3", 95.0, 1010)];
+        let mut update = OrderUpdate {
+            take_profit_order_id: Some("2".into()),
+            stop_loss_order_id: Some("3".into()),
+            exit_reason: Some("tp".into()),
+            ..Default::default()
+        };
+        assert_eq!(exit_trade_id(&update, Some(&orders)).as_deref(), Some("99"));
+        update.exit_reason = Some("manual".into());
+        assert_eq!(exit_trade_id(&update, Some(&orders)), None);
+    }
+}
@@ -0,0 +1,389 @@
+use crate::models::{Contract, MonitorConfig, RiskSettings, TradingSignal};
+use crate::repository::{OrderRepository, RiskRepository, RoundTripRepository};
+use crate::services::exchange::Exchange;
+use anyhow::{Result, anyhow};
+use sqlx::SqlitePool;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Mutex as AsyncMutex;
+
+/// 只统计最近7天创建的订单的持仓，和订单对账的范围一致
+const ACTIVE_WINDOW_SECS: i64 = 7 * 86400;
+
+/// 风控检查时的账户状态
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RiskState {
+    pub active_positions: i64,
+    pub symbol_positions: i64,
+    pub daily_pnl: f64,
+    pub orders_last_hour: i64,
+    pub consecutive_losses: i64,
+    pub last_loss_at: Option<i64>,
+}
+
+/// 风控检查的结果
+#[derive(Debug)]
+pub enum RiskDecision {
+    /// 允许下单，持有的名额在订单记录保存后释放
+    Allowed(RiskPermit),
+    /// 拦截原因
+    Blocked(String),
+}
+
+/// 已经通过风控检查、订单记录还没保存的下单名额，释放前计入持仓数量和下单次数
+#[derive(Debug)]
+pub struct RiskPermit {
+    pending: Arc<Mutex<Vec<(String, String)>>>,
+    key: (String, String),
+}
+
+impl Drop for RiskPermit {
+    fn drop(&mut self) {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(index) = pending.iter().position(|key| *key == self.key) {
+            pending.swap_remove(index);
+        }
+    }
+}
+
+/// 风控：每次自动下单前检查持仓数量、当日亏损、下单频率和连续亏损
+///
+/// 同一根K线收盘时多个配置会同时下单，订单记录要等下单完成才保存。检查依次进行，
+/// 通过的下单在保存之前作为 `RiskPermit` 计入，避免同时通过检查超出上限；监控任务共用一个实例
+#[derive(Debug, Clone)]
+pub struct RiskManager {
+    db: SqlitePool,
+    check_lock: Arc<AsyncMutex<()>>,
+    // 通过检查还没保存的下单：(交易所, 交易对)
+    pending: Arc<Mutex<Vec<(String, String)>>>,
+}
+
+impl RiskManager {
+    pub fn new(db: SqlitePool) -> Self {
+        Self {
+            db,
+            check_lock: Arc::new(AsyncMutex::new(())),
+            pending: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// 检查是否允许在该交易所下单，允许时占用一个名额，直到返回的 `RiskPermit` 被释放
+    pub async fn check(&self, exchange: &str, symbol: &str, now: i64) -> Result<RiskDecision> {
+        let _guard = self.check_lock.lock().await;
+        let settings = RiskRepository::get_settings(&self.db).await?;
+        if settings.halted {
+            return Ok(RiskDecision::Blocked(format!(
+                "交易已暂停（{}），恢复后才会自动下单",
+                settings.halted_reason.as_deref().unwrap_or("紧急平仓")
+            )));
+        }
+        if settings.enabled {
+            let mut state = self.load_state(&settings, exchange, symbol, now).await?;
+            self.add_pending(&mut state, exchange, symbol);
+            if let Some(reason) = evaluate(&settings, &state, now) {
+                return Ok(RiskDecision::Blocked(reason));
+            }
+        }
+
+        let key = (exchange.to_string(), symbol.to_string());
+        self.pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(key.clone());
+        Ok(RiskDecision::Allowed(RiskPermit {
+            pending: self.pending.clone(),
+            key,
+        }))
+    }
+
+    /// 把还没保存订单记录的下单计入持仓数量和下单次数
+    fn add_pending(&self, state: &mut RiskState, exchange: &str, symbol: &str) {
+        let pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        let on_exchange = pending.iter().filter(|(e, _)| e == exchange).count() as i64;
+        let on_symbol = pending
+            .iter()
+            .filter(|(e, s)| e == exchange && s == symbol)
+            .count() as i64;
+        state.active_positions += on_exchange;
+        state.symbol_positions += on_symbol;
+        state.orders_last_hour += on_exchange;
+    }
+
+    async fn load_state(
+        &self,
+        settings: &RiskSettings,
+        exchange: &str,
+        symbol: &str,
+        now: i64,
+    ) -> Result<RiskState> {
+        let since = now - ACTIVE_WINDOW_SECS;
+        let recent = RoundTripRepository::get_latest_by_exchange(
+            &self.db,
+            exchange,
+            settings.max_consecutive_losses.max(1),
+        )
+        .await?;
+        let losses: Vec<_> = recent.iter().take_while(|t| t.realized_pnl < 0.0).collect();
+
+        Ok(RiskState {
+            active_positions: OrderRepository::count_active(&self.db, exchange, None, since)
+                .await?,
+            symbol_positions: OrderRepository::count_active(
+                &self.db,
+                exchange,
+                Some(symbol),
+                since,
+            )
+            .await?,
+            daily_pnl: RoundTripRepository::realized_pnl_since(
+                &self.db,
+                exchange,
+                start_of_day(now),
+            )
+            .await?,
+            orders_last_hour: OrderRepository::count_placed_since(&self.db, exchange, now - 3600)
+                .await?,
+            consecutive_losses: losses.len() as i64,
+            last_loss_at: losses.first().map(|t| t.closed_at),
+        })
+    }
+}
+
+/// 按风控设置判断是否拦截，返回第一条触发的原因
+pub fn evaluate(settings: &RiskSettings, state: &RiskState, now: i64) -> Option<String> {
+    if settings.max_positions > 0 && state.active_positions >= settings.max_positions {
+        return Some(format!(
+            "持仓数量已达上限({}/{})",
+            state.active_positions, settings.max_positions
+        ));
+    }
+    if settings.max_positions_per_symbol > 0
+        && state.symbol_positions >= settings.max_positions_per_symbol
+    {
+        return Some(format!(
+            "该交易对持仓数量已达上限({}/{})",
+            state.symbol_positions, settings.max_positions_per_symbol
+        ));
+    }
+    if settings.max_daily_loss > 0.0 && -state.daily_pnl >= settings.max_daily_loss {
+        return Some(format!(
+            "当日亏损{:.2} USDT已达上限{:.2} USDT",
+            -state.daily_pnl, settings.max_daily_loss
+        ));
+    }
+    if settings.max_orders_per_hour > 0 && state.orders_last_hour >= settings.max_orders_per_hour {
+        return Some(format!(
+            "最近1小时下单{}次已达上限{}次",
+            state.orders_last_hour, settings.max_orders_per_hour
+        ));
+    }
+    if settings.max_consecutive_losses > 0
+        && state.consecutive_losses >= settings.max_consecutive_losses
+        && let Some(last_loss_at) = state.last_loss_at
+    {
+        let cooldown_until = last_loss_at + settings.cooldown_minutes * 60;
+        if now < cooldown_until {
+            return Some(format!(
+                "连续亏损{}次，冷却至{}",
+                state.consecutive_losses,
+                utils::format_timestamp(cooldown_until, 8)
+            ));
+        }
+    }
+    None
+}
+
+/// 按配置的仓位计算方式得到下单张数
+///
+/// 固定张数模式直接使用 `order_size`；风险模式下止损时的亏损等于固定金额或权益的百分比
+pub async fn order_size_for(
+    exchange: &dyn Exchange,
+    config: &MonitorConfig,
+    contract: &Contract,
+    trading_signal: &TradingSignal,
+) -> Result<i64> {
+    let risk_usdt = match config.sizing_mode.as_str() {
+        "risk_amount" => config.risk_per_trade,
+        "risk_percent" => {
+            let equity = exchange
+                .get_account_info()
+                .await?
+                .equity
+                .ok_or_else(|| anyhow!("无法获取账户权益"))?;
+            equity * config.risk_per_trade / 100.0
+        }
+        _ => return Ok(config.order_size),
+    };
+
+    let quanto_multiplier = contract
+        .quanto_multiplier
+        .parse::<f64>()
+        .map_err(|_| anyhow!("合约数量乘数无效: {}", contract.quanto_multiplier))?;
+    risk_order_size(
+        risk_usdt,
+        trading_signal.entry_price,
+        trading_signal.stop_loss,
+        quanto_multiplier,
+        contract.order_size_min,
+        contract.order_size_max,
+    )
+}
+
+/// 根据止损距离计算张数：每张亏损 = |入场价 - 止损价| × 数量乘数
+///
+/// 结果向下取整并限制在合约的最大下单张数内；不足最小下单张数时返回错误，避免超出风险预算
+pub fn risk_order_size(
+    risk_usdt: f64,
+    entry_price: f64,
+    stop_loss: f64,
+    quanto_multiplier: f64,
+    order_size_min: i64,
+    order_size_max: i64,
+) -> Result<i64> {
+    if risk_usdt <= 0.0 {
+        return Err(anyhow!("每笔风险金额必须大于0"));
+    }
+    let loss_per_contract = (entry_price - stop_loss).abs() * quanto_multiplier;
+    if loss_per_contract <= 0.0 {
+        return Err(anyhow!("止损价与入场价相同，无法计算仓位"));
+    }
+
+    let mut size = (risk_usdt / loss_per_contract).floor() as i64;
+    if order_size_max > 0 {
+        size = size.min(order_size_max);
+    }
+    let min = order_size_min.max(1);
+    if size < min {
+        return Err(anyhow!(
+            "风险金额{:.2} USDT不足最小下单{}张（每张止损亏损{:.4} USDT）",
+            risk_usdt,
+            min,
+            loss_per_contract
+        ));
+    }
+    Ok(size)
+}
+
+/// 当天（北京时间）零点的时间戳
+fn start_of_day(now: i64) -> i64 {
+    let offset = 8 * 3600;
+    (now + offset).div_euclid(86400) * 86400 - offset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    #[test]
+    fn test_evaluate() {
+        let settings = RiskSettings {
+            enabled: true,
+            max_positions: 3,
+            max_positions_per_symbol: 1,
+            max_daily_loss: 50.0,
+            max_orders_per_hour: 5,
+            max_consecutive_losses: 2,
+            cooldown_minutes: 30,
+            halted: false,
+            halted_reason: None,
+        };
+        let now = 1_700_000_000;
+        assert_eq!(evaluate(&settings, &RiskState::default(), now), None);
+
+        let blocked = |state: RiskState| evaluate(&settings, &state, now).is_some();
+        assert!(blocked(RiskState {
+            active_positions: 3,
+            ..Default::default()
+        }));
+        assert!(blocked(RiskState {
+            symbol_positions: 1,
+            ..Default::default()
+        }));
+        assert!(blocked(RiskState {
+            daily_pnl: -50.0,
+            ..Default::default()
+        }));
+        assert!(!blocked(RiskState {
+            daily_pnl: 80.0,
+            ..Default::default()
+        }));
+        assert!(blocked(RiskState {
+            orders_last_hour: 5,
+            ..Default::default()
+        }));
+        assert!(blocked(RiskState {
+            consecutive_losses: 2,
+            last_loss_at: Some(now - 600),
+            ..Default::default()
+        }));
+        // 冷却结束后恢复下单
+        assert!(!blocked(RiskState {
+            consecutive_losses: 2,
+            last_loss_at: Some(now - 1800),
+            ..Default::default()
+        }));
+    }
+
+    #[test]
+    fn test_risk_order_size() {
+        // 每张止损亏损 (100 - 95) × 0.01 = 0.05 USDT
+        assert_eq!(risk_order_size(1.0, 100.0, 95.0, 0.01, 1, 0).unwrap(), 20);
+        assert_eq!(risk_order_size(1.02, 100.0, 105.0, 0.01, 1, 0).unwrap(), 20);
+        assert_eq!(risk_order_size(1.0, 100.0, 95.0, 0.01, 1, 8).unwrap(), 8);
+        assert!(risk_order_size(0.04, 100.0, 95.0, 0.01, 1, 0).is_err());
+        assert!(risk_order_size(1.0, 100.0, 95.0, 0.01, 30, 0).is_err());
+        assert!(risk_order_size(1.0, 100.0, 100.0, 0.01, 1, 0).is_err());
+    }
+
+    #[test]
+    fn test_start_of_day() {
+        // 2023-11-15 06:13:20 北京时间
+        assert_eq!(start_of_day(1_700_000_000), 1_699_977_600);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_checks_share_last_slot() {
+        let db = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("../migrations").run(&db).await.unwrap();
+        RiskRepository::save_settings(
+            &db,
+            &RiskSettings {
+                enabled: true,
+                max_positions: 1,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        let risk = RiskManager::new(db);
+        let now = 1_700_000_000;
+        let (first, second) = tokio::join!(
+            risk.check("gate", "BTC_USDT", now),
+            risk.check("gate", "ETH_USDT", now)
+        );
+        let (first, second) = (first.unwrap(), second.unwrap());
+        let allowed = [&first, &second]
+            .iter()
+            .filter(|d| matches!(d, RiskDecision::Allowed(_)))
+            .count();
+        assert_eq!(allowed, 1);
+
+        // 其他交易所不占用名额
+        assert!(matches!(
+            risk.check("paper", "BTC_USDT", now).await.unwrap(),
+            RiskDecision::Allowed(_)
+        ));
+
+        // 名额释放（下单失败没有保存订单）后可以再次下单
+        drop((first, second));
+        assert!(matches!(
+            risk.check("gate", "BTC_USDT", now).await.unwrap(),
+            RiskDecision::Allowed(_)
+        ));
+    }
+}
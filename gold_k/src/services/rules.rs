@@ -0,0 +1,594 @@
+use crate::models::{KlineData, Signal};
+use crate::services::indicators::{self, BB_STD_DEV, MACD_FAST, MACD_SIGNAL, MACD_SLOW};
+use anyhow::{Result, anyhow, bail};
+
+/// 规则中可以使用的变量，数值为信号K线的数据，布尔值为信号的方向和K线类型
+const NUMBER_VARIABLES: &[&str] = &[
+    "open",
+    "high",
+    "low",
+    "close",
+    "volume",
+    "body",
+    "upper_shadow",
+    "lower_shadow",
+    "main_shadow",
+    "shadow_ratio",
+    "volume_multiplier",
+];
+const BOOL_VARIABLES: &[&str] = &["long", "short", "bull", "bear"];
+
+/// 函数参数的上限，避免一次获取过多K线
+const MAX_PERIOD: usize = 500;
+/// 规则长度和括号、`!`、负号嵌套层数的上限，解析和求值都是递归的，避免栈溢出
+const MAX_RULE_LEN: usize = 1000;
+const MAX_DEPTH: usize = 32;
+
+/// 函数的参数个数和计算需要的K线数量（含信号K线）
+fn function(name: &str, period: usize) -> Option<(usize, usize)> {
+    let spec = match name {
+        "sma" | "vwap" | "bb_upper" | "bb_lower" => (1, period),
+        // EMA类指标多取一倍的K线预热
+        "ema" => (1, period * 2),
+        "rsi" => (1, period * 2 + 1),
+        // 平均成交量、最高价和最低价统计信号K线之前的K线
+        "atr" | "avg_volume" | "highest" | "lowest" => (1, period + 1),
+        "macd_hist" => (0, (MACD_SLOW + MACD_SIGNAL) * 2),
+        _ => return None,
+    };
+    Some(spec)
+}
+
+/// 规则的语法树
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Bool(bool),
+    Variable(String),
+    Call(String, usize),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Type {
+    Number,
+    Bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+    Comma,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(number) => write!(f, "{}", number),
+            Token::Ident(name) => write!(f, "{}", name),
+            Token::Op(op) => write!(f, "{}", op),
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+            Token::Comma => write!(f, ","),
+        }
+    }
+}
+
+const OPERATORS: &[&str] = &[
+    "&&", "||", ">=", "<=", "==", "!=", ">", "<", "!", "+", "-", "*", "/",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let number = text
+                .parse()
+                .map_err(|_| anyhow!("无法识别的数字 {}", text))?;
+            tokens.push(Token::Number(number));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if c == '(' {
+            tokens.push(Token::LParen);
+            i += 1;
+        } else if c == ')' {
+            tokens.push(Token::RParen);
+            i += 1;
+        } else if c == ',' {
+            tokens.push(Token::Comma);
+            i += 1;
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) else {
+                bail!("第{}个字符 '{}' 无法识别", i + 1, c);
+            };
+            tokens.push(Token::Op(op));
+            i += op.len();
+        }
+    }
+    Ok(tokens)
+}
+
+/// 递归下降解析，优先级从低到高：|| && ! 比较 加减 乘除 负号
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat_op(&mut self, ops: &[&'static str]) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Op(op)) if ops.contains(op) => {
+                let op = *op;
+                self.pos += 1;
+                Some(op)
+            }
+            _ => None,
+        }
+    }
+
+    /// 解析嵌套的一层，超过 `MAX_DEPTH` 层时报错
+    fn nested(&mut self, operand: fn(&mut Self) -> Result<Expr>) -> Result<Expr> {
+        if self.depth >= MAX_DEPTH {
+            bail!("规则嵌套超过{}层", MAX_DEPTH);
+        }
+        self.depth += 1;
+        let expr = operand(self);
+        self.depth -= 1;
+        expr
+    }
+
+    fn binary(
+        &mut self,
+        ops: &[&'static str],
+        operand: fn(&mut Self) -> Result<Expr>,
+    ) -> Result<Expr> {
+        let mut left = operand(self)?;
+        while let Some(op) = self.eat_op(ops) {
+            let right = operand(self)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        self.binary(&["||"], Self::and)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        self.binary(&["&&"], Self::not)
+    }
+
+    fn not(&mut self) -> Result<Expr> {
+        if self.eat_op(&["!"]).is_some() {
+            return Ok(Expr::Unary("!", Box::new(self.nested(Self::not)?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr> {
+        let left = self.additive()?;
+        match self.eat_op(&[">=", "<=", "==", "!=", ">", "<"]) {
+            Some(op) => Ok(Expr::Binary(op, Box::new(left), Box::new(self.additive()?))),
+            None => Ok(left),
+        }
+    }
+
+    fn additive(&mut self) -> Result<Expr> {
+        self.binary(&["+", "-"], Self::multiplicative)
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr> {
+        self.binary(&["*", "/"], Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.eat_op(&["-"]).is_some() {
+            return Ok(Expr::Unary("-", Box::new(self.nested(Self::unary)?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Number(number)) => Ok(Expr::Number(number)),
+            Some(Token::LParen) => {
+                let expr = self.nested(Self::or)?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(expr),
+                    _ => bail!("缺少右括号"),
+                }
+            }
+            Some(Token::Ident(name)) if name == "true" || name == "false" => {
+                Ok(Expr::Bool(name == "true"))
+            }
+            Some(Token::Ident(name)) if self.peek() == Some(&Token::LParen) => {
+                self.pos += 1;
+                self.call(name)
+            }
+            Some(Token::Ident(name)) => {
+                if !NUMBER_VARIABLES.contains(&name.as_str())
+                    && !BOOL_VARIABLES.contains(&name.as_str())
+                {
+                    bail!("未知的变量 {}", name);
+                }
+                Ok(Expr::Variable(name))
+            }
+            Some(token) => bail!("不应出现 {}", token),
+            None => bail!("规则不完整"),
+        }
+    }
+
+    /// 函数的参数必须是整数，方便在保存时计算需要的K线数量
+    fn call(&mut self, name: String) -> Result<Expr> {
+        let Some((args, _)) = function(&name, 0) else {
+            bail!("未知的函数 {}", name);
+        };
+        let period = if args == 0 {
+            0
+        } else {
+            match self.next() {
+                Some(Token::Number(n))
+                    if n >= 1.0 && n <= MAX_PERIOD as f64 && n.fract() == 0.0 =>
+                {
+                    n as usize
+                }
+                _ => bail!("{} 的参数必须是1到{}之间的整数", name, MAX_PERIOD),
+            }
+        };
+        match self.next() {
+            Some(Token::RParen) => Ok(Expr::Call(name, period)),
+            Some(Token::Comma) => bail!("{} 只有 {} 个参数", name, args),
+            _ => bail!("{} 缺少右括号", name),
+        }
+    }
+}
+
+fn check(expr: &Expr) -> Result<Type> {
+    match expr {
+        Expr::Number(_) | Expr::Call(..) => Ok(Type::Number),
+        Expr::Bool(_) => Ok(Type::Bool),
+        Expr::Variable(name) if BOOL_VARIABLES.contains(&name.as_str()) => Ok(Type::Bool),
+        Expr::Variable(_) => Ok(Type::Number),
+        Expr::Unary(op, operand) => {
+            let expected = if *op == "!" { Type::Bool } else { Type::Number };
+            if check(operand)? != expected {
+                bail!("{} 的操作数类型不对", op);
+            }
+            Ok(expected)
+        }
+        Expr::Binary(op, left, right) => {
+            let (left, right) = (check(left)?, check(right)?);
+            match *op {
+                "&&" | "||" if left == Type::Bool && right == Type::Bool => Ok(Type::Bool),
+                "==" | "!=" if left == right => Ok(Type::Bool),
+                ">=" | "<=" | ">" | "<" if left == Type::Number && right == Type::Number => {
+                    Ok(Type::Bool)
+                }
+                "+" | "-" | "*" | "/" if left == Type::Number && right == Type::Number => {
+                    Ok(Type::Number)
+                }
+                _ => bail!("{} 两边的类型不对", op),
+            }
+        }
+    }
+}
+
+/// 解析规则并检查类型，规则的结果必须是布尔值
+pub fn parse(source: &str) -> Result<Expr> {
+    if source.chars().count() > MAX_RULE_LEN {
+        bail!("规则不能超过{}个字符", MAX_RULE_LEN);
+    }
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        pos: 0,
+        depth: 0,
+    };
+    let expr = parser.or()?;
+    if let Some(token) = parser.peek() {
+        bail!("多余的 {}", token);
+    }
+    if check(&expr)? != Type::Bool {
+        bail!("规则的结果必须是true或false，例如 close > ema(50)");
+    }
+    Ok(expr)
+}
+
+/// 保存配置时检查规则，空规则表示不启用
+pub fn validate(source: &str) -> Result<()> {
+    if source.trim().is_empty() {
+        return Ok(());
+    }
+    parse(source).map(|_| ())
+}
+
+fn lookback(expr: &Expr) -> usize {
+    match expr {
+        Expr::Call(name, period) => function(name, *period).map_or(0, |(_, n)| n),
+        Expr::Unary(_, operand) => lookback(operand),
+        Expr::Binary(_, left, right) => lookback(left).max(lookback(right)),
+        _ => 0,
+    }
+}
+
+/// 规则需要的K线数量（含信号K线），规则为空或无法解析时为0
+pub fn required_klines(source: &str) -> usize {
+    if source.trim().is_empty() {
+        return 0;
+    }
+    parse(source).map_or(0, |expr| lookback(&expr))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Value {
+    Number(f64),
+    Bool(bool),
+}
+
+/// 规则求值用到的信号和K线，`klines` 按时间升序，最后一根为信号K线
+struct Context<'a> {
+    signal: &'a Signal,
+    klines: &'a [KlineData],
+}
+
+impl Context<'_> {
+    fn variable(&self, name: &str) -> Value {
+        let s = self.signal;
+        let number = match name {
+            "open" => s.open_price,
+            "high" => s.high_price,
+            "low" => s.low_price,
+            "close" => s.close_price,
+            "volume" => s.volume,
+            "body" => s.body_length,
+            "upper_shadow" => s.high_price - s.open_price.max(s.close_price),
+            "lower_shadow" => s.open_price.min(s.close_price) - s.low_price,
+            "main_shadow" => s.main_shadow_length,
+            "shadow_ratio" => s.shadow_ratio,
+            "volume_multiplier" => s.volume_multiplier,
+            "long" => return Value::Bool(s.direction == "long"),
+            "short" => return Value::Bool(s.direction == "short"),
+            "bull" => return Value::Bool(s.candle_type == "bull"),
+            _ => return Value::Bool(s.candle_type == "bear"),
+        };
+        Value::Number(number)
+    }
+
+    fn call(&self, name: &str, period: usize) -> Result<f64> {
+        let klines = self.klines;
+        let closes: Vec<f64> = klines.iter().map(|k| k.close).collect();
+        // 信号K线之前的 `period` 根K线
+        let previous = || {
+            let end = klines.len().checked_sub(1)?;
+            klines.get(end.checked_sub(period)?..end)
+        };
+        let value = match name {
+            "sma" => indicators::sma(&closes, period),
+            "ema" => indicators::ema(&closes, period),
+            "rsi" => indicators::rsi(&closes, period),
+            "atr" => indicators::atr(klines, period),
+            "vwap" => klines
+                .get(klines.len().saturating_sub(period)..)
+                .filter(|window| window.len() == period)
+                .and_then(indicators::vwap),
+            "bb_upper" => indicators::bollinger(&closes, period, BB_STD_DEV).map(|b| b.upper),
+            "bb_lower" => indicators::bollinger(&closes, period, BB_STD_DEV).map(|b| b.lower),
+            "avg_volume" => {
+                previous().map(|w| w.iter().map(|k| k.volume).sum::<f64>() / period as f64)
+            }
+            "highest" => previous().map(|w| w.iter().map(|k| k.high).fold(f64::MIN, f64::max)),
+            "lowest" => previous().map(|w| w.iter().map(|k| k.low).fold(f64::MAX, f64::min)),
+            _ => indicators::macd(&closes, MACD_FAST, MACD_SLOW, MACD_SIGNAL).map(|m| m.histogram),
+        };
+        value.ok_or_else(|| {
+            if name == "macd_hist" {
+                anyhow!("K线不足，无法计算 macd_hist()")
+            } else {
+                anyhow!("K线不足，无法计算 {}({})", name, period)
+            }
+        })
+    }
+
+    fn eval(&self, expr: &Expr) -> Result<Value> {
+        let value = match expr {
+            Expr::Number(number) => Value::Number(*number),
+            Expr::Bool(value) => Value::Bool(*value),
+            Expr::Variable(name) => self.variable(name),
+            Expr::Call(name, period) => Value::Number(self.call(name, *period)?),
+            Expr::Unary(op, operand) => match (*op, self.eval(operand)?) {
+                ("!", Value::Bool(value)) => Value::Bool(!value),
+                (_, Value::Number(number)) => Value::Number(-number),
+                _ => bail!("{} 的操作数类型不对", op),
+            },
+            // 短路求值，右边K线不足时不影响结果
+            Expr::Binary("&&", left, right) => {
+                Value::Bool(self.eval_bool(left)? && self.eval_bool(right)?)
+            }
+            Expr::Binary("||", left, right) => {
+                Value::Bool(self.eval_bool(left)? || self.eval_bool(right)?)
+            }
+            Expr::Binary(op, left, right) => match (self.eval(left)?, self.eval(right)?) {
+                (Value::Number(a), Value::Number(b)) => match *op {
+                    "+" => Value::Number(a + b),
+                    "-" => Value::Number(a - b),
+                    "*" => Value::Number(a * b),
+                    "/" if b == 0.0 => bail!("除数为0"),
+                    "/" => Value::Number(a / b),
+                    ">=" => Value::Bool(a >= b),
+                    "<=" => Value::Bool(a <= b),
+                    ">" => Value::Bool(a > b),
+                    "<" => Value::Bool(a < b),
+                    "==" => Value::Bool(a == b),
+                    _ => Value::Bool(a != b),
+                },
+                (Value::Bool(a), Value::Bool(b)) if *op == "==" => Value::Bool(a == b),
+                (Value::Bool(a), Value::Bool(b)) if *op == "!=" => Value::Bool(a != b),
+                _ => bail!("{} 两边的类型不对", op),
+            },
+        };
+        Ok(value)
+    }
+
+    fn eval_bool(&self, expr: &Expr) -> Result<bool> {
+        match self.eval(expr)? {
+            Value::Bool(value) => Ok(value),
+            Value::Number(_) => bail!("需要true或false"),
+        }
+    }
+}
+
+/// 用信号和信号K线及之前的K线计算规则，K线不足、除数为0等求值错误返回Err
+pub fn evaluate(
+    source: &str,
+    signal: &Signal,
+    historical: &[KlineData],
+    latest: &KlineData,
+) -> Result<bool> {
+    let expr = parse(source)?;
+    let needed = lookback(&expr).saturating_sub(1);
+    let mut klines = historical[historical.len().saturating_sub(needed)..].to_vec();
+    klines.push(latest.clone());
+    Context {
+        signal,
+        klines: &klines,
+    }
+    .eval_bool(&expr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kline(close: f64, volume: f64) -> KlineData {
+        KlineData {
+            timestamp: 0,
+            open: close,
+            high: close + 1.0,
+            low: close - 1.0,
+            close,
+            volume,
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let expr = parse("shadow_ratio >= 2 && volume / avg_volume(36) > 1.5 && close > ema(50)");
+        assert!(expr.is_ok());
+        assert_eq!(required_klines("close > ema(50) || rsi(14) < 30"), 100);
+        assert_eq!(
+            parse("1 + 2 * 3 > 6").unwrap(),
+            Expr::Binary(
+                ">",
+                Box::new(Expr::Binary(
+                    "+",
+                    Box::new(Expr::Number(1.0)),
+                    Box::new(Expr::Binary(
+                        "*",
+                        Box::new(Expr::Number(2.0)),
+                        Box::new(Expr::Number(3.0))
+                    ))
+                )),
+                Box::new(Expr::Number(6.0))
+            )
+        );
+        assert!(parse("!(long && bull) || short == bear").is_ok());
+
+        for (rule, error) in [
+            ("close > ema(50", "ema 缺少右括号"),
+            ("close > foo(3)", "未知的函数 foo"),
+            ("closing > 1", "未知的变量 closing"),
+            ("close > ema(2.5)", "ema 的参数必须是1到500之间的整数"),
+            (
+                "close + 1",
+                "规则的结果必须是true或false，例如 close > ema(50)",
+            ),
+            ("long > 1", "> 两边的类型不对"),
+            ("close > 1 1", "多余的 1"),
+            ("close # 1", "第7个字符 '#' 无法识别"),
+            ("close >", "规则不完整"),
+        ] {
+            assert_eq!(parse(rule).unwrap_err().to_string(), error, "{}", rule);
+        }
+
+        // 嵌套太深或太长的规则直接拒绝，不会栈溢出
+        let nested = format!("{}long{}", "(".repeat(100), ")".repeat(100));
+        assert_eq!(parse(&nested).unwrap_err().to_string(), "规则嵌套超过32层");
+        let negated = format!("{}long", "!".repeat(500));
+        assert_eq!(parse(&negated).unwrap_err().to_string(), "规则嵌套超过32层");
+        assert!(parse(&format!("{}close > 1", "-".repeat(500))).is_err());
+        assert!(parse(&format!("{}long{}", "(".repeat(32), ")".repeat(32))).is_ok());
+        let long_rule = vec!["close > 1"; 200].join(" && ");
+        assert_eq!(
+            validate(&long_rule).unwrap_err().to_string(),
+            "规则不能超过1000个字符"
+        );
+        assert!(validate("  ").is_ok());
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let historical: Vec<KlineData> = (0..10).map(|i| kline(100.0 + i as f64, 1.0)).collect();
+        let latest = kline(112.0, 3.0);
+        let signal = Signal {
+            open_price: 112.0,
+            high_price: 113.0,
+            low_price: 111.0,
+            close_price: 112.0,
+            volume: 3.0,
+            shadow_ratio: 2.5,
+            direction: "long".into(),
+            candle_type: "bull".into(),
+            ..Default::default()
+        };
+        let eval = |rule: &str| evaluate(rule, &signal, &historical, &latest);
+
+        assert!(
+            eval("shadow_ratio >= 2 && volume / avg_volume(5) > 1.5 && close > sma(5)").unwrap()
+        );
+        assert!(!eval("short || bear").unwrap());
+        // 突破前5根K线的最高价
+        assert!(eval("close > highest(5) && lowest(5) == 104").unwrap());
+        assert!(eval("-(close - 100) < -10").unwrap());
+
+        assert_eq!(
+            eval("close > ema(20)").unwrap_err().to_string(),
+            "K线不足，无法计算 ema(20)"
+        );
+        assert_eq!(
+            eval("close / (volume - 3) > 1").unwrap_err().to_string(),
+            "除数为0"
+        );
+        // 短路求值
+        assert!(!eval("short && close > ema(20)").unwrap());
+    }
+}
@@ -0,0 +1,406 @@
+use crate::models::{Contract, KlineData, MonitorConfig, StrategyScript};
+use crate::services::indicators;
+use anyhow::{Result, anyhow, bail};
+use rhai::{AST, Array, Dynamic, Engine, EvalAltResult, Map, Scope};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// 传给脚本的K线数量（含信号K线）
+pub const SCRIPT_KLINES: usize = 200;
+/// 单次运行的时间上限，超时的脚本被终止，不会拖住 `check_symbol_signals`
+pub const SCRIPT_TIME_LIMIT: Duration = Duration::from_millis(200);
+/// 单次运行的操作数上限
+const MAX_OPERATIONS: u64 = 5_000_000;
+
+/// 编译好的策略脚本
+#[derive(Debug, Clone)]
+pub struct ScriptStrategy {
+    pub name: String,
+    pub version: i64,
+    ast: AST,
+}
+
+/// 脚本给出的信号，入场价为空时使用信号K线的收盘价，止盈价为空时按盈亏比计算
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptSignal {
+    pub direction: &'static str, // 'long' or 'short'
+    pub entry: Option<f64>,
+    pub stop: f64,
+    pub target: Option<f64>,
+}
+
+/// 脚本名称只允许字母、数字、下划线和短横线
+pub fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.len() > 64 {
+        bail!("脚本名称长度必须在1到64之间");
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        bail!("脚本名称只能包含字母、数字、下划线和短横线");
+    }
+    Ok(())
+}
+
+/// 沙箱引擎：限制操作数、调用层数和数据大小，禁用 eval，print/debug 写入日志。
+/// 传入 `deadline` 时超过截止时间立即终止
+fn engine(deadline: Option<Instant>) -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_max_operations(MAX_OPERATIONS)
+        .set_max_call_levels(32)
+        .set_max_expr_depths(64, 32)
+        .set_max_string_size(10_000)
+        .set_max_array_size(100_000)
+        .set_max_map_size(100_000)
+        .disable_symbol("eval")
+        .on_print(|text| debug!("script: {}", text))
+        .on_debug(|text, _, _| debug!("script: {}", text));
+    if let Some(deadline) = deadline {
+        engine.on_progress(move |_| (Instant::now() >= deadline).then(|| Dynamic::from("timeout")));
+    }
+    register_indicators(&mut engine);
+    engine
+}
+
+fn number(value: &Dynamic) -> Option<f64> {
+    value
+        .as_float()
+        .ok()
+        .or_else(|| value.as_int().ok().map(|i| i as f64))
+}
+
+fn numbers(values: &Array) -> Vec<f64> {
+    values.iter().filter_map(number).collect()
+}
+
+/// 脚本里的K线对象转回 KlineData，缺少的字段按0处理
+fn klines(values: &Array) -> Vec<KlineData> {
+    values
+        .iter()
+        .filter_map(|value| value.read_lock::<Map>().map(|map| map.clone()))
+        .map(|map| {
+            let field = |key: &str| map.get(key).and_then(number).unwrap_or(0.0);
+            KlineData {
+                timestamp: field("timestamp") as i64,
+                open: field("open"),
+                high: field("high"),
+                low: field("low"),
+                close: field("close"),
+                volume: field("volume"),
+            }
+        })
+        .collect()
+}
+
+fn period(value: i64) -> usize {
+    value.max(0) as usize
+}
+
+fn or_unit(value: Option<f64>) -> Dynamic {
+    value.map_or(Dynamic::UNIT, Dynamic::from_float)
+}
+
+/// 注册指标函数，K线不足时返回 ()
+fn register_indicators(engine: &mut Engine) {
+    engine
+        .register_fn("sma", |values: Array, n: i64| {
+            or_unit(indicators::sma(&numbers(&values), period(n)))
+        })
+        .register_fn("ema", |values: Array, n: i64| {
+            or_unit(indicators::ema(&numbers(&values), period(n)))
+        })
+        .register_fn("rsi", |values: Array, n: i64| {
+            or_unit(indicators::rsi(&numbers(&values), period(n)))
+        })
+        .register_fn("atr", |candles: Array, n: i64| {
+            or_unit(indicators::atr(&klines(&candles), period(n)))
+        })
+        .register_fn("vwap", |candles: Array| {
+            or_unit(indicators::vwap(&klines(&candles)))
+        })
+        .register_fn("bollinger", |values: Array, n: i64, std_dev: f64| {
+            indicators::bollinger(&numbers(&values), period(n), std_dev).map_or(
+                Dynamic::UNIT,
+                |bands| {
+                    let mut map = Map::new();
+                    map.insert("middle".into(), bands.middle.into());
+                    map.insert("upper".into(), bands.upper.into());
+                    map.insert("lower".into(), bands.lower.into());
+                    map.into()
+                },
+            )
+        })
+        .register_fn("macd", |values: Array| {
+            indicators::macd(
+                &numbers(&values),
+                indicators::MACD_FAST,
+                indicators::MACD_SLOW,
+                indicators::MACD_SIGNAL,
+            )
+            .map_or(Dynamic::UNIT, |macd| {
+                let mut map = Map::new();
+                map.insert("macd".into(), macd.macd.into());
+                map.insert("signal".into(), macd.signal.into());
+                map.insert("histogram".into(), macd.histogram.into());
+                map.into()
+            })
+        });
+}
+
+fn candle_map(kline: &KlineData) -> Dynamic {
+    let mut map = Map::new();
+    map.insert("timestamp".into(), kline.timestamp.into());
+    map.insert("open".into(), kline.open.into());
+    map.insert("high".into(), kline.high.into());
+    map.insert("low".into(), kline.low.into());
+    map.insert("close".into(), kline.close.into());
+    map.insert("volume".into(), kline.volume.into());
+    map.into()
+}
+
+fn contract_map(contract: Option<&Contract>) -> Dynamic {
+    let Some(contract) = contract else {
+        return Dynamic::UNIT;
+    };
+    let mut map = Map::new();
+    map.insert("name".into(), contract.name.clone().into());
+    map.insert(
+        "order_price_round".into(),
+        contract
+            .order_price_round
+            .parse::<f64>()
+            .unwrap_or(0.0)
+            .into(),
+    );
+    map.insert(
+        "quanto_multiplier".into(),
+        contract
+            .quanto_multiplier
+            .parse::<f64>()
+            .unwrap_or(0.0)
+            .into(),
+    );
+    map.insert("order_size_min".into(), contract.order_size_min.into());
+    map.insert("order_size_max".into(), contract.order_size_max.into());
+    map.into()
+}
+
+fn config_map(config: &MonitorConfig) -> Dynamic {
+    let mut map = Map::new();
+    map.insert("symbol".into(), config.symbol.clone().into());
+    map.insert("interval".into(), config.interval_type.clone().into());
+    map.insert("risk_reward_ratio".into(), config.risk_reward_ratio.into());
+    map.insert(
+        "trade_direction".into(),
+        config.trade_direction.clone().into(),
+    );
+    map.into()
+}
+
+/// 检查脚本的返回值：() 表示没有信号，否则为 #{direction, stop, entry?, target?}
+fn parse_output(result: Dynamic, close: f64) -> Result<Option<ScriptSignal>> {
+    if result.is_unit() {
+        return Ok(None);
+    }
+    let Some(map) = result.try_cast::<Map>() else {
+        bail!("脚本必须返回 () 或者 #{{direction, stop, entry, target}}");
+    };
+    let price = |key: &str| -> Result<Option<f64>> {
+        match map.get(key) {
+            None => Ok(None),
+            Some(value) if value.is_unit() => Ok(None),
+            Some(value) => match number(value) {
+                Some(price) if price.is_finite() && price > 0.0 => Ok(Some(price)),
+                _ => bail!("{} 必须是正数", key),
+            },
+        }
+    };
+    let direction = match map
+        .get("direction")
+        .and_then(|value| value.clone().into_string().ok())
+        .as_deref()
+    {
+        Some("long") => "long",
+        Some("short") => "short",
+        _ => bail!("direction 必须是 \"long\" 或 \"short\""),
+    };
+    let stop = price("stop")?.ok_or_else(|| anyhow!("缺少 stop"))?;
+    let entry = price("entry")?;
+    let target = price("target")?;
+
+    let sign = if direction == "long" { 1.0 } else { -1.0 };
+    let entry_price = entry.unwrap_or(close);
+    if (entry_price - stop) * sign <= 0.0 {
+        bail!("止损价 {} 在入场价 {} 的错误一侧", stop, entry_price);
+    }
+    if let Some(target) = target
+        && (target - entry_price) * sign <= 0.0
+    {
+        bail!("止盈价 {} 在入场价 {} 的错误一侧", target, entry_price);
+    }
+    Ok(Some(ScriptSignal {
+        direction,
+        entry,
+        stop,
+        target,
+    }))
+}
+
+impl ScriptStrategy {
+    /// 编译脚本，有语法错误时返回Err
+    pub fn compile(script: &StrategyScript) -> Result<Self> {
+        let ast = engine(None)
+            .compile(&script.source)
+            .map_err(|e| anyhow!("脚本语法错误: {}", e))?;
+        Ok(Self {
+            name: script.name.clone(),
+            version: script.version,
+            ast,
+        })
+    }
+
+    /// 运行脚本，`historical` 为信号K线之前的K线（按时间升序），`latest` 为刚收盘的信号K线。
+    /// 脚本中可用的变量：`candles` 含信号K线的K线数组，`closes` 收盘价数组，
+    /// `contract` 合约信息（没有时为 ()），`config` 监控配置
+    pub fn run(
+        &self,
+        config: &MonitorConfig,
+        historical: &[KlineData],
+        latest: &KlineData,
+        contract: Option<&Contract>,
+    ) -> Result<Option<ScriptSignal>> {
+        let window = &historical[historical.len().saturating_sub(SCRIPT_KLINES - 1)..];
+        let candles: Array = window
+            .iter()
+            .chain(std::iter::once(latest))
+            .map(candle_map)
+            .collect();
+        let closes: Array = window
+            .iter()
+            .chain(std::iter::once(latest))
+            .map(|k| Dynamic::from_float(k.close))
+            .collect();
+
+        let mut scope = Scope::new();
+        scope.push_constant("candles", candles);
+        scope.push_constant("closes", closes);
+        scope.push_constant_dynamic("contract", contract_map(contract));
+        scope.push_constant_dynamic("config", config_map(config));
+
+        let engine = engine(Some(Instant::now() + SCRIPT_TIME_LIMIT));
+        let result = engine
+            .eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast)
+            .map_err(|e| match *e {
+                EvalAltResult::ErrorTerminated(..) => {
+                    anyhow!("脚本运行超过{}毫秒，已终止", SCRIPT_TIME_LIMIT.as_millis())
+                }
+                EvalAltResult::ErrorTooManyOperations(..) => {
+                    anyhow!("脚本运行超过{}次操作，已终止", MAX_OPERATIONS)
+                }
+                e => anyhow!("脚本运行出错: {}", e),
+            })?;
+        parse_output(result, latest.close)
+            .map_err(|e| anyhow!("脚本 {} v{} 返回值无效: {}", self.name, self.version, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile(source: &str) -> Result<ScriptStrategy> {
+        ScriptStrategy::compile(&StrategyScript {
+            name: "test".into(),
+            version: 1,
+            source: source.into(),
+            ..Default::default()
+        })
+    }
+
+    fn klines(count: usize) -> Vec<KlineData> {
+        (0..count)
+            .map(|i| KlineData {
+                timestamp: i as i64 * 60,
+                open: 100.0 + i as f64,
+                high: 101.5 + i as f64,
+                low: 99.5 + i as f64,
+                close: 101.0 + i as f64,
+                volume: 10.0,
+            })
+            .collect()
+    }
+
+    fn run(source: &str, count: usize) -> Result<Option<ScriptSignal>> {
+        let klines = klines(count);
+        let (latest, historical) = klines.split_last().unwrap();
+        compile(source)
+            .unwrap()
+            .run(&MonitorConfig::default(), historical, latest, None)
+    }
+
+    #[test]
+    fn test_run() {
+        let source = r#"
+            let last = candles[candles.len() - 1];
+            let fast = ema(closes, 5);
+            let slow = ema(closes, 20);
+            if fast == () || slow == () { return (); }
+            if fast > slow {
+                #{ direction: "long", stop: last.low, target: last.close + 3.0 }
+            }
+        "#;
+        assert_eq!(
+            run(source, 30).unwrap(),
+            Some(ScriptSignal {
+                direction: "long",
+                entry: None,
+                stop: 128.5,
+                target: Some(133.0),
+            })
+        );
+        // K线不足时指标为 ()
+        assert_eq!(run(source, 10).unwrap(), None);
+        // 脚本拿到的K线不超过 SCRIPT_KLINES
+        assert_eq!(
+            run(
+                "if candles.len() == 200 { #{direction: \"short\", stop: 1000} }",
+                300
+            )
+            .unwrap(),
+            Some(ScriptSignal {
+                direction: "short",
+                entry: None,
+                stop: 1000.0,
+                target: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_output() {
+        assert!(run("42", 5).is_err());
+        assert!(run(r#"#{ direction: "up", stop: 1.0 }"#, 5).is_err());
+        // 做多的止损在入场价之上
+        assert!(run(r#"#{ direction: "long", stop: 200.0 }"#, 5).is_err());
+        assert!(run(r#"#{ direction: "short", stop: 200.0, target: 300.0 }"#, 5).is_err());
+    }
+
+    #[test]
+    fn test_sandbox() {
+        assert!(compile("let x = ;").is_err());
+        assert!(compile(r#"eval("1")"#).is_err());
+        let started = Instant::now();
+        let err = run("loop { }", 5).unwrap_err();
+        assert!(err.to_string().contains("已终止"), "{}", err);
+        assert!(started.elapsed() < SCRIPT_TIME_LIMIT * 5);
+    }
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("ema_cross-2").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("a b").is_err());
+    }
+}
@@ -0,0 +1,248 @@
+use crate::models::{KlineData, Order, StopAdjustment};
+use crate::repository::{ApiKeyRepository, OrderRepository, StopAdjustmentRepository};
+use crate::services::exchange::{Exchange, TriggerOrderRequest};
+use crate::services::indicators::atr;
+use crate::services::monitor::round_price;
+use anyhow::Result;
+use sqlx::SqlitePool;
+use tracing::{info, warn};
+
+/// ATR移动止损使用的周期
+pub const ATR_PERIOD: usize = 14;
+
+/// 订单的移动止损规则，下单时从监控配置复制
+#[derive(Debug, Clone, PartialEq)]
+pub struct StopRules {
+    /// 浮盈达到初始风险的多少倍后止损移到开仓价，0表示不启用
+    pub break_even_r: f64,
+    /// 'none', 'fixed' 固定距离, 'percent' 收盘价的百分比, 'atr' ATR倍数
+    pub trailing_mode: String,
+    pub trailing_value: f64,
+}
+
+impl StopRules {
+    pub fn from_order(order: &Order) -> Self {
+        Self {
+            break_even_r: order.break_even_r,
+            trailing_mode: order.trailing_mode.clone(),
+            trailing_value: order.trailing_value,
+        }
+    }
+
+    /// 移动止损和收盘价的距离，没有启用或缺少ATR时返回None
+    fn trailing_distance(&self, close: f64, atr: Option<f64>) -> Option<f64> {
+        let distance = match self.trailing_mode.as_str() {
+            "fixed" => self.trailing_value,
+            "percent" => close * self.trailing_value / 100.0,
+            "atr" => atr? * self.trailing_value,
+            _ => return None,
+        };
+        (distance > 0.0).then_some(distance)
+    }
+}
+
+/// 根据收盘价计算新的止损价和调整原因（'break_even' 或 'trailing'）
+///
+/// 止损只向盈利方向移动，并且必须在收盘价的亏损一侧，否则返回None
+pub fn next_stop(
+    side: &str,
+    entry_price: f64,
+    initial_stop: f64,
+    current_stop: f64,
+    close: f64,
+    atr: Option<f64>,
+    rules: &StopRules,
+) -> Option<(f64, &'static str)> {
+    // 做空时价格取反，统一按做多计算
+    let direction = if side == "buy" { 1.0 } else { -1.0 };
+    let risk = (entry_price - initial_stop) * direction;
+    let profit = (close - entry_price) * direction;
+
+    let mut candidates = Vec::new();
+    if rules.break_even_r > 0.0 && risk > 0.0 && profit >= rules.break_even_r * risk {
+        candidates.push((entry_price, "break_even"));
+    }
+    if let Some(distance) = rules.trailing_distance(close, atr) {
+        candidates.push((close - distance * direction, "trailing"));
+    }
+
+    candidates
+        .into_iter()
+        .filter(|(stop, _)| {
+            (stop - current_stop) * direction > 0.0 && (close - stop) * direction > 0.0
+        })
+        .max_by(|(a, _), (b, _)| (a * direction).total_cmp(&(b * direction)))
+}
+
+/// K线收盘后移动该交易所、交易对和周期下持仓订单的止损，返回调整的订单数量
+///
+/// 先创建新的止损委托再撤销旧的；单个订单失败只记录日志，下一根K线收盘时重试
+pub async fn manage_stops(
+    db: &SqlitePool,
+    exchange: &dyn Exchange,
+    symbol: &str,
+    interval_type: &str,
+    klines: &[KlineData],
+) -> Result<usize> {
+    let orders = OrderRepository::get_trailing(db, exchange.name(), symbol, interval_type).await?;
+    let Some(last) = klines.last() else {
+        return Ok(0);
+    };
+    if orders.is_empty() {
+        return Ok(0);
+    }
+    let close = last.close;
+    let atr = atr(klines, ATR_PERIOD);
+    let precision = ApiKeyRepository::get_contract_by_symbol(db, symbol)
+        .await?
+        .map(|contract| contract.order_price_round);
+
+    let mut adjusted = 0;
+    for order in orders {
+        let Some(old_order_id) = order.stop_loss_order_id.as_deref() else {
+            continue;
+        };
+        let entry_price = order.avg_fill_price.unwrap_or(order.entry_price);
+        let initial_stop = order.initial_stop_price.unwrap_or(order.stop_loss_price);
+        let rules = StopRules::from_order(&order);
+        let Some((stop, reason)) = next_stop(
+            &order.side,
+            entry_price,
+            initial_stop,
+            order.stop_loss_price,
+            close,
+            atr,
+            &rules,
+        ) else {
+            continue;
+        };
+
+        // 按合约精度取整后重新检查，避免取整后止损倒退或越过收盘价
+        let stop = match precision.as_deref() {
+            Some(precision) => round_price(stop, precision),
+            None => stop,
+        };
+        let direction = if order.side == "buy" { 1.0 } else { -1.0 };
+        if (stop - order.stop_loss_price) * direction <= 0.0 || (close - stop) * direction <= 0.0 {
+            continue;
+        }
+
+        // 分批止盈已经平掉的部分不需要止损
+        let size = if order.filled_size > 0 {
+            order.filled_size - order.closed_size
+        } else {
+            order.order_size
+        };
+        let trigger = TriggerOrderRequest::exit(symbol, &order.side, size, stop, "sl");
+        let new_order_id = match exchange
+            .replace_stop_loss(old_order_id, &trigger, "usdt")
+            .await
+        {
+            Ok(id) => id,
+            Err(e) => {
+                warn!(
+                    "Failed to move stop loss of order {} from {} to {}: {}",
+                    order.id, order.stop_loss_price, stop, e
+                );
+                continue;
+            }
+        };
+
+        OrderRepository::update_stop_loss(db, order.id, stop, &new_order_id).await?;
+        StopAdjustmentRepository::save(
+            db,
+            &StopAdjustment {
+                order_id: order.id,
+                reason: reason.to_string(),
+                old_stop: order.stop_loss_price,
+                new_stop: stop,
+                price: close,
+                stop_loss_order_id: Some(new_order_id),
+                ..Default::default()
+            },
+        )
+        .await?;
+        info!(
+            "Moved stop loss of order {} ({} {}) from {} to {} ({}) at close {}",
+            order.id, order.symbol, order.side, order.stop_loss_price, stop, reason, close
+        );
+        adjusted += 1;
+    }
+    Ok(adjusted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(break_even_r: f64, trailing_mode: &str, trailing_value: f64) -> StopRules {
+        StopRules {
+            break_even_r,
+            trailing_mode: trailing_mode.into(),
+            trailing_value,
+        }
+    }
+
+    #[test]
+    fn test_next_stop() {
+        // 做多：开仓100，止损95，1R = 5
+        let be = rules(1.0, "none", 0.0);
+        assert_eq!(next_stop("buy", 100.0, 95.0, 95.0, 104.0, None, &be), None);
+        assert_eq!(
+            next_stop("buy", 100.0, 95.0, 95.0, 105.0, None, &be),
+            Some((100.0, "break_even"))
+        );
+        // 已经保本后不再重复调整
+        assert_eq!(next_stop("buy", 100.0, 95.0, 100.0, 110.0, None, &be), None);
+
+        // 固定距离跟随，止损只上移不下移
+        let fixed = rules(0.0, "fixed", 3.0);
+        assert_eq!(
+            next_stop("buy", 100.0, 95.0, 95.0, 102.0, None, &fixed),
+            Some((99.0, "trailing"))
+        );
+        assert_eq!(
+            next_stop("buy", 100.0, 95.0, 99.0, 101.0, None, &fixed),
+            None
+        );
+
+        // 保本和移动止损同时启用时取更有利的止损
+        let both = rules(1.0, "percent", 10.0);
+        assert_eq!(
+            next_stop("buy", 100.0, 95.0, 95.0, 106.0, None, &both),
+            Some((100.0, "break_even"))
+        );
+        assert_eq!(
+            next_stop("buy", 100.0, 95.0, 100.0, 120.0, None, &both),
+            Some((108.0, "trailing"))
+        );
+
+        // 做空：开仓100，止损105
+        let atr_rules = rules(0.0, "atr", 2.0);
+        assert_eq!(
+            next_stop("sell", 100.0, 105.0, 105.0, 96.0, None, &atr_rules),
+            None
+        );
+        assert_eq!(
+            next_stop("sell", 100.0, 105.0, 105.0, 96.0, Some(1.5), &atr_rules),
+            Some((99.0, "trailing"))
+        );
+        assert_eq!(
+            next_stop("sell", 100.0, 105.0, 105.0, 94.0, None, &be),
+            Some((100.0, "break_even"))
+        );
+        // 移动止损不要求先有浮盈，亏损时也会收紧止损
+        assert_eq!(
+            next_stop(
+                "sell",
+                100.0,
+                105.0,
+                105.0,
+                101.0,
+                None,
+                &rules(0.0, "fixed", 0.5)
+            ),
+            Some((101.5, "trailing"))
+        );
+    }
+}
@@ -0,0 +1,436 @@
+use crate::models::{MonitorConfig, Order, StopAdjustment, TakeProfitTranche};
+use crate::repository::{
+    ApiKeyRepository, OrderRepository, StopAdjustmentRepository, TakeProfitTrancheRepository,
+};
+use crate::services::exchange::{Exchange, PriceOrder, TriggerOrderRequest};
+use crate::services::monitor::round_price;
+use anyhow::{Result, anyhow};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use tracing::{info, warn};
+
+/// 分批止盈中的一步：`percent` 为仓位的百分比，`r_multiple` 为None时这部分不设止盈，由止损或移动止损平仓
+#[derive(Debug, Clone, PartialEq)]
+pub struct LadderStep {
+    pub percent: f64,
+    pub r_multiple: Option<f64>,
+}
+
+/// 分批止盈需要单独的只减仓触发委托，只有签名API下单的实盘配置支持
+pub fn supports_ladder(config: &MonitorConfig) -> bool {
+    !config.paper_trading && config.execution_mode == "api"
+}
+
+/// 保存配置时检查分批止盈设置，不支持分批止盈的配置不能设置
+pub fn validate_ladder(config: &MonitorConfig) -> Result<()> {
+    parse_ladder(&config.take_profit_ladder)?;
+    if !config.take_profit_ladder.trim().is_empty() && !supports_ladder(config) {
+        return Err(anyhow!("分批止盈只支持签名API下单的实盘配置"));
+    }
+    Ok(())
+}
+
+/// 解析分批止盈设置，例如 "50:1,30:2,20" 表示50%在1R止盈、30%在2R止盈、剩余20%跟随止损
+///
+/// 空字符串表示不分批；百分比之和必须为100，最多一步没有R倍数
+pub fn parse_ladder(spec: &str) -> Result<Vec<LadderStep>> {
+    let mut steps = Vec::new();
+    for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (percent, r_multiple) = match part.split_once(':') {
+            Some((percent, r)) => (percent.trim(), Some(r.trim())),
+            None => (part, None),
+        };
+        let percent = percent
+            .parse::<f64>()
+            .ok()
+            .filter(|p| *p > 0.0)
+            .ok_or_else(|| anyhow!("分批止盈的百分比无效: {}", part))?;
+        let r_multiple = match r_multiple {
+            Some(r) => Some(
+                r.parse::<f64>()
+                    .ok()
+                    .filter(|r| *r > 0.0)
+                    .ok_or_else(|| anyhow!("分批止盈的R倍数无效: {}", part))?,
+            ),
+            None => None,
+        };
+        steps.push(LadderStep {
+            percent,
+            r_multiple,
+        });
+    }
+    if steps.is_empty() {
+        return Ok(steps);
+    }
+
+    let total: f64 = steps.iter().map(|s| s.percent).sum();
+    if (total - 100.0).abs() > 1e-6 {
+        return Err(anyhow!("分批止盈的百分比之和为{}，必须为100", total));
+    }
+    if steps.iter().filter(|s| s.r_multiple.is_none()).count() > 1 {
+        return Err(anyhow!("分批止盈最多只能有一步不设止盈"));
+    }
+    if steps.iter().all(|s| s.r_multiple.is_none()) {
+        return Err(anyhow!("分批止盈至少需要一步设置R倍数"));
+    }
+    Ok(steps)
+}
+
+/// 按百分比拆分张数，返回设置止盈的每一步 (张数, R倍数)
+///
+/// 每步向下取整，余下的张数留给不设止盈的一步，没有这一步时加到最后一个止盈；取整后为0张的步骤跳过
+pub fn split_ladder(steps: &[LadderStep], size: i64) -> Vec<(i64, f64)> {
+    let mut tranches: Vec<(i64, f64)> = steps
+        .iter()
+        .filter_map(|s| {
+            Some((
+                (size as f64 * s.percent / 100.0).floor() as i64,
+                s.r_multiple?,
+            ))
+        })
+        .collect();
+    if steps.iter().all(|s| s.r_multiple.is_some()) {
+        let leftover = size - tranches.iter().map(|(size, _)| size).sum::<i64>();
+        if let Some(last) = tranches.last_mut() {
+            last.0 += leftover;
+        }
+    }
+    tranches.retain(|(size, _)| *size > 0);
+    tranches
+}
+
+/// 第 `r_multiple` R 的止盈价，1R 为开仓价到初始止损价的距离
+pub fn ladder_price(side: &str, entry_price: f64, initial_stop: f64, r_multiple: f64) -> f64 {
+    let direction = if side == "buy" { 1.0 } else { -1.0 };
+    entry_price + (entry_price - initial_stop).abs() * r_multiple * direction
+}
+
+/// 对账时同步订单的分批止盈，订单记录有变化（已平仓张数或止损）时返回true
+///
+/// 成交后创建每一批的只减仓止盈委托；一批触发后累加已平仓张数，并把止损委托改为剩余张数，
+/// 开启 `ladder_break_even` 时第一批触发后止损同时移到开仓价；订单平仓后撤销还没有触发的批次
+pub async fn sync_tranches(
+    db: &SqlitePool,
+    exchange: &dyn Exchange,
+    order: &Order,
+    price_orders: &mut HashMap<(String, String), Vec<PriceOrder>>,
+) -> Result<bool> {
+    if order.take_profit_ladder.is_empty() || order.status != "filled" {
+        return Ok(false);
+    }
+    if order.exit_reason.is_some() {
+        cancel_tranches(db, exchange, order).await?;
+        return Ok(false);
+    }
+
+    let mut tranches = TakeProfitTrancheRepository::get_by_order(db, order.id).await?;
+    if tranches.is_empty() {
+        create_tranches(db, order).await?;
+        tranches = TakeProfitTrancheRepository::get_by_order(db, order.id).await?;
+    }
+
+    for tranche in tranches.iter_mut().filter(|t| t.status == "pending") {
+        let trigger = TriggerOrderRequest::exit(
+            &order.symbol,
+            &order.side,
+            tranche.size,
+            tranche.trigger_price,
+            "tp",
+        );
+        match exchange.place_trigger_order(&trigger, "usdt").await {
+            Ok(id) => {
+                info!(
+                    "Created take profit tranche {} trigger {} for order {}",
+                    tranche.seq, id, order.id
+                );
+                TakeProfitTrancheRepository::mark_open(db, tranche.id, &id).await?;
+                tranche.trigger_order_id = Some(id);
+                tranche.status = "open".to_string();
+            }
+            Err(e) => warn!(
+                "Failed to create take profit tranche {} for order {}: {}",
+                tranche.seq, order.id, e
+            ),
+        }
+    }
+
+    if !tranches.iter().any(|t| t.status == "open") {
+        return Ok(false);
+    }
+    let key = (order.exchange.clone(), order.symbol.clone());
+    if !price_orders.contains_key(&key) {
+        let orders = exchange.get_price_orders(&order.symbol, "usdt").await?;
+        price_orders.insert(key.clone(), orders);
+    }
+
+    let first_fill = !tranches.iter().any(|t| t.status == "triggered");
+    let mut newly_closed = 0;
+    for tranche in tranches.iter().filter(|t| t.status == "open") {
+        let Some(price_order) = tranche
+            .trigger_order_id
+            .as_deref()
+            .and_then(|id| price_orders[&key].iter().find(|p| p.id == id))
+        else {
+            continue;
+        };
+        match price_order.status.as_str() {
+            "triggered" => {
+                let (fill_price, fee) =
+                    tranche_fill(db, exchange, order, tranche, price_order).await;
+                if TakeProfitTrancheRepository::mark_triggered(db, tranche.id, fill_price, fee)
+                    .await?
+                {
+                    OrderRepository::add_closed_size(db, order.id, tranche.size).await?;
+                    newly_closed += tranche.size;
+                    info!(
+                        "Take profit tranche {} of order {} filled: {} @ {}",
+                        tranche.seq, order.id, tranche.size, fill_price
+                    );
+                }
+            }
+            // 在交易所被撤销的批次不再跟踪，这部分仓位由止损平仓
+            "cancelled" | "failed" => {
+                TakeProfitTrancheRepository::mark_cancelled(db, tranche.id).await?;
+            }
+            _ => {}
+        }
+    }
+    if newly_closed == 0 {
+        return Ok(false);
+    }
+
+    let remaining = order.filled_size - order.closed_size - newly_closed;
+    if remaining > 0 {
+        resize_stop_loss(db, exchange, order, remaining, first_fill).await?;
+    }
+    Ok(true)
+}
+
+/// 撤销订单还没有触发的批次，撤销失败的下一轮对账重试
+pub async fn cancel_tranches(
+    db: &SqlitePool,
+    exchange: &dyn Exchange,
+    order: &Order,
+) -> Result<()> {
+    for tranche in TakeProfitTrancheRepository::get_by_order(db, order.id).await? {
+        match (tranche.status.as_str(), tranche.trigger_order_id.as_deref()) {
+            ("open", Some(trigger_order_id)) => {
+                match exchange.cancel_price_order(trigger_order_id, "usdt").await {
+                    Ok(()) => {
+                        info!(
+                            "Cancelled take profit tranche {} of order {}",
+                            tranche.seq, order.id
+                        );
+                        TakeProfitTrancheRepository::mark_cancelled(db, tranche.id).await?;
+                    }
+                    Err(e) => warn!(
+                        "Failed to cancel take profit tranche {} of order {}: {}",
+                        tranche.seq, order.id, e
+                    ),
+                }
+            }
+            ("pending", _) => TakeProfitTrancheRepository::mark_cancelled(db, tranche.id).await?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// 按成交张数和开仓价生成每一批，止盈价按合约精度取整
+async fn create_tranches(db: &SqlitePool, order: &Order) -> Result<()> {
+    let steps = parse_ladder(&order.take_profit_ladder)?;
+    let entry_price = order.avg_fill_price.unwrap_or(order.entry_price);
+    let initial_stop = order.initial_stop_price.unwrap_or(order.stop_loss_price);
+    let precision = ApiKeyRepository::get_contract_by_symbol(db, &order.symbol)
+        .await?
+        .map(|contract| contract.order_price_round);
+
+    let tranches: Vec<TakeProfitTranche> = split_ladder(&steps, order.filled_size)
+        .into_iter()
+        .enumerate()
+        .map(|(index, (size, r_multiple))| {
+            let price = ladder_price(&order.side, entry_price, initial_stop, r_multiple);
+            TakeProfitTranche {
+                order_id: order.id,
+                seq: index as i64 + 1,
+                size,
+                r_multiple,
+                trigger_price: match precision.as_deref() {
+                    Some(precision) => round_price(price, precision),
+                    None => price,
+                },
+                ..Default::default()
+            }
+        })
+        .collect();
+    TakeProfitTrancheRepository::save_batch(db, &tranches).await
+}
+
+/// 触发后生成的平仓订单的成交价和手续费，查不到时按触发价记录、不计手续费
+async fn tranche_fill(
+    db: &SqlitePool,
+    exchange: &dyn Exchange,
+    order: &Order,
+    tranche: &TakeProfitTranche,
+    price_order: &PriceOrder,
+) -> (f64, f64) {
+    let Some(trade_id) = price_order.trade_id.as_deref() else {
+        return (tranche.trigger_price, 0.0);
+    };
+    let exit_order = match exchange.get_order(trade_id, "usdt").await {
+        Ok(exit_order) => exit_order,
+        Err(e) => {
+            warn!(
+                "Failed to get take profit tranche order {}: {}",
+                trade_id, e
+            );
+            return (tranche.trigger_price, 0.0);
+        }
+    };
+    let fill_price = exit_order.fill_price.unwrap_or(tranche.trigger_price);
+    let quanto_multiplier = ApiKeyRepository::get_quanto_multiplier(db, &order.symbol)
+        .await
+        .unwrap_or(0.0);
+    let fee = tranche.size as f64 * fill_price * quanto_multiplier * exit_order.fee_rate;
+    (fill_price, fee)
+}
+
+/// 把止损委托改为剩余张数，第一批止盈后按设置移到开仓价；交易所替换失败时保留原来的止损，下一批触发时再调整
+async fn resize_stop_loss(
+    db: &SqlitePool,
+    exchange: &dyn Exchange,
+    order: &Order,
+    remaining: i64,
+    first_fill: bool,
+) -> Result<()> {
+    let (Some(old_order_id), Some("open")) = (
+        order.stop_loss_order_id.as_deref(),
+        order.stop_loss_status.as_deref(),
+    ) else {
+        return Ok(());
+    };
+    let entry_price = order.avg_fill_price.unwrap_or(order.entry_price);
+    let direction = if order.side == "buy" { 1.0 } else { -1.0 };
+    let break_even = order.ladder_break_even
+        && first_fill
+        && (entry_price - order.stop_loss_price) * direction > 0.0;
+    let stop = if break_even {
+        entry_price
+    } else {
+        order.stop_loss_price
+    };
+
+    let trigger = TriggerOrderRequest::exit(&order.symbol, &order.side, remaining, stop, "sl");
+    let new_order_id = match exchange
+        .replace_stop_loss(old_order_id, &trigger, "usdt")
+        .await
+    {
+        Ok(id) => id,
+        Err(e) => {
+            warn!(
+                "Failed to resize stop loss of order {} to {}: {}",
+                order.id, remaining, e
+            );
+            return Ok(());
+        }
+    };
+
+    OrderRepository::update_stop_loss(db, order.id, stop, &new_order_id).await?;
+    if break_even {
+        StopAdjustmentRepository::save(
+            db,
+            &StopAdjustment {
+                order_id: order.id,
+                reason: "break_even".to_string(),
+                old_stop: order.stop_loss_price,
+                new_stop: stop,
+                price: entry_price,
+                stop_loss_order_id: Some(new_order_id),
+                ..Default::default()
+            },
+        )
+        .await?;
+    }
+    info!(
+        "Stop loss of order {} resized to {} @ {}",
+        order.id, remaining, stop
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ladder() {
+        assert!(parse_ladder("").unwrap().is_empty());
+        assert_eq!(
+            parse_ladder("50:1, 30:2, 20").unwrap(),
+            vec![
+                LadderStep {
+                    percent: 50.0,
+                    r_multiple: Some(1.0)
+                },
+                LadderStep {
+                    percent: 30.0,
+                    r_multiple: Some(2.0)
+                },
+                LadderStep {
+                    percent: 20.0,
+                    r_multiple: None
+                },
+            ]
+        );
+        assert!(parse_ladder("50:1,30:2").is_err());
+        assert!(parse_ladder("50,50").is_err());
+        assert!(parse_ladder("100").is_err());
+        assert!(parse_ladder("50:0,50:2").is_err());
+        assert!(parse_ladder("50:x,50:2").is_err());
+    }
+
+    #[test]
+    fn test_validate_ladder() {
+        let api = MonitorConfig {
+            execution_mode: "api".into(),
+            take_profit_ladder: "50:1,50:2".into(),
+            ..Default::default()
+        };
+        assert!(validate_ladder(&api).is_ok());
+        // 网页接口和模拟交易不会创建分批止盈委托
+        let web = MonitorConfig {
+            execution_mode: "web".into(),
+            ..api.clone()
+        };
+        assert!(validate_ladder(&web).is_err());
+        let paper = MonitorConfig {
+            paper_trading: true,
+            ..api.clone()
+        };
+        assert!(validate_ladder(&paper).is_err());
+        let empty = MonitorConfig {
+            take_profit_ladder: String::new(),
+            ..paper
+        };
+        assert!(validate_ladder(&empty).is_ok());
+    }
+
+    #[test]
+    fn test_split_ladder() {
+        let steps = parse_ladder("50:1,30:2,20").unwrap();
+        assert_eq!(split_ladder(&steps, 10), vec![(5, 1.0), (3, 2.0)]);
+        // 取整余下的张数留给跟随止损的部分
+        assert_eq!(split_ladder(&steps, 7), vec![(3, 1.0), (2, 2.0)]);
+        assert_eq!(split_ladder(&steps, 1), vec![]);
+
+        let steps = parse_ladder("50:1,50:3").unwrap();
+        assert_eq!(split_ladder(&steps, 5), vec![(2, 1.0), (3, 3.0)]);
+        assert_eq!(split_ladder(&steps, 1), vec![(1, 3.0)]);
+    }
+
+    #[test]
+    fn test_ladder_price() {
+        assert_eq!(ladder_price("buy", 100.0, 95.0, 2.0), 110.0);
+        assert_eq!(ladder_price("sell", 100.0, 105.0, 1.5), 92.5);
+    }
+}
@@ -0,0 +1,286 @@
+use crate::models::{KlineData, MonitorConfig};
+use crate::services::backtest::{BacktestOptions, BacktestReport, run_backtest_from};
+use crate::services::confirmation;
+use crate::services::optimizer::{Objective, ParamSet, optimize};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// 滚动窗口参数，长度都以K线数量计
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalkForwardOptions {
+    pub in_sample: usize,     // 样本内（寻优）窗口长度
+    pub out_of_sample: usize, // 样本外（验证）窗口长度
+    pub step: usize,          // 窗口每次向前滚动的长度，通常等于样本外长度
+    pub objective: Objective,
+    pub min_trades: usize,
+    pub workers: usize,
+}
+
+/// 单个窗口的寻优和验证结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalkForwardWindow {
+    pub index: usize,
+    pub in_sample_start: i64,
+    pub in_sample_end: i64,
+    pub out_of_sample_start: i64,
+    pub out_of_sample_end: i64,
+    pub best_params: Option<ParamSet>, // 样本内没有满足最少交易数的参数组合时为空
+    pub in_sample_score: f64,
+    pub out_of_sample_score: Option<f64>, // 样本外没有得分（没有亏损交易时的盈亏因子）时为空
+    pub in_sample: Option<BacktestReport>,
+    pub out_of_sample: Option<BacktestReport>,
+}
+
+/// 某个参数在各窗口最优值上的分布，变异系数越小说明参数越稳定
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParamStability {
+    pub name: String,
+    pub mean: f64,
+    pub std_dev: f64,
+    pub min: f64,
+    pub max: f64,
+    pub coefficient_of_variation: f64,
+}
+
+/// 滚动验证报告
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalkForwardReport {
+    pub symbol: String,
+    pub interval_type: String,
+    pub objective: Objective,
+    pub windows: Vec<WalkForwardWindow>,
+    pub valid_windows: usize,
+    pub avg_in_sample_score: f64,
+    pub avg_out_of_sample_score: f64,
+    pub in_sample_net_pnl: f64,
+    pub out_of_sample_net_pnl: f64,
+    pub profitable_out_of_sample_windows: usize,
+    /// 样本外平均得分 / 样本内平均得分，明显小于1说明参数过拟合；样本内得分不为正时为0
+    pub efficiency: f64,
+    pub param_stability: Vec<ParamStability>,
+}
+
+/// 滚动窗口验证：每个样本内窗口做参数寻优，用最优参数在紧随其后的样本外窗口上回测
+pub async fn walk_forward(
+    config: &MonitorConfig,
+    klines: Arc<Vec<KlineData>>,
+    options: &BacktestOptions,
+    candidates: Vec<ParamSet>,
+    wf: &WalkForwardOptions,
+) -> Result<WalkForwardReport> {
+    if wf.in_sample == 0 || wf.out_of_sample == 0 || wf.step == 0 {
+        return Err(anyhow!("Walk-forward window sizes must be positive"));
+    }
+    if klines.len() < wf.in_sample + wf.out_of_sample {
+        return Err(anyhow!(
+            "Not enough klines for walk-forward: need {}, got {}",
+            wf.in_sample + wf.out_of_sample,
+            klines.len()
+        ));
+    }
+
+    let mut windows = Vec::new();
+    let mut start = 0;
+    while start + wf.in_sample + wf.out_of_sample <= klines.len() {
+        let oos_start = start + wf.in_sample;
+        let oos_end = oos_start + wf.out_of_sample;
+        let in_sample = Arc::new(klines[start..oos_start].to_vec());
+
+        let results = optimize(
+            config,
+            in_sample,
+            options,
+            candidates.clone(),
+            wf.objective,
+            wf.min_trades,
+            wf.workers,
+        )
+        .await?;
+
+        let mut window = WalkForwardWindow {
+            index: windows.len(),
+            in_sample_start: klines[start].timestamp,
+            in_sample_end: klines[oos_start - 1].timestamp,
+            out_of_sample_start: klines[oos_start].timestamp,
+            out_of_sample_end: klines[oos_end - 1].timestamp,
+            best_params: None,
+            in_sample_score: 0.0,
+            out_of_sample_score: None,
+            in_sample: None,
+            out_of_sample: None,
+        };
+
+        if let Some(best) = results.into_iter().next() {
+            // 样本外窗口之前的K线只作为历史数据
+            let best_config = best.params.apply(config);
+            let warmup = warmup_klines(&best_config).min(oos_start);
+            let mut report = run_backtest_from(
+                &best_config,
+                &klines[oos_start - warmup..oos_end],
+                warmup,
+                options,
+            );
+            report.trades.clear();
+
+            window.best_params = Some(best.params);
+            window.in_sample_score = best.score;
+            window.out_of_sample_score = wf.objective.score(&report);
+            window.in_sample = Some(best.report);
+            window.out_of_sample = Some(report);
+        }
+
+        windows.push(window);
+        start += wf.step;
+    }
+
+    Ok(summarize(config, wf.objective, windows))
+}
+
+/// 样本外回测需要的历史K线数量：成交量、指标、入场规则、脚本和合成大周期K线中最多的
+fn warmup_klines(config: &MonitorConfig) -> usize {
+    config
+        .required_klines()
+        .max(confirmation::base_klines(config))
+}
+
+fn summarize(
+    config: &MonitorConfig,
+    objective: Objective,
+    windows: Vec<WalkForwardWindow>,
+) -> WalkForwardReport {
+    let valid: Vec<&WalkForwardWindow> =
+        windows.iter().filter(|w| w.best_params.is_some()).collect();
+    let n = valid.len().max(1) as f64;
+
+    let avg_in_sample_score = valid.iter().map(|w| w.in_sample_score).sum::<f64>() / n;
+    // 样本外没有得分的窗口不计入平均
+    let out_of_sample_scores: Vec<f64> =
+        valid.iter().filter_map(|w| w.out_of_sample_score).collect();
+    let avg_out_of_sample_score =
+        out_of_sample_scores.iter().sum::<f64>() / out_of_sample_scores.len().max(1) as f64;
+    let net_pnl = |r: &Option<BacktestReport>| r.as_ref().map(|r| r.net_pnl).unwrap_or(0.0);
+
+    let params: Vec<ParamSet> = valid.iter().filter_map(|w| w.best_params).collect();
+    let param_stability = [
+        (
+            "shadow_ratio",
+            params.iter().map(|p| p.shadow_ratio).collect(),
+        ),
+        (
+            "main_shadow_body_ratio",
+            params.iter().map(|p| p.main_shadow_body_ratio).collect(),
+        ),
+        (
+            "volume_multiplier",
+            params.iter().map(|p| p.volume_multiplier).collect(),
+        ),
+        (
+            "risk_reward_ratio",
+            params.iter().map(|p| p.risk_reward_ratio).collect(),
+        ),
+        (
+            "expected_profit_rate",
+            params.iter().map(|p| p.expected_profit_rate).collect(),
+        ),
+        (
+            "history_hours",
+            params.iter().map(|p| p.history_hours).collect(),
+        ),
+    ]
+    .into_iter()
+    .map(|(name, values): (&str, Vec<f64>)| stability(name, &values))
+    .collect();
+
+    WalkForwardReport {
+        symbol: config.symbol.clone(),
+        interval_type: config.interval_type.clone(),
+        objective,
+        valid_windows: valid.len(),
+        avg_in_sample_score,
+        avg_out_of_sample_score,
+        in_sample_net_pnl: valid.iter().map(|w| net_pnl(&w.in_sample)).sum(),
+        out_of_sample_net_pnl: valid.iter().map(|w| net_pnl(&w.out_of_sample)).sum(),
+        profitable_out_of_sample_windows: valid
+            .iter()
+            .filter(|w| net_pnl(&w.out_of_sample) > 0.0)
+            .count(),
+        efficiency: if avg_in_sample_score > f64::EPSILON {
+            avg_out_of_sample_score / avg_in_sample_score
+        } else {
+            0.0
+        },
+        param_stability,
+        windows,
+    }
+}
+
+fn stability(name: &str, values: &[f64]) -> ParamStability {
+    if values.is_empty() {
+        return ParamStability {
+            name: name.to_string(),
+            mean: 0.0,
+            std_dev: 0.0,
+            min: 0.0,
+            max: 0.0,
+            coefficient_of_variation: 0.0,
+        };
+    }
+
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let std_dev = (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n).sqrt();
+    ParamStability {
+        name: name.to_string(),
+        mean,
+        std_dev,
+        min: values.iter().copied().fold(f64::INFINITY, f64::min),
+        max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        coefficient_of_variation: if mean.abs() > f64::EPSILON {
+            std_dev / mean.abs()
+        } else {
+            0.0
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stability() {
+        let s = stability("shadow_ratio", &[1.0, 2.0, 3.0]);
+        assert_eq!(s.mean, 2.0);
+        assert_eq!(s.min, 1.0);
+        assert_eq!(s.max, 3.0);
+        assert!((s.std_dev - (2.0f64 / 3.0).sqrt()).abs() < 1e-12);
+        assert!((s.coefficient_of_variation - s.std_dev / 2.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_warmup_klines() {
+        let config = MonitorConfig {
+            interval_type: "15m".into(),
+            history_hours: 3.0,
+            ..Default::default()
+        };
+        assert_eq!(warmup_klines(&config), 13);
+
+        let filtered = MonitorConfig {
+            trend_ema_period: 200,
+            ..config.clone()
+        };
+        assert_eq!(warmup_klines(&filtered), filtered.required_klines());
+        assert!(warmup_klines(&filtered) >= 200);
+
+        // 4小时EMA(50)确认：101根4小时K线，每根16根15分钟K线
+        let confirmed = MonitorConfig {
+            confirm_interval: "4h".into(),
+            confirm_mode: "ema".into(),
+            confirm_ema_period: 50,
+            ..config
+        };
+        assert_eq!(warmup_klines(&confirmed), 101 * 16);
+    }
+}
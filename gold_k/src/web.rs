@@ -1,7 +1,7 @@
 use askama::Template;
 use axum::{
     Json, Router,
-    extract::State,
+    extract::{Query, State},
     http::StatusCode,
     response::{Html, IntoResponse},
     routing::{get, post},
@@ -16,7 +16,9 @@ use tracing::{info, warn};
 use validator::Validate;
 
 use crate::repository::{
-    ApiKeyRepository, MonitorConfigRepository, OrderRepository, SignalRepository,
+    self, ApiKeyRepository, MonitorConfigRepository, OrderRepository, PaperRepository,
+    PositionRepository, RiskRepository, RoundTripRepository, SignalRepository,
+    StrategyScriptRepository,
 };
 use crate::services::*;
 use crate::templates::*;
@@ -26,38 +28,32 @@ pub type AppState = Arc<AppStateInner>;
 
 pub struct AppStateInner {
     pub db: SqlitePool,
-    pub gate_service: RwLock<GateService>,
+    pub exchange: Arc<RwLock<dyn Exchange>>,
     pub monitor_service: RwLock<MonitorService>,
 }
 
 pub async fn start() -> anyhow::Result<()> {
     // 初始化数据库
     let c = get_global_config().await;
-    let db = SqlitePool::connect(&c.database_url).await?;
-
-    // 运行数据库迁移
-    sqlx::migrate!("../migrations").run(&db).await?;
+    let db = repository::connect(&c.database_url).await?;
 
     // 初始化服务
     let mut gate_service = GateService::new();
-    let monitor_service = MonitorService::new(db.clone());
 
     // 加载当前活跃的API配置
     if let Ok(Some(key)) = ApiKeyRepository::get_active(&db).await {
-        gate_service.update_credentials(&key.api_key, &key.secret_key);
-        if let Some(cookie) = &key.cookie {
-            gate_service.set_cookie(cookie);
-        }
-        if let Some(contracts) = &key.contracts {
-            gate_service.set_contracts(contracts);
-        }
+        gate_service.apply_api_key(&key);
         info!("Loaded API configuration: {}", key.name);
     }
 
+    // web接口和监控服务共用同一个交易所实例
+    let exchange: Arc<RwLock<dyn Exchange>> = Arc::new(RwLock::new(gate_service));
+    let monitor_service = MonitorService::with_exchange(db.clone(), exchange.clone());
+
     // 创建应用状态
     let state = Arc::new(AppStateInner {
         db,
-        gate_service: RwLock::new(gate_service),
+        exchange,
         monitor_service: RwLock::new(monitor_service),
     });
 
@@ -78,8 +74,18 @@ pub async fn start() -> anyhow::Result<()> {
             "/api/configs",
             get(get_monitor_configs).post(save_monitor_configs),
         )
+        .route("/api/scripts", get(get_scripts).post(save_script))
+        .route("/api/scripts/{name}", get(get_script_versions))
         .route("/api/dingding/test", get(dingding_test))
         .route("/api/order/place", post(place_order))
+        .route("/api/paper/account", get(get_paper_account))
+        .route("/api/paper/orders", get(get_paper_orders))
+        .route("/api/paper/reset", post(reset_paper_account))
+        .route("/api/risk", get(get_risk_settings).post(save_risk_settings))
+        .route("/api/emergency/flatten", post(flatten_all))
+        .route("/api/emergency/resume", post(resume_trading))
+        .route("/api/positions", get(get_positions))
+        .route("/api/pnl", get(get_pnl))
         .route("/keys", get(keys_page))
         .route("/monitor", get(monitor_page))
         .nest_service("/static", ServeDir::new("static"))
@@ -149,10 +155,8 @@ async fn save_api_keys(
     {
         Ok(_) => {
             // 更新服务配置
-            let mut gate_service = state.gate_service.write().await;
-            gate_service.update_credentials(&payload.api_key, &payload.secret_key);
-            if let Some(cookie) = &payload.cookie {
-                gate_service.set_cookie(cookie);
+            if let Ok(Some(key)) = ApiKeyRepository::get_active(&state.db).await {
+                state.exchange.write().await.apply_api_key(&key);
             }
 
             Json(serde_json::json!({"success": true})).into_response()
@@ -179,14 +183,7 @@ async fn activate_api_key(
         Ok(_) => {
             // 获取激活的密钥并更新服务配置
             if let Ok(Some(key)) = ApiKeyRepository::get_by_id(&state.db, id).await {
-                let mut gate_service = state.gate_service.write().await;
-                gate_service.update_credentials(&key.api_key, &key.secret_key);
-                if let Some(cookie) = &key.cookie {
-                    gate_service.set_cookie(cookie);
-                }
-                if let Some(contracts) = &key.contracts {
-                    gate_service.set_contracts(contracts);
-                }
+                state.exchange.write().await.apply_api_key(&key);
             }
 
             Json(serde_json::json!({"success": true})).into_response()
@@ -257,6 +254,152 @@ async fn get_orders(State(state): State<AppState>) -> impl IntoResponse {
     }
 }
 
+async fn get_risk_settings(State(state): State<AppState>) -> impl IntoResponse {
+    match RiskRepository::get_settings(&state.db).await {
+        Ok(settings) => Json(settings).into_response(),
+        Err(e) => {
+            warn!("Failed to get risk settings: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+async fn save_risk_settings(
+    State(state): State<AppState>,
+    Json(settings): Json<RiskSettings>,
+) -> impl IntoResponse {
+    if settings.max_positions < 0
+        || settings.max_positions_per_symbol < 0
+        || settings.max_daily_loss < 0.0
+        || settings.max_orders_per_hour < 0
+        || settings.max_consecutive_losses < 0
+        || settings.cooldown_minutes < 0
+    {
+        return Json(serde_json::json!({
+            "success": false,
+            "message": "风控参数不能为负数"
+        }))
+        .into_response();
+    }
+
+    match RiskRepository::save_settings(&state.db, &settings).await {
+        Ok(_) => Json(serde_json::json!({"success": true})).into_response(),
+        Err(e) => {
+            warn!("Failed to save risk settings: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+async fn flatten_all(State(state): State<AppState>) -> impl IntoResponse {
+    // 先停止监控，避免平仓过程中又产生新订单
+    let exchanges = {
+        let mut monitor_service = state.monitor_service.write().await;
+        monitor_service.stop().await;
+        monitor_service.exchanges()
+    };
+
+    let mut dingtalk_service = DingTalkService::new();
+    if let Ok(Some(key)) = ApiKeyRepository::get_active(&state.db).await
+        && let Some(url) = key.webhook_url.filter(|u| !u.is_empty())
+    {
+        dingtalk_service.set_webhook_url(&url);
+    }
+
+    match emergency_flatten(&state.db, &exchanges, Some(&dingtalk_service)).await {
+        Ok(reports) => Json(serde_json::json!({
+            "success": reports.iter().all(|r| r.errors.is_empty() && r.closed.iter().all(|c| c.success)),
+            "message": "监控已停止，自动下单已暂停",
+            "reports": reports,
+        }))
+        .into_response(),
+        Err(e) => {
+            warn!("Failed to flatten positions: {}", e);
+            Json(serde_json::json!({
+                "success": false,
+                "message": format!("紧急平仓失败: {}", e)
+            }))
+            .into_response()
+        }
+    }
+}
+
+async fn resume_trading(State(state): State<AppState>) -> impl IntoResponse {
+    match RiskRepository::set_halted(&state.db, false, None).await {
+        Ok(_) => {
+            Json(serde_json::json!({"success": true, "message": "已恢复自动下单"})).into_response()
+        }
+        Err(e) => {
+            warn!("Failed to resume trading: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+async fn get_positions(
+    State(state): State<AppState>,
+    Query(query): Query<PositionsQuery>,
+) -> impl IntoResponse {
+    let exchange_name = query.exchange.unwrap_or_else(|| "gate".to_string());
+
+    // 实盘持仓实时查询，同时保存快照；查询失败或模拟交易时返回最近的快照
+    if exchange_name == "gate" {
+        let exchange = state.exchange.read().await;
+        match exchange.get_positions("usdt").await {
+            Ok(positions) => {
+                if let Err(e) = positions::save_snapshots(&state.db, "gate", &positions).await {
+                    warn!("Failed to save position snapshots: {}", e);
+                }
+                return Json(serde_json::json!({
+                    "source": "live",
+                    "positions": positions,
+                }))
+                .into_response();
+            }
+            Err(e) => warn!("Failed to get positions: {}", e),
+        }
+    }
+
+    match PositionRepository::get_latest(&state.db, &exchange_name).await {
+        Ok(snapshots) => Json(serde_json::json!({
+            "source": "snapshot",
+            "positions": snapshots.into_iter().filter(|s| s.size != 0).collect::<Vec<_>>(),
+        }))
+        .into_response(),
+        Err(e) => {
+            warn!("Failed to get position snapshots: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+async fn get_pnl(
+    State(state): State<AppState>,
+    Query(query): Query<PnlQuery>,
+) -> impl IntoResponse {
+    let days = query.days.unwrap_or(30).max(1);
+    let since = chrono::Utc::now().timestamp() - days * 86400;
+
+    let result = async {
+        anyhow::Ok(serde_json::json!({
+            "days": days,
+            "total": RoundTripRepository::summary_total(&state.db, since).await?,
+            "daily": RoundTripRepository::summary_by_day(&state.db, since).await?,
+            "by_symbol": RoundTripRepository::summary_by_symbol(&state.db, since).await?,
+            "recent": RoundTripRepository::get_recent(&state.db, since, 200).await?,
+        }))
+    }
+    .await;
+
+    match result {
+        Ok(pnl) => Json(pnl).into_response(),
+        Err(e) => {
+            warn!("Failed to get pnl: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
 async fn get_monitor_configs(State(state): State<AppState>) -> impl IntoResponse {
     match MonitorConfigRepository::get_all(&state.db).await {
         Ok(configs) => Json(configs).into_response(),
@@ -271,6 +414,77 @@ async fn save_monitor_configs(
     State(state): State<AppState>,
     Json(configs): Json<Vec<MonitorConfig>>,
 ) -> impl IntoResponse {
+    for config in &configs {
+        if !config.strategy.is_empty() && !patterns::STRATEGIES.contains(&config.strategy.as_str())
+        {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "{} {}: 未知的策略 {}",
+                    config.symbol, config.interval_type, config.strategy
+                ),
+            )
+                .into_response();
+        }
+        if config.strategy == "script" {
+            let script = StrategyScriptRepository::get(
+                &state.db,
+                config.script_name.trim(),
+                config.script_version,
+            )
+            .await;
+            let error = match script {
+                Ok(Some(_)) => None,
+                Ok(None) if config.script_name.trim().is_empty() => {
+                    Some("脚本策略需要选择脚本".to_string())
+                }
+                Ok(None) => Some(format!(
+                    "脚本 {} v{} 不存在",
+                    config.script_name, config.script_version
+                )),
+                Err(e) => {
+                    warn!("Failed to get script {}: {}", config.script_name, e);
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                }
+            };
+            if let Some(error) = error {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("{} {}: {}", config.symbol, config.interval_type, error),
+                )
+                    .into_response();
+            }
+        }
+        if let Err(e) = rules::validate(&config.entry_rule) {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("{} {}: 入场规则 {}", config.symbol, config.interval_type, e),
+            )
+                .into_response();
+        }
+        if let Err(e) = confirmation::validate(config) {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("{} {}: {}", config.symbol, config.interval_type, e),
+            )
+                .into_response();
+        }
+        if let Err(e) = indicators::validate_filters(config) {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("{} {}: {}", config.symbol, config.interval_type, e),
+            )
+                .into_response();
+        }
+        if let Err(e) = take_profit::validate_ladder(config) {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("{} {}: {}", config.symbol, config.interval_type, e),
+            )
+                .into_response();
+        }
+    }
+
     match MonitorConfigRepository::save_batch(&state.db, &configs).await {
         Ok(_) => Json(serde_json::json!({"success": true})).into_response(),
         Err(e) => {
@@ -280,6 +494,57 @@ async fn save_monitor_configs(
     }
 }
 
+async fn get_scripts(State(state): State<AppState>) -> impl IntoResponse {
+    match StrategyScriptRepository::get_latest_all(&state.db).await {
+        Ok(scripts) => Json(scripts).into_response(),
+        Err(e) => {
+            warn!("Failed to get scripts: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+async fn get_script_versions(
+    State(state): State<AppState>,
+    axum::extract::Path(name): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    match StrategyScriptRepository::get_versions(&state.db, &name).await {
+        Ok(scripts) => Json(scripts).into_response(),
+        Err(e) => {
+            warn!("Failed to get script {}: {}", name, e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// 保存脚本的新版本，名称不合法或编译失败时返回400
+async fn save_script(
+    State(state): State<AppState>,
+    Json(request): Json<SaveScriptRequest>,
+) -> impl IntoResponse {
+    let name = request.name.trim();
+    let compiled = scripting::validate_name(name).and_then(|_| {
+        scripting::ScriptStrategy::compile(&StrategyScript {
+            name: name.to_string(),
+            source: request.source.clone(),
+            ..Default::default()
+        })
+    });
+    if let Err(e) = compiled {
+        return (StatusCode::BAD_REQUEST, format!("{}: {}", name, e)).into_response();
+    }
+
+    match StrategyScriptRepository::save(&state.db, name, &request.source).await {
+        Ok(version) => {
+            Json(serde_json::json!({"success": true, "version": version})).into_response()
+        }
+        Err(e) => {
+            warn!("Failed to save script {}: {}", name, e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
 async fn get_current_api_key(State(state): State<AppState>) -> impl IntoResponse {
     match ApiKeyRepository::get_active(&state.db).await {
         Ok(Some(key)) => Json(key).into_response(),
@@ -305,9 +570,9 @@ async fn fetch_contracts(State(state): State<AppState>) -> impl IntoResponse {
         }
     };
 
-    // 使用Gate服务获取合约数据
-    let gate_service = state.gate_service.read().await;
-    match gate_service.get_contracts("usdt").await {
+    // 使用交易所服务获取合约数据
+    let exchange = state.exchange.read().await;
+    match exchange.get_contracts("usdt").await {
         Ok(contracts) => {
             let contracts_json = serde_json::to_string(&contracts).unwrap_or_default();
 
@@ -337,6 +602,68 @@ async fn fetch_contracts(State(state): State<AppState>) -> impl IntoResponse {
     }
 }
 
+async fn get_paper_account(State(state): State<AppState>) -> impl IntoResponse {
+    match PaperRepository::get_account(&state.db).await {
+        Ok(account) => Json(account).into_response(),
+        Err(e) => {
+            warn!("Failed to get paper account: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+async fn get_paper_orders(State(state): State<AppState>) -> impl IntoResponse {
+    match PaperRepository::get_recent(&state.db, 200).await {
+        Ok(orders) => Json(orders).into_response(),
+        Err(e) => {
+            warn!("Failed to get paper orders: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+async fn reset_paper_account(
+    State(state): State<AppState>,
+    Json(payload): Json<ResetPaperAccountRequest>,
+) -> impl IntoResponse {
+    if payload.initial_balance <= 0.0 {
+        return Json(serde_json::json!({
+            "success": false,
+            "message": "初始资金必须大于0"
+        }))
+        .into_response();
+    }
+
+    match PaperRepository::reset(&state.db, payload.initial_balance).await {
+        Ok(_) => Json(serde_json::json!({"success": true})).into_response(),
+        Err(e) => {
+            warn!("Failed to reset paper account: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PositionsQuery {
+    exchange: Option<String>, // 'gate'（默认）或 'paper'
+}
+
+#[derive(Deserialize)]
+struct PnlQuery {
+    days: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct ResetPaperAccountRequest {
+    initial_balance: f64,
+}
+
+#[derive(Deserialize)]
+struct SaveScriptRequest {
+    name: String,
+    source: String, // Rhai脚本
+}
+
 #[derive(Deserialize)]
 struct SaveApiKeysRequest {
     name: String,
@@ -452,8 +779,8 @@ async fn place_order(
     if let Err(errors) = request.validate() {
         let error_messages: Vec<String> = errors
             .field_errors()
-            .into_iter()
-            .flat_map(|(_, errors)| {
+            .into_values()
+            .flat_map(|errors| {
                 errors.iter().map(|error| {
                     error
                         .message
@@ -472,35 +799,34 @@ async fn place_order(
     }
 
     // 构建订单数据
-    let order_data = build_order_data(
-        &request.symbol,
-        &request.order_type,
-        &request.side,
-        request.entry_price,
-        request.size,
-        request.take_profit,
-        request.stop_loss,
-    );
-
-    // 调用Gate服务下单
-    let gate_service = state.gate_service.read().await;
-    match gate_service
-        .place_order_with_stop_profit_loss(order_data, "usdt")
-        .await
-    {
+    let order_request = OrderRequest {
+        symbol: request.symbol,
+        order_type: request.order_type,
+        side: request.side,
+        price: request.entry_price,
+        size: request.size,
+        take_profit: request.take_profit,
+        stop_loss: request.stop_loss,
+        client_order_id: None,
+        execution_mode: "web".to_string(),
+    };
+
+    // 调用交易所服务下单
+    let exchange = state.exchange.read().await;
+    match exchange.place_order(&order_request, "usdt").await {
         Ok(response) => {
-            info!("Order placed successfully: {:?}", response);
-            if response.get("code").cloned() == Some(200.into()) {
+            info!("Order placed successfully: {:?}", response.raw);
+            if response.success {
                 Json(serde_json::json!({
                     "success": true,
                     "message": "下单成功",
-                    "data": response
+                    "data": response.raw
                 }))
             } else {
                 Json(serde_json::json!({
                     "success": false,
-                    "message": format!("下单失败: {}", response.get("message").unwrap_or(&"未知错误".into())),
-                    "data": response
+                    "message": format!("下单失败: {}", response.message),
+                    "data": response.raw
                 }))
             }
             .into_response()
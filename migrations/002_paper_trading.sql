@@ -0,0 +1,37 @@
+-- 模拟交易账户表
+CREATE TABLE IF NOT EXISTS paper_accounts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    initial_balance REAL NOT NULL, -- 初始资金(USDT)
+    balance REAL NOT NULL, -- 当前资金(USDT)，只包含已实现盈亏
+    realized_pnl REAL NOT NULL DEFAULT 0, -- 累计已实现盈亏
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+    updated_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+);
+
+INSERT OR IGNORE INTO paper_accounts (id, initial_balance, balance) VALUES (1, 10000, 10000);
+
+-- 模拟交易订单/持仓表
+CREATE TABLE IF NOT EXISTS paper_orders (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    symbol TEXT NOT NULL,
+    side TEXT NOT NULL, -- 'buy' or 'sell'
+    order_type TEXT NOT NULL, -- 'market' 或 'limit'
+    order_size INTEGER NOT NULL, -- 张
+    quanto_multiplier REAL NOT NULL DEFAULT 1, -- 合约数量乘数
+    order_price REAL NOT NULL, -- 下单价格
+    take_profit_price REAL,
+    stop_loss_price REAL,
+    status TEXT NOT NULL DEFAULT 'pending', -- 'pending', 'open', 'closed', 'cancelled'
+    fill_price REAL,
+    filled_at INTEGER,
+    exit_price REAL,
+    exit_reason TEXT, -- 'tp' or 'sl'
+    closed_at INTEGER,
+    realized_pnl REAL,
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+);
+
+-- 监控配置增加模拟交易开关
+ALTER TABLE monitor_configs ADD COLUMN paper_trading BOOLEAN NOT NULL DEFAULT 0; -- 是否使用模拟交易
+
+CREATE INDEX IF NOT EXISTS idx_paper_orders_symbol_status ON paper_orders(symbol, status);
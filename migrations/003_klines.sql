@@ -0,0 +1,14 @@
+-- K线数据表
+CREATE TABLE IF NOT EXISTS klines (
+    settle TEXT NOT NULL, -- 结算币种，如 usdt
+    contract TEXT NOT NULL, -- 合约，如 BTC_USDT
+    interval_type TEXT NOT NULL, -- '1m', '5m', '15m', etc.
+    timestamp INTEGER NOT NULL, -- K线开始时间(秒)
+    open REAL NOT NULL,
+    high REAL NOT NULL,
+    low REAL NOT NULL,
+    close REAL NOT NULL,
+    volume REAL NOT NULL,
+    updated_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+    PRIMARY KEY (settle, contract, interval_type, timestamp)
+);
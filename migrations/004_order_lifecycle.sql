@@ -0,0 +1,19 @@
+-- 订单生命周期：记录交易所订单ID、状态、成交和止盈止损委托
+ALTER TABLE orders ADD COLUMN exchange TEXT NOT NULL DEFAULT 'gate'; -- 'gate' or 'paper'
+ALTER TABLE orders ADD COLUMN exchange_order_id TEXT;
+ALTER TABLE orders ADD COLUMN client_order_id TEXT; -- 下单时的text字段，t-开头
+ALTER TABLE orders ADD COLUMN status TEXT NOT NULL DEFAULT 'pending'; -- 'pending', 'open', 'filled', 'cancelled', 'rejected'
+ALTER TABLE orders ADD COLUMN filled_size INTEGER NOT NULL DEFAULT 0; -- 已成交张数
+ALTER TABLE orders ADD COLUMN avg_fill_price REAL;
+ALTER TABLE orders ADD COLUMN fee REAL NOT NULL DEFAULT 0; -- 手续费(USDT)
+ALTER TABLE orders ADD COLUMN take_profit_order_id TEXT; -- 止盈触发委托ID
+ALTER TABLE orders ADD COLUMN take_profit_status TEXT; -- 'open', 'triggered', 'cancelled', 'failed'
+ALTER TABLE orders ADD COLUMN stop_loss_order_id TEXT; -- 止损触发委托ID
+ALTER TABLE orders ADD COLUMN stop_loss_status TEXT;
+ALTER TABLE orders ADD COLUMN error_message TEXT;
+ALTER TABLE orders ADD COLUMN updated_at INTEGER;
+
+-- 升级前的订单无法确认是否真实成交
+UPDATE orders SET status = 'unknown';
+
+CREATE INDEX IF NOT EXISTS idx_orders_status ON orders(status);
@@ -0,0 +1,40 @@
+-- 持仓快照表
+CREATE TABLE IF NOT EXISTS position_snapshots (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    exchange TEXT NOT NULL, -- 'gate' or 'paper'
+    symbol TEXT NOT NULL,
+    mode TEXT NOT NULL DEFAULT 'single', -- 'single', 'dual_long', 'dual_short'
+    size INTEGER NOT NULL, -- 张，正数多头，负数空头
+    entry_price REAL NOT NULL,
+    mark_price REAL NOT NULL,
+    unrealized_pnl REAL NOT NULL,
+    realized_pnl REAL NOT NULL,
+    leverage REAL NOT NULL DEFAULT 0,
+    liq_price REAL NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+);
+
+-- 已平仓的完整交易，按止盈/止损触发计算已实现盈亏
+CREATE TABLE IF NOT EXISTS round_trips (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    exchange TEXT NOT NULL,
+    symbol TEXT NOT NULL,
+    side TEXT NOT NULL, -- 'buy' or 'sell'，开仓方向
+    size INTEGER NOT NULL, -- 张
+    entry_price REAL NOT NULL,
+    exit_price REAL NOT NULL,
+    exit_reason TEXT NOT NULL, -- 'tp', 'sl'
+    gross_pnl REAL NOT NULL,
+    fee REAL NOT NULL, -- 开仓和平仓手续费
+    realized_pnl REAL NOT NULL, -- 扣除手续费后的盈亏
+    order_id INTEGER NOT NULL UNIQUE,
+    signal_id INTEGER,
+    opened_at INTEGER NOT NULL,
+    closed_at INTEGER NOT NULL,
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+    FOREIGN KEY (order_id) REFERENCES orders(id),
+    FOREIGN KEY (signal_id) REFERENCES signals(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_position_snapshots_symbol ON position_snapshots(exchange, symbol, created_at);
+CREATE INDEX IF NOT EXISTS idx_round_trips_closed_at ON round_trips(closed_at);
@@ -0,0 +1,19 @@
+-- 自动下单的风控设置，只有一行
+CREATE TABLE IF NOT EXISTS risk_settings (
+    id INTEGER PRIMARY KEY,
+    enabled BOOLEAN NOT NULL DEFAULT 1,
+    max_positions INTEGER NOT NULL DEFAULT 5, -- 同时持仓（含未成交订单）的最大数量，0表示不限制
+    max_positions_per_symbol INTEGER NOT NULL DEFAULT 1, -- 单个交易对同时持仓的最大数量
+    max_daily_loss REAL NOT NULL DEFAULT 0, -- 当日（北京时间）已实现亏损上限(USDT)
+    max_orders_per_hour INTEGER NOT NULL DEFAULT 10, -- 每小时最多下单次数
+    max_consecutive_losses INTEGER NOT NULL DEFAULT 3, -- 连续亏损次数达到后进入冷却
+    cooldown_minutes INTEGER NOT NULL DEFAULT 60, -- 冷却时长(分钟)
+    updated_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+);
+
+INSERT OR IGNORE INTO risk_settings (id) VALUES (1);
+
+-- 被风控拦截的信号记录拦截原因
+ALTER TABLE signals ADD COLUMN blocked_reason TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_orders_exchange_created_at ON orders(exchange, created_at);
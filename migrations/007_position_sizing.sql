@@ -0,0 +1,3 @@
+-- 监控配置增加仓位计算方式
+ALTER TABLE monitor_configs ADD COLUMN sizing_mode TEXT NOT NULL DEFAULT 'fixed'; -- 'fixed' 固定张数, 'risk_amount' 固定风险金额, 'risk_percent' 权益百分比
+ALTER TABLE monitor_configs ADD COLUMN risk_per_trade REAL NOT NULL DEFAULT 0; -- 每笔风险，risk_amount为USDT，risk_percent为%
@@ -0,0 +1,2 @@
+-- 紧急平仓后暂停所有自动下单，需要手动恢复
+ALTER TABLE risk_settings ADD COLUMN halted BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+-- 监控配置增加K线收盘后的宽限时间，收盘后等待这么久再获取K线
+ALTER TABLE monitor_configs ADD COLUMN close_grace_secs INTEGER NOT NULL DEFAULT 3;
+-- 宽限时间过后交易所还没有返回收盘K线时的重试间隔
+ALTER TABLE monitor_configs ADD COLUMN close_retry_secs INTEGER NOT NULL DEFAULT 2;
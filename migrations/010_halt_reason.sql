@@ -0,0 +1,2 @@
+-- 记录暂停自动下单的原因：紧急平仓或余额不足
+ALTER TABLE risk_settings ADD COLUMN halted_reason TEXT;
@@ -0,0 +1,3 @@
+-- 下单接口：'web' 网页端接口一起设置止盈止损（需要cookie），'api' 签名API成交后单独设置止盈止损
+ALTER TABLE monitor_configs ADD COLUMN execution_mode TEXT NOT NULL DEFAULT 'web';
+ALTER TABLE orders ADD COLUMN execution_mode TEXT NOT NULL DEFAULT 'web';
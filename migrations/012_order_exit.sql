@@ -0,0 +1,4 @@
+-- 订单平仓记录：止盈止损一边触发后撤销另一边，或者检测到手动平仓
+ALTER TABLE orders ADD COLUMN exit_price REAL;
+ALTER TABLE orders ADD COLUMN exit_reason TEXT; -- 'tp', 'sl', 'manual'
+ALTER TABLE orders ADD COLUMN closed_at INTEGER;
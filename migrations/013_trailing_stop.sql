@@ -0,0 +1,26 @@
+-- 移动止损：浮盈达到初始风险的倍数后移到开仓价（保本），或者按固定距离、百分比、ATR倍数跟随
+ALTER TABLE monitor_configs ADD COLUMN break_even_r REAL NOT NULL DEFAULT 0; -- 0表示不启用保本
+ALTER TABLE monitor_configs ADD COLUMN trailing_mode TEXT NOT NULL DEFAULT 'none'; -- 'none', 'fixed', 'percent', 'atr'
+ALTER TABLE monitor_configs ADD COLUMN trailing_value REAL NOT NULL DEFAULT 0;
+
+-- 下单时复制配置中的规则，配置修改后不影响已有持仓
+ALTER TABLE orders ADD COLUMN interval_type TEXT;
+ALTER TABLE orders ADD COLUMN break_even_r REAL NOT NULL DEFAULT 0;
+ALTER TABLE orders ADD COLUMN trailing_mode TEXT NOT NULL DEFAULT 'none';
+ALTER TABLE orders ADD COLUMN trailing_value REAL NOT NULL DEFAULT 0;
+ALTER TABLE orders ADD COLUMN initial_stop_price REAL; -- 下单时的止损价，stop_loss_price 为移动后的止损价
+
+-- 每次移动止损的记录
+CREATE TABLE IF NOT EXISTS stop_adjustments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    order_id INTEGER NOT NULL,
+    reason TEXT NOT NULL, -- 'break_even', 'trailing'
+    old_stop REAL NOT NULL,
+    new_stop REAL NOT NULL,
+    price REAL NOT NULL, -- 触发调整的K线收盘价
+    stop_loss_order_id TEXT, -- 新的止损委托ID
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+    FOREIGN KEY (order_id) REFERENCES orders(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_stop_adjustments_order ON stop_adjustments(order_id);
@@ -0,0 +1,26 @@
+-- 分批止盈：把仓位分成几份，每份在不同的R倍数单独设置只减仓的止盈委托，剩余部分由止损/移动止损平仓
+ALTER TABLE monitor_configs ADD COLUMN take_profit_ladder TEXT NOT NULL DEFAULT ''; -- 例如 '50:1,30:2,20'，空表示不分批
+ALTER TABLE monitor_configs ADD COLUMN ladder_break_even BOOLEAN NOT NULL DEFAULT 0; -- 第一批止盈后止损移到开仓价
+
+ALTER TABLE orders ADD COLUMN take_profit_ladder TEXT NOT NULL DEFAULT '';
+ALTER TABLE orders ADD COLUMN ladder_break_even BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE orders ADD COLUMN closed_size INTEGER NOT NULL DEFAULT 0; -- 分批止盈已经平掉的张数
+
+-- 分批止盈的每一批
+CREATE TABLE IF NOT EXISTS take_profit_tranches (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    order_id INTEGER NOT NULL,
+    seq INTEGER NOT NULL, -- 从1开始
+    size INTEGER NOT NULL,
+    r_multiple REAL NOT NULL,
+    trigger_price REAL NOT NULL,
+    trigger_order_id TEXT,
+    status TEXT NOT NULL DEFAULT 'pending', -- 'pending' 未创建委托, 'open', 'triggered', 'cancelled'
+    fill_price REAL,
+    fee REAL NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+    updated_at INTEGER,
+    FOREIGN KEY (order_id) REFERENCES orders(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_take_profit_tranches_order ON take_profit_tranches(order_id);
@@ -0,0 +1,10 @@
+-- 多策略：每个监控配置选择一种信号策略，长影线或K线形态
+ALTER TABLE monitor_configs ADD COLUMN strategy TEXT NOT NULL DEFAULT 'long_shadow'; -- 'long_shadow', 'engulfing', 'pin_bar', 'inside_bar', 'outside_bar', 'star', 'doji'
+
+ALTER TABLE signals ADD COLUMN strategy TEXT NOT NULL DEFAULT 'long_shadow';
+ALTER TABLE signals ADD COLUMN pattern TEXT; -- 识别到的形态，例如 'bullish_engulfing'，长影线策略为空
+ALTER TABLE signals ADD COLUMN direction TEXT NOT NULL DEFAULT ''; -- 'long' or 'short'
+ALTER TABLE signals ADD COLUMN stop_price REAL; -- 形态的止损价，长影线策略为空，使用K线最高/最低价
+
+-- 已有的长影线信号：下影线做多，上影线做空
+UPDATE signals SET direction = CASE WHEN shadow_type = 'lower' THEN 'long' ELSE 'short' END;
@@ -0,0 +1,12 @@
+-- 指标过滤：信号还要满足配置的趋势、动量和波动率条件才下单，0表示不启用
+ALTER TABLE monitor_configs ADD COLUMN trend_ema_period INTEGER NOT NULL DEFAULT 0; -- 收盘价在EMA之上才做多，之下才做空
+ALTER TABLE monitor_configs ADD COLUMN rsi_period INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE monitor_configs ADD COLUMN rsi_overbought REAL NOT NULL DEFAULT 0; -- RSI高于此值不做多
+ALTER TABLE monitor_configs ADD COLUMN rsi_oversold REAL NOT NULL DEFAULT 0; -- RSI低于此值不做空
+ALTER TABLE monitor_configs ADD COLUMN min_atr REAL NOT NULL DEFAULT 0; -- ATR(14)低于此值不下单
+ALTER TABLE monitor_configs ADD COLUMN bb_period INTEGER NOT NULL DEFAULT 0; -- 收盘价在布林带上轨之外不做多，下轨之外不做空
+ALTER TABLE monitor_configs ADD COLUMN macd_confirm BOOLEAN NOT NULL DEFAULT 0; -- MACD柱为正才做多，为负才做空
+ALTER TABLE monitor_configs ADD COLUMN vwap_confirm BOOLEAN NOT NULL DEFAULT 0; -- 收盘价在VWAP之上才做多，之下才做空
+
+-- 每个启用的过滤条件的指标值、阈值和是否通过，JSON数组
+ALTER TABLE signals ADD COLUMN indicator_filters TEXT;
@@ -0,0 +1,7 @@
+-- 多周期确认：信号还需要更大周期的K线方向、EMA或长影线同向才下单
+ALTER TABLE monitor_configs ADD COLUMN confirm_interval TEXT NOT NULL DEFAULT ''; -- 确认用的大周期，空表示不启用
+ALTER TABLE monitor_configs ADD COLUMN confirm_mode TEXT NOT NULL DEFAULT 'candle'; -- 'candle', 'ema', 'shadow'
+ALTER TABLE monitor_configs ADD COLUMN confirm_ema_period INTEGER NOT NULL DEFAULT 50;
+
+-- 确认使用的大周期K线、指标值和结果，JSON对象
+ALTER TABLE signals ADD COLUMN confirmation TEXT;
@@ -0,0 +1,3 @@
+-- 自定义入场规则：用表达式描述额外的入场条件，例如 close > ema(50) && volume / avg_volume(36) > 1.5
+ALTER TABLE monitor_configs ADD COLUMN entry_rule TEXT NOT NULL DEFAULT ''; -- 空表示不启用
+ALTER TABLE monitor_configs ADD COLUMN rule_error TEXT; -- 最近一次规则求值的错误，保存配置时清空
@@ -0,0 +1,17 @@
+-- 脚本策略：用Rhai脚本描述信号，每次保存生成新版本
+CREATE TABLE IF NOT EXISTS strategy_scripts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    version INTEGER NOT NULL, -- 同名脚本从1开始递增
+    source TEXT NOT NULL,
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+    UNIQUE (name, version)
+);
+
+ALTER TABLE monitor_configs ADD COLUMN script_name TEXT NOT NULL DEFAULT ''; -- strategy 为 'script' 时运行的脚本
+ALTER TABLE monitor_configs ADD COLUMN script_version INTEGER NOT NULL DEFAULT 0; -- 0表示最新版本
+
+-- 脚本给出的入场价和止盈价，为空时按收盘价和盈亏比计算
+ALTER TABLE signals ADD COLUMN script_version INTEGER;
+ALTER TABLE signals ADD COLUMN entry_price REAL;
+ALTER TABLE signals ADD COLUMN target_price REAL;
@@ -0,0 +1,10 @@
+-- 交易所确认没有K线的时间段（如上线前、停盘期间），回补缺口时不再重复请求
+CREATE TABLE IF NOT EXISTS kline_empty_ranges (
+    settle TEXT NOT NULL,
+    contract TEXT NOT NULL,
+    interval_type TEXT NOT NULL,
+    range_from INTEGER NOT NULL, -- 起始K线时间(秒)，包含
+    range_to INTEGER NOT NULL, -- 结束K线时间(秒)，包含
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+    PRIMARY KEY (settle, contract, interval_type, range_from, range_to)
+);
@@ -0,0 +1,2 @@
+-- 模拟订单下单时记录合约的吃单手续费率，市价入场和止盈止损平仓时收取
+ALTER TABLE paper_orders ADD COLUMN fee_rate REAL NOT NULL DEFAULT 0;